@@ -0,0 +1,116 @@
+// Micro-benchmarks for the VM's dispatch loop. Run with `cargo bench`; each
+// benchmark reports the fastest of several runs
+
+use std::{collections::HashMap, hint::black_box, time::Instant};
+
+use block_macros::block;
+use pidgin::{
+  Argument::*, Block, EvaluationState, GenericInstruction::*, Value,
+};
+
+const RUNS: usize = 5;
+
+fn bench(name: &str, program: impl Fn() -> Block) {
+  let globals = HashMap::new();
+  let fastest = (0..RUNS)
+    .map(|_| {
+      let mut state = EvaluationState::new(program());
+      let start = Instant::now();
+      black_box(state.evaluate(&globals).unwrap());
+      start.elapsed()
+    })
+    .min()
+    .unwrap();
+  println!("{name:<24} {:>10.3} ms", fastest.as_secs_f64() * 1000.);
+}
+
+fn main() {
+  bench("decrement loop", || {
+    block![
+      Const(0, 10000000),
+      Const(
+        1,
+        Value::composite_fn(
+          1,
+          block![IsPos(1, 0), If(1), Dec(0, 0), Jump(0), EndIf, Return(0)]
+        )
+      ),
+      Call(0, 1, [StealArgument(0)]),
+    ]
+  });
+  bench("fused counting loop", || {
+    block![
+      Const(0, -10000000),
+      label start,
+      IncAndJumpIfPos(0, end),
+      Jump(start),
+      label end,
+      Return(0),
+    ]
+  });
+  bench("arithmetic", || {
+    block![
+      Const(0, 1000000),
+      Const(1, 0),
+      label start,
+      IsPos(2, 0),
+      JumpIfNot(2, end),
+      Const(3, 3),
+      Multiply(4, 0, 3),
+      Add(1, 1, 4),
+      Const(3, 7),
+      Subtract(1, 1, 3),
+      Dec(0, 0),
+      Jump(start),
+      label end,
+      Return(1),
+    ]
+  });
+  bench("function calls", || {
+    block![
+      Const(0, 1000000),
+      Const(1, Value::composite_fn(2, block![Add(0, 0, 1), Return(0)])),
+      Const(2, 0),
+      label start,
+      IsPos(3, 0),
+      JumpIfNot(3, end),
+      Call(2, 1, [StealArgument(2), CopyArgument(0)]),
+      Dec(0, 0),
+      Jump(start),
+      label end,
+      Return(2),
+    ]
+  });
+  bench("coroutine resumes", || {
+    block![
+      Const(0, 1000000),
+      Const(
+        1,
+        Value::composite_fn(0, block![label start, Yield(0), Jump(start)])
+      ),
+      CreateCoroutine(1),
+      label start,
+      IsPos(2, 0),
+      JumpIfNot(2, end),
+      Call(3, 1, []),
+      Dec(0, 0),
+      Jump(start),
+      label end,
+      Return(0),
+    ]
+  });
+  bench("list building", || {
+    block![
+      Const(0, 100000),
+      Const(1, Value::from(Vec::<Value>::new())),
+      label start,
+      IsPos(2, 0),
+      JumpIfNot(2, end),
+      Push(1, 0),
+      Dec(0, 0),
+      Jump(start),
+      label end,
+      Return(1),
+    ]
+  });
+}
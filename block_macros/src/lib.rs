@@ -1,133 +1,379 @@
 extern crate proc_macro;
-use proc_macro::{TokenStream, TokenTree};
-
-fn generic_block(block_type_name: &str, input: TokenStream) -> TokenStream {
-  use TokenTree::*;
-  let mut instructions: Vec<String> = vec![];
-  let mut constants: Vec<String> = vec![];
-  let mut tokens = input.into_iter();
-  while let Some(hopefully_instruction_identifier) = tokens.next() {
-    instructions.push(match hopefully_instruction_identifier {
-      Ident(instruction_identifier) => {
-        let input_instruction_string = instruction_identifier.to_string();
-        if let Some(next_token) = tokens.next() {
-          match next_token {
-            Group(group) => {
-              let result_instruction_string = if input_instruction_string
-                == "Const"
-              {
-                match group.stream().into_iter().collect::<Vec<_>>().as_slice()
-                {
-                  [Literal(lit), Punct(separator), const_expression @ ..] => {
-                    let separator_string = separator.to_string();
-                    if separator_string == "," {
-                      constants.push(
-                        const_expression
-                          .into_iter()
-                          .map(|e| e.to_string())
-                          .collect::<Vec<_>>()
-                          .join(""),
-                      );
-                      format!("Const({},{})", lit, constants.len() - 1)
-                    } else {
-                      panic!(
-                        "invalid separator in Const in program!, expected ',', 
-                        got '{}'",
-                        separator_string
-                      )
-                    }
-                  }
-                  _ => panic!(
-                    "expected literal and expression in Const in program!"
-                  ),
-                }
-              } else {
-                input_instruction_string.to_owned() + &group.to_string()
-              };
-              if let Some(comma_token) = tokens.next() {
-                match comma_token {
-                  Punct(punct) => {
-                    let punct_string = punct.to_string();
-                    if punct_string == "," {
-                      result_instruction_string
-                    } else {
-                      panic!(
-                        "expected ',' after instruction in program!, got {}",
-                        punct_string
-                      )
-                    }
-                  }
-                  other => panic!(
-                    "expected ',' after instruction in program!, got {}",
-                    other.to_string()
-                  ),
-                }
-              } else {
-                result_instruction_string
-              }
-            }
-            Punct(punct) => {
-              let punct_string = punct.to_string();
-              if punct_string == "," {
-                input_instruction_string
-              } else {
-                panic!(
-                  "unexpected punctuation '{}' in program!, expecting group \
-                  or ','",
-                  punct_string
-                )
-              }
-            }
-            Literal(_) => {
-              panic!("unexpected literal in program!, expecting group or ','")
-            }
-            Ident(_) => {
-              panic!(
-                "unexpected identifier in program!, expecting group or ','"
-              )
-            }
-          }
-        } else {
-          input_instruction_string
+use std::collections::HashMap;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::{
+  parenthesized,
+  parse::{Parse, ParseStream},
+  parse_macro_input,
+  punctuated::Punctuated,
+  spanned::Spanned,
+  token::Paren,
+  Expr, FnArg, Ident, ImplItem, ItemImpl, Pat, ReturnType, Token, Type,
+  Visibility,
+};
+
+// An item of a `block!`: either an instruction or a label naming the index of
+// the instruction that follows it
+enum BlockItem {
+  Label(Ident),
+  Const {
+    register: Box<Expr>,
+    value: Box<Expr>,
+  },
+  Instruction {
+    name: Ident,
+    args: Option<Punctuated<Expr, Token![,]>>,
+  },
+}
+
+impl Parse for BlockItem {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    let name: Ident = input.parse()?;
+    if name == "label" && input.peek(Ident) {
+      return Ok(BlockItem::Label(input.parse()?));
+    }
+    if !input.peek(Paren) {
+      return Ok(BlockItem::Instruction { name, args: None });
+    }
+    let content;
+    parenthesized!(content in input);
+    if name == "Const" {
+      let register = content.parse()?;
+      content.parse::<Token![,]>()?;
+      let value = content.parse()?;
+      if !content.is_empty() {
+        return Err(content.error("expected `)` after the constant value"));
+      }
+      Ok(BlockItem::Const { register, value })
+    } else {
+      Ok(BlockItem::Instruction {
+        name,
+        args: Some(content.parse_terminated(Expr::parse, Token![,])?),
+      })
+    }
+  }
+}
+
+struct BlockInput {
+  items: Punctuated<BlockItem, Token![,]>,
+}
+
+impl Parse for BlockInput {
+  fn parse(input: ParseStream) -> syn::Result<Self> {
+    Ok(BlockInput {
+      items: input.parse_terminated(BlockItem::parse, Token![,])?,
+    })
+  }
+}
+
+// Replaces an argument naming a label with the index of the instruction that
+// the label is attached to
+// Labels resolve to the index of the instruction they precede, or for
+// `RelativeJump`, to the offset from the jump itself
+fn resolve_label(
+  arg: &Expr,
+  labels: &HashMap<String, usize>,
+  origin: usize,
+) -> proc_macro2::TokenStream {
+  if let Expr::Path(path) = arg {
+    if let Some(index) = path
+      .path
+      .get_ident()
+      .and_then(|ident| labels.get(&ident.to_string()))
+    {
+      let index = proc_macro2::Literal::isize_unsuffixed(
+        *index as isize - origin as isize,
+      );
+      return quote_spanned! { arg.span() => #index };
+    }
+  }
+  quote! { #arg }
+}
+
+fn generic_block(
+  block_type_name: &str,
+  input: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+  syn::parse2(input)
+    .and_then(|input| block_tokens(block_type_name, input))
+    .unwrap_or_else(syn::Error::into_compile_error)
+}
+
+fn block_tokens(
+  block_type_name: &str,
+  input: BlockInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+  let mut labels = HashMap::new();
+  let mut instruction_count = 0;
+  for item in &input.items {
+    match item {
+      BlockItem::Label(label) => {
+        if labels
+          .insert(label.to_string(), instruction_count)
+          .is_some()
+        {
+          return Err(syn::Error::new(
+            label.span(),
+            format!("label `{label}` is defined more than once"),
+          ));
         }
       }
-      Group(_) => {
-        panic!("unexpected group in program!, expecting instruction identifier")
+      _ => instruction_count += 1,
+    }
+  }
+  let mut instructions: Vec<proc_macro2::TokenStream> = vec![];
+  let mut constants = vec![];
+  for item in input.items {
+    match item {
+      BlockItem::Label(_) => {}
+      BlockItem::Const { register, value } => {
+        let index = proc_macro2::Literal::usize_unsuffixed(constants.len());
+        instructions.push(quote! { Const(#register, #index) });
+        constants.push(value);
       }
-      Punct(_) => {
-        panic!(
-          "unexpected punctuation in program!, expecting instruction \
-          identifier"
-        )
+      BlockItem::Instruction { name, args: None } => {
+        instructions.push(quote! { #name })
       }
-      Literal(_) => {
-        panic!(
-          "unexpected raw literal in program!, expecting instruction \
-          identifier"
-        )
+      BlockItem::Instruction {
+        name,
+        args: Some(args),
+      } => {
+        let origin = if name == "RelativeJump" {
+          instructions.len()
+        } else {
+          0
+        };
+        // array literals are the arguments carried by calls
+        let args = args.iter().map(|arg| match arg {
+          Expr::Array(array) => {
+            let elements = &array.elems;
+            quote! { ::std::rc::Rc::from([#elements]) }
+          }
+          arg => resolve_label(arg, &labels, origin),
+        });
+        instructions.push(quote! { #name(#(#args),*) })
       }
-    })
+    }
   }
-  format!(
-    "{}::new(vec![{}], vec![{}])",
-    block_type_name,
-    instructions.join(", "),
-    constants
-      .into_iter()
-      .map(|x| format!("({}).into()", x))
-      .collect::<Vec<_>>()
-      .join(", "),
-  )
-  .parse()
-  .unwrap()
+  let block_type = Ident::new(block_type_name, Span::call_site());
+  Ok(quote! {
+    #block_type::new(
+      vec![#(#instructions),*],
+      vec![#((#constants).into()),*],
+    )
+  })
 }
 
 #[proc_macro]
 pub fn block(input: TokenStream) -> TokenStream {
-  generic_block("Block", input)
+  generic_block("Block", input.into()).into()
 }
 
 #[proc_macro]
 pub fn ssa_block(input: TokenStream) -> TokenStream {
-  generic_block("SSABlock", input)
+  generic_block("SSABlock", input.into()).into()
+}
+
+fn kebab_case(name: &str) -> String {
+  name.trim_start_matches("r#").replace('_', "-")
+}
+
+fn is_result_type(ty: &Type) -> bool {
+  match ty {
+    Type::Path(path) => path
+      .path
+      .segments
+      .last()
+      .is_some_and(|segment| segment.ident == "Result"),
+    _ => false,
+  }
+}
+
+// Generates the glue for exposing each `pub fn` of an impl block that takes
+// `&self` as a method that scripts can call on external objects of that type.
+// Methods are named in kebab-case, so `fn add_item` becomes `add-item`
+fn export_impl(item: ItemImpl) -> syn::Result<proc_macro2::TokenStream> {
+  let self_type = &item.self_ty;
+  let mut registrations = vec![];
+  for impl_item in &item.items {
+    let ImplItem::Fn(method) = impl_item else {
+      continue;
+    };
+    if !matches!(method.vis, Visibility::Public(_)) {
+      continue;
+    }
+    let signature = &method.sig;
+    let mut inputs = signature.inputs.iter();
+    match inputs.next() {
+      Some(FnArg::Receiver(receiver)) => {
+        if receiver.reference.is_none() || receiver.mutability.is_some() {
+          return Err(syn::Error::new(
+            receiver.span(),
+            "exported methods must take `&self`, since external objects are \
+            shared. Use interior mutability for state that scripts change",
+          ));
+        }
+      }
+      _ => continue,
+    }
+    let mut arg_names = vec![];
+    let mut arg_types = vec![];
+    for (arg_index, input) in inputs.enumerate() {
+      let FnArg::Typed(typed) = input else {
+        unreachable!("receiver after first argument")
+      };
+      arg_names.push(match &*typed.pat {
+        Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+        other => syn::Ident::new(&format!("arg_{arg_index}"), other.span()),
+      });
+      arg_types.push(&typed.ty);
+    }
+    let method_ident = &signature.ident;
+    let name = kebab_case(&method_ident.to_string());
+    let arg_count = arg_names.len() as u8;
+    let call = quote! { this.#method_ident(#(#arg_names),*) };
+    let wrapped_result = match &signature.output {
+      ReturnType::Default => quote! {
+        #call;
+        Ok(::pidgin::Value::Nil)
+      },
+      ReturnType::Type(_, ty) if is_result_type(ty) => quote! {
+        #call.map(::pidgin::Value::from).map_err(|error| {
+          ::std::rc::Rc::new(error) as ::std::rc::Rc<dyn ::std::error::Error>
+        })
+      },
+      ReturnType::Type(_, _) => quote! {
+        Ok(::pidgin::Value::from(#call))
+      },
+    };
+    registrations.push(quote! {
+      evaluator.define_method::<Self>(#name, #arg_count, |this, args| {
+        let mut args = args.into_iter();
+        #(
+          let #arg_names: #arg_types =
+            ::pidgin::extract_arg(args.next().unwrap())?;
+        )*
+        #wrapped_result
+      });
+    });
+  }
+  let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+  Ok(quote! {
+    #item
+    impl #impl_generics ::pidgin::Export for #self_type
+      #where_clause
+    {
+      fn export_methods(evaluator: &mut ::pidgin::Evaluator) {
+        #(#registrations)*
+      }
+    }
+  })
+}
+
+#[proc_macro_attribute]
+pub fn export(_attribute: TokenStream, input: TokenStream) -> TokenStream {
+  let item = parse_macro_input!(input as ItemImpl);
+  export_impl(item)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+  use quote::quote;
+
+  use super::generic_block;
+
+  fn expand(input: proc_macro2::TokenStream) -> String {
+    generic_block("Block", input).to_string()
+  }
+
+  #[test]
+  fn constants_with_commas_inside() {
+    let expansion = expand(quote! {
+      Const(0, HashMap::<u8, u8>::new()),
+      Const(1, values.get(1, 2).unwrap()),
+      Return(0),
+    });
+    assert_eq!(
+      expansion,
+      quote! {
+        Block::new(
+          vec![Const(0, 0), Const(1, 1), Return(0)],
+          vec![
+            (HashMap::<u8, u8>::new()).into(),
+            (values.get(1, 2).unwrap()).into()
+          ],
+        )
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn nested_blocks_are_left_to_expand() {
+    let expansion = expand(quote! {
+      Const(0, Value::composite_fn(1, block![label start, Jump(start)])),
+      Jump(end),
+      label end,
+    });
+    assert_eq!(
+      expansion,
+      quote! {
+        Block::new(
+          vec![Const(0, 0), Jump(2)],
+          vec![
+            (Value::composite_fn(1, block![label start, Jump(start)])).into()
+          ],
+        )
+      }
+      .to_string()
+    );
+  }
+
+  #[test]
+  fn relative_jumps_resolve_to_offsets() {
+    let expansion = expand(quote! {
+      label start,
+      Inc(0, 0),
+      JumpIf(0, end),
+      RelativeJump(start),
+      label end,
+      RelativeJump(start),
+    });
+    assert!(expansion.contains("JumpIf (0 , 3)"));
+    assert!(expansion.contains("RelativeJump (- 2)"));
+    assert!(expansion.contains("RelativeJump (- 3)"));
+  }
+
+  #[test]
+  fn call_arguments_become_slices() {
+    let expansion = expand(quote! {
+      Call(2, 0, [CopyArgument(1), StealArgument(0)]),
+      CallAndReturn(0, []),
+    });
+    assert!(expansion.contains(
+      &quote! {
+        Call(
+          2,
+          0,
+          ::std::rc::Rc::from([CopyArgument(1), StealArgument(0)])
+        )
+      }
+      .to_string()
+    ));
+    assert!(expansion.contains(
+      &quote! { CallAndReturn(0, ::std::rc::Rc::from([])) }.to_string()
+    ));
+  }
+
+  #[test]
+  fn malformed_input_is_a_compile_error() {
+    assert!(expand(quote! { Const(0 1) }).contains("compile_error"));
+    assert!(expand(quote! { label a, label a, Return(0) })
+      .contains("defined more than once"));
+  }
 }
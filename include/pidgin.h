@@ -0,0 +1,56 @@
+/* The crate is built as an rlib by default, so C hosts choose the kind of
+ * library to link against when building it, with
+ *
+ *   cargo rustc --release --lib --features capi --crate-type staticlib
+ *
+ * for a static library, or `--crate-type cdylib` for a shared one */
+
+#ifndef PIDGIN_H
+#define PIDGIN_H
+
+/* Generated by cbindgen from src/frontend/ffi.rs, don't edit by hand */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct PidginEvaluator PidginEvaluator;
+
+typedef struct PidginValue PidginValue;
+
+typedef PidginValue *(*PidginCallback)(void *user_data,
+                                       const PidginValue *const *args,
+                                       size_t arg_count);
+
+PidginEvaluator *pidgin_evaluator_new(void);
+
+void pidgin_evaluator_free(PidginEvaluator *evaluator);
+
+PidginValue *pidgin_eval(PidginEvaluator *evaluator, const char *source);
+
+bool pidgin_define_fn(PidginEvaluator *evaluator,
+                      const char *name,
+                      PidginCallback callback,
+                      void *user_data);
+
+PidginValue *pidgin_value_nil(void);
+
+PidginValue *pidgin_value_from_number(double n);
+
+PidginValue *pidgin_value_from_int(int64_t n);
+
+PidginValue *pidgin_value_from_string(const char *s);
+
+PidginValue *pidgin_value_error(const char *message);
+
+bool pidgin_value_is_error(const PidginValue *value);
+
+bool pidgin_value_as_number(const PidginValue *value, double *out);
+
+char *pidgin_value_to_string(const PidginEvaluator *evaluator, const PidginValue *value);
+
+void pidgin_value_free(PidginValue *value);
+
+void pidgin_string_free(char *s);
+
+#endif /* PIDGIN_H */
@@ -1,12 +1,73 @@
-use std::{ops::Index, rc::Rc};
+use std::{cell::RefCell, fmt::Debug, ops::Index, rc::Rc};
 
-use crate::{instructions::GenericInstruction, runtime::data::GenericValue};
+use crate::{
+  instructions::GenericInstruction,
+  runtime::{
+    control::Block,
+    data::{GenericValue, Value},
+    evaluation::{ConstIndex, Instruction, Register},
+  },
+};
 
-#[derive(Debug, Clone, PartialEq)]
+// Values that `Lookup` instructions have resolved, indexed by instruction and
+// tagged with the generation of the global environment they were resolved in,
+// so that redefining a global invalidates them
+pub(crate) struct LookupCache<V>(RefCell<Vec<Option<(u64, V)>>>);
+impl<V> Default for LookupCache<V> {
+  fn default() -> Self {
+    Self(RefCell::new(vec![]))
+  }
+}
+impl<V: Clone> LookupCache<V> {
+  pub(crate) fn get(&self, index: usize, generation: u64) -> Option<V> {
+    match self.0.borrow().get(index) {
+      Some(Some((cached_generation, value)))
+        if *cached_generation == generation =>
+      {
+        Some(value.clone())
+      }
+      _ => None,
+    }
+  }
+  pub(crate) fn insert(&self, index: usize, generation: u64, value: V) {
+    let mut entries = self.0.borrow_mut();
+    if entries.len() <= index {
+      entries.resize_with(index + 1, || None);
+    }
+    entries[index] = Some((generation, value));
+  }
+}
+
+#[derive(Clone)]
 pub struct GenericBlock<I, O, R, M> {
   pub instructions: Rc<[GenericInstruction<I, O, R>]>,
   pub constants: Rc<[GenericValue<I, O, R, M>]>,
   pub metadata: M,
+  pub(crate) lookup_cache: Rc<LookupCache<GenericValue<I, O, R, M>>>,
+}
+impl<I: Clone + Debug, O: Clone + Debug, R: Clone + Debug, M: Clone + Debug>
+  Debug for GenericBlock<I, O, R, M>
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("GenericBlock")
+      .field("instructions", &self.instructions)
+      .field("constants", &self.constants)
+      .field("metadata", &self.metadata)
+      .finish()
+  }
+}
+impl<
+    I: Clone + PartialEq,
+    O: Clone + PartialEq,
+    R: Clone + PartialEq,
+    M: Clone + PartialEq,
+  > PartialEq for GenericBlock<I, O, R, M>
+{
+  fn eq(&self, other: &Self) -> bool {
+    self.instructions == other.instructions
+      && self.constants == other.constants
+      && self.metadata == other.metadata
+  }
 }
 impl<I, O, R, M> Index<usize> for GenericBlock<I, O, R, M> {
   type Output = GenericInstruction<I, O, R>;
@@ -24,6 +85,7 @@ impl<I, O, R, M> GenericBlock<I, O, R, M> {
       instructions: instructions.into(),
       constants: constants.into(),
       metadata,
+      lookup_cache: Rc::default(),
     }
   }
 }
@@ -83,3 +145,94 @@ impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericBlock<I, O, R, M> {
     self.translate_inner(0, translator)
   }
 }
+
+// A position in a block under construction that jumps can target before the
+// position itself is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+// Builds a block one instruction at a time, keeping track of constant indexes
+// and resolving jumps to labels once the block is finished
+#[derive(Debug, Default)]
+pub struct BlockBuilder {
+  instructions: Vec<Instruction>,
+  constants: Vec<Value>,
+  label_positions: Vec<Option<usize>>,
+  jumps: Vec<(usize, Label)>,
+}
+
+impl BlockBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn push_instruction(&mut self, instruction: Instruction) -> &mut Self {
+    self.instructions.push(instruction);
+    self
+  }
+  pub fn add_constant(&mut self, value: impl Into<Value>) -> ConstIndex {
+    self.constants.push(value.into());
+    (self.constants.len() - 1) as ConstIndex
+  }
+  // Adds a constant along with an instruction loading it into `register`
+  pub fn push_const(
+    &mut self,
+    register: Register,
+    value: impl Into<Value>,
+  ) -> &mut Self {
+    let index = self.add_constant(value);
+    self.push_instruction(GenericInstruction::Const(register, index))
+  }
+  pub fn new_label(&mut self) -> Label {
+    self.label_positions.push(None);
+    Label(self.label_positions.len() - 1)
+  }
+  // Attaches the label to the next instruction to be pushed
+  pub fn place_label(&mut self, label: Label) -> &mut Self {
+    let position = &mut self.label_positions[label.0];
+    assert!(position.is_none(), "label placed more than once");
+    *position = Some(self.instructions.len());
+    self
+  }
+  // Pushes a jump instruction whose target will be filled in with the label's
+  // position by `finish`
+  fn push_jump_to(&mut self, label: Label, jump: Instruction) -> &mut Self {
+    self.jumps.push((self.instructions.len(), label));
+    self.push_instruction(jump)
+  }
+  pub fn push_jump(&mut self, label: Label) -> &mut Self {
+    self.push_jump_to(label, GenericInstruction::Jump(0))
+  }
+  pub fn push_jump_if(
+    &mut self,
+    condition: Register,
+    label: Label,
+  ) -> &mut Self {
+    self.push_jump_to(label, GenericInstruction::JumpIf(condition, 0))
+  }
+  pub fn push_jump_if_not(
+    &mut self,
+    condition: Register,
+    label: Label,
+  ) -> &mut Self {
+    self.push_jump_to(label, GenericInstruction::JumpIfNot(condition, 0))
+  }
+  pub fn finish(mut self) -> Block {
+    for (instruction_index, label) in self.jumps {
+      let target = self.label_positions[label.0]
+        .expect("jumped to a label that was never placed");
+      let target = u16::try_from(target)
+        .expect("jump target is too far into the block to be encoded");
+      let jump = &mut self.instructions[instruction_index];
+      *jump = match *jump {
+        GenericInstruction::JumpIf(condition, _) => {
+          GenericInstruction::JumpIf(condition, target)
+        }
+        GenericInstruction::JumpIfNot(condition, _) => {
+          GenericInstruction::JumpIfNot(condition, target)
+        }
+        _ => GenericInstruction::Jump(target),
+      };
+    }
+    Block::new(self.instructions, self.constants)
+  }
+}
@@ -8,6 +8,29 @@ pub enum ASTError {
   InvalidFunctionDefintionArgumentNameList(Option<LiteralTree>),
   InvalidFunctionDefintionArgumentName(Expression),
   InvalidDefLength(usize),
+  InvalidDeclaration(String),
+  UnknownDefModifier(String),
+  InvalidNamespaceForm(String),
+  UnknownNamespace(String),
+  InvalidLoadFile(String),
+  InvalidMacroexpansion(String),
+  InvalidBindingForm,
+  InvalidLetrec(String),
+  InvalidTimeForm(usize),
+  InvalidTry(String),
+  InvalidWithOpen(String),
+  InvalidWithSeedForm(usize),
+  InvalidMethodCall(String),
+  InvalidTestDefinition(String),
+  InvalidIsForm(usize),
+  InvalidTestRun(String),
+  InvalidComptime(String),
+  NonDynamicBinding(String),
+  InvalidRecordDefinition(String),
+  InvalidProtocolDefinition(String),
+  InvalidTypeExtension(String),
+  UnknownType(String),
+  NotAProtocolMethod(String),
   FunctionDefinitionMissingBody,
   UnboundSymbol(String),
   MultipleExpressionsInQuote,
@@ -15,6 +38,24 @@ pub enum ASTError {
   MultipleExpressionsInUnquote,
   ShadowedBinding(String),
 }
+impl ASTError {
+  // The source text that caused the error, when it's a single token that can
+  // be pointed out in the input
+  pub(crate) fn offending_token(&self) -> Option<&str> {
+    use ASTError::*;
+    match self {
+      CantParseToken(token)
+      | UnknownDefModifier(token)
+      | UnknownNamespace(token)
+      | NonDynamicBinding(token)
+      | UnknownType(token)
+      | NotAProtocolMethod(token)
+      | UnboundSymbol(token)
+      | ShadowedBinding(token) => Some(token),
+      _ => None,
+    }
+  }
+}
 impl Display for ASTError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     use ASTError::*;
@@ -39,6 +80,109 @@ impl Display for ASTError {
       InvalidDefLength(length) => {
         write!(f, "def needs 2 arguments, got {length}")
       }
+      InvalidDeclaration(expression) => {
+        write!(
+          f,
+          "invalid declaration {expression}, expected (declare name ...)"
+        )
+      }
+      InvalidRecordDefinition(expression) => {
+        write!(
+          f,
+          "invalid record definition {expression}, expected \
+          (defrecord Name (field ...))"
+        )
+      }
+      InvalidProtocolDefinition(expression) => {
+        write!(
+          f,
+          "invalid protocol definition {expression}, expected \
+          (defprotocol Name method ...)"
+        )
+      }
+      InvalidTypeExtension(expression) => {
+        write!(
+          f,
+          "invalid type extension {expression}, expected \
+          (extend-type Type (method implementation) ...)"
+        )
+      }
+      UnknownType(type_name) => write!(f, "unknown type {type_name}"),
+      NotAProtocolMethod(name) => {
+        write!(f, "{name} is not a protocol method")
+      }
+      UnknownDefModifier(modifier) => {
+        write!(f, "unknown def modifier {modifier}")
+      }
+      InvalidNamespaceForm(expression) => {
+        write!(f, "invalid namespace form {expression}")
+      }
+      UnknownNamespace(name) => write!(f, "unknown namespace {name}"),
+      InvalidLoadFile(expression) => {
+        write!(
+          f,
+          "invalid load-file form {expression}, expected (load-file path)"
+        )
+      }
+      InvalidMacroexpansion(expression) => write!(
+        f,
+        "invalid macroexpand form {expression}, expected \
+        (macroexpand (quote form)) or (macroexpand-1 (quote form))"
+      ),
+      InvalidBindingForm => write!(
+        f,
+        "invalid binding form, expected (binding (var value ...) body ...)"
+      ),
+      InvalidLetrec(expression) => write!(
+        f,
+        "invalid letrec form {expression}, expected \
+        (letrec (name value ...) body)"
+      ),
+      InvalidComptime(expression) => {
+        write!(
+          f,
+          "invalid comptime form {expression}, expected (comptime expr)"
+        )
+      }
+      InvalidTimeForm(length) => {
+        write!(f, "time needs 1 argument, got {}", length - 1)
+      }
+      InvalidTry(expression) => write!(
+        f,
+        "invalid try form {expression}, expected \
+        (try body (catch name handler) (finally cleanup)), where the catch \
+        and finally clauses are both optional"
+      ),
+      InvalidWithOpen(expression) => write!(
+        f,
+        "invalid with-open form {expression}, expected \
+        (with-open (name resource) body)"
+      ),
+      InvalidWithSeedForm(length) => write!(
+        f,
+        "with-seed needs 2 arguments, got {}, expected (with-seed seed body)",
+        length - 1
+      ),
+      InvalidMethodCall(expression) => write!(
+        f,
+        "invalid method call {expression}, expected \
+        (. object method args...)"
+      ),
+      InvalidTestDefinition(expression) => write!(
+        f,
+        "invalid test definition {expression}, expected (deftest name body)"
+      ),
+      InvalidIsForm(length) => {
+        write!(f, "is needs 1 argument, got {}", length - 1)
+      }
+      InvalidTestRun(expression) => {
+        write!(f, "invalid form {expression}, run-tests takes no arguments")
+      }
+      NonDynamicBinding(name) => write!(
+        f,
+        "can't rebind {name} with binding, as it wasn't defined with \
+        (def ^:dynamic ...)"
+      ),
       FunctionDefinitionMissingBody => {
         write!(f, "no body for function definition")
       }
@@ -1,8 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
   compiler::SSAValue,
-  runtime::{core_functions::CoreFnId, evaluation::SymbolIndex},
+  runtime::{
+    core_functions::CoreFnId,
+    data::{ListStorage, Value},
+    error::{RuntimeError, RuntimeResult},
+    evaluation::SymbolIndex,
+  },
 };
 
 use super::{
@@ -17,6 +22,7 @@ impl LiteralValue {
     match token {
       Token::Nil => SSAValue::Nil,
       Token::IntLiteral(i) => i.into(),
+      Token::BigIntLiteral(i) => i.into(),
       Token::FloatLiteral(f) => f.into(),
       Token::StringLiteral(s) => s.into(),
       Token::Symbol(s) => SSAValue::Symbol(symbol_ledger.symbol_index(s)),
@@ -32,7 +38,7 @@ impl LiteralTree {
     match token_tree {
       Tree::Inner(subtrees) => {
         if subtrees.len() == 0 {
-          Ok(Self::Leaf(SSAValue::List(vec![].into())))
+          Ok(Self::Leaf(vec![].into()))
         } else {
           Ok(Self::Inner(
             subtrees
@@ -63,18 +69,43 @@ impl LiteralTree {
   pub(crate) fn as_literal(self) -> LiteralValue {
     match self {
       Tree::Leaf(value) => value,
-      Tree::Inner(values) => LiteralValue::List(
-        values
-          .into_iter()
-          .map(|value| value.as_literal())
-          .collect::<Vec<LiteralValue>>()
-          .into(),
-      ),
+      Tree::Inner(values) => values
+        .into_iter()
+        .map(|value| value.as_literal())
+        .collect::<ListStorage<LiteralValue>>()
+        .into(),
     }
   }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+// Forms handled by the compiler or evaluator rather than by any function
+pub(crate) const SPECIAL_FORMS: [&str; 23] = [
+  "def",
+  "declare",
+  "fn",
+  "quote",
+  "binding",
+  "letrec",
+  "time",
+  "try",
+  "with-open",
+  "with-seed",
+  ".",
+  "comptime",
+  "ns",
+  "require",
+  "load-file",
+  "macroexpand",
+  "macroexpand-1",
+  "defrecord",
+  "defprotocol",
+  "extend-type",
+  "deftest",
+  "is",
+  "run-tests",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub(crate) enum Expression {
   Literal(SSAValue<()>),
   Quoted(LiteralTree),
@@ -87,11 +118,298 @@ pub(crate) enum Expression {
 use itertools::Itertools;
 use Expression::*;
 
+// How far the built-in expansions are applied when a literal tree becomes an
+// expression. `Once` only expands the outermost form, leaving the forms inside
+// it as they were written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expansion {
+  Full,
+  Once,
+  Never,
+}
+
+impl Expansion {
+  fn inner(self) -> Self {
+    match self {
+      Expansion::Full => Expansion::Full,
+      Expansion::Once | Expansion::Never => Expansion::Never,
+    }
+  }
+}
+
+// `(letrec (name value ...) body)` binds each name to a cell that's filled in
+// with its value, so the values can refer to any of the names, including their
+// own, as long as they only do so from inside functions. The form becomes
+//
+//   ((fn (cell ...)
+//      ((fn (ignored ...) body) (reset! cell value) ...))
+//    (atom nil) ...)
+//
+// with each reference to a name in the values and body replaced by
+// `(deref cell)`
+fn expand_letrec(
+  subtrees: Vec<LiteralTree>,
+  symbol_ledger: &mut SymbolLedger,
+  inner: Expansion,
+) -> ASTResult<Expression> {
+  let invalid = |subtrees: &[LiteralTree], symbol_ledger: &SymbolLedger| {
+    ASTError::InvalidLetrec(
+      LiteralTree::Inner(subtrees.to_vec()).to_string(symbol_ledger),
+    )
+  };
+  let (pairs, body) = match &subtrees[..] {
+    [_, Tree::Inner(pairs), body] if pairs.len() % 2 == 0 => (pairs, body),
+    [_, Tree::Leaf(SSAValue::List(empty)), body] if empty.is_empty() => {
+      return Expression::expand_literal_tree(
+        body.clone(),
+        symbol_ledger,
+        inner,
+      )
+    }
+    _ => return Err(invalid(&subtrees, symbol_ledger)),
+  };
+  let mut cells = HashMap::new();
+  let mut names = vec![];
+  for name in pairs.iter().step_by(2) {
+    let Tree::Leaf(SSAValue::Symbol(name)) = name else {
+      return Err(invalid(&subtrees, symbol_ledger));
+    };
+    let cell = symbol_ledger.generate_unique_symbol();
+    cells.insert(
+      *name,
+      Application(vec![
+        Literal(SSAValue::CoreFn(CoreFnId::GetCellValue)),
+        Literal(SSAValue::Symbol(cell)),
+      ]),
+    );
+    names.push((*name, cell));
+  }
+  let mut assignments = vec![];
+  for ((_, cell), value) in names.iter().zip(pairs.iter().skip(1).step_by(2)) {
+    assignments.push(Application(vec![
+      Literal(SSAValue::CoreFn(CoreFnId::SetCellValue)),
+      Literal(SSAValue::Symbol(*cell)),
+      Expression::expand_literal_tree(value.clone(), symbol_ledger, inner)?
+        .substitute(&cells, symbol_ledger)?,
+    ]));
+  }
+  let body =
+    Expression::expand_literal_tree(body.clone(), symbol_ledger, inner)?
+      .substitute(&cells, symbol_ledger)?;
+  let ignored_names = assignments
+    .iter()
+    .map(|_| symbol_ledger.generate_unique_symbol())
+    .collect();
+  Ok(Application(
+    std::iter::once(Function {
+      arg_names: names.iter().map(|(_, cell)| *cell).collect(),
+      body: vec![Application(
+        std::iter::once(Function {
+          arg_names: ignored_names,
+          body: vec![body],
+        })
+        .chain(assignments)
+        .collect(),
+      )],
+    })
+    .chain(names.iter().map(|_| {
+      Application(vec![
+        Literal(SSAValue::CoreFn(CoreFnId::CreateCell)),
+        Literal(SSAValue::Nil),
+      ])
+    }))
+    .collect(),
+  ))
+}
+
+// `(try body (catch name handler) (finally cleanup))` becomes
+//
+//   (try-call (fn (ignored) body) (fn (name) handler) (fn (ignored) cleanup))
+//
+// with `nil` in place of a missing clause. `try-call` runs the body, and if it
+// fails, passes the error to the handler. The cleanup runs afterwards either
+// way, and whatever the body or handler did is returned or rethrown
+fn expand_try(
+  subtrees: Vec<LiteralTree>,
+  symbol_ledger: &mut SymbolLedger,
+  inner: Expansion,
+) -> ASTResult<Expression> {
+  let invalid = |subtrees: &[LiteralTree], symbol_ledger: &SymbolLedger| {
+    ASTError::InvalidTry(
+      LiteralTree::Inner(subtrees.to_vec()).to_string(symbol_ledger),
+    )
+  };
+  let [_, body, clauses @ ..] = &subtrees[..] else {
+    return Err(invalid(&subtrees, symbol_ledger));
+  };
+  let (mut catch, mut finally) = (None, None);
+  for clause in clauses {
+    let Tree::Inner(clause) = clause else {
+      return Err(invalid(&subtrees, symbol_ledger));
+    };
+    let clause_name = match clause.first() {
+      Some(Tree::Leaf(SSAValue::Symbol(name))) => {
+        symbol_ledger.symbol_name(name).map(String::as_str)
+      }
+      _ => None,
+    };
+    match (clause_name, &clause[1..]) {
+      (Some("catch"), [Tree::Leaf(SSAValue::Symbol(name)), handler])
+        if catch.is_none() && finally.is_none() =>
+      {
+        catch = Some((*name, handler.clone()))
+      }
+      (Some("finally"), [cleanup]) if finally.is_none() => {
+        finally = Some(cleanup.clone())
+      }
+      _ => return Err(invalid(&subtrees, symbol_ledger)),
+    }
+  }
+  let mut thunk = |tree: LiteralTree| {
+    ASTResult::Ok(Function {
+      arg_names: vec![symbol_ledger.generate_unique_symbol()],
+      body: vec![Expression::expand_literal_tree(tree, symbol_ledger, inner)?],
+    })
+  };
+  let body = thunk(body.clone())?;
+  let cleanup = match finally {
+    Some(cleanup) => thunk(cleanup)?,
+    None => Literal(SSAValue::Nil),
+  };
+  let handler = match catch {
+    Some((name, handler)) => Function {
+      arg_names: vec![name],
+      body: vec![Expression::expand_literal_tree(
+        handler,
+        symbol_ledger,
+        inner,
+      )?],
+    },
+    None => Literal(SSAValue::Nil),
+  };
+  Ok(Application(vec![
+    Literal(SSAValue::CoreFn(CoreFnId::TryCall)),
+    body,
+    handler,
+    cleanup,
+  ]))
+}
+
+// `(with-open (name resource) body)` becomes
+//
+//   ((fn (name) (try body (finally (close name)))) resource)
+//
+// where `close` is whatever it refers to where the form appears, usually a
+// protocol method
+fn invalid_with_open(
+  subtrees: &[LiteralTree],
+  symbol_ledger: &SymbolLedger,
+) -> ASTError {
+  ASTError::InvalidWithOpen(
+    LiteralTree::Inner(subtrees.to_vec()).to_string(symbol_ledger),
+  )
+}
+fn expand_with_open(
+  subtrees: Vec<LiteralTree>,
+  symbol_ledger: &mut SymbolLedger,
+  inner: Expansion,
+) -> ASTResult<Expression> {
+  let (name, resource, body) = match &subtrees[..] {
+    [_, Tree::Inner(binding), body] => match &binding[..] {
+      [name @ Tree::Leaf(SSAValue::Symbol(_)), resource] => {
+        (name, resource, body)
+      }
+      _ => return Err(invalid_with_open(&subtrees, symbol_ledger)),
+    },
+    _ => return Err(invalid_with_open(&subtrees, symbol_ledger)),
+  };
+  let symbol = |name: &str, symbol_ledger: &mut SymbolLedger| {
+    Tree::Leaf(SSAValue::Symbol(
+      symbol_ledger.symbol_index(name.to_string()),
+    ))
+  };
+  let try_form = Tree::Inner(vec![
+    symbol("try", symbol_ledger),
+    body.clone(),
+    Tree::Inner(vec![
+      symbol("finally", symbol_ledger),
+      Tree::Inner(vec![symbol("close", symbol_ledger), name.clone()]),
+    ]),
+  ]);
+  Expression::expand_literal_tree(
+    Tree::Inner(vec![
+      Tree::Inner(vec![
+        symbol("fn", symbol_ledger),
+        Tree::Inner(vec![name.clone()]),
+        try_form,
+      ]),
+      resource.clone(),
+    ]),
+    symbol_ledger,
+    inner,
+  )
+}
+
 impl Expression {
+  // Replaces each occurrence of the given symbols, outside of quotes. A nested
+  // function that rebinds one of them is treated like any other shadowed local
+  fn substitute(
+    self,
+    replacements: &HashMap<SymbolIndex, Expression>,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Self> {
+    Ok(match self {
+      Literal(SSAValue::Symbol(symbol))
+        if replacements.contains_key(&symbol) =>
+      {
+        replacements[&symbol].clone()
+      }
+      Literal(value) => Literal(value),
+      Quoted(tree) => Quoted(tree),
+      Application(subexpressions) => Application(
+        subexpressions
+          .into_iter()
+          .map(|subexpression| {
+            subexpression.substitute(replacements, symbol_ledger)
+          })
+          .collect::<Result<_, _>>()?,
+      ),
+      Function { arg_names, body } => {
+        if let Some(arg_name) = arg_names
+          .iter()
+          .find(|arg_name| replacements.contains_key(arg_name))
+        {
+          return Err(ASTError::ShadowedBinding(
+            symbol_ledger
+              .symbol_name(arg_name)
+              .cloned()
+              .unwrap_or("<unknown symbol>".to_string()),
+          ));
+        }
+        Function {
+          arg_names,
+          body: body
+            .into_iter()
+            .map(|expression| {
+              expression.substitute(replacements, symbol_ledger)
+            })
+            .collect::<Result<_, _>>()?,
+        }
+      }
+    })
+  }
   fn from_literal_tree(
     literal_tree: LiteralTree,
     symbol_ledger: &mut SymbolLedger,
   ) -> ASTResult<Self> {
+    Self::expand_literal_tree(literal_tree, symbol_ledger, Expansion::Full)
+  }
+  fn expand_literal_tree(
+    literal_tree: LiteralTree,
+    symbol_ledger: &mut SymbolLedger,
+    expansion: Expansion,
+  ) -> ASTResult<Self> {
+    let inner = expansion.inner();
     match literal_tree {
       Tree::Leaf(literal) => Ok(Literal(literal)),
       Tree::Inner(subtrees) => {
@@ -112,10 +430,12 @@ impl Expression {
                   arg_names: arg_names
                     .into_iter()
                     .map(|arg_name_subtree| {
-                      let arg_name_expression = Expression::from_literal_tree(
-                        arg_name_subtree,
-                        symbol_ledger,
-                      )?;
+                      let arg_name_expression =
+                        Expression::expand_literal_tree(
+                          arg_name_subtree,
+                          symbol_ledger,
+                          inner,
+                        )?;
                       if let Literal(SSAValue::Symbol(arg_name_symbol_index)) =
                         arg_name_expression
                       {
@@ -129,7 +449,11 @@ impl Expression {
                     .collect::<Result<_, _>>()?,
                   body: subtrees_iter
                     .map(|body_subtree| {
-                      Expression::from_literal_tree(body_subtree, symbol_ledger)
+                      Expression::expand_literal_tree(
+                        body_subtree,
+                        symbol_ledger,
+                        inner,
+                      )
                     })
                     .collect::<Result<_, _>>()?,
                 })
@@ -153,6 +477,112 @@ impl Expression {
                 Err(ASTError::MultipleExpressionsInHardQuote)
               }
             }
+            // forms that aren't being expanded are left as applications
+            "time" | "is" | "with-seed" | "." | "letrec" | "try"
+            | "with-open"
+              if expansion == Expansion::Never => {}
+            // `(time expr)` becomes `(report-elapsed (nano-time) expr)`.
+            // Arguments are evaluated in order, so the clock is read before
+            // the expression is evaluated and again after it
+            "time" => {
+              return if subtrees.len() == 2 {
+                Ok(Application(vec![
+                  Literal(SSAValue::CoreFn(CoreFnId::ReportElapsed)),
+                  Application(vec![Literal(SSAValue::CoreFn(
+                    CoreFnId::NanoTime,
+                  ))]),
+                  Expression::expand_literal_tree(
+                    subtrees.into_iter().nth(1).unwrap(),
+                    symbol_ledger,
+                    inner,
+                  )?,
+                ]))
+              } else {
+                Err(ASTError::InvalidTimeForm(subtrees.len()))
+              };
+            }
+            // `(is expr)` becomes `(assert expr (quote expr))`, so that a
+            // failed assertion can say which expression was false
+            "is" => {
+              return if subtrees.len() == 2 {
+                let form = subtrees.into_iter().nth(1).unwrap();
+                Ok(Application(vec![
+                  Literal(SSAValue::CoreFn(CoreFnId::Assert)),
+                  Expression::expand_literal_tree(
+                    form.clone(),
+                    symbol_ledger,
+                    inner,
+                  )?,
+                  Quoted(form),
+                ]))
+              } else {
+                Err(ASTError::InvalidIsForm(subtrees.len()))
+              };
+            }
+            // `(with-seed seed body)` becomes
+            // `(with-seed-call seed (fn (ignored) body))`, which runs the body
+            // with a random number generator seeded with `seed`
+            "with-seed" => {
+              let [_, seed, body] = <[LiteralTree; 3]>::try_from(subtrees)
+                .map_err(|subtrees| {
+                  ASTError::InvalidWithSeedForm(subtrees.len())
+                })?;
+              return Ok(Application(vec![
+                Literal(SSAValue::CoreFn(CoreFnId::WithSeed)),
+                Expression::expand_literal_tree(seed, symbol_ledger, inner)?,
+                Function {
+                  arg_names: vec![symbol_ledger.generate_unique_symbol()],
+                  body: vec![Expression::expand_literal_tree(
+                    body,
+                    symbol_ledger,
+                    inner,
+                  )?],
+                },
+              ]));
+            }
+            // `(. object method args...)` becomes
+            // `(call-method object "method" args...)`, which calls the method
+            // registered for the object's type
+            "." => {
+              let [_, object, Tree::Leaf(SSAValue::Symbol(method)), args @ ..] =
+                &subtrees[..]
+              else {
+                return Err(ASTError::InvalidMethodCall(
+                  LiteralTree::Inner(subtrees).to_string(symbol_ledger),
+                ));
+              };
+              let method = symbol_ledger.symbol_name(method).unwrap().clone();
+              return Ok(Application(
+                [
+                  Literal(SSAValue::CoreFn(CoreFnId::CallMethod)),
+                  Expression::expand_literal_tree(
+                    object.clone(),
+                    symbol_ledger,
+                    inner,
+                  )?,
+                  Literal(method.into()),
+                ]
+                .into_iter()
+                .chain(
+                  args
+                    .iter()
+                    .map(|arg| {
+                      Expression::expand_literal_tree(
+                        arg.clone(),
+                        symbol_ledger,
+                        inner,
+                      )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                )
+                .collect(),
+              ));
+            }
+            "letrec" => return expand_letrec(subtrees, symbol_ledger, inner),
+            "try" => return expand_try(subtrees, symbol_ledger, inner),
+            "with-open" => {
+              return expand_with_open(subtrees, symbol_ledger, inner)
+            }
             "unquote" => {
               return if subtrees.len() == 2 {
                 todo!("unquoting isn't implemented yet!")
@@ -167,7 +597,7 @@ impl Expression {
           subtrees
             .into_iter()
             .map(|subtree| {
-              Expression::from_literal_tree(subtree, symbol_ledger)
+              Expression::expand_literal_tree(subtree, symbol_ledger, inner)
             })
             .collect::<Result<_, _>>()?,
         ))
@@ -183,6 +613,17 @@ impl Expression {
       symbol_ledger,
     )
   }
+  // Like `from_token_tree`, but only the outermost form is expanded
+  pub(crate) fn from_token_tree_once(
+    token_tree: TokenTree,
+    symbol_ledger: &mut SymbolLedger,
+  ) -> ASTResult<Self> {
+    Self::expand_literal_tree(
+      LiteralTree::from_token_tree(token_tree, symbol_ledger)?,
+      symbol_ledger,
+      Expansion::Once,
+    )
+  }
   fn unbound_internal_symbols(
     &self,
     bindings: &Vec<SymbolIndex>,
@@ -296,11 +737,7 @@ impl Expression {
       Function { arg_names, body } => {
         for arg_name in arg_names.iter() {
           if parent_bindings.contains(arg_name)
-            || symbol_ledger
-              .symbol_name(arg_name)
-              .map(|name| CoreFnId::from_name(name))
-              .flatten()
-              .is_some()
+            || symbol_ledger.is_built_in(arg_name)
           {
             return Err(ASTError::ShadowedBinding(
               symbol_ledger
@@ -310,24 +747,21 @@ impl Expression {
             ));
           }
         }
+        // Only symbols bound by enclosing functions need to be captured. Any
+        // other free symbol is left for the compiler to resolve as a global,
+        // which is looked up each time the function runs, so redefining the
+        // global changes what the function sees
         let unbound_body_symbols: Vec<SymbolIndex> = body
           .iter()
           .flat_map(|body_expression| {
             body_expression.unbound_internal_symbols(&arg_names)
           })
           .unique()
-          .filter(|body_symbol| !symbol_ledger.is_built_in(body_symbol))
+          .filter(|body_symbol| {
+            !symbol_ledger.is_built_in(body_symbol)
+              && parent_bindings.contains(body_symbol)
+          })
           .collect();
-        for unbound_body_symbol in unbound_body_symbols.iter() {
-          if !parent_bindings.contains(unbound_body_symbol) {
-            return Err(ASTError::UnboundSymbol(
-              symbol_ledger
-                .symbol_name(unbound_body_symbol)
-                .unwrap()
-                .clone(),
-            ));
-          }
-        }
         if unbound_body_symbols.is_empty() {
           Function {
             body: body
@@ -398,6 +832,9 @@ impl Expression {
 
   pub(crate) fn to_string(&self, symbol_ledger: &SymbolLedger) -> String {
     match self {
+      // Core functions inserted by expansions are written as the names that
+      // refer to them, so the result can be read back as source
+      Literal(SSAValue::CoreFn(core_fn_id)) => core_fn_id.name().to_string(),
       Literal(value) => value.description(Some(symbol_ledger)),
       Quoted(subexpression) => {
         format!("(quote {})", subexpression.to_string(symbol_ledger))
@@ -429,26 +866,82 @@ impl Expression {
     }
   }
 
+  // The expression as the data it could have been read from. Like with
+  // `to_string`, core functions inserted by expansions become the symbols
+  // that refer to them
+  pub(crate) fn to_value(&self, symbol_ledger: &mut SymbolLedger) -> Value {
+    let literal = |value: LiteralValue| {
+      value
+        .translate(&|_, _, _, _| Err(()))
+        .expect("expressions only hold functions whose compilation is deferred")
+    };
+    match self {
+      Literal(SSAValue::CoreFn(core_fn_id)) => {
+        Value::Symbol(symbol_ledger.symbol_index(core_fn_id.name().to_string()))
+      }
+      Literal(value) => literal(value.clone()),
+      Quoted(tree) => Value::from(vec![
+        Value::Symbol(symbol_ledger.symbol_index("quote".to_string())),
+        literal(tree.clone().as_literal()),
+      ]),
+      Application(subexpressions) => subexpressions
+        .iter()
+        .map(|subexpression| subexpression.to_value(symbol_ledger))
+        .collect(),
+      Function { arg_names, body } => {
+        let mut form = vec![
+          Value::Symbol(symbol_ledger.symbol_index("fn".to_string())),
+          arg_names.iter().map(|name| Value::Symbol(*name)).collect(),
+        ];
+        form.extend(
+          body
+            .iter()
+            .map(|body_expression| body_expression.to_value(symbol_ledger)),
+        );
+        form.into()
+      }
+    }
+  }
+
+  // Recognizes `(def name value)`, where the name may be preceded by
+  // `^:dynamic` and/or `^:private`
   pub(crate) fn as_definition(
     &self,
     symbol_ledger: &SymbolLedger,
-  ) -> ASTResult<Option<(SymbolIndex, Expression)>> {
+  ) -> ASTResult<Option<Definition>> {
     if let Expression::Application(subexpressions) = self {
       if let Literal(SSAValue::Symbol(symbol_index)) = subexpressions[0] {
         if symbol_ledger.symbol_name(&symbol_index).expect(
           "unregistered symbol encountered in Expression::as_definition",
         ) == "def"
         {
-          if subexpressions.len() == 3 {
-            Ok(Some((
-              if let Literal(SSAValue::Symbol(name_index)) = &subexpressions[1]
+          let (mut dynamic, mut private) = (false, false);
+          let mut name_index = 1;
+          while let Some(modifier) = subexpressions
+            .get(name_index)
+            .and_then(|subexpression| subexpression.symbol_name(symbol_ledger))
+            .filter(|name| name.starts_with("^:"))
+          {
+            match modifier.as_str() {
+              "^:dynamic" => dynamic = true,
+              "^:private" => private = true,
+              _ => return Err(ASTError::UnknownDefModifier(modifier)),
+            }
+            name_index += 1;
+          }
+          if subexpressions.len() == name_index + 2 {
+            Ok(Some(Definition {
+              name: if let Literal(SSAValue::Symbol(name_index)) =
+                &subexpressions[name_index]
               {
                 *name_index
               } else {
                 todo!("destructing of `def`s doesn't work yet!")
               },
-              subexpressions[2].clone(),
-            )))
+              value: subexpressions[name_index + 1].clone(),
+              dynamic,
+              private,
+            }))
           } else {
             Err(ASTError::InvalidDefLength(subexpressions.len()))
           }
@@ -462,8 +955,317 @@ impl Expression {
       Ok(None)
     }
   }
+
+  // Recognizes `(ns name)`, returning the namespace name
+  pub(crate) fn as_namespace_declaration(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<String>> {
+    if let Some(subexpressions) = self.as_special_form("ns", symbol_ledger) {
+      match subexpressions {
+        [_, name] => {
+          name.symbol_name(symbol_ledger).map(Some).ok_or_else(|| {
+            ASTError::InvalidNamespaceForm(self.to_string(symbol_ledger))
+          })
+        }
+        _ => Err(ASTError::InvalidNamespaceForm(
+          self.to_string(symbol_ledger),
+        )),
+      }
+    } else {
+      Ok(None)
+    }
+  }
+
+  // Recognizes `(load-file path)`, returning the path expression
+  pub(crate) fn as_load_file(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<Expression>> {
+    match self.as_special_form("load-file", symbol_ledger) {
+      Some([_, path]) => Ok(Some(path.clone())),
+      Some(_) => Err(ASTError::InvalidLoadFile(self.to_string(symbol_ledger))),
+      None => Ok(None),
+    }
+  }
+
+  // Recognizes `(require 'name ...)`, returning the required namespace names.
+  // The names may be given either quoted or as bare symbols
+  pub(crate) fn as_require(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<Vec<String>>> {
+    if let Some(subexpressions) = self.as_special_form("require", symbol_ledger)
+    {
+      subexpressions[1..]
+        .iter()
+        .map(|subexpression| match subexpression {
+          Quoted(Tree::Leaf(SSAValue::Symbol(index))) => {
+            symbol_ledger.symbol_name(index).cloned()
+          }
+          other => other.symbol_name(symbol_ledger),
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(Some)
+        .ok_or_else(|| {
+          ASTError::InvalidNamespaceForm(self.to_string(symbol_ledger))
+        })
+    } else {
+      Ok(None)
+    }
+  }
+
+  // Recognizes `(deftest name body)`, returning the name of the test and its
+  // body
+  pub(crate) fn as_test_definition(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<(SymbolIndex, Expression)>> {
+    match self.as_special_form("deftest", symbol_ledger) {
+      Some([_, Literal(SSAValue::Symbol(name)), body]) => {
+        Ok(Some((*name, body.clone())))
+      }
+      Some(_) => Err(ASTError::InvalidTestDefinition(
+        self.to_string(symbol_ledger),
+      )),
+      None => Ok(None),
+    }
+  }
+
+  // Recognizes `(run-tests)`
+  pub(crate) fn is_test_run(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<bool> {
+    match self.as_special_form("run-tests", symbol_ledger) {
+      Some([_]) => Ok(true),
+      Some(_) => Err(ASTError::InvalidTestRun(self.to_string(symbol_ledger))),
+      None => Ok(false),
+    }
+  }
+
+  // Recognizes `(macroexpand (quote form))` and `(macroexpand-1 (quote form))`,
+  // returning the form as data after the built-in expansions have been applied
+  // to it. Those are the ones the parser does itself: `time`, `is`,
+  // `with-seed`, `letrec`, `try`, `with-open`, and `.`. Every other form,
+  // including the special forms that the compiler or evaluator handles, like
+  // `def` and `fn`, comes back unchanged apart from the expansion of the forms
+  // inside it. `macroexpand-1` only expands the outermost form, so the forms
+  // inside it, and the forms its expansion introduces, are left as written
+  pub(crate) fn as_macroexpansion(
+    &self,
+    symbol_ledger: &mut SymbolLedger,
+  ) -> ASTResult<Option<Value>> {
+    let (subexpressions, expansion) =
+      match self.as_special_form("macroexpand", symbol_ledger) {
+        Some(subexpressions) => (subexpressions, Expansion::Full),
+        None => match self.as_special_form("macroexpand-1", symbol_ledger) {
+          Some(subexpressions) => (subexpressions, Expansion::Once),
+          None => return Ok(None),
+        },
+      };
+    match subexpressions {
+      [_, Quoted(form)] => {
+        let expansion = Expression::expand_literal_tree(
+          form.clone(),
+          symbol_ledger,
+          expansion,
+        )?;
+        Ok(Some(expansion.to_value(symbol_ledger)))
+      }
+      _ => Err(ASTError::InvalidMacroexpansion(
+        self.to_string(symbol_ledger),
+      )),
+    }
+  }
+
+  // Recognizes `(comptime expr)`, returning the expression to be evaluated
+  // during compilation
+  pub(crate) fn as_comptime(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<&Expression>> {
+    match self.as_special_form("comptime", symbol_ledger) {
+      Some([_, form]) => Ok(Some(form)),
+      Some(_) => Err(ASTError::InvalidComptime(self.to_string(symbol_ledger))),
+      None => Ok(None),
+    }
+  }
+
+  // An expression that evaluates to the given value, which is embedded as a
+  // constant. Functions can only be embedded while their compilation is still
+  // deferred, since compiled functions can't be turned back into IR
+  pub(crate) fn constant(value: Value) -> RuntimeResult<Self> {
+    let constant =
+      value
+        .clone()
+        .translate(&|_, _, _, _| Err(()))
+        .map_err(|()| {
+          RuntimeError::CantConvert(value, "compile-time constant")
+        })?;
+    Ok(match constant {
+      SSAValue::Symbol(symbol) => Quoted(Tree::Leaf(SSAValue::Symbol(symbol))),
+      other => Literal(other),
+    })
+  }
+
+  // Recognizes `(declare name ...)`, returning the symbols being declared
+  pub(crate) fn as_declaration(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<Vec<SymbolIndex>>> {
+    if let Some(subexpressions) = self.as_special_form("declare", symbol_ledger)
+    {
+      subexpressions[1..]
+        .iter()
+        .map(|subexpression| match subexpression {
+          Literal(SSAValue::Symbol(index)) => Some(*index),
+          _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(Some)
+        .ok_or_else(|| {
+          ASTError::InvalidDeclaration(self.to_string(symbol_ledger))
+        })
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn symbol_name(&self, symbol_ledger: &SymbolLedger) -> Option<String> {
+    if let Literal(SSAValue::Symbol(index)) = self {
+      symbol_ledger.symbol_name(index).cloned()
+    } else {
+      None
+    }
+  }
+
+  // Returns the subexpressions of an application whose first element is the
+  // given symbol, such as `(defrecord ...)`
+  fn as_special_form(
+    &self,
+    name: &str,
+    symbol_ledger: &SymbolLedger,
+  ) -> Option<&[Expression]> {
+    if let Expression::Application(subexpressions) = self {
+      if subexpressions
+        .first()
+        .and_then(|first| first.symbol_name(symbol_ledger))
+        .as_deref()
+        == Some(name)
+      {
+        return Some(subexpressions);
+      }
+    }
+    None
+  }
+
+  // Recognizes `(defrecord Name (field ...))`, returning the record name and
+  // the names of its fields
+  pub(crate) fn as_record_definition(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<(String, Vec<String>)>> {
+    if let Some(subexpressions) =
+      self.as_special_form("defrecord", symbol_ledger)
+    {
+      if let [_, name, Expression::Application(fields)] = subexpressions {
+        if let (Some(name), Some(field_names)) = (
+          name.symbol_name(symbol_ledger),
+          fields
+            .iter()
+            .map(|field| field.symbol_name(symbol_ledger))
+            .collect::<Option<Vec<_>>>(),
+        ) {
+          return Ok(Some((name, field_names)));
+        }
+      }
+      Err(ASTError::InvalidRecordDefinition(
+        self.to_string(symbol_ledger),
+      ))
+    } else {
+      Ok(None)
+    }
+  }
+
+  // Recognizes `(defprotocol Name method ...)`, returning the protocol name
+  // and the names of its methods
+  pub(crate) fn as_protocol_definition(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<(String, Vec<String>)>> {
+    if let Some(subexpressions) =
+      self.as_special_form("defprotocol", symbol_ledger)
+    {
+      if let [_, name, methods @ ..] = subexpressions {
+        if let (Some(name), Some(method_names)) = (
+          name.symbol_name(symbol_ledger),
+          methods
+            .iter()
+            .map(|method| method.symbol_name(symbol_ledger))
+            .collect::<Option<Vec<_>>>(),
+        ) {
+          return Ok(Some((name, method_names)));
+        }
+      }
+      Err(ASTError::InvalidProtocolDefinition(
+        self.to_string(symbol_ledger),
+      ))
+    } else {
+      Ok(None)
+    }
+  }
+
+  // Recognizes `(extend-type Type (method implementation) ...)`, returning the
+  // type name and each method name along with its implementation expression
+  pub(crate) fn as_type_extension(
+    &self,
+    symbol_ledger: &SymbolLedger,
+  ) -> ASTResult<Option<TypeExtension>> {
+    if let Some(subexpressions) =
+      self.as_special_form("extend-type", symbol_ledger)
+    {
+      if let [_, type_name, implementations @ ..] = subexpressions {
+        if let (Some(type_name), Some(implementations)) = (
+          type_name.symbol_name(symbol_ledger),
+          implementations
+            .iter()
+            .map(|implementation| match implementation {
+              Expression::Application(method_and_f) => {
+                match &method_and_f[..] {
+                  [method, f] => method
+                    .symbol_name(symbol_ledger)
+                    .map(|method_name| (method_name, f.clone())),
+                  _ => None,
+                }
+              }
+              _ => None,
+            })
+            .collect::<Option<Vec<_>>>(),
+        ) {
+          return Ok(Some((type_name, implementations)));
+        }
+      }
+      Err(ASTError::InvalidTypeExtension(
+        self.to_string(symbol_ledger),
+      ))
+    } else {
+      Ok(None)
+    }
+  }
 }
 
+pub(crate) struct Definition {
+  pub name: SymbolIndex,
+  pub value: Expression,
+  pub dynamic: bool,
+  pub private: bool,
+}
+
+// The type name of an `extend-type` form, along with each method name and the
+// expression for its implementation
+pub(crate) type TypeExtension = (String, Vec<(String, Expression)>);
+
 #[cfg(test)]
 mod tests {
   use std::collections::HashSet;
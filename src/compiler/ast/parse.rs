@@ -12,7 +12,37 @@ fn tokenize(input: &str) -> Vec<String> {
     .collect()
 }
 
-pub fn parse_sexp(input: &str) -> Tree<String> {
+// Reader shorthands, such as `@x` for `(deref x)` and `'x` for `(quote x)`
+fn prefix_form(prefix: char) -> Option<&'static str> {
+  match prefix {
+    '@' => Some("deref"),
+    '\'' => Some("quote"),
+    _ => None,
+  }
+}
+
+// A pending prefix is kept as a leaf until the following form is finished, at
+// which point the two are combined
+fn push_tree(siblings: &mut Vec<Tree<String>>, tree: Tree<String>) {
+  let pending_form = match siblings.last() {
+    Some(Tree::Leaf(leaf)) if leaf.chars().count() == 1 => {
+      leaf.chars().next().and_then(prefix_form)
+    }
+    _ => None,
+  };
+  if let Some(form) = pending_form {
+    siblings.pop();
+    push_tree(
+      siblings,
+      Tree::Inner(vec![Tree::Leaf(form.to_string()), tree]),
+    );
+  } else {
+    siblings.push(tree);
+  }
+}
+
+// Parses every top-level form in the input
+pub fn parse_sexps(input: &str) -> Vec<Tree<String>> {
   let mut ast_stack: Vec<Vec<Tree<String>>> = vec![vec![]];
   for token in tokenize(input) {
     match token.as_str() {
@@ -21,13 +51,25 @@ pub fn parse_sexp(input: &str) -> Tree<String> {
         let finished_list = ast_stack.pop().unwrap();
         let tree = Tree::Inner(finished_list);
         let l = ast_stack.len();
-        ast_stack[l - 1].push(tree);
+        push_tree(&mut ast_stack[l - 1], tree);
       }
       other => {
         let l = ast_stack.len();
-        ast_stack[l - 1].push(Tree::Leaf(other.to_string()))
+        let name = other.trim_start_matches(|c| prefix_form(c).is_some());
+        if name.is_empty() {
+          ast_stack[l - 1].push(Tree::Leaf(other.to_string()))
+        } else {
+          for prefix in other[..other.len() - name.len()].chars() {
+            ast_stack[l - 1].push(Tree::Leaf(prefix.to_string()))
+          }
+          push_tree(&mut ast_stack[l - 1], Tree::Leaf(name.to_string()))
+        }
       }
     }
   }
-  ast_stack.pop().unwrap().first().unwrap().clone()
+  ast_stack.pop().unwrap()
+}
+
+pub fn parse_sexp(input: &str) -> Tree<String> {
+  parse_sexps(input).first().unwrap().clone()
 }
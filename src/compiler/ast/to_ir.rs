@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use crate::{
-  instructions::GenericInstruction::*,
+  instructions::{Argument::*, GenericInstruction::*},
   runtime::{
     core_functions::CoreFnId,
     data::GenericValue::*,
@@ -28,9 +28,92 @@ pub fn push_constant(
   *taken_virtual_registers - 1
 }
 
+fn is_binding_form(
+  subexpressions: &[Expression],
+  local_bindings: &HashMap<SymbolIndex, Register>,
+  symbol_ledger: &SymbolLedger,
+) -> bool {
+  if let Some(Expression::Literal(Symbol(symbol_index))) =
+    subexpressions.first()
+  {
+    !local_bindings.contains_key(symbol_index)
+      && symbol_ledger.symbol_name(symbol_index).map(String::as_str)
+        == Some("binding")
+  } else {
+    false
+  }
+}
+
+// Compiles `(binding (var value ...) body ...)`. All of the values are
+// evaluated before any of the vars are rebound, and the bindings are popped
+// after the body so that they only last for its dynamic extent
+fn build_binding_ir(
+  subexpressions: Vec<Expression>,
+  global_resolver: &impl Fn(SymbolIndex) -> Option<SymbolIndex>,
+  local_bindings: &HashMap<SymbolIndex, Register>,
+  symbol_ledger: &mut SymbolLedger,
+  taken_virtual_registers: &mut usize,
+  instructions: &mut Vec<SSAInstruction>,
+  constants: &mut Vec<SSAValue<()>>,
+) -> ASTResult<SSARegister> {
+  let mut subexpressions_iter = subexpressions.into_iter().skip(1);
+  let pairs = match subexpressions_iter.next() {
+    Some(Expression::Application(pairs)) if pairs.len() % 2 == 0 => pairs,
+    _ => return Err(ASTError::InvalidBindingForm),
+  };
+  let mut bindings = vec![];
+  let mut pairs_iter = pairs.into_iter();
+  while let (Some(var), Some(value)) = (pairs_iter.next(), pairs_iter.next()) {
+    let Expression::Literal(Symbol(symbol_index)) = var else {
+      return Err(ASTError::InvalidBindingForm);
+    };
+    let Some(global_index) = global_resolver(symbol_index) else {
+      return Err(ASTError::UnboundSymbol(
+        symbol_ledger.symbol_name(&symbol_index).unwrap().clone(),
+      ));
+    };
+    if !symbol_ledger.is_dynamic(&global_index) {
+      return Err(ASTError::NonDynamicBinding(
+        symbol_ledger.symbol_name(&symbol_index).unwrap().clone(),
+      ));
+    }
+    let value_register = build_expression_ir(
+      value,
+      global_resolver,
+      local_bindings,
+      symbol_ledger,
+      taken_virtual_registers,
+      instructions,
+      constants,
+    )?;
+    bindings.push((global_index, value_register));
+  }
+  let binding_count = bindings.len() as u8;
+  for (symbol_index, value_register) in bindings {
+    instructions.push(PushDynamicBinding(symbol_index, value_register));
+  }
+  let mut result_register = None;
+  for body_expression in subexpressions_iter {
+    result_register = Some(build_expression_ir(
+      body_expression,
+      global_resolver,
+      local_bindings,
+      symbol_ledger,
+      taken_virtual_registers,
+      instructions,
+      constants,
+    )?);
+  }
+  let result_register = result_register.unwrap_or_else(|| {
+    push_constant(Nil, taken_virtual_registers, instructions, constants)
+  });
+  instructions.push(PopDynamicBindings(binding_count));
+  Ok(result_register)
+}
+
 pub fn build_expression_ir(
   expression: Expression,
-  global_binding_checker: &impl Fn(SymbolIndex) -> bool,
+  global_resolver: &impl Fn(SymbolIndex) -> Option<SymbolIndex>,
   local_bindings: &HashMap<SymbolIndex, Register>,
   symbol_ledger: &mut SymbolLedger,
   taken_virtual_registers: &mut usize,
@@ -51,8 +134,19 @@ pub fn build_expression_ir(
               instructions,
               constants,
             ))
-          } else if global_binding_checker(symbol_index) {
-            instructions.push(Lookup(*taken_virtual_registers, symbol_index));
+          } else if let Some(builtin) = symbol_ledger.builtin(&symbol_index) {
+            Ok(push_constant(
+              ExternalFn(builtin.clone()),
+              taken_virtual_registers,
+              instructions,
+              constants,
+            ))
+          } else if let Some(global_index) = global_resolver(symbol_index) {
+            instructions.push(if symbol_ledger.is_dynamic(&global_index) {
+              LookupDynamic(*taken_virtual_registers, global_index)
+            } else {
+              Lookup(*taken_virtual_registers, global_index)
+            });
             *taken_virtual_registers += 1;
             Ok(*taken_virtual_registers - 1)
           } else {
@@ -74,6 +168,19 @@ pub fn build_expression_ir(
       instructions,
       constants,
     )),
+    Expression::Application(subexpressions)
+      if is_binding_form(&subexpressions, local_bindings, symbol_ledger) =>
+    {
+      build_binding_ir(
+        subexpressions,
+        global_resolver,
+        local_bindings,
+        symbol_ledger,
+        taken_virtual_registers,
+        instructions,
+        constants,
+      )
+    }
     Expression::Application(subexpressions) => {
       let mut subexpressions_iter = subexpressions.into_iter();
       let first_subexpression = subexpressions_iter.next().expect(
@@ -85,7 +192,7 @@ pub fn build_expression_ir(
         .map(|arg| {
           build_expression_ir(
             arg,
-            global_binding_checker,
+            global_resolver,
             local_bindings,
             symbol_ledger,
             taken_virtual_registers,
@@ -96,7 +203,7 @@ pub fn build_expression_ir(
         .collect::<Result<Vec<SSARegister>, _>>()?;
       let f_register = build_expression_ir(
         first_subexpression,
-        global_binding_checker,
+        global_resolver,
         local_bindings,
         symbol_ledger,
         taken_virtual_registers,
@@ -106,11 +213,8 @@ pub fn build_expression_ir(
       instructions.push(Call(
         *taken_virtual_registers,
         f_register,
-        arg_registers.len() as Register,
+        arg_registers.into_iter().map(CopyArgument).collect(),
       ));
-      for arg_register in arg_registers {
-        instructions.push(CopyArgument(arg_register))
-      }
       *taken_virtual_registers += 1;
       Ok(*taken_virtual_registers - 1)
     }
@@ -128,7 +232,7 @@ pub fn build_expression_ir(
           let mut function_constants = vec![];
           let function_return_register = build_expression_ir(
             body.into_iter().next().unwrap(),
-            global_binding_checker,
+            global_resolver,
             &new_bindings,
             symbol_ledger,
             &mut (arg_count.clone() as SSARegister),
@@ -1,9 +1,16 @@
-use std::collections::HashMap;
+use std::{
+  collections::{HashMap, HashSet},
+  rc::Rc,
+};
+
+use num_bigint::BigInt;
 
 use crate::{
   compiler::SSAValue,
   runtime::{
-    core_functions::CoreFnId, data::GenericValue, evaluation::SymbolIndex,
+    core_functions::CoreFnId,
+    data::{ExternalFunction, GenericValue},
+    evaluation::SymbolIndex,
   },
 };
 
@@ -15,6 +22,7 @@ const GENSYM_PREFIX: &str = "__gensym_";
 pub(crate) enum Token {
   Nil,
   IntLiteral(i64),
+  BigIntLiteral(BigInt),
   FloatLiteral(f64),
   StringLiteral(String),
   Symbol(String),
@@ -27,6 +35,8 @@ impl TryFrom<String> for Token {
     use Token::*;
     if let Ok(i) = s.parse::<i64>() {
       Ok(IntLiteral(i))
+    } else if let Ok(i) = s.parse::<BigInt>() {
+      Ok(BigIntLiteral(i))
     } else if let Ok(f) = s.parse::<f64>() {
       Ok(FloatLiteral(f))
     } else {
@@ -71,6 +81,12 @@ pub(crate) struct SymbolLedger {
   gensym_count: u16,
   pub(crate) names_to_indeces: HashMap<String, SymbolIndex>,
   indeces_to_names: HashMap<SymbolIndex, String>,
+  // Globals defined with `(def ^:dynamic ...)`, which can be rebound with
+  // `binding`
+  dynamic_symbols: HashSet<SymbolIndex>,
+  // Host functions registered with `Evaluator::define_builtin`, which the
+  // compiler resolves like core functions rather than as globals
+  builtins: HashMap<SymbolIndex, Rc<ExternalFunction>>,
 }
 impl SymbolLedger {
   pub(crate) fn symbol_index(&mut self, symbol: String) -> SymbolIndex {
@@ -90,17 +106,65 @@ impl SymbolLedger {
   pub(crate) fn symbol_name(&self, index: &SymbolIndex) -> Option<&String> {
     self.indeces_to_names.get(index)
   }
+  // Every symbol's name, in order of index
+  #[cfg(feature = "serde")]
+  pub(crate) fn symbol_names(&self) -> impl Iterator<Item = &String> {
+    (0..self.names_to_indeces.len() as SymbolIndex)
+      .map(|index| &self.indeces_to_names[&index])
+  }
   pub(crate) fn generate_unique_symbol(&mut self) -> SymbolIndex {
-    let symbol_name = format!("{GENSYM_PREFIX}{}", self.gensym_count);
-    self.gensym_count += 1;
-    self.symbol_index(symbol_name)
+    self.generate_prefixed_symbol(GENSYM_PREFIX)
+  }
+  // Skips any names that are already in use, so the symbol is distinct from
+  // every symbol seen so far, not just from other generated ones
+  pub(crate) fn generate_prefixed_symbol(
+    &mut self,
+    prefix: &str,
+  ) -> SymbolIndex {
+    loop {
+      let symbol_name = format!("{prefix}{}", self.gensym_count);
+      self.gensym_count += 1;
+      if !self.names_to_indeces.contains_key(&symbol_name) {
+        return self.symbol_index(symbol_name);
+      }
+    }
+  }
+  pub(crate) fn set_dynamic(&mut self, index: SymbolIndex, dynamic: bool) {
+    if dynamic {
+      self.dynamic_symbols.insert(index);
+    } else {
+      self.dynamic_symbols.remove(&index);
+    }
+  }
+  pub(crate) fn is_dynamic(&self, index: &SymbolIndex) -> bool {
+    self.dynamic_symbols.contains(index)
   }
   pub(crate) fn is_built_in(&self, index: &SymbolIndex) -> bool {
-    CoreFnId::from_name(self.symbol_name(index).expect(
-      "called is_built_in with symbol index that doesn't map to any \
-      registered symbol",
-    ))
-    .is_some()
+    self.builtins.contains_key(index)
+      || CoreFnId::from_name(self.symbol_name(index).expect(
+        "called is_built_in with symbol index that doesn't map to any \
+        registered symbol",
+      ))
+      .is_some()
+  }
+  // Replaces any builtin that already has the same name, but can't replace a
+  // core function, which always takes precedence
+  pub(crate) fn define_builtin(&mut self, f: ExternalFunction) {
+    let name = f.name.clone().expect("builtins must be named");
+    let index = self.symbol_index(name);
+    self.builtins.insert(index, Rc::new(f));
+  }
+  pub(crate) fn builtin(
+    &self,
+    index: &SymbolIndex,
+  ) -> Option<&Rc<ExternalFunction>> {
+    self.builtins.get(index)
+  }
+  pub(crate) fn builtin_names(&self) -> impl Iterator<Item = &String> {
+    self
+      .builtins
+      .keys()
+      .filter_map(|index| self.symbol_name(index))
   }
 }
 
@@ -112,6 +176,7 @@ pub fn token_to_value(
   match token {
     Token::Nil => Nil,
     Token::IntLiteral(i) => i.into(),
+    Token::BigIntLiteral(i) => i.into(),
     Token::FloatLiteral(f) => f.into(),
     Token::StringLiteral(s) => s.into(),
     Token::Symbol(s) => Symbol(symbol_ledger.symbol_index(s)),
@@ -127,6 +192,15 @@ mod tests {
     assert_eq!(Token::try_from("1"), Ok(IntLiteral(1)));
   }
   #[test]
+  fn parse_big_int() {
+    assert_eq!(
+      Token::try_from("123456789012345678901234567890"),
+      Ok(BigIntLiteral(
+        "123456789012345678901234567890".parse().unwrap()
+      ))
+    );
+  }
+  #[test]
   fn parse_float() {
     assert_eq!(Token::try_from("1."), Ok(FloatLiteral(1.)));
     assert_eq!(Token::try_from("-1.5".to_string()), Ok(FloatLiteral(-1.5)));
@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, PartialEq)]
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Tree<T> {
   Inner(Vec<Tree<T>>),
   Leaf(T),
@@ -21,3 +23,21 @@ impl<T> Tree<T> {
     }
   }
 }
+
+impl<T: Display> Display for Tree<T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Inner(subtrees) => {
+        write!(f, "(")?;
+        for (i, subtree) in subtrees.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{subtree}")?;
+        }
+        write!(f, ")")
+      }
+      Leaf(leaf) => write!(f, "{leaf}"),
+    }
+  }
+}
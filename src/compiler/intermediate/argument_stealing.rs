@@ -0,0 +1,66 @@
+use std::{collections::HashSet, rc::Rc};
+
+use crate::{
+  blocks::GenericBlock,
+  compiler::{SSABlock, SSARegister},
+  instructions::{Argument, Argument::*, GenericInstruction::*},
+};
+
+use super::{
+  control_flow::SSAGraph,
+  error::IntermediateCompilationResult,
+  lifetimes::{live_after_instructions, Liveness},
+};
+
+// An argument can only be stolen from its last occurrence in the call, since
+// the earlier ones would otherwise read an emptied register, and never from
+// the register holding the function itself
+fn steal_arguments(
+  f: SSARegister,
+  args: &[Argument<SSARegister>],
+  live_after: &HashSet<SSARegister>,
+) -> Rc<[Argument<SSARegister>]> {
+  args
+    .iter()
+    .enumerate()
+    .map(|(i, arg)| match *arg {
+      CopyArgument(register)
+        if register != f
+          && !live_after.contains(&register)
+          && !args[i + 1..]
+            .iter()
+            .any(|later| *later.register() == register) =>
+      {
+        StealArgument(register)
+      }
+      arg => arg,
+    })
+    .collect()
+}
+
+// Arguments whose values aren't needed after a call are moved into the
+// callee's frame rather than copied, so that a collection passed along at the
+// end of its lifetime isn't shared with a dead register, and can be updated in
+// place by the callee
+pub fn steal_dying_arguments(
+  block: SSABlock<(SSAGraph, Liveness)>,
+) -> IntermediateCompilationResult<SSABlock<(SSAGraph, Liveness)>> {
+  block.translate(&|_, instructions, constants, (mut graph, liveness)| {
+    for (id, basic_block) in graph.blocks.iter_mut().enumerate() {
+      let (_, live_after) =
+        live_after_instructions(basic_block, &liveness.live_out[id]);
+      for (instruction, live_after) in
+        basic_block.instructions.iter_mut().zip(live_after)
+      {
+        if let Call(_, f, args) | CallAndReturn(f, args) = instruction {
+          *args = steal_arguments(*f, args, &live_after);
+        }
+      }
+    }
+    Ok(GenericBlock::new_with_metadata(
+      instructions,
+      constants,
+      (graph, liveness),
+    ))
+  })
+}
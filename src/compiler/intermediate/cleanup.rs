@@ -1,28 +1,41 @@
-use crate::{compiler::SSABlock, instructions::GenericInstruction::*};
+use crate::{
+  blocks::GenericBlock, compiler::SSABlock, instructions::GenericInstruction::*,
+};
 
 use super::{
-  error::IntermediateCompilationResult, lifetimes::calculate_register_lifetimes,
+  control_flow::SSAGraph, error::IntermediateCompilationResult,
+  lifetimes::calculate_register_lifetimes,
 };
 
-pub fn erase_unused_constants<M: Clone>(
-  block: SSABlock<M>,
-) -> IntermediateCompilationResult<SSABlock<()>> {
-  block.translate(&|preallocated_registers, instructions, constants, _| {
+pub fn erase_unused_constants(
+  block: SSABlock<SSAGraph>,
+) -> IntermediateCompilationResult<SSABlock<SSAGraph>> {
+  block.translate(&|preallocated_registers,
+                    instructions,
+                    constants,
+                    mut graph| {
     let lifetimes =
-      calculate_register_lifetimes(preallocated_registers, &instructions)?;
-    let mut filtered_instructions = vec![];
+      calculate_register_lifetimes(preallocated_registers, &graph)?;
     let mut filtered_constants = vec![];
-    for instruction in instructions.into_iter() {
-      if let Const(target, const_index) = instruction {
-        if lifetimes[&target].is_used() {
-          filtered_instructions
-            .push(Const(target, filtered_constants.len() as u16));
-          filtered_constants.push(constants[const_index as usize].clone());
+    for basic_block in graph.blocks.iter_mut() {
+      let mut filtered_instructions = vec![];
+      for instruction in std::mem::take(&mut basic_block.instructions) {
+        if let Const(target, const_index) = instruction {
+          if lifetimes[&target].is_used() {
+            filtered_instructions
+              .push(Const(target, filtered_constants.len() as u16));
+            filtered_constants.push(constants[const_index as usize].clone());
+          }
+        } else {
+          filtered_instructions.push(instruction)
         }
-      } else {
-        filtered_instructions.push(instruction)
       }
+      basic_block.instructions = filtered_instructions;
     }
-    Ok(SSABlock::new(filtered_instructions, filtered_constants))
+    Ok(GenericBlock::new_with_metadata(
+      instructions,
+      filtered_constants,
+      graph,
+    ))
   })
 }
@@ -0,0 +1,376 @@
+// The intermediate passes operate on a control flow graph of basic blocks
+// rather than a flat instruction list. Values flow into a basic block through
+// its parameters, which every jump to the block passes arguments for, so the
+// graph stays in SSA form across branches. The graph is lowered back to a flat
+// list of instructions with jumps once registers have been allocated
+
+use std::{
+  collections::{HashMap, HashSet},
+  iter::once,
+  ops::RangeInclusive,
+};
+
+use crate::{
+  blocks::GenericBlock,
+  compiler::{SSABlock, SSAInstruction, SSARegister},
+  instructions::GenericInstruction::{self, *},
+};
+
+use super::{
+  error::{IntermediateCompilationError, IntermediateCompilationResult},
+  InstructionTimestamp,
+};
+
+pub type BlockId = usize;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Terminator<I> {
+  // Leaves the graph, either through a `Return`, `CallAndReturn`, or
+  // `ApplyAndReturn` at the end of the block, or by running off the end of
+  // the root block
+  Exit,
+  Jump(BlockId, Vec<I>),
+  Branch {
+    condition: I,
+    then: BlockId,
+    otherwise: BlockId,
+  },
+}
+impl<I> Terminator<I> {
+  pub fn inputs(&self) -> &[I] {
+    match self {
+      Terminator::Exit => &[],
+      Terminator::Jump(_, arguments) => arguments,
+      Terminator::Branch { condition, .. } => std::slice::from_ref(condition),
+    }
+  }
+  pub fn successors(&self) -> impl Iterator<Item = BlockId> {
+    let (first, second) = match self {
+      Terminator::Exit => (None, None),
+      Terminator::Jump(target, _) => (Some(*target), None),
+      Terminator::Branch {
+        then, otherwise, ..
+      } => (Some(*then), Some(*otherwise)),
+    };
+    first.into_iter().chain(second)
+  }
+  pub fn translate<NewI>(
+    self,
+    input_translator: impl Fn(I) -> NewI,
+  ) -> Terminator<NewI> {
+    match self {
+      Terminator::Exit => Terminator::Exit,
+      Terminator::Jump(target, arguments) => Terminator::Jump(
+        target,
+        arguments.into_iter().map(input_translator).collect(),
+      ),
+      Terminator::Branch {
+        condition,
+        then,
+        otherwise,
+      } => Terminator::Branch {
+        condition: input_translator(condition),
+        then,
+        otherwise,
+      },
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock<I, O, R> {
+  pub parameters: Vec<O>,
+  pub instructions: Vec<GenericInstruction<I, O, R>>,
+  pub terminator: Terminator<I>,
+}
+impl<I, O, R> Default for BasicBlock<I, O, R> {
+  fn default() -> Self {
+    Self {
+      parameters: vec![],
+      instructions: vec![],
+      terminator: Terminator::Exit,
+    }
+  }
+}
+
+// The first block is the entry point, and the blocks are laid out in order
+// when the graph is lowered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlFlowGraph<I, O, R> {
+  pub blocks: Vec<BasicBlock<I, O, R>>,
+}
+
+pub type SSABasicBlock =
+  BasicBlock<SSARegister, SSARegister, (SSARegister, SSARegister)>;
+pub type SSAGraph =
+  ControlFlowGraph<SSARegister, SSARegister, (SSARegister, SSARegister)>;
+
+// One step of executing a graph. Steps are visited in layout order, and each
+// one gets its own timestamp in the lifetime analysis
+pub(crate) enum Step<'a, I, O, R> {
+  Parameters(&'a [O]),
+  Instruction(&'a GenericInstruction<I, O, R>),
+  Terminator(&'a Terminator<I>),
+}
+
+impl<I, O, R> ControlFlowGraph<I, O, R> {
+  pub(crate) fn steps(&self) -> impl Iterator<Item = Step<'_, I, O, R>> {
+    self.blocks.iter().flat_map(|block| {
+      once(Step::Parameters(&block.parameters[..]))
+        .chain(block.instructions.iter().map(Step::Instruction))
+        .chain(once(Step::Terminator(&block.terminator)))
+    })
+  }
+}
+
+impl SSAGraph {
+  // Renames registers throughout a range of blocks
+  fn rename(
+    &mut self,
+    blocks: RangeInclusive<BlockId>,
+    renames: &HashMap<SSARegister, SSARegister>,
+  ) {
+    let rename = |register: SSARegister| -> SSARegister {
+      *renames.get(&register).unwrap_or(&register)
+    };
+    for block in &mut self.blocks[blocks] {
+      for parameter in block.parameters.iter_mut() {
+        *parameter = rename(*parameter);
+      }
+      for instruction in block.instructions.iter_mut() {
+        *instruction =
+          instruction
+            .clone()
+            .translate(rename, rename, |(a, b)| (rename(a), rename(b)));
+      }
+      block.terminator =
+        std::mem::replace(&mut block.terminator, Terminator::Exit)
+          .translate(rename);
+    }
+  }
+  // The registers that any block in the range creates
+  fn outputs(&self, blocks: RangeInclusive<BlockId>) -> Vec<SSARegister> {
+    self.blocks[blocks]
+      .iter()
+      .flat_map(|block| {
+        block.parameters.iter().copied().chain(
+          block.instructions.iter().flat_map(|instruction| {
+            let usages = instruction.usages();
+            usages
+              .outputs
+              .chain(usages.replacements.map(|(_, replacement)| replacement))
+          }),
+        )
+      })
+      .collect()
+  }
+  fn exits(&self, block: BlockId) -> bool {
+    matches!(
+      self.blocks[block].instructions.last(),
+      Some(Return(_) | CallAndReturn(..) | ApplyAndReturn(..))
+    )
+  }
+}
+
+struct OpenIf {
+  start: InstructionTimestamp,
+  // the first and last blocks of each arm that has been closed
+  arms: Vec<RangeInclusive<BlockId>>,
+  arm_start: BlockId,
+  // the block whose branch falls through to the next arm, or to the end of
+  // the `If` if there's no `Else`
+  pending_branch: Option<BlockId>,
+}
+
+// Splits a flat instruction list into basic blocks at its `If`, `ElseIf`,
+// `Else`, and `EndIf` instructions. A register that's created in every arm of
+// an `If` is merged into a parameter of the block after the `EndIf`, and later
+// uses of the register are renamed to the parameter
+pub(crate) fn build_control_flow_graph(
+  preallocated_registers: u8,
+  instructions: Vec<SSAInstruction>,
+) -> IntermediateCompilationResult<SSAGraph> {
+  let mut next_register = instructions
+    .iter()
+    .filter_map(|instruction| {
+      let usages = instruction.usages();
+      usages
+        .inputs
+        .chain(usages.outputs)
+        .chain(usages.replacements.flat_map(|(a, b)| [a, b]))
+        .max()
+    })
+    .max()
+    .map_or(0, |max| max + 1)
+    .max(preallocated_registers as SSARegister);
+  let mut fresh_register = || {
+    next_register += 1;
+    next_register - 1
+  };
+  let mut graph = SSAGraph {
+    blocks: vec![BasicBlock::default()],
+  };
+  let mut open_ifs: Vec<OpenIf> = vec![];
+  let mut renames: HashMap<SSARegister, SSARegister> = HashMap::new();
+  let mut origins: HashMap<SSARegister, SSARegister> = HashMap::new();
+  for (timestamp, instruction) in instructions.into_iter().enumerate() {
+    let timestamp = timestamp as InstructionTimestamp;
+    let rename = |register: SSARegister| -> SSARegister {
+      *renames.get(&register).unwrap_or(&register)
+    };
+    let instruction =
+      instruction.translate(rename, rename, |(a, b)| (rename(a), rename(b)));
+    let current = graph.blocks.len() - 1;
+    match instruction {
+      If(condition) => {
+        graph.blocks[current].terminator = Terminator::Branch {
+          condition,
+          then: current + 1,
+          otherwise: current + 1,
+        };
+        graph.blocks.push(BasicBlock::default());
+        open_ifs.push(OpenIf {
+          start: timestamp,
+          arms: vec![],
+          arm_start: current + 1,
+          pending_branch: Some(current),
+        });
+      }
+      ElseIf(_) | Else => {
+        let open_if = open_ifs
+          .last_mut()
+          .filter(|open_if| open_if.pending_branch.is_some())
+          .ok_or(IntermediateCompilationError::UnexpectedBranch(timestamp))?;
+        open_if.arms.push(open_if.arm_start..=current);
+        let next = current + 1;
+        if let Terminator::Branch { otherwise, .. } =
+          &mut graph.blocks[open_if.pending_branch.unwrap()].terminator
+        {
+          *otherwise = next;
+        }
+        graph.blocks.push(BasicBlock::default());
+        if let ElseIf(condition) = instruction {
+          graph.blocks[next].terminator = Terminator::Branch {
+            condition,
+            then: next + 1,
+            otherwise: next + 1,
+          };
+          graph.blocks.push(BasicBlock::default());
+          open_if.arm_start = next + 1;
+          open_if.pending_branch = Some(next);
+        } else {
+          open_if.arm_start = next;
+          open_if.pending_branch = None;
+        }
+      }
+      EndIf => {
+        let mut open_if = open_ifs
+          .pop()
+          .ok_or(IntermediateCompilationError::UnexpectedBranch(timestamp))?;
+        open_if.arms.push(open_if.arm_start..=current);
+        let join = current + 1;
+        if let Some(pending_branch) = open_if.pending_branch {
+          if let Terminator::Branch { otherwise, .. } =
+            &mut graph.blocks[pending_branch].terminator
+          {
+            *otherwise = join;
+          }
+        }
+        let joining_arms: Vec<RangeInclusive<BlockId>> = open_if
+          .arms
+          .into_iter()
+          .filter(|arm| !graph.exits(*arm.end()))
+          .collect();
+        // the registers that each arm creates, along with the arm's final name
+        // for each of them, keyed by the name the register had before any
+        // merges renamed it
+        let arm_outputs: Vec<(
+          Vec<SSARegister>,
+          HashMap<SSARegister, SSARegister>,
+        )> = joining_arms
+          .iter()
+          .map(|arm| {
+            let names = graph.outputs(arm.clone());
+            let final_names = names
+              .iter()
+              .map(|name| (*origins.get(name).unwrap_or(name), *name))
+              .collect();
+            (names, final_names)
+          })
+          .collect();
+        // without an `Else`, the `If` can fall through to the join without
+        // creating anything, so there's nothing to merge
+        let mut merged: Vec<SSARegister> =
+          if open_if.pending_branch.is_none() && joining_arms.len() > 1 {
+            arm_outputs[0]
+              .1
+              .keys()
+              .copied()
+              .filter(|register| {
+                arm_outputs[1..]
+                  .iter()
+                  .all(|(_, final_names)| final_names.contains_key(register))
+              })
+              .collect()
+          } else {
+            vec![]
+          };
+        merged.sort();
+        // registers that an arm creates under the same name as an earlier arm
+        // are renamed, to keep the graph in SSA form
+        let mut claimed_names = HashSet::new();
+        for (arm, (names, final_names)) in
+          joining_arms.into_iter().zip(arm_outputs)
+        {
+          let mut arm_renames = HashMap::new();
+          for name in names.iter() {
+            if !claimed_names.insert(*name) {
+              arm_renames.insert(*name, fresh_register());
+            }
+          }
+          graph.rename(arm.clone(), &arm_renames);
+          graph.blocks[*arm.end()].terminator = Terminator::Jump(
+            join,
+            merged
+              .iter()
+              .map(|register| {
+                let name = final_names[register];
+                *arm_renames.get(&name).unwrap_or(&name)
+              })
+              .collect(),
+          );
+        }
+        let mut parameters = vec![];
+        for register in merged {
+          let parameter = fresh_register();
+          renames.insert(register, parameter);
+          origins.insert(parameter, register);
+          parameters.push(parameter);
+        }
+        graph.blocks.push(BasicBlock {
+          parameters,
+          ..BasicBlock::default()
+        });
+      }
+      Jump(_) | JumpIf(..) | JumpIfNot(..) | RelativeJump(_)
+      | IncAndJumpIfPos(..) => {
+        return Err(IntermediateCompilationError::UnstructuredJump(timestamp))
+      }
+      instruction => graph.blocks[current].instructions.push(instruction),
+    }
+  }
+  if let Some(open_if) = open_ifs.last() {
+    return Err(IntermediateCompilationError::UnclosedIf(open_if.start));
+  }
+  Ok(graph)
+}
+
+pub fn build_control_flow_graphs<M: Clone>(
+  block: SSABlock<M>,
+) -> IntermediateCompilationResult<SSABlock<SSAGraph>> {
+  block.translate(&|preallocated_registers, instructions, constants, _| {
+    let graph = build_control_flow_graph(preallocated_registers, instructions)?;
+    // the instructions live in the graph until it's lowered
+    Ok(GenericBlock::new_with_metadata(vec![], constants, graph))
+  })
+}
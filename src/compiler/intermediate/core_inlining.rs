@@ -1,203 +1,283 @@
+use std::collections::{HashMap, VecDeque};
+
 use crate::{
   blocks::GenericBlock,
   compiler::{
     intermediate::get_max_ssa_register, SSABlock, SSAInstruction, SSARegister,
+    SSAValue,
+  },
+  instructions::{Argument::*, GenericInstruction::*},
+  runtime::{
+    core_functions::CoreFnId,
+    data::{GenericValue, GenericValue::*, Value},
+    evaluation::ConstIndex,
   },
-  instructions::GenericInstruction::*,
-  runtime::{core_functions::CoreFnId, data::GenericValue::*},
 };
 
-use super::{
-  error::IntermediateCompilationResult,
-  lifetimes::{calculate_register_lifetimes, Lifetimes},
-};
+use super::{control_flow::SSAGraph, error::IntermediateCompilationResult};
+
+// The instructions that a call of a core function can be replaced with, if
+// there's a specialized instruction for it. `max_register` is the highest
+// register used anywhere in the graph, so that any registers above it are
+// free to hold intermediate values
+fn inline_core_fn_call(
+  fn_id: CoreFnId,
+  target: &SSARegister,
+  args: &[SSARegister],
+  max_register: SSARegister,
+) -> Option<Vec<SSAInstruction>> {
+  use CoreFnId as F;
+  let arg_count = args.len();
+  match fn_id {
+    F::CreateList => Some(if arg_count == 0 {
+      vec![EmptyList(*target)]
+    } else {
+      let mut list_instructions = vec![EmptyList(max_register + 1)];
+      for i in 0..arg_count - 1 {
+        list_instructions
+          .push(Push((max_register + i + 1, max_register + i + 2), args[i]));
+      }
+      list_instructions.push(Push(
+        (max_register + arg_count, *target),
+        args[arg_count - 1],
+      ));
+      list_instructions
+    }),
+    F::SetCellValue if arg_count == 2 => {
+      Some(vec![SetCellValue(args[0], args[1]), Copy(*target, args[1])])
+    }
+    // `(swap! cell f args...)` becomes an ordinary call of `f`
+    // on the cell's current value, so `f` can be any function
+    F::UpdateCell if arg_count >= 2 => {
+      let (old_value, new_value) = (max_register + 1, max_register + 2);
+      Some(vec![
+        GetCellValue(old_value, args[0]),
+        Call(
+          new_value,
+          args[1],
+          std::iter::once(CopyArgument(old_value))
+            .chain(args[2..].iter().map(|arg| CopyArgument(*arg)))
+            .collect(),
+        ),
+        SetCellValue(args[0], new_value),
+        Copy(*target, new_value),
+      ])
+    }
+    _ => match args.len() {
+      0 => {
+        if let Some(nullary_instruction) = match fn_id {
+          F::CreateList => Some(EmptyList(*target)),
+          _ => None,
+        } {
+          Some(vec![nullary_instruction])
+        } else {
+          None
+        }
+      }
+      1 => {
+        if let Some(nonreplacing_unary_instruction) = match fn_id {
+          F::First => Some(First(*target, args[0])),
+          F::Last => Some(Last(*target, args[0])),
+          F::IsEmpty => Some(IsEmpty(*target, args[0])),
+          F::BitNot => Some(BitNot(*target, args[0])),
+          F::CreateCell => Some(CreateCell(*target, args[0])),
+          F::GetCellValue => Some(GetCellValue(*target, args[0])),
+          _ => None,
+        } {
+          Some(vec![nonreplacing_unary_instruction])
+        } else if let Some(replacing_unary_instruction) = match fn_id {
+          F::Rest => Some(Rest((args[0], *target))),
+          F::ButLast => Some(ButLast((args[0], *target))),
+          _ => None,
+        } {
+          Some(vec![replacing_unary_instruction])
+        } else {
+          None
+        }
+      }
+      2 => {
+        if let Some(nonreplacing_binary_instruction) = match fn_id {
+          F::Add => Some(Add(*target, args[0], args[1])),
+          F::Subtract => Some(Subtract(*target, args[0], args[1])),
+          F::Multiply => Some(Multiply(*target, args[0], args[1])),
+          F::Divide => Some(Divide(*target, args[0], args[1])),
+          F::BitAnd => Some(BitAnd(*target, args[0], args[1])),
+          F::BitOr => Some(BitOr(*target, args[0], args[1])),
+          F::BitXor => Some(BitXor(*target, args[0], args[1])),
+          F::ShiftLeft => Some(ShiftLeft(*target, args[0], args[1])),
+          F::ShiftRight => Some(ShiftRight(*target, args[0], args[1])),
+          _ => None,
+        } {
+          Some(vec![nonreplacing_binary_instruction])
+        } else if let Some(replacing_binary_instruction) = match fn_id {
+          F::Push => Some(Push((args[0], *target), args[1])),
+          F::Cons => Some(Cons((args[0], *target), args[1])),
+          _ => None,
+        } {
+          Some(vec![replacing_binary_instruction])
+        } else {
+          None
+        }
+      }
+      arg_count => {
+        let maybe_instruction_builder: Option<
+          fn(SSARegister, SSARegister, SSARegister) -> SSAInstruction,
+        > = match fn_id {
+          F::Add => Some(|a, b, c| Add(a, b, c)),
+          F::Multiply => Some(|a, b, c| Multiply(a, b, c)),
+          F::BitAnd => Some(BitAnd),
+          F::BitOr => Some(BitOr),
+          F::BitXor => Some(BitXor),
+          _ => None,
+        };
+        if let Some(instruction_builder) = maybe_instruction_builder {
+          let first_free_register = max_register + 1;
+          let mut new_instructions =
+            vec![instruction_builder(first_free_register, args[0], args[1])];
+          if arg_count == 3 {
+            new_instructions.push(instruction_builder(
+              *target,
+              first_free_register,
+              args[2],
+            ));
+          } else {
+            for i in 0..(arg_count - 3) {
+              new_instructions.push(instruction_builder(
+                first_free_register + i + 1,
+                first_free_register + i,
+                args[i + 2],
+              ))
+            }
+            new_instructions.push(instruction_builder(
+              *target,
+              first_free_register + arg_count - 3,
+              args[arg_count - 1],
+            ))
+          }
+          Some(new_instructions)
+        } else {
+          None
+        }
+      }
+    },
+  }
+}
+
+// Constants that mean the same thing in a block being compiled as they do at
+// runtime, which are the only ones that pure builtins are called on early
+fn plain_data<I, O, R, M, NewI, NewO, NewR, NewM>(
+  value: &GenericValue<I, O, R, M>,
+) -> Option<GenericValue<NewI, NewO, NewR, NewM>> {
+  match value {
+    Nil => Some(Nil),
+    Bool(b) => Some(Bool(*b)),
+    Char(c) => Some(Char(*c)),
+    Number(n) => Some(Number(n.clone())),
+    Symbol(index) => Some(Symbol(*index)),
+    Str(s) => Some(Str(s.clone())),
+    _ => None,
+  }
+}
 
-pub fn inline_core_fn_calls<M: Clone>(
-  block: SSABlock<M>,
-) -> IntermediateCompilationResult<SSABlock<Lifetimes>> {
+// The result of calling a pure builtin on arguments that are all constants,
+// unless the call fails, in which case it's left to fail at runtime
+fn fold_pure_call<M>(
+  f: &SSAValue<M>,
+  args: &[&SSAValue<M>],
+) -> Option<SSAValue<M>> {
+  let ExternalFn(f) = f else {
+    return None;
+  };
+  if !f.pure {
+    return None;
+  }
+  let args = args
+    .iter()
+    .map(|arg| plain_data(*arg))
+    .collect::<Option<Vec<Value>>>()?;
+  plain_data(&f.call(args).ok()?)
+}
+
+pub fn inline_core_fn_calls(
+  block: SSABlock<SSAGraph>,
+) -> IntermediateCompilationResult<SSABlock<SSAGraph>> {
   block.translate(&|preallocated_registers,
-                    mut instructions,
+                    instructions,
                     constants,
-                    _|
+                    mut graph|
    -> IntermediateCompilationResult<_> {
-    let final_lifetimes = loop {
-      let lifetimes =
-        calculate_register_lifetimes(preallocated_registers, &instructions)?;
-      let mut modified = false;
-      for (timestamp, instruction) in instructions.iter().enumerate() {
-        if let Call(target, f_register, arg_count) = instruction {
-          if let Some(f_creation_timestamp) = lifetimes[f_register].creation {
-            if let Const(_, const_index) =
-              instructions[f_creation_timestamp as usize]
-            {
-              if let CoreFn(fn_id) = constants[const_index as usize] {
-                use CoreFnId as F;
-                let args: Vec<_> = ((timestamp + 1)
-                  ..(timestamp + 1 + *arg_count as usize))
-                  .map(|instruction_index| {
-                    match instructions[instruction_index] {
-                      CopyArgument(arg) => arg,
-                      StealArgument(arg) => arg,
-                      _ => panic!(
-                        "didn't find CopyArgument or StealArgument after \
-                         Call in inline_core_fn_calls"
-                      ),
-                    }
-                  })
-                  .collect();
-                if let Some(replacement_instructions) = match fn_id {
-                  F::CreateList => Some(if *arg_count == 0 {
-                    vec![EmptyList(*target)]
-                  } else {
-                    let max_register = get_max_ssa_register(
-                      preallocated_registers,
-                      &instructions,
-                    );
-                    let mut list_instructions =
-                      vec![EmptyList(max_register + 1)];
-                    for i in 0..*arg_count as usize - 1 {
-                      list_instructions.push(Push(
-                        (max_register + i + 1, max_register + i + 2),
-                        args[i],
-                      ));
-                    }
-                    list_instructions.push(Push(
-                      (max_register + *arg_count as usize, *target),
-                      args[*arg_count as usize - 1],
-                    ));
-                    list_instructions
-                  }),
-                  _ => match args.len() {
-                    0 => {
-                      if let Some(nullary_instruction) = match fn_id {
-                        F::CreateList => Some(EmptyList(*target)),
-                        _ => None,
-                      } {
-                        Some(vec![nullary_instruction])
-                      } else {
-                        None
-                      }
-                    }
-                    1 => {
-                      if let Some(nonreplacing_unary_instruction) = match fn_id
-                      {
-                        F::First => Some(First(*target, args[0])),
-                        F::Last => Some(Last(*target, args[0])),
-                        F::IsEmpty => Some(IsEmpty(*target, args[0])),
-                        _ => None,
-                      } {
-                        Some(vec![nonreplacing_unary_instruction])
-                      } else if let Some(replacing_unary_instruction) =
-                        match fn_id {
-                          F::Rest => Some(Rest((args[0], *target))),
-                          F::ButLast => Some(ButLast((args[0], *target))),
-                          _ => None,
-                        }
-                      {
-                        Some(vec![replacing_unary_instruction])
-                      } else {
-                        None
-                      }
-                    }
-                    2 => {
-                      if let Some(nonreplacing_binary_instruction) = match fn_id
-                      {
-                        F::Add => Some(Add(*target, args[0], args[1])),
-                        F::Subtract => {
-                          Some(Subtract(*target, args[0], args[1]))
-                        }
-                        F::Multiply => {
-                          Some(Multiply(*target, args[0], args[1]))
-                        }
-                        F::Divide => Some(Divide(*target, args[0], args[1])),
-                        _ => None,
-                      } {
-                        Some(vec![nonreplacing_binary_instruction])
-                      } else if let Some(replacing_binary_instruction) =
-                        match fn_id {
-                          F::Push => Some(Push((args[0], *target), args[1])),
-                          F::Cons => Some(Cons((args[0], *target), args[1])),
-                          _ => None,
-                        }
-                      {
-                        Some(vec![replacing_binary_instruction])
-                      } else {
-                        None
-                      }
-                    }
-                    arg_count => {
-                      let maybe_instruction_builder: Option<
-                        fn(
-                          SSARegister,
-                          SSARegister,
-                          SSARegister,
-                        ) -> SSAInstruction,
-                      > = match fn_id {
-                        F::Add => Some(|a, b, c| Add(a, b, c)),
-                        F::Multiply => Some(|a, b, c| Multiply(a, b, c)),
-                        _ => None,
-                      };
-                      if let Some(instruction_builder) =
-                        maybe_instruction_builder
-                      {
-                        let first_free_register = get_max_ssa_register(
-                          preallocated_registers,
-                          &instructions,
-                        ) + 1;
-                        let mut new_instructions = vec![instruction_builder(
-                          first_free_register,
-                          args[0],
-                          args[1],
-                        )];
-                        if arg_count == 3 {
-                          new_instructions.push(instruction_builder(
-                            *target,
-                            first_free_register,
-                            args[2],
-                          ));
-                        } else {
-                          for i in 0..(arg_count - 3) {
-                            new_instructions.push(instruction_builder(
-                              first_free_register + i + 1,
-                              first_free_register + i,
-                              args[i + 2],
-                            ))
-                          }
-                          new_instructions.push(instruction_builder(
-                            *target,
-                            first_free_register + arg_count - 3,
-                            args[arg_count - 1],
-                          ))
-                        }
-                        Some(new_instructions)
-                      } else {
-                        None
-                      }
-                    }
-                  },
-                } {
-                  let _ = instructions
-                    .splice(
-                      timestamp..(timestamp + 1 + *arg_count as usize),
-                      replacement_instructions,
-                    )
-                    .collect::<Vec<_>>();
-                  modified = true;
-                  break;
-                }
-              }
+    let mut constants = constants;
+    let mut constant_registers: HashMap<SSARegister, ConstIndex> = graph
+      .blocks
+      .iter()
+      .flat_map(|basic_block| basic_block.instructions.iter())
+      .filter_map(|instruction| match instruction {
+        Const(register, const_index) => Some((*register, *const_index)),
+        _ => None,
+      })
+      .collect();
+    let core_fn_registers: HashMap<SSARegister, CoreFnId> = constant_registers
+      .iter()
+      .filter_map(|(register, const_index)| {
+        match constants[*const_index as usize] {
+          CoreFn(fn_id) => Some((*register, fn_id)),
+          _ => None,
+        }
+      })
+      .collect();
+    let mut max_register = get_max_ssa_register(preallocated_registers, &graph);
+    for basic_block in graph.blocks.iter_mut() {
+      // replacements are pushed back onto the front of the queue, since they
+      // can contain calls that can be inlined themselves
+      let mut pending: VecDeque<SSAInstruction> =
+        std::mem::take(&mut basic_block.instructions).into();
+      while let Some(instruction) = pending.pop_front() {
+        let replacement = match &instruction {
+          Call(target, f_register, call_args) => {
+            let args: Vec<_> =
+              call_args.iter().map(|arg| *arg.register()).collect();
+            if let Some(fn_id) = core_fn_registers.get(f_register) {
+              inline_core_fn_call(*fn_id, target, &args, max_register)
+            } else {
+              let constant = |register| {
+                constant_registers
+                  .get(register)
+                  .map(|const_index| &constants[*const_index as usize])
+              };
+              constant(f_register)
+                .zip(args.iter().map(constant).collect::<Option<Vec<_>>>())
+                .and_then(|(f, args)| fold_pure_call(f, &args))
+                .map(|result| {
+                  let const_index = constants.len() as ConstIndex;
+                  constants.push(result);
+                  constant_registers.insert(*target, const_index);
+                  vec![Const(*target, const_index)]
+                })
             }
           }
+          _ => None,
+        };
+        if let Some(replacement) = replacement {
+          for replacement_instruction in replacement.into_iter().rev() {
+            max_register = {
+              let usages = replacement_instruction.usages();
+              usages
+                .inputs
+                .chain(usages.outputs)
+                .chain(usages.replacements.flat_map(|(a, b)| [a, b]))
+                .fold(max_register, SSARegister::max)
+            };
+            pending.push_front(replacement_instruction);
+          }
+        } else {
+          basic_block.instructions.push(instruction);
         }
       }
-      if !modified {
-        break lifetimes;
-      }
-    };
+    }
     Ok(GenericBlock::new_with_metadata(
       instructions,
       constants,
-      final_lifetimes,
+      graph,
     ))
   })
 }
@@ -205,17 +285,20 @@ pub fn inline_core_fn_calls<M: Clone>(
 #[cfg(test)]
 mod tests {
   use block_macros::ssa_block;
+  use im_rc::vector;
   use std::fmt::Debug;
   use std::rc::Rc;
 
   use crate::{
     compiler::{
       intermediate::{
-        cleanup::erase_unused_constants, core_inlining::inline_core_fn_calls,
+        cleanup::erase_unused_constants,
+        control_flow::{build_control_flow_graphs, SSAGraph},
+        core_inlining::inline_core_fn_calls,
       },
-      SSABlock,
+      SSABlock, SSAInstruction, SSAValue,
     },
-    instructions::GenericInstruction::*,
+    instructions::{Argument::*, GenericInstruction::*},
     runtime::core_functions::CoreFnId,
     runtime::data::GenericValue::*,
   };
@@ -224,23 +307,35 @@ mod tests {
     format!("{:?}", x)
   }
 
+  // Inlines the calls in a program without any branches, returning the
+  // instructions of its only basic block along with its constants
+  fn inline_calls(
+    raw_ir: SSABlock<()>,
+  ) -> (Rc<[SSAInstruction]>, Rc<[SSAValue<SSAGraph>]>) {
+    let inlined_ir = erase_unused_constants(
+      inline_core_fn_calls(build_control_flow_graphs(raw_ir).unwrap()).unwrap(),
+    )
+    .unwrap();
+    (
+      inlined_ir.metadata.blocks[0].instructions.clone().into(),
+      inlined_ir.constants,
+    )
+  }
+
   #[test]
   fn inline_binary_addition() {
     let raw_ir = ssa_block![
       Const(0, 1),
       Const(1, 2),
       Const(2, CoreFn(CoreFnId::Add)),
-      Call(3, 2, 2),
-      CopyArgument(0),
-      CopyArgument(1),
+      Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
       Return(3)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir =
       ssa_block![Const(0, 1), Const(1, 2), Add(3, 0, 1), Return(3)];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -255,14 +350,10 @@ mod tests {
       Const(1, 2),
       Const(2, 3),
       Const(3, CoreFn(CoreFnId::Add)),
-      Call(4, 3, 3),
-      CopyArgument(0),
-      CopyArgument(1),
-      CopyArgument(2),
+      Call(4, 3, [CopyArgument(0), CopyArgument(1), CopyArgument(2)]),
       Return(4)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir = ssa_block![
       Const(0, 1),
       Const(1, 2),
@@ -272,7 +363,7 @@ mod tests {
       Return(4)
     ];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -288,15 +379,19 @@ mod tests {
       Const(2, 3),
       Const(3, 4),
       Const(4, CoreFn(CoreFnId::Add)),
-      Call(5, 4, 4),
-      CopyArgument(0),
-      CopyArgument(1),
-      CopyArgument(2),
-      CopyArgument(3),
+      Call(
+        5,
+        4,
+        [
+          CopyArgument(0),
+          CopyArgument(1),
+          CopyArgument(2),
+          CopyArgument(3)
+        ]
+      ),
       Return(5)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir = ssa_block![
       Const(0, 1),
       Const(1, 2),
@@ -308,7 +403,7 @@ mod tests {
       Return(5)
     ];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -324,15 +419,19 @@ mod tests {
       Const(2, 3),
       Const(3, 4),
       Const(4, CoreFn(CoreFnId::Multiply)),
-      Call(5, 4, 4),
-      CopyArgument(0),
-      CopyArgument(1),
-      CopyArgument(2),
-      CopyArgument(3),
+      Call(
+        5,
+        4,
+        [
+          CopyArgument(0),
+          CopyArgument(1),
+          CopyArgument(2),
+          CopyArgument(3)
+        ]
+      ),
       Return(5)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir = ssa_block![
       Const(0, 1),
       Const(1, 2),
@@ -344,7 +443,7 @@ mod tests {
       Return(5)
     ];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -358,17 +457,14 @@ mod tests {
       EmptyList(0),
       Const(1, 5),
       Const(2, CoreFn(CoreFnId::Push)),
-      Call(3, 2, 2),
-      CopyArgument(0),
-      CopyArgument(1),
+      Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
       Return(3)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir =
       ssa_block![EmptyList(0), Const(1, 5), Push((0, 3), 1), Return(3)];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -379,21 +475,19 @@ mod tests {
   #[test]
   fn inline_first() {
     let raw_ir = ssa_block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       Const(1, CoreFn(CoreFnId::First)),
-      Call(2, 1, 1),
-      CopyArgument(0),
+      Call(2, 1, [CopyArgument(0)]),
       Return(2)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir = ssa_block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       First(2, 0),
       Return(2)
     ];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
@@ -404,21 +498,19 @@ mod tests {
   #[test]
   fn inline_rest() {
     let raw_ir = ssa_block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       Const(1, CoreFn(CoreFnId::Rest)),
-      Call(2, 1, 1),
-      CopyArgument(0),
+      Call(2, 1, [CopyArgument(0)]),
       Return(2)
     ];
-    let inlined_ir =
-      erase_unused_constants(inline_core_fn_calls(raw_ir).unwrap()).unwrap();
+    let inlined_ir = inline_calls(raw_ir);
     let expected_inlined_ir = ssa_block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       Rest((0, 2)),
       Return(2)
     ];
     assert_eq!(
-      debug_string(&(inlined_ir.instructions, inlined_ir.constants)),
+      debug_string(&inlined_ir),
       debug_string(&(
         expected_inlined_ir.instructions,
         expected_inlined_ir.constants
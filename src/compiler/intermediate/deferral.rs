@@ -0,0 +1,53 @@
+use std::rc::Rc;
+
+use crate::{
+  compiler::{SSABlock, SSAValue},
+  instructions::GenericInstruction::*,
+  runtime::{
+    control::{Block, FunctionBody, GenericCompositeFunction},
+    data::{AritySpecifier, GenericValue::*},
+  },
+};
+
+use super::{error::IntermediateCompilationResult, raw_ir_to_bytecode};
+
+// Replaces the functions among a block's constants with deferred functions
+// that hold onto their IR, so they're only compiled once they're called.
+// Functions nested inside of those are deferred in turn when their parent is
+// compiled
+pub(crate) fn defer_function_constants(block: SSABlock<()>) -> SSABlock<()> {
+  let constants = block
+    .constants
+    .iter()
+    .map(|constant| match constant {
+      CompositeFn(f) => match &f.body {
+        FunctionBody::Compiled(body) => CompositeFn(Rc::new(
+          GenericCompositeFunction::deferred(f.args.clone(), body.clone()),
+        )),
+        FunctionBody::Deferred(_) => constant.clone(),
+      },
+      constant => constant.clone(),
+    })
+    .collect();
+  SSABlock::new(block.instructions.to_vec(), constants)
+}
+
+// Compiles the IR of a deferred function. The passes only know how to compile
+// functions that are constants of another block, so the function is wrapped in
+// a block that does nothing but return it
+pub(crate) fn deferred_fn_to_bytecode(
+  args: AritySpecifier,
+  ir: SSABlock<()>,
+) -> IntermediateCompilationResult<Block> {
+  let wrapper = SSABlock::new(
+    vec![Const(0, 0), Return(0)],
+    vec![SSAValue::composite_fn(args, defer_function_constants(ir))],
+  );
+  match &raw_ir_to_bytecode(wrapper)?.constants[0] {
+    CompositeFn(f) => match &f.body {
+      FunctionBody::Compiled(block) => Ok(block.clone()),
+      FunctionBody::Deferred(_) => unreachable!(),
+    },
+    _ => unreachable!(),
+  }
+}
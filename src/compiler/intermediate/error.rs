@@ -19,6 +19,9 @@ pub enum IntermediateCompilationError {
     SSARegister,
     InstructionTimestamp,
   ),
+  UnexpectedBranch(InstructionTimestamp),
+  UnclosedIf(InstructionTimestamp),
+  UnstructuredJump(InstructionTimestamp),
 }
 impl Display for IntermediateCompilationError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -51,6 +54,19 @@ impl Display for IntermediateCompilationError {
          but the register as already replaced by {already_replaced_register} at
          timestamp {already_replaced_timestamp}"
       ),
+      UnexpectedBranch(timestamp) => write!(
+        f,
+        "encountered an Else, ElseIf, or EndIf without a matching If at \
+         timestamp {timestamp}"
+      ),
+      UnclosedIf(timestamp) => {
+        write!(f, "the If at timestamp {timestamp} has no matching EndIf")
+      }
+      UnstructuredJump(timestamp) => write!(
+        f,
+        "encountered a jump at timestamp {timestamp}, but jumps can't be \
+         used before the control flow graph is lowered"
+      ),
     }
   }
 }
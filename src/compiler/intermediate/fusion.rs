@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use crate::{
+  instructions::GenericInstruction::*,
+  runtime::{
+    control::Block,
+    evaluation::{Instruction, Register},
+  },
+};
+
+use super::{
+  error::IntermediateCompilationResult, register_allocation::get_max_register,
+};
+
+// The position an instruction jumps to, if it's a jump
+fn jump_target(position: usize, instruction: &Instruction) -> Option<usize> {
+  match instruction {
+    Jump(target)
+    | JumpIf(_, target)
+    | JumpIfNot(_, target)
+    | IncAndJumpIfPos(_, target) => Some(*target as usize),
+    RelativeJump(offset) => {
+      Some((position as isize + *offset as isize) as usize)
+    }
+    _ => None,
+  }
+}
+
+// Whether the value in `register` can't be read by any instruction from
+// `start` onwards before it's overwritten. Jumps are followed down both of
+// their paths, but structured branches conservatively count as a read
+fn is_dead_from(
+  instructions: &[Instruction],
+  start: usize,
+  register: Register,
+) -> bool {
+  let mut visited = HashSet::new();
+  let mut pending = vec![start];
+  while let Some(mut position) = pending.pop() {
+    while visited.insert(position) {
+      let Some(instruction) = instructions.get(position) else {
+        break;
+      };
+      let mut usages = instruction.usages();
+      if usages.inputs.any(|input| input == register)
+        || usages
+          .replacements
+          .any(|replacement| replacement == register)
+      {
+        return false;
+      }
+      if usages.outputs.any(|output| output == register) {
+        break;
+      }
+      match instruction {
+        Return(_) | CallAndReturn(..) | ApplyAndReturn(..) => break,
+        Jump(_) | RelativeJump(_) => {
+          position = jump_target(position, instruction).unwrap();
+          continue;
+        }
+        JumpIf(..) | JumpIfNot(..) | IncAndJumpIfPos(..) => {
+          pending.push(jump_target(position, instruction).unwrap())
+        }
+        If(_) | Else | ElseIf(_) | EndIf | Yield(_) | YieldAndAccept(..)
+        | DebugPrint(_) => return false,
+        _ => {}
+      }
+      position += 1;
+    }
+  }
+  true
+}
+
+// Tries to fuse the instructions starting at `position` into a single
+// instruction, returning it along with the number of instructions it replaces
+fn fuse_at(
+  instructions: &[Instruction],
+  position: usize,
+) -> Option<(Instruction, usize)> {
+  match &instructions[position..] {
+    [Const(constant, const_index), Add(result, a, b), ..] => {
+      let other = match (a == constant, b == constant) {
+        (true, false) => *b,
+        (false, true) => *a,
+        _ => return None,
+      };
+      (result == constant
+        || is_dead_from(instructions, position + 2, *constant))
+      .then_some((ConstAdd(*result, *const_index, other), 2))
+    }
+    [Const(constant, const_index), Get(result, collection, key), ..]
+      if key == constant && collection != constant =>
+    {
+      (result == constant
+        || is_dead_from(instructions, position + 2, *constant))
+      .then_some((GetConstKey(*result, *collection, *const_index), 2))
+    }
+    [Inc(num, num_input), IsPos(cond, pos_input), JumpIf(jump_cond, target), ..]
+      if num == num_input
+        && num == pos_input
+        && cond == jump_cond
+        && cond != num =>
+    {
+      (is_dead_from(instructions, position + 3, *cond)
+        && is_dead_from(instructions, *target as usize, *cond))
+      .then_some((IncAndJumpIfPos(*num, *target), 3))
+    }
+    _ => None,
+  }
+}
+
+// Replaces common sequences of instructions with single fused instructions,
+// to cut down on dispatch overhead in arithmetic- and loop-heavy code. This
+// runs after register allocation, so the SSA layer never sees the fused
+// instructions
+pub(crate) fn fuse_instructions(
+  block: Block,
+) -> IntermediateCompilationResult<Block> {
+  block.translate(&|_, instructions, constants, _| {
+    let jump_targets: HashSet<usize> = instructions
+      .iter()
+      .enumerate()
+      .filter_map(|(position, instruction)| jump_target(position, instruction))
+      .collect();
+    let mut fused = vec![];
+    let mut origins = vec![];
+    let mut new_positions = vec![0; instructions.len() + 1];
+    let mut position = 0;
+    while position < instructions.len() {
+      let (instruction, replaced) = fuse_at(&instructions, position)
+        .filter(|(_, replaced)| {
+          // a jump into the middle of a fused sequence would have nowhere to
+          // land
+          (position + 1..position + replaced)
+            .all(|inner| !jump_targets.contains(&inner))
+        })
+        .unwrap_or_else(|| (instructions[position].clone(), 1));
+      new_positions[position..position + replaced].fill(fused.len());
+      origins.push(position);
+      fused.push(instruction);
+      position += replaced;
+    }
+    new_positions[instructions.len()] = fused.len();
+    for (new_position, instruction) in fused.iter_mut().enumerate() {
+      let Some(old_target) = jump_target(origins[new_position], instruction)
+      else {
+        continue;
+      };
+      let target = new_positions[old_target];
+      *instruction = match instruction.clone() {
+        Jump(_) => Jump(target as u16),
+        JumpIf(condition, _) => JumpIf(condition, target as u16),
+        JumpIfNot(condition, _) => JumpIfNot(condition, target as u16),
+        IncAndJumpIfPos(num, _) => IncAndJumpIfPos(num, target as u16),
+        RelativeJump(_) => {
+          RelativeJump((target as isize - new_position as isize) as i16)
+        }
+        other => other,
+      };
+    }
+    let max_register = get_max_register(&fused);
+    Ok(Block::new_with_metadata(fused, constants, max_register))
+  })
+}
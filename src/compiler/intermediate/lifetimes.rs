@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
   blocks::GenericBlock,
-  compiler::{SSABlock, SSAInstruction, SSARegister},
+  compiler::{SSABlock, SSARegister},
 };
 
 use super::{
+  control_flow::{SSABasicBlock, SSAGraph, Step},
   error::{IntermediateCompilationError, IntermediateCompilationResult},
   InstructionTimestamp,
 };
@@ -14,7 +15,6 @@ use super::{
 pub struct RegisterLifetime {
   pub(crate) creation: Option<InstructionTimestamp>,
   usages: Vec<InstructionTimestamp>,
-  pub(crate) replacing: Option<SSARegister>,
   pub(crate) replaced_by: Option<SSARegister>,
 }
 impl RegisterLifetime {
@@ -22,7 +22,6 @@ impl RegisterLifetime {
     Self {
       creation: None,
       usages: vec![],
-      replacing: None,
       replaced_by: None,
     }
   }
@@ -30,18 +29,6 @@ impl RegisterLifetime {
     Self {
       creation: Some(creation_timestamp),
       usages: vec![],
-      replacing: None,
-      replaced_by: None,
-    }
-  }
-  fn new_replacing(
-    creation_timestamp: InstructionTimestamp,
-    replacing: SSARegister,
-  ) -> Self {
-    Self {
-      creation: Some(creation_timestamp),
-      usages: vec![],
-      replacing: Some(replacing),
       replaced_by: None,
     }
   }
@@ -54,9 +41,50 @@ impl RegisterLifetime {
 }
 pub(crate) type Lifetimes = HashMap<SSARegister, RegisterLifetime>;
 
+fn record_usage(
+  lifetimes: &mut Lifetimes,
+  register: SSARegister,
+  timestamp: InstructionTimestamp,
+) -> IntermediateCompilationResult<()> {
+  if let Some(lifetime) = lifetimes.get_mut(&register) {
+    if let Some(replaced_by) = lifetime.replaced_by {
+      return Err(IntermediateCompilationError::UsedAfterReplacement(
+        register,
+        timestamp,
+        replaced_by,
+        lifetime.last_usage().unwrap(),
+      ));
+    }
+    lifetime.usages.push(timestamp);
+    Ok(())
+  } else {
+    Err(IntermediateCompilationError::UsedBeforeCreation(
+      register, timestamp,
+    ))
+  }
+}
+
+fn record_creation(
+  lifetimes: &mut Lifetimes,
+  register: SSARegister,
+  lifetime: RegisterLifetime,
+  timestamp: InstructionTimestamp,
+) -> IntermediateCompilationResult<()> {
+  if let Some(existing_lifetime) = lifetimes.get(&register) {
+    Err(IntermediateCompilationError::OutputToExisting(
+      register,
+      existing_lifetime.creation,
+      timestamp,
+    ))
+  } else {
+    lifetimes.insert(register, lifetime);
+    Ok(())
+  }
+}
+
 pub(crate) fn calculate_register_lifetimes(
   preallocated_registers: u8,
-  instructions: &Vec<SSAInstruction>,
+  graph: &SSAGraph,
 ) -> IntermediateCompilationResult<Lifetimes> {
   let mut lifetimes: Lifetimes = Lifetimes::new();
   for preallocated_register in 0..preallocated_registers {
@@ -65,37 +93,39 @@ pub(crate) fn calculate_register_lifetimes(
       RegisterLifetime::new_preexisting(),
     );
   }
-  for (timestamp, instruction) in instructions.iter().enumerate() {
+  for (timestamp, step) in graph.steps().enumerate() {
     let timestamp = timestamp as InstructionTimestamp;
-    let usages = instruction.usages();
-    for input_register in usages.inputs {
-      if let Some(lifetime) = lifetimes.get_mut(&input_register) {
-        if let Some(replaced_by) = lifetime.replaced_by {
-          return Err(IntermediateCompilationError::UsedAfterReplacement(
-            input_register,
+    let instruction = match step {
+      Step::Parameters(parameters) => {
+        for parameter in parameters {
+          record_creation(
+            &mut lifetimes,
+            *parameter,
+            RegisterLifetime::new(timestamp),
             timestamp,
-            replaced_by,
-            lifetime.last_usage().unwrap(),
-          ));
+          )?;
         }
-        lifetime.usages.push(timestamp);
-      } else {
-        return Err(IntermediateCompilationError::UsedBeforeCreation(
-          input_register,
-          timestamp,
-        ));
+        continue;
       }
+      Step::Terminator(terminator) => {
+        for input_register in terminator.inputs() {
+          record_usage(&mut lifetimes, *input_register, timestamp)?;
+        }
+        continue;
+      }
+      Step::Instruction(instruction) => instruction,
+    };
+    let usages = instruction.usages();
+    for input_register in usages.inputs {
+      record_usage(&mut lifetimes, input_register, timestamp)?;
     }
     for output_register in usages.outputs {
-      if let Some(existing_lifetime) = lifetimes.get(&output_register) {
-        return Err(IntermediateCompilationError::OutputToExisting(
-          output_register,
-          existing_lifetime.creation,
-          timestamp,
-        ));
-      } else {
-        lifetimes.insert(output_register, RegisterLifetime::new(timestamp));
-      }
+      record_creation(
+        &mut lifetimes,
+        output_register,
+        RegisterLifetime::new(timestamp),
+        timestamp,
+      )?;
     }
     for (from_register, to_register) in usages.replacements {
       if let Some(from_lifetime) = lifetimes.get_mut(&from_register) {
@@ -116,33 +146,95 @@ pub(crate) fn calculate_register_lifetimes(
           timestamp,
         ));
       }
-      if let Some(to_lifetime) = lifetimes.get(&to_register) {
-        return Err(IntermediateCompilationError::OutputToExisting(
-          to_register,
-          to_lifetime.creation,
-          timestamp,
-        ));
-      } else {
-        lifetimes.insert(
-          to_register,
-          RegisterLifetime::new_replacing(timestamp, from_register),
-        );
-      }
+      record_creation(
+        &mut lifetimes,
+        to_register,
+        RegisterLifetime::new(timestamp),
+        timestamp,
+      )?;
     }
   }
   Ok(lifetimes)
 }
 
-pub fn track_register_lifetimes<M: Clone>(
-  block: SSABlock<M>,
-) -> Result<SSABlock<Lifetimes>, IntermediateCompilationError> {
-  block.translate(&|preallocated_registers, instructions, constants, _| {
-    let lifetimes =
-      calculate_register_lifetimes(preallocated_registers, &instructions)?;
+// The registers that are live on entry to and on exit from each basic block.
+// Unlike a lifetime, which spans from a register's creation to its last usage
+// in layout order, this follows the edges of the graph, so a register that's
+// only used in one arm of a branch isn't live in the other arm
+#[derive(Clone, Debug, Default)]
+pub struct Liveness {
+  pub(crate) live_in: Vec<HashSet<SSARegister>>,
+  pub(crate) live_out: Vec<HashSet<SSARegister>>,
+}
+
+// Walks backwards through a basic block from the registers that are live when
+// it exits, returning the registers that are live after each of its
+// instructions, along with the registers that are live on entry (including
+// any of its parameters that get used)
+pub(crate) fn live_after_instructions(
+  block: &SSABasicBlock,
+  live_out: &HashSet<SSARegister>,
+) -> (HashSet<SSARegister>, Vec<HashSet<SSARegister>>) {
+  let mut live = live_out.clone();
+  live.extend(block.terminator.inputs().iter().copied());
+  let mut live_after = vec![HashSet::new(); block.instructions.len()];
+  for (i, instruction) in block.instructions.iter().enumerate().rev() {
+    live_after[i] = live.clone();
+    let usages = instruction.usages();
+    for output in usages.outputs {
+      live.remove(&output);
+    }
+    for (from, to) in usages.replacements {
+      live.remove(&to);
+      live.insert(from);
+    }
+    live.extend(usages.inputs);
+  }
+  (live, live_after)
+}
+
+pub(crate) fn calculate_liveness(graph: &SSAGraph) -> Liveness {
+  let block_count = graph.blocks.len();
+  let mut liveness = Liveness {
+    live_in: vec![HashSet::new(); block_count],
+    live_out: vec![HashSet::new(); block_count],
+  };
+  loop {
+    let mut changed = false;
+    for (id, block) in graph.blocks.iter().enumerate().rev() {
+      let live_out: HashSet<SSARegister> = block
+        .terminator
+        .successors()
+        .flat_map(|successor| liveness.live_in[successor].iter().copied())
+        .collect();
+      let (mut live_in, _) = live_after_instructions(block, &live_out);
+      for parameter in block.parameters.iter() {
+        live_in.remove(parameter);
+      }
+      if live_in != liveness.live_in[id] || live_out != liveness.live_out[id] {
+        liveness.live_in[id] = live_in;
+        liveness.live_out[id] = live_out;
+        changed = true;
+      }
+    }
+    if !changed {
+      break liveness;
+    }
+  }
+}
+
+// Checks that every register is created before it's used and isn't used after
+// it's been replaced, then works out where each register is live
+pub fn track_register_lifetimes(
+  block: SSABlock<SSAGraph>,
+) -> IntermediateCompilationResult<SSABlock<(SSAGraph, Liveness)>> {
+  block.translate(&|preallocated_registers, instructions, constants, graph| {
+    calculate_register_lifetimes(preallocated_registers, &graph)?;
+    let liveness = calculate_liveness(&graph);
     Ok(GenericBlock::new_with_metadata(
       instructions,
       constants,
-      lifetimes,
+      (graph, liveness),
     ))
   })
 }
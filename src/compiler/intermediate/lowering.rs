@@ -0,0 +1,115 @@
+use crate::{
+  blocks::{BlockBuilder, GenericBlock},
+  instructions::GenericInstruction::*,
+  runtime::{control::Block, evaluation::Register},
+};
+
+use super::{
+  control_flow::{Step, Terminator},
+  error::IntermediateCompilationResult,
+  register_allocation::AllocatedGraph,
+};
+
+fn get_max_graph_register(graph: &AllocatedGraph) -> Register {
+  graph
+    .steps()
+    .filter_map(|step| match step {
+      Step::Parameters(parameters) => parameters.iter().copied().max(),
+      Step::Instruction(instruction) => {
+        let usages = instruction.usages();
+        usages
+          .inputs
+          .chain(usages.outputs)
+          .chain(usages.replacements)
+          .max()
+      }
+      Step::Terminator(terminator) => terminator.inputs().iter().copied().max(),
+    })
+    .max()
+    .unwrap_or(0)
+}
+
+// Copies the arguments of a jump into the parameters of the block it jumps
+// to. The copies all happen at once as far as the target block can tell, so
+// they're ordered such that no argument is overwritten before it's read, and
+// cycles are broken by moving one of the values into a scratch register
+fn push_parallel_copies(
+  builder: &mut BlockBuilder,
+  parameters: &[Register],
+  arguments: &[Register],
+  scratch_register: impl Fn() -> Register,
+) {
+  let mut copies: Vec<(Register, Register)> = parameters
+    .iter()
+    .copied()
+    .zip(arguments.iter().copied())
+    .filter(|(parameter, argument)| parameter != argument)
+    .collect();
+  while !copies.is_empty() {
+    if let Some(ready) = copies.iter().position(|(parameter, _)| {
+      copies.iter().all(|(_, argument)| argument != parameter)
+    }) {
+      let (parameter, argument) = copies.remove(ready);
+      builder.push_instruction(Copy(parameter, argument));
+    } else {
+      let (parameter, _) = copies[0];
+      let scratch = scratch_register();
+      builder.push_instruction(Copy(scratch, parameter));
+      for (_, argument) in copies.iter_mut() {
+        if *argument == parameter {
+          *argument = scratch;
+        }
+      }
+    }
+  }
+}
+
+// Lays the blocks of each graph out in order, turning their terminators into
+// jumps. Jumps to the block that immediately follows are left out
+pub(crate) fn lower_control_flow_graphs(
+  block: GenericBlock<Register, Register, Register, AllocatedGraph>,
+) -> IntermediateCompilationResult<Block> {
+  block.translate(&|_, _, constants, graph| {
+    let mut builder = BlockBuilder::new();
+    for constant in constants {
+      builder.add_constant(constant);
+    }
+    let labels: Vec<_> =
+      graph.blocks.iter().map(|_| builder.new_label()).collect();
+    for (id, basic_block) in graph.blocks.iter().enumerate() {
+      builder.place_label(labels[id]);
+      for instruction in basic_block.instructions.iter() {
+        builder.push_instruction(instruction.clone());
+      }
+      match &basic_block.terminator {
+        Terminator::Exit => {}
+        Terminator::Jump(target, arguments) => {
+          push_parallel_copies(
+            &mut builder,
+            &graph.blocks[*target].parameters,
+            arguments,
+            || get_max_graph_register(&graph) + 1,
+          );
+          if *target != id + 1 {
+            builder.push_jump(labels[*target]);
+          }
+        }
+        Terminator::Branch {
+          condition,
+          then,
+          otherwise,
+        } => {
+          if *then == id + 1 {
+            builder.push_jump_if_not(*condition, labels[*otherwise]);
+          } else {
+            builder.push_jump_if(*condition, labels[*then]);
+            if *otherwise != id + 1 {
+              builder.push_jump(labels[*otherwise]);
+            }
+          }
+        }
+      }
+    }
+    Ok(builder.finish())
+  })
+}
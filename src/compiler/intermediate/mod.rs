@@ -1,36 +1,64 @@
+pub mod argument_stealing;
 pub mod cleanup;
+pub mod control_flow;
 pub mod core_inlining;
+pub mod deferral;
 pub mod error;
+pub mod fusion;
 pub mod lifetimes;
+pub mod lowering;
 pub mod register_allocation;
+pub mod uniqueness;
 
 use crate::runtime::control::Block;
 
 use self::{
-  cleanup::erase_unused_constants, core_inlining::inline_core_fn_calls,
-  error::IntermediateCompilationResult, lifetimes::track_register_lifetimes,
+  argument_stealing::steal_dying_arguments,
+  cleanup::erase_unused_constants,
+  control_flow::{build_control_flow_graphs, SSAGraph, Step},
+  core_inlining::inline_core_fn_calls,
+  error::IntermediateCompilationResult,
+  fusion::fuse_instructions,
+  lifetimes::track_register_lifetimes,
+  lowering::lower_control_flow_graphs,
   register_allocation::allocate_registers,
+  uniqueness::mark_unique_list_updates,
 };
 
-use super::{SSABlock, SSAInstruction, SSARegister};
+use super::{SSABlock, SSARegister};
 
 pub(crate) type InstructionTimestamp = u16;
 
 fn get_max_ssa_register(
   preallocated_registers: u8,
-  instructions: &Vec<SSAInstruction>,
+  graph: &SSAGraph,
 ) -> SSARegister {
   let mut max_register =
     preallocated_registers.checked_sub(1).unwrap_or(0) as SSARegister;
-  for usage in instructions.iter().map(|instruction| instruction.usages()) {
-    for input in usage.inputs {
-      max_register = max_register.max(input)
-    }
-    for output in usage.outputs {
-      max_register = max_register.max(output)
-    }
-    for (old, new) in usage.replacements {
-      max_register = max_register.max(old).max(new)
+  for step in graph.steps() {
+    match step {
+      Step::Parameters(parameters) => {
+        for parameter in parameters {
+          max_register = max_register.max(*parameter)
+        }
+      }
+      Step::Instruction(instruction) => {
+        let usage = instruction.usages();
+        for input in usage.inputs {
+          max_register = max_register.max(input)
+        }
+        for output in usage.outputs {
+          max_register = max_register.max(output)
+        }
+        for (old, new) in usage.replacements {
+          max_register = max_register.max(old).max(new)
+        }
+      }
+      Step::Terminator(terminator) => {
+        for input in terminator.inputs() {
+          max_register = max_register.max(*input)
+        }
+      }
     }
   }
   max_register
@@ -39,7 +67,11 @@ fn get_max_ssa_register(
 pub(crate) fn raw_ir_to_bytecode(
   raw_ir: SSABlock<()>,
 ) -> IntermediateCompilationResult<Block> {
-  allocate_registers(track_register_lifetimes(erase_unused_constants(
-    inline_core_fn_calls(raw_ir)?,
+  fuse_instructions(lower_control_flow_graphs(allocate_registers(
+    steal_dying_arguments(track_register_lifetimes(
+      mark_unique_list_updates(erase_unused_constants(inline_core_fn_calls(
+        build_control_flow_graphs(raw_ir)?,
+      )?)?)?,
+    )?)?,
   )?)?)
 }
@@ -1,16 +1,18 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
-  compiler::{
-    intermediate::error::IntermediateCompilationError, SSABlock, SSARegister,
-  },
-  runtime::{
-    control::Block,
-    evaluation::{Instruction, Register},
-  },
+  blocks::GenericBlock,
+  compiler::{SSABlock, SSARegister},
+  runtime::evaluation::{Instruction, Register},
 };
 
-use super::lifetimes::Lifetimes;
+use super::{
+  control_flow::{BasicBlock, ControlFlowGraph, SSAGraph},
+  error::IntermediateCompilationResult,
+  lifetimes::{live_after_instructions, Liveness},
+};
+
+pub type AllocatedGraph = ControlFlowGraph<Register, Register, Register>;
 
 pub(crate) fn get_max_register(instructions: &Vec<Instruction>) -> Register {
   let mut max_register = 0;
@@ -28,119 +30,109 @@ pub(crate) fn get_max_register(instructions: &Vec<Instruction>) -> Register {
   max_register
 }
 
+fn take_free_register(
+  taken_runtime_registers: &mut HashSet<Register>,
+) -> Register {
+  let register = (0..Register::MAX)
+    .find(|i| !taken_runtime_registers.contains(i))
+    .expect("Failed to find unused register");
+  taken_runtime_registers.insert(register);
+  register
+}
+
+// Assigns each SSA register a runtime register that isn't held by any other
+// register that's live at the same time. The blocks are visited in layout
+// order, which puts every block after the blocks that its live registers are
+// created in, so only the registers that are live on entry to a block need to
+// be kept out of the way of the ones created inside of it. Registers that are
+// only live in one arm of a branch can therefore share runtime registers with
+// registers in the other arm
 pub(crate) fn allocate_registers(
-  block: SSABlock<Lifetimes>,
-) -> Result<Block, IntermediateCompilationError> {
-  block.translate(&|preallocated_registers,
-                    instructions,
-                    constants,
-                    lifetimes| {
-    let mut ssa_to_runtime_registers: HashMap<SSARegister, Register> =
-      HashMap::new();
-    let mut taken_runtime_registers: HashSet<Register> = HashSet::new();
-    for preallocated_register in 0..preallocated_registers {
-      ssa_to_runtime_registers
-        .insert(preallocated_register as usize, preallocated_register);
-      taken_runtime_registers.insert(preallocated_register);
-    }
-    let mut translated_instructions = vec![];
-    for (timestamp, instruction) in instructions.iter().enumerate() {
-      let timestamp = timestamp as u16;
-      let mut finished_ssa_to_runtime_registers: HashMap<
-        SSARegister,
-        Register,
-      > = HashMap::new();
-      let finished_ssa_registers: Vec<SSARegister> = ssa_to_runtime_registers
+  block: SSABlock<(SSAGraph, Liveness)>,
+) -> IntermediateCompilationResult<
+  GenericBlock<Register, Register, Register, AllocatedGraph>,
+> {
+  block.translate(&|preallocated_registers, _, constants, (graph, liveness)| {
+    let mut ssa_to_runtime_registers: HashMap<SSARegister, Register> = (0
+      ..preallocated_registers)
+      .map(|register| (register as SSARegister, register))
+      .collect();
+    let mut blocks = vec![];
+    for (id, basic_block) in graph.blocks.into_iter().enumerate() {
+      let runtime_register = |ssa_register: &SSARegister| -> Register {
+        *ssa_to_runtime_registers
+          .get(ssa_register)
+          .unwrap_or_else(|| {
+            panic!(
+            "no real register found for ssa register {ssa_register} in block \
+            {id}"
+          )
+          })
+      };
+      let (live_on_entry, live_after) =
+        live_after_instructions(&basic_block, &liveness.live_out[id]);
+      let mut taken_runtime_registers: HashSet<Register> =
+        liveness.live_in[id].iter().map(runtime_register).collect();
+      // the jumps into the block write all of its parameters at once, so they
+      // can't share registers even if they're unused
+      let parameters: Vec<Register> = basic_block
+        .parameters
         .iter()
-        .filter_map(|(ssa_register, _)| {
-          let lifetime = lifetimes.get(&ssa_register).unwrap();
-          if lifetime.replaced_by.is_none() {
-            lifetime
-              .last_usage()
-              .map(|last_usage| {
-                if last_usage == timestamp {
-                  Some(*ssa_register)
-                } else {
-                  None
-                }
-              })
-              .flatten()
-          } else {
-            None
-          }
-        })
+        .map(|_| take_free_register(&mut taken_runtime_registers))
         .collect();
-      for finished_ssa_register in finished_ssa_registers {
-        let finised_runtime_register = ssa_to_runtime_registers
-          .remove(&finished_ssa_register)
-          .unwrap();
-        finished_ssa_to_runtime_registers
-          .insert(finished_ssa_register, finised_runtime_register);
-        let removed = taken_runtime_registers.remove(&finised_runtime_register);
-        #[cfg(debug_assertions)]
-        assert!(removed)
+      for (parameter, register) in
+        basic_block.parameters.iter().zip(parameters.iter())
+      {
+        ssa_to_runtime_registers.insert(*parameter, *register);
+        if !live_on_entry.contains(parameter) {
+          taken_runtime_registers.remove(register);
+        }
       }
-      for (ssa_registser, register_lifetime) in lifetimes.iter() {
-        if register_lifetime.creation == Some(timestamp)
-          && (timestamp != 0
-            || *ssa_registser >= preallocated_registers as usize)
-        {
-          if let Some(replaced_ssa_registser) = register_lifetime.replacing {
-            let register = ssa_to_runtime_registers
-              .remove(&replaced_ssa_registser)
-              .expect("Didn't find register when trying to replace");
-            ssa_to_runtime_registers.insert(*ssa_registser, register);
-          } else {
-            let min_unused_register = (0..Register::MAX)
-              .filter(|i| !taken_runtime_registers.contains(i))
-              .next()
-              .expect("Failed to find unused register");
-            let replaced_register = ssa_to_runtime_registers
-              .insert(*ssa_registser, min_unused_register);
-            #[cfg(debug_assertions)]
-            assert!(replaced_register.is_none());
-            let register_free =
-              taken_runtime_registers.insert(min_unused_register);
-            #[cfg(debug_assertions)]
-            assert!(register_free);
+      let mut translated_instructions = vec![];
+      for (instruction, live_after) in
+        basic_block.instructions.iter().zip(live_after)
+      {
+        let usages = instruction.usages();
+        // the registers of inputs that aren't needed anymore are freed before
+        // the outputs are assigned, so an output can reuse one of them
+        for input in usages.inputs {
+          if !live_after.contains(&input) {
+            taken_runtime_registers.remove(&ssa_to_runtime_registers[&input]);
           }
         }
+        for (from, to) in usages.replacements {
+          let register = ssa_to_runtime_registers[&from];
+          ssa_to_runtime_registers.insert(to, register);
+          if !live_after.contains(&to) {
+            taken_runtime_registers.remove(&register);
+          }
+        }
+        for output in usages.outputs {
+          let register = take_free_register(&mut taken_runtime_registers);
+          ssa_to_runtime_registers.insert(output, register);
+          if !live_after.contains(&output) {
+            taken_runtime_registers.remove(&register);
+          }
+        }
+        translated_instructions.push(instruction.clone().translate(
+          |input| ssa_to_runtime_registers[&input],
+          |output| ssa_to_runtime_registers[&output],
+          |(_, to)| ssa_to_runtime_registers[&to],
+        ));
       }
-      translated_instructions.push(instruction.clone().translate(
-        |input: usize| -> u8 {
-          *ssa_to_runtime_registers
-            .get(&input)
-            .or_else(|| finished_ssa_to_runtime_registers.get(&input))
-            .unwrap_or_else(|| {
-              panic!(
-                "no current real register found for input ssa register \
-                {input} at timestamp {timestamp}"
-              )
-            })
-        },
-        |output: usize| -> u8 {
-          *ssa_to_runtime_registers.get(&output).unwrap_or_else(|| {
-            panic!(
-              "no current real register found for output ssa register \
-              {output} at timestamp {timestamp}"
-            )
-          })
-        },
-        |(input, output): (usize, usize)| -> u8 {
-          *ssa_to_runtime_registers.get(&output).unwrap_or_else(|| {
-            panic!(
-              "no current real register found for replacable ssa register \
-              ({input} => {output}) at timestamp {timestamp}\n"
-            )
-          })
-        },
-      ));
+      let terminator = basic_block
+        .terminator
+        .translate(|input| ssa_to_runtime_registers[&input]);
+      blocks.push(BasicBlock {
+        parameters,
+        instructions: translated_instructions,
+        terminator,
+      });
     }
-    let max_register = get_max_register(&translated_instructions);
-    Ok(Block::new_with_metadata(
-      translated_instructions,
+    Ok(GenericBlock::new_with_metadata(
+      vec![],
       constants,
-      max_register,
+      AllocatedGraph { blocks },
     ))
   })
 }
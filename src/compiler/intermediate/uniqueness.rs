@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use crate::{
+  blocks::GenericBlock,
+  compiler::{SSABlock, SSAInstruction, SSARegister},
+  instructions::GenericInstruction::*,
+};
+
+use super::{
+  control_flow::{SSAGraph, Step},
+  error::IntermediateCompilationResult,
+};
+
+// Whether an instruction only looks at its inputs, without keeping a
+// reference to any of them around
+fn only_reads_inputs(instruction: &SSAInstruction) -> bool {
+  matches!(
+    instruction,
+    First(..) | Last(..) | IsEmpty(..) | Count(..) | Get(..)
+  )
+}
+
+// The lists that are guaranteed to be the only reference to their value. A
+// list is unique if it was freshly created by an `EmptyList`, or by updating
+// another unique list, and no instruction ever copies it anywhere else. Block
+// parameters are never considered unique, since the jumps that pass arguments
+// to them copy the arguments
+fn find_unique_lists(graph: &SSAGraph) -> HashSet<SSARegister> {
+  let mut aliased = HashSet::new();
+  for step in graph.steps() {
+    match step {
+      Step::Parameters(_) => {}
+      Step::Instruction(instruction) => {
+        if !only_reads_inputs(instruction) {
+          aliased.extend(instruction.usages().inputs)
+        }
+      }
+      Step::Terminator(terminator) => {
+        aliased.extend(terminator.inputs().iter().copied())
+      }
+    }
+  }
+  let mut unique = HashSet::new();
+  for step in graph.steps() {
+    if let Step::Instruction(instruction) = step {
+      let created = match instruction {
+        EmptyList(list) => Some(*list),
+        Push((from, to), _)
+        | Cons((from, to), _)
+        | Rest((from, to))
+        | ButLast((from, to))
+          if unique.contains(from) =>
+        {
+          Some(*to)
+        }
+        _ => None,
+      };
+      if let Some(list) = created.filter(|list| !aliased.contains(list)) {
+        unique.insert(list);
+      }
+    }
+  }
+  unique
+}
+
+// Marks the list updates whose list is known to be unique, so that the VM can
+// change them in place without checking whether the list is shared first
+pub(crate) fn mark_unique_list_updates(
+  block: SSABlock<SSAGraph>,
+) -> IntermediateCompilationResult<SSABlock<SSAGraph>> {
+  block.translate(&|_, instructions, constants, mut graph| {
+    let unique = find_unique_lists(&graph);
+    for basic_block in graph.blocks.iter_mut() {
+      for instruction in basic_block.instructions.iter_mut() {
+        *instruction = match instruction.clone() {
+          Push(list, value) if unique.contains(&list.0) => {
+            PushUnique(list, value)
+          }
+          Cons(list, value) if unique.contains(&list.0) => {
+            ConsUnique(list, value)
+          }
+          Rest(list) if unique.contains(&list.0) => RestUnique(list),
+          ButLast(list) if unique.contains(&list.0) => ButLastUnique(list),
+          instruction => instruction,
+        }
+      }
+    }
+    Ok(GenericBlock::new_with_metadata(
+      instructions,
+      constants,
+      graph,
+    ))
+  })
+}
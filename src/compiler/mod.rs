@@ -1,5 +1,6 @@
 pub mod ast;
 pub mod intermediate;
+pub mod reference;
 
 use crate::{
   blocks::GenericBlock, instructions::GenericInstruction,
@@ -18,29 +19,35 @@ impl SSABlock<()> {
     instructions: Vec<SSAInstruction>,
     constants: Vec<SSAValue<()>>,
   ) -> Self {
-    Self {
-      instructions: instructions.into(),
-      constants: constants.into(),
-      metadata: (),
-    }
+    Self::new_with_metadata(instructions, constants, ())
   }
 }
 
 #[cfg(test)]
 mod tests {
   use block_macros::{block, ssa_block};
+  use im_rc::vector;
   use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
   };
 
   use crate::{
+    blocks::GenericBlock,
     compiler::{
       ast::{parse::parse_sexp, token::SymbolLedger},
-      intermediate::raw_ir_to_bytecode,
+      intermediate::{
+        control_flow::{build_control_flow_graphs, BasicBlock, Terminator},
+        error::IntermediateCompilationError,
+        fusion::fuse_instructions,
+        lowering::lower_control_flow_graphs,
+        raw_ir_to_bytecode,
+        register_allocation::AllocatedGraph,
+        uniqueness::mark_unique_list_updates,
+      },
       SSABlock,
     },
-    instructions::GenericInstruction::*,
+    instructions::{Argument::*, GenericInstruction::*},
     runtime::control::Block,
     runtime::core_functions::CoreFnId,
     runtime::data::GenericValue::{self, *},
@@ -67,7 +74,7 @@ mod tests {
     let last_register = build_expression_ir(
       Expression::from_token_tree(ast.try_into()?, symbol_ledger)?
         .lift_lambdas(&HashSet::new(), symbol_ledger)?,
-      &|_| false,
+      &|_| None,
       &HashMap::new(),
       symbol_ledger,
       &mut 0,
@@ -125,15 +132,16 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::Add)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![Const(0, 1), Const(1, 2), Add(0, 0, 1), Return(0)])
+      (Block::new(
+        vec![Const(0, 0), ConstAdd(0, 1, 0), Return(0)],
+        vec![1.into(), 2.into()]
+      ))
     );
     test_output!(sexp, 3);
   }
@@ -147,9 +155,7 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::Subtract)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -169,9 +175,7 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::Multiply)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -191,9 +195,7 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::Divide)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -213,27 +215,19 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::Add)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Const(4, 3),
         Const(5, CoreFn(CoreFnId::Add)),
-        Call(6, 5, 2),
-        CopyArgument(3),
-        CopyArgument(4),
+        Call(6, 5, [CopyArgument(3), CopyArgument(4)]),
         Return(6)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![
-        Const(0, 1),
-        Const(1, 2),
-        Add(0, 0, 1),
-        Const(1, 3),
-        Add(0, 0, 1),
-        Return(0)
-      ])
+      (Block::new(
+        vec![Const(0, 0), ConstAdd(0, 1, 0), ConstAdd(0, 2, 0), Return(0)],
+        vec![1.into(), 2.into(), 3.into()]
+      ))
     );
     test_output!(sexp, 6);
   }
@@ -248,10 +242,7 @@ mod tests {
         Const(1, 2),
         Const(2, 3),
         Const(3, CoreFn(CoreFnId::Add)),
-        Call(4, 3, 3),
-        CopyArgument(0),
-        CopyArgument(1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(0), CopyArgument(1), CopyArgument(2)]),
         Return(4)
       ])
     );
@@ -281,12 +272,17 @@ mod tests {
         Const(3, 4),
         Const(4, 5),
         Const(5, CoreFn(CoreFnId::Add)),
-        Call(6, 5, 5),
-        CopyArgument(0),
-        CopyArgument(1),
-        CopyArgument(2),
-        CopyArgument(3),
-        CopyArgument(4),
+        Call(
+          6,
+          5,
+          [
+            CopyArgument(0),
+            CopyArgument(1),
+            CopyArgument(2),
+            CopyArgument(3),
+            CopyArgument(4)
+          ]
+        ),
         Return(6)
       ])
     );
@@ -318,10 +314,7 @@ mod tests {
         Const(1, 3),
         Const(2, 4),
         Const(3, CoreFn(CoreFnId::Multiply)),
-        Call(4, 3, 3),
-        CopyArgument(0),
-        CopyArgument(1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(0), CopyArgument(1), CopyArgument(2)]),
         Return(4)
       ])
     );
@@ -351,12 +344,17 @@ mod tests {
         Const(3, 5),
         Const(4, 6),
         Const(5, CoreFn(CoreFnId::Multiply)),
-        Call(6, 5, 5),
-        CopyArgument(0),
-        CopyArgument(1),
-        CopyArgument(2),
-        CopyArgument(3),
-        CopyArgument(4),
+        Call(
+          6,
+          5,
+          [
+            CopyArgument(0),
+            CopyArgument(1),
+            CopyArgument(2),
+            CopyArgument(3),
+            CopyArgument(4)
+          ]
+        ),
         Return(6)
       ])
     );
@@ -384,13 +382,16 @@ mod tests {
     test_raw_ir!(
       sexp,
       ssa_block![
-        Const(0, GenericValue::List(std::rc::Rc::new(vec![]))),
+        Const(0, GenericValue::List(std::rc::Rc::new(vector![]))),
         Return(0)
       ]
     );
     test_bytecode!(
       sexp,
-      block![Const(0, Value::List(std::rc::Rc::new(vec![]))), Return(0)]
+      block![
+        Const(0, Value::List(std::rc::Rc::new(vector![]))),
+        Return(0)
+      ]
     );
     test_output!(sexp, vec![]);
   }
@@ -402,7 +403,7 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Return(1)
       ])
     );
@@ -418,14 +419,13 @@ mod tests {
       (ssa_block![
         Const(0, 1),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Return(2)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![Const(0, 1), EmptyList(1), Push(1, 0), Return(1)])
+      (block![Const(0, 1), EmptyList(1), PushUnique(1, 0), Return(1)])
     );
     test_output!(sexp, (vec![1.into()]));
   }
@@ -440,10 +440,7 @@ mod tests {
         Const(1, 2),
         Const(2, 3),
         Const(3, CoreFn(CoreFnId::CreateList)),
-        Call(4, 3, 3),
-        CopyArgument(0),
-        CopyArgument(1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(0), CopyArgument(1), CopyArgument(2)]),
         Return(4)
       ])
     );
@@ -454,9 +451,9 @@ mod tests {
         Const(1, 2),
         Const(2, 3),
         EmptyList(3),
-        Push(3, 0),
-        Push(3, 1),
-        Push(3, 2),
+        PushUnique(3, 0),
+        PushUnique(3, 1),
+        PushUnique(3, 2),
         Return(3)
       ])
     );
@@ -471,8 +468,7 @@ mod tests {
       (ssa_block![
         Const(0, Nil),
         Const(1, CoreFn(CoreFnId::First)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Return(2)
       ])
     );
@@ -487,10 +483,9 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, CoreFn(CoreFnId::First)),
-        Call(3, 2, 1),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -506,11 +501,9 @@ mod tests {
       (ssa_block![
         Const(0, 1),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Const(3, CoreFn(CoreFnId::First)),
-        Call(4, 3, 1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(2)]),
         Return(4)
       ])
     );
@@ -519,7 +512,7 @@ mod tests {
       (block![
         Const(0, 1),
         EmptyList(1),
-        Push(1, 0),
+        PushUnique(1, 0),
         First(0, 1),
         Return(0)
       ])
@@ -535,8 +528,7 @@ mod tests {
       (ssa_block![
         Const(0, Nil),
         Const(1, CoreFn(CoreFnId::Last)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Return(2)
       ])
     );
@@ -551,10 +543,9 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, CoreFn(CoreFnId::Last)),
-        Call(3, 2, 1),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -570,17 +561,21 @@ mod tests {
       (ssa_block![
         Const(0, 1),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Const(3, CoreFn(CoreFnId::Last)),
-        Call(4, 3, 1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(2)]),
         Return(4)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![Const(0, 1), EmptyList(1), Push(1, 0), Last(0, 1), Return(0)])
+      (block![
+        Const(0, 1),
+        EmptyList(1),
+        PushUnique(1, 0),
+        Last(0, 1),
+        Return(0)
+      ])
     );
     test_output!(sexp, 1);
   }
@@ -593,8 +588,7 @@ mod tests {
       (ssa_block![
         Const(0, Nil),
         Const(1, CoreFn(CoreFnId::Rest)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Return(2)
       ])
     );
@@ -609,14 +603,13 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, CoreFn(CoreFnId::Rest)),
-        Call(3, 2, 1),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(1)]),
         Return(3)
       ])
     );
-    test_bytecode!(sexp, (block![EmptyList(0), Rest(0), Return(0)]));
+    test_bytecode!(sexp, (block![EmptyList(0), RestUnique(0), Return(0)]));
     test_output!(sexp, (vec![]));
   }
 
@@ -629,12 +622,9 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::CreateList)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Const(4, CoreFn(CoreFnId::Rest)),
-        Call(5, 4, 1),
-        CopyArgument(3),
+        Call(5, 4, [CopyArgument(3)]),
         Return(5)
       ])
     );
@@ -644,9 +634,9 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         EmptyList(2),
-        Push(2, 0),
-        Push(2, 1),
-        Rest(2),
+        PushUnique(2, 0),
+        PushUnique(2, 1),
+        RestUnique(2),
         Return(2)
       ])
     );
@@ -661,8 +651,7 @@ mod tests {
       (ssa_block![
         Const(0, Nil),
         Const(1, CoreFn(CoreFnId::ButLast)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Return(2)
       ])
     );
@@ -677,14 +666,13 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, CoreFn(CoreFnId::ButLast)),
-        Call(3, 2, 1),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(1)]),
         Return(3)
       ])
     );
-    test_bytecode!(sexp, (block![EmptyList(0), ButLast(0), Return(0)]));
+    test_bytecode!(sexp, (block![EmptyList(0), ButLastUnique(0), Return(0)]));
     test_output!(sexp, (vec![]));
   }
 
@@ -697,12 +685,9 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         Const(2, CoreFn(CoreFnId::CreateList)),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Const(4, CoreFn(CoreFnId::ButLast)),
-        Call(5, 4, 1),
-        CopyArgument(3),
+        Call(5, 4, [CopyArgument(3)]),
         Return(5)
       ])
     );
@@ -712,9 +697,9 @@ mod tests {
         Const(0, 1),
         Const(1, 2),
         EmptyList(2),
-        Push(2, 0),
-        Push(2, 1),
-        ButLast(2),
+        PushUnique(2, 0),
+        PushUnique(2, 1),
+        ButLastUnique(2),
         Return(2)
       ])
     );
@@ -728,18 +713,16 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, 1),
         Const(3, CoreFn(CoreFnId::Push)),
-        Call(4, 3, 2),
-        CopyArgument(1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(1), CopyArgument(2)]),
         Return(4)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![EmptyList(0), Const(1, 1), Push(0, 1), Return(0)])
+      (block![EmptyList(0), Const(1, 1), PushUnique(0, 1), Return(0)])
     );
     test_output!(sexp, (vec![1.into()]));
   }
@@ -752,13 +735,10 @@ mod tests {
       (ssa_block![
         Const(0, 1),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Const(3, 2),
         Const(4, CoreFn(CoreFnId::Push)),
-        Call(5, 4, 2),
-        CopyArgument(2),
-        CopyArgument(3),
+        Call(5, 4, [CopyArgument(2), CopyArgument(3)]),
         Return(5)
       ])
     );
@@ -767,9 +747,9 @@ mod tests {
       (block![
         Const(0, 1),
         EmptyList(1),
-        Push(1, 0),
+        PushUnique(1, 0),
         Const(0, 2),
-        Push(1, 0),
+        PushUnique(1, 0),
         Return(1)
       ])
     );
@@ -783,18 +763,16 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, 1),
         Const(3, CoreFn(CoreFnId::Cons)),
-        Call(4, 3, 2),
-        CopyArgument(1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(1), CopyArgument(2)]),
         Return(4)
       ])
     );
     test_bytecode!(
       sexp,
-      (block![EmptyList(0), Const(1, 1), Cons(0, 1), Return(0)])
+      (block![EmptyList(0), Const(1, 1), ConsUnique(0, 1), Return(0)])
     );
     test_output!(sexp, (vec![1.into()]));
   }
@@ -807,13 +785,10 @@ mod tests {
       (ssa_block![
         Const(0, 2),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Const(3, 1),
         Const(4, CoreFn(CoreFnId::Cons)),
-        Call(5, 4, 2),
-        CopyArgument(2),
-        CopyArgument(3),
+        Call(5, 4, [CopyArgument(2), CopyArgument(3)]),
         Return(5)
       ])
     );
@@ -822,9 +797,9 @@ mod tests {
       (block![
         Const(0, 2),
         EmptyList(1),
-        Push(1, 0),
+        PushUnique(1, 0),
         Const(0, 1),
-        Cons(1, 0),
+        ConsUnique(1, 0),
         Return(1)
       ])
     );
@@ -838,10 +813,9 @@ mod tests {
       sexp,
       (ssa_block![
         Const(0, CoreFn(CoreFnId::CreateList)),
-        Call(1, 0, 0),
+        Call(1, 0, []),
         Const(2, CoreFn(CoreFnId::IsEmpty)),
-        Call(3, 2, 1),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -857,11 +831,9 @@ mod tests {
       (ssa_block![
         Const(0, 1),
         Const(1, CoreFn(CoreFnId::CreateList)),
-        Call(2, 1, 1),
-        CopyArgument(0),
+        Call(2, 1, [CopyArgument(0)]),
         Const(3, CoreFn(CoreFnId::IsEmpty)),
-        Call(4, 3, 1),
-        CopyArgument(2),
+        Call(4, 3, [CopyArgument(2)]),
         Return(4)
       ])
     );
@@ -870,7 +842,7 @@ mod tests {
       (block![
         Const(0, 1),
         EmptyList(1),
-        Push(1, 0),
+        PushUnique(1, 0),
         IsEmpty(0, 1),
         Return(0)
       ])
@@ -890,9 +862,7 @@ mod tests {
             1,
             ssa_block![
               Const(1, CoreFn(CoreFnId::Multiply)),
-              Call(2, 1, 2),
-              CopyArgument(0),
-              CopyArgument(0),
+              Call(2, 1, [CopyArgument(0), CopyArgument(0)]),
               Return(2)
             ]
           )
@@ -928,11 +898,16 @@ mod tests {
             2,
             ssa_block![
               Const(2, CoreFn(CoreFnId::Multiply)),
-              Call(3, 2, 4),
-              CopyArgument(0),
-              CopyArgument(0),
-              CopyArgument(1),
-              CopyArgument(1),
+              Call(
+                3,
+                2,
+                [
+                  CopyArgument(0),
+                  CopyArgument(0),
+                  CopyArgument(1),
+                  CopyArgument(1)
+                ]
+              ),
               Return(3)
             ]
           )
@@ -986,18 +961,21 @@ mod tests {
             2,
             ssa_block![
               Const(2, CoreFn(CoreFnId::Multiply)),
-              Call(3, 2, 4),
-              CopyArgument(0),
-              CopyArgument(0),
-              CopyArgument(1),
-              CopyArgument(1),
+              Call(
+                3,
+                2,
+                [
+                  CopyArgument(0),
+                  CopyArgument(0),
+                  CopyArgument(1),
+                  CopyArgument(1)
+                ]
+              ),
               Return(3)
             ]
           )
         ),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ])
     );
@@ -1018,10 +996,8 @@ mod tests {
             ]
           )
         ),
-        Call(2, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
-        Return(2)
+        Call(0, 2, [StealArgument(0), StealArgument(1)]),
+        Return(0)
       ])
     );
     test_output!(sexp, 36);
@@ -1039,9 +1015,7 @@ mod tests {
             1,
             ssa_block![
               Const(1, CoreFn(CoreFnId::Multiply)),
-              Call(2, 1, 2),
-              CopyArgument(0),
-              CopyArgument(0),
+              Call(2, 1, [CopyArgument(0), CopyArgument(0)]),
               Return(2)
             ]
           )
@@ -1052,17 +1026,13 @@ mod tests {
           GenericValue::composite_fn(
             2,
             ssa_block![
-              Call(2, 0, 1),
-              CopyArgument(1),
-              Call(3, 0, 1),
-              CopyArgument(2),
+              Call(2, 0, [CopyArgument(1)]),
+              Call(3, 0, [CopyArgument(2)]),
               Return(3)
             ]
           )
         ),
-        Call(3, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
+        Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
         Return(3)
       ]
     );
@@ -1079,18 +1049,14 @@ mod tests {
           GenericValue::composite_fn(
             2,
             block![
-              Call(2, 0, 1),
-              CopyArgument(1),
-              Call(0, 0, 1),
-              CopyArgument(2),
+              Call(1, 0, [StealArgument(1)]),
+              Call(0, 0, [StealArgument(1)]),
               Return(0)
             ]
           )
         ),
-        Call(2, 2, 2),
-        CopyArgument(0),
-        CopyArgument(1),
-        Return(2)
+        Call(0, 2, [StealArgument(0), StealArgument(1)]),
+        Return(0)
       ]
     );
     test_output!(sexp, 16);
@@ -1139,7 +1105,9 @@ mod tests {
           0,
           vec![
             GenericValue::Symbol(0),
-            GenericValue::List(vec![GenericValue::Symbol(0), 1.into()].into())
+            GenericValue::List(
+              vector![GenericValue::Symbol(0), 1.into()].into()
+            )
           ]
         ),
         Return(0)
@@ -1166,9 +1134,9 @@ mod tests {
           0,
           vec![
             GenericValue::Symbol(1),
-            GenericValue::List(vec![GenericValue::Symbol(2)].into()),
+            GenericValue::List(vector![GenericValue::Symbol(2)].into()),
             GenericValue::List(
-              vec![
+              vector![
                 GenericValue::Symbol(3),
                 GenericValue::Symbol(2),
                 GenericValue::Symbol(2)
@@ -1182,6 +1150,89 @@ mod tests {
     );
   }
 
+  #[test]
+  fn steal_arguments_at_end_of_lifetime() {
+    // the list isn't needed after the call, so it's moved into the function,
+    // while a value that's passed twice is only stolen by its last occurrence
+    let sexp = "((fn (l x) (push l x)) (list 1) 2)";
+    test_bytecode!(
+      sexp,
+      block![
+        Const(0, 1),
+        EmptyList(1),
+        PushUnique(1, 0),
+        Const(0, 2),
+        Const(
+          2,
+          GenericValue::composite_fn(2, block![Push(0, 1), Return(0)])
+        ),
+        Call(0, 2, [StealArgument(1), StealArgument(0)]),
+        Return(0)
+      ]
+    );
+    test_output!(sexp, vector![1.into(), 2.into()]);
+    let passed_twice = ssa_block![
+      Const(0, 5),
+      Const(
+        1,
+        GenericValue::composite_fn(2, ssa_block![Add(2, 0, 1), Return(2)])
+      ),
+      Call(2, 1, [CopyArgument(0), CopyArgument(0)]),
+      Return(2)
+    ];
+    // functions only compare equal by reference, so their debug strings are
+    // compared instead
+    assert_eq!(
+      debug_string(&raw_ir_to_bytecode(passed_twice).unwrap()),
+      debug_string(&block![
+        Const(0, 5),
+        Const(
+          1,
+          GenericValue::composite_fn(2, block![Add(0, 0, 1), Return(0)])
+        ),
+        Call(0, 1, [CopyArgument(0), StealArgument(0)]),
+        Return(0)
+      ])
+    );
+  }
+
+  #[test]
+  fn mark_unique_list_updates_until_aliased() {
+    // the second list is pushed onto the first, so the first list's register
+    // is still referenced from inside the second list when it's updated again
+    let raw_ir = ssa_block![
+      EmptyList(0),
+      Const(1, 1),
+      Push((0, 2), 1),
+      EmptyList(3),
+      Push((3, 4), 2),
+      Push((2, 5), 4),
+      Return(5)
+    ];
+    let marked = mark_unique_list_updates(
+      build_control_flow_graphs(raw_ir.clone()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+      marked.metadata.blocks[0].instructions,
+      ssa_block![
+        EmptyList(0),
+        Const(1, 1),
+        PushUnique((0, 2), 1),
+        EmptyList(3),
+        PushUnique((3, 4), 2),
+        Push((2, 5), 4),
+        Return(5)
+      ]
+      .instructions
+      .to_vec()
+    );
+    assert_eq!(
+      run_ir(raw_ir),
+      Some(vector![1.into(), vector![vector![1.into()].into()].into()].into())
+    );
+  }
+
   #[test]
   fn two_arg_fn_compilation() {
     let sexp = "(fn (x y) (* x y))";
@@ -1194,9 +1245,7 @@ mod tests {
             2,
             ssa_block![
               Const(2, CoreFn(CoreFnId::Multiply)),
-              Call(3, 2, 2),
-              CopyArgument(0),
-              CopyArgument(1),
+              Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
               Return(3)
             ]
           )
@@ -1223,17 +1272,13 @@ mod tests {
                   2,
                   ssa_block![
                     Const(2, CoreFn(CoreFnId::Multiply)),
-                    Call(3, 2, 2),
-                    CopyArgument(0),
-                    CopyArgument(1),
+                    Call(3, 2, [CopyArgument(0), CopyArgument(1)]),
                     Return(3)
                   ]
                 )
               ),
               Const(2, CoreFn(CoreFnId::Partial)),
-              Call(3, 2, 2),
-              CopyArgument(1),
-              CopyArgument(0),
+              Call(3, 2, [CopyArgument(1), CopyArgument(0)]),
               Return(3)
             ]
           )
@@ -1242,4 +1287,252 @@ mod tests {
       ]
     );
   }
+
+  #[test]
+  fn instruction_usages() {
+    let call: super::SSAInstruction =
+      Call(4, 1, [CopyArgument(2), StealArgument(3)].into());
+    let usages = call.usages();
+    assert_eq!(usages.inputs.collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(usages.outputs.collect::<Vec<_>>(), vec![4]);
+    assert_eq!(usages.replacements.count(), 0);
+    let push: super::SSAInstruction = Push((0, 5), 2);
+    let usages = push.usages();
+    assert_eq!(usages.inputs.collect::<Vec<_>>(), vec![2]);
+    assert_eq!(usages.outputs.count(), 0);
+    assert_eq!(usages.replacements.collect::<Vec<_>>(), vec![(0, 5)]);
+  }
+
+  fn run_ir(raw_ir: SSABlock<()>) -> Option<Value> {
+    EvaluationState::new(raw_ir_to_bytecode(raw_ir).unwrap())
+      .evaluate(&HashMap::new())
+      .unwrap()
+  }
+
+  #[test]
+  fn branches_merge_into_block_parameters() {
+    let diamond = |condition: bool| {
+      ssa_block![
+        Const(0, condition),
+        If(0),
+        Const(1, 10),
+        Else,
+        Const(1, 20),
+        EndIf,
+        Const(2, 1),
+        Add(3, 1, 2),
+        Return(3)
+      ]
+    };
+    let graph = build_control_flow_graphs(diamond(true)).unwrap().metadata;
+    assert_eq!(
+      graph
+        .blocks
+        .iter()
+        .map(|block| (block.parameters.clone(), block.terminator.clone()))
+        .collect::<Vec<_>>(),
+      vec![
+        (
+          vec![],
+          Terminator::Branch {
+            condition: 0,
+            then: 1,
+            otherwise: 2
+          }
+        ),
+        (vec![], Terminator::Jump(3, vec![1])),
+        (vec![], Terminator::Jump(3, vec![4])),
+        (vec![5], Terminator::Exit),
+      ]
+    );
+    assert_eq!(graph.blocks[3].instructions[1], Add(3, 5, 2));
+    assert_eq!(run_ir(diamond(true)), Some(11.into()));
+    assert_eq!(run_ir(diamond(false)), Some(21.into()));
+    let chain = |first: bool, second: bool| {
+      ssa_block![
+        Const(0, first),
+        Const(1, second),
+        If(0),
+        Const(2, "a"),
+        ElseIf(1),
+        Const(2, "b"),
+        Else,
+        Const(2, "c"),
+        EndIf,
+        Return(2)
+      ]
+    };
+    assert_eq!(run_ir(chain(true, true)), Some("a".into()));
+    assert_eq!(run_ir(chain(false, true)), Some("b".into()));
+    assert_eq!(run_ir(chain(false, false)), Some("c".into()));
+    assert_eq!(
+      build_control_flow_graphs(ssa_block![Const(0, 1), Else, Return(0)])
+        .unwrap_err(),
+      IntermediateCompilationError::UnexpectedBranch(1)
+    );
+    assert_eq!(
+      build_control_flow_graphs(ssa_block![Const(0, 1), If(0), Return(0)])
+        .unwrap_err(),
+      IntermediateCompilationError::UnclosedIf(1)
+    );
+  }
+
+  #[test]
+  fn allocate_registers_across_branches() {
+    // register 1 is only live in the `Else` arm, so the `If` arm can reuse its
+    // runtime register
+    let diamond = |condition: bool| {
+      ssa_block![
+        Const(0, condition),
+        Const(1, 100),
+        If(0),
+        Const(2, 1),
+        Const(3, 2),
+        Multiply(4, 2, 3),
+        Else,
+        Add(4, 1, 1),
+        EndIf,
+        Return(4)
+      ]
+    };
+    assert_eq!(
+      raw_ir_to_bytecode(diamond(true)).unwrap(),
+      Block::new(
+        vec![
+          Const(0, 0),
+          Const(1, 1),
+          JumpIfNot(0, 7),
+          Const(0, 2),
+          Const(1, 3),
+          Multiply(0, 0, 1),
+          Jump(8),
+          Add(0, 1, 1),
+          Return(0)
+        ],
+        vec![true.into(), 100.into(), 1.into(), 2.into()]
+      )
+    );
+    assert_eq!(run_ir(diamond(true)), Some(2.into()));
+    assert_eq!(run_ir(diamond(false)), Some(200.into()));
+    let nested = |first: bool, second: bool| {
+      ssa_block![
+        Const(0, first),
+        Const(1, second),
+        Const(2, 10),
+        If(0),
+        Const(3, 1),
+        If(1),
+        Add(4, 2, 3),
+        Else,
+        Subtract(4, 2, 3),
+        EndIf,
+        Multiply(5, 4, 4),
+        Else,
+        Const(6, 3),
+        If(1),
+        Multiply(5, 2, 6),
+        Else,
+        Const(5, 0),
+        EndIf,
+        EndIf,
+        Add(7, 5, 2),
+        Return(7)
+      ]
+    };
+    // values from the two outer arms share runtime registers, as do values
+    // from the inner arms
+    assert_eq!(raw_ir_to_bytecode(nested(true, true)).unwrap().metadata, 2);
+    assert_eq!(run_ir(nested(true, true)), Some(131.into()));
+    assert_eq!(run_ir(nested(true, false)), Some(91.into()));
+    assert_eq!(run_ir(nested(false, true)), Some(40.into()));
+    assert_eq!(run_ir(nested(false, false)), Some(10.into()));
+  }
+
+  #[test]
+  fn lower_swapped_block_arguments() {
+    let graph = AllocatedGraph {
+      blocks: vec![
+        BasicBlock {
+          parameters: vec![],
+          instructions: vec![Const(0, 0), Const(1, 1)],
+          terminator: Terminator::Jump(1, vec![1, 0]),
+        },
+        BasicBlock {
+          parameters: vec![0, 1],
+          instructions: vec![Subtract(2, 0, 1), Return(2)],
+          terminator: Terminator::Exit,
+        },
+      ],
+    };
+    let lowered = lower_control_flow_graphs(GenericBlock::new_with_metadata(
+      vec![],
+      vec![10.into(), 3.into()],
+      graph,
+    ))
+    .unwrap();
+    assert_eq!(
+      lowered,
+      Block::new(
+        vec![
+          Const(0, 0),
+          Const(1, 1),
+          Copy(3, 0),
+          Copy(0, 1),
+          Copy(1, 3),
+          Subtract(2, 0, 1),
+          Return(2)
+        ],
+        vec![10.into(), 3.into()]
+      )
+    );
+    assert_eq!(
+      EvaluationState::new(lowered)
+        .evaluate(&HashMap::new())
+        .unwrap(),
+      Some((-7).into())
+    );
+  }
+
+  #[test]
+  fn fuse_superinstructions() {
+    let fused = |block: Block| fuse_instructions(block).unwrap();
+    // the constant is still needed after the addition, so it isn't fused
+    let live_constant =
+      block![Const(0, 1), Add(1, 0, 1), Add(1, 1, 0), Return(1)];
+    assert_eq!(fused(live_constant.clone()), live_constant);
+    assert_eq!(
+      fused(block![
+        Const(0, -3),
+        label loop_start,
+        Inc(0, 0),
+        IsPos(1, 0),
+        JumpIf(1, end),
+        Jump(loop_start),
+        label end,
+        Const(1, "a"),
+        Get(0, 2, 1),
+        Return(0),
+      ]),
+      Block::new(
+        vec![
+          Const(0, 0),
+          IncAndJumpIfPos(0, 3),
+          Jump(1),
+          GetConstKey(0, 2, 1),
+          Return(0)
+        ],
+        vec![(-3).into(), "a".into()]
+      )
+    );
+    // a jump into the middle of the sequence keeps it from being fused
+    let jumped_into = block![
+      Const(0, 1),
+      Jump(middle),
+      Const(1, 2),
+      label middle,
+      Add(0, 0, 1),
+      Return(0),
+    ];
+    assert_eq!(fused(jumped_into.clone()), jumped_into);
+  }
 }
@@ -0,0 +1,220 @@
+// A reference interpreter that runs raw IR directly, before any of the passes
+// that turn it into bytecode, so that the bytecode VM's results can be checked
+// against it. It's written to be obviously correct rather than fast: each SSA
+// register is just an entry in a map, and functions in the IR become external
+// functions that interpret their own blocks. Calls to anything else, like core
+// functions or globals that were compiled earlier, are made on a fresh VM
+
+use std::{cell::RefCell, collections::HashMap, error::Error, rc::Rc};
+
+use crate::{
+  instructions::{
+    Argument::{self, CopyArgument},
+    GenericInstruction::*,
+  },
+  runtime::{
+    capabilities::Capabilities,
+    control::{Block, FunctionBody},
+    data::{ExternalFunction, GenericValue::*, Value},
+    error::{RuntimeError, RuntimeResult},
+    evaluation::{EvaluationState, SymbolIndex},
+  },
+};
+
+use super::{
+  ast::token::SymbolLedger, intermediate::raw_ir_to_bytecode, SSABlock,
+  SSAValue,
+};
+
+pub(crate) struct ReferenceInterpreter {
+  global_bindings: HashMap<SymbolIndex, Value>,
+  capabilities: Capabilities,
+  symbol_ledger: RefCell<Option<Rc<SymbolLedger>>>,
+  // Innermost last
+  dynamic_bindings: RefCell<Vec<(SymbolIndex, Value)>>,
+}
+
+impl ReferenceInterpreter {
+  pub fn new(
+    global_bindings: HashMap<SymbolIndex, Value>,
+    capabilities: Capabilities,
+    symbol_ledger: Rc<SymbolLedger>,
+  ) -> Rc<Self> {
+    Rc::new(Self {
+      global_bindings,
+      capabilities,
+      symbol_ledger: RefCell::new(Some(symbol_ledger)),
+      dynamic_bindings: RefCell::new(vec![]),
+    })
+  }
+  // Symbols read by `edn/read` are added to the ledger, so it has to be taken
+  // back once evaluation finishes
+  pub fn take_symbol_ledger(&self) -> Option<Rc<SymbolLedger>> {
+    self.symbol_ledger.take()
+  }
+  pub fn evaluate(
+    self: &Rc<Self>,
+    block: &SSABlock<()>,
+  ) -> RuntimeResult<Value> {
+    self.run(block, vec![])
+  }
+  fn run(
+    self: &Rc<Self>,
+    block: &SSABlock<()>,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    // bindings that a failed `binding` form didn't get to pop are dropped
+    // along with the block
+    let binding_depth = self.dynamic_bindings.borrow().len();
+    let result = self.run_instructions(block, args);
+    self.dynamic_bindings.borrow_mut().truncate(binding_depth);
+    result
+  }
+  fn run_instructions(
+    self: &Rc<Self>,
+    block: &SSABlock<()>,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    let mut registers: HashMap<usize, Value> =
+      args.into_iter().enumerate().collect();
+    let get = |registers: &HashMap<usize, Value>, register: &usize| {
+      registers.get(register).cloned().unwrap_or(Nil)
+    };
+    for instruction in block.instructions.iter() {
+      let (register, value) = match instruction {
+        Const(register, index) => {
+          (register, self.constant(&block.constants[*index as usize])?)
+        }
+        Lookup(register, symbol_index) => (
+          register,
+          self
+            .global_bindings
+            .get(symbol_index)
+            .cloned()
+            .ok_or(RuntimeError::UndefinedGlobal(Symbol(*symbol_index)))?,
+        ),
+        LookupDynamic(register, symbol_index) => (
+          register,
+          self
+            .dynamic_bindings
+            .borrow()
+            .iter()
+            .rev()
+            .find(|(bound_symbol, _)| bound_symbol == symbol_index)
+            .map(|(_, value)| value.clone())
+            .or_else(|| self.global_bindings.get(symbol_index).cloned())
+            .ok_or(RuntimeError::UndefinedGlobal(Symbol(*symbol_index)))?,
+        ),
+        PushDynamicBinding(symbol_index, register) => {
+          self
+            .dynamic_bindings
+            .borrow_mut()
+            .push((*symbol_index, get(&registers, register)));
+          continue;
+        }
+        PopDynamicBindings(count) => {
+          let mut dynamic_bindings = self.dynamic_bindings.borrow_mut();
+          let depth = dynamic_bindings.len().saturating_sub(*count as usize);
+          dynamic_bindings.truncate(depth);
+          continue;
+        }
+        Call(register, f, args) => {
+          let args = args
+            .iter()
+            .map(|arg| match arg {
+              Argument::CopyArgument(register)
+              | Argument::StealArgument(register) => get(&registers, register),
+            })
+            .collect();
+          (register, self.call(get(&registers, f), args)?)
+        }
+        Return(register) => return Ok(get(&registers, register)),
+        // the raw IR doesn't contain any other instructions, which are only
+        // introduced by later passes
+        _ => return Err(RuntimeError::NotYetImplemented),
+      };
+      registers.insert(*register, value);
+    }
+    Ok(Nil)
+  }
+  fn constant(
+    self: &Rc<Self>,
+    constant: &SSAValue<()>,
+  ) -> RuntimeResult<Value> {
+    match constant {
+      CompositeFn(f) => {
+        let FunctionBody::Compiled(block) = &f.body else {
+          return Err(RuntimeError::NotYetImplemented);
+        };
+        let (interpreter, block) = (self.clone(), block.clone());
+        Ok(
+          ExternalFunction {
+            name: None,
+            args: Some(f.args.clone()),
+            f: Rc::new(move |args| {
+              interpreter
+                .run(&block, args)
+                .map_err(|error| Rc::new(error) as Rc<dyn Error>)
+            }),
+            pure: false,
+          }
+          .into(),
+        )
+      }
+      // other constants are plain data, which holds no blocks to translate
+      other => other
+        .clone()
+        .translate(&|_, _, _, _| Err(RuntimeError::NotYetImplemented)),
+    }
+  }
+  fn call(self: &Rc<Self>, f: Value, args: Vec<Value>) -> RuntimeResult<Value> {
+    match f {
+      ExternalFn(f) => f.call(args),
+      PartialApplication(f_and_args) => {
+        let (f, partial_args) = &*f_and_args;
+        self.call(
+          f.clone(),
+          partial_args.iter().cloned().chain(args).collect(),
+        )
+      }
+      // many core functions only work once their calls have been replaced
+      // with instructions, so they're called from a function that's compiled
+      // just like a direct call of them would be
+      CoreFn(id) => {
+        let arg_count = args.len();
+        let call = SSAValue::composite_fn(
+          arg_count as u8,
+          SSABlock::new(
+            vec![
+              Const(arg_count, 0),
+              Call(
+                arg_count + 1,
+                arg_count,
+                (0..arg_count).map(CopyArgument).collect(),
+              ),
+              Return(arg_count + 1),
+            ],
+            vec![CoreFn(id)],
+          ),
+        );
+        let block = raw_ir_to_bytecode(SSABlock::new(
+          vec![Const(0, 0), Return(0)],
+          vec![call],
+        ))
+        .expect("couldn't compile a call of a core function");
+        self.call(block.constants[0].clone(), args)
+      }
+      f => {
+        let mut state = EvaluationState::new(Block::for_call(f, args))
+          .with_capabilities(self.capabilities)
+          .with_dynamic_bindings(self.dynamic_bindings.borrow().clone());
+        if let Some(symbol_ledger) = self.symbol_ledger.take() {
+          state.set_symbol_ledger(symbol_ledger);
+        }
+        let result = state.evaluate(&self.global_bindings);
+        *self.symbol_ledger.borrow_mut() = state.take_symbol_ledger();
+        Ok(result?.unwrap_or(Nil))
+      }
+    }
+  }
+}
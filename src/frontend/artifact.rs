@@ -0,0 +1,162 @@
+// Programs compiled ahead of time, so that they can be loaded without the
+// parser or compiler. An artifact is a single EDN map holding the symbol table
+// that the bytecode's symbol indexes refer to, and the bytecode of each
+// top-level form in order, along with the definition the form makes, if any.
+// Loading an artifact runs its forms and returns the value of the last one
+
+use std::sync::Arc;
+
+use crate::{
+  compiler::ast::token::SymbolLedger,
+  runtime::{
+    control::Block,
+    data::{GenericValue::*, Value},
+    edn::{read_edn, write_edn},
+    error::RuntimeError,
+    evaluation::SymbolIndex,
+    portable::{decode_block, encode_block, field, list, string_map as map},
+    validation::validate_block,
+  },
+};
+
+use super::error::{PidginError, PidginResult};
+
+const ARTIFACT_VERSION: i64 = 1;
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledDefinition {
+  pub name: String,
+  pub private: bool,
+  pub dynamic: bool,
+}
+
+// The bytecode of one top-level form, and the definition it makes, if any
+#[derive(Debug, Clone)]
+pub(crate) struct CompiledForm {
+  pub namespace: String,
+  pub definition: Option<CompiledDefinition>,
+  pub block: Block,
+}
+
+pub(crate) struct Artifact {
+  pub forms: Vec<CompiledForm>,
+  // The namespace that was current once the whole source had been compiled
+  pub namespace: String,
+}
+
+// The globals of an evaluator, written as an artifact that defines each of
+// them. Unlike evaluators, snapshots can be shared between threads, so that
+// each thread can start an evaluator of its own with the same globals. Values
+// can't be shared between threads, so each evaluator made from a snapshot
+// reads and runs the whole artifact, costing about as much as loading a
+// compiled program
+#[derive(Clone, Debug)]
+pub struct EnvironmentSnapshot {
+  pub(crate) artifact: Arc<str>,
+}
+
+fn invalid(message: impl Into<String>) -> PidginError {
+  PidginError::InvalidArtifact(message.into())
+}
+
+pub(crate) fn encode_artifact(
+  artifact: &Artifact,
+  symbol_ledger: &SymbolLedger,
+) -> PidginResult<Value> {
+  let forms = artifact
+    .forms
+    .iter()
+    .map(|form| {
+      let definition = match &form.definition {
+        Some(definition) => map(vec![
+          ("name", definition.name.as_str().into()),
+          ("private", definition.private.into()),
+          ("dynamic", definition.dynamic.into()),
+        ]),
+        None => Nil,
+      };
+      Ok(map(vec![
+        ("namespace", form.namespace.as_str().into()),
+        ("definition", definition),
+        ("block", encode_block(&form.block)?),
+      ]))
+    })
+    .collect::<PidginResult<Vec<Value>>>()?;
+  let artifact = map(vec![
+    ("version", ARTIFACT_VERSION.into()),
+    (
+      "symbols",
+      symbol_ledger
+        .symbol_names()
+        .map(|name| Value::from(name.as_str()))
+        .collect(),
+    ),
+    ("forms", forms.into()),
+    ("namespace", artifact.namespace.as_str().into()),
+  ]);
+  Ok(artifact)
+}
+
+pub(crate) fn write_artifact(
+  artifact: &Artifact,
+  symbol_ledger: &SymbolLedger,
+) -> PidginResult<String> {
+  let artifact = encode_artifact(artifact, symbol_ledger)?;
+  Ok(write_edn(&artifact, Some(symbol_ledger))?)
+}
+
+pub(crate) fn decode_artifact(
+  artifact: &Value,
+  symbol_ledger: &mut SymbolLedger,
+) -> PidginResult<Artifact> {
+  let version = i64::try_from(field(artifact, "version")?)?;
+  if version != ARTIFACT_VERSION {
+    return Err(invalid(format!("unsupported version {version}")));
+  }
+  let symbols = list(field(artifact, "symbols")?)?
+    .into_iter()
+    .map(|name| Ok(symbol_ledger.symbol_index(String::try_from(name)?)))
+    .collect::<PidginResult<Vec<_>>>()?;
+  // symbol indexes in the artifact's instructions refer to its own symbol
+  // table, so they're mapped to the indexes of the same names in the ledger
+  let symbol = |index: SymbolIndex| {
+    symbols.get(index as usize).copied().ok_or_else(|| {
+      RuntimeError::Serialization(format!("unknown symbol {index}"))
+    })
+  };
+  let forms = list(field(artifact, "forms")?)?
+    .into_iter()
+    .map(|form| {
+      let definition = match field(&form, "definition")? {
+        Nil => None,
+        definition => Some(CompiledDefinition {
+          name: String::try_from(field(&definition, "name")?)?,
+          private: bool::try_from(field(&definition, "private")?)?,
+          dynamic: bool::try_from(field(&definition, "dynamic")?)?,
+        }),
+      };
+      // the bytecode didn't come from this compiler, so it's checked before
+      // it can run
+      let block = decode_block(&field(&form, "block")?, &symbol)?;
+      validate_block(&block, false)
+        .map_err(|error| invalid(error.to_string()))?;
+      Ok(CompiledForm {
+        namespace: String::try_from(field(&form, "namespace")?)?,
+        definition,
+        block,
+      })
+    })
+    .collect::<PidginResult<Vec<_>>>()?;
+  Ok(Artifact {
+    forms,
+    namespace: String::try_from(field(artifact, "namespace")?)?,
+  })
+}
+
+pub(crate) fn read_artifact(
+  source: &str,
+  symbol_ledger: &mut SymbolLedger,
+) -> PidginResult<Artifact> {
+  let artifact = read_edn(source, symbol_ledger)?;
+  decode_artifact(&artifact, symbol_ledger)
+}
@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use crate::{compiler::ast::expressions::Expression, runtime::control::Block};
+
+// Bytecode for the forms that have already been compiled, so that evaluating
+// the same form again skips the compiler. Compiling a form depends on which
+// globals its names resolve to, so every entry is dropped once the version of
+// the environment the forms were compiled against changes
+#[derive(Default)]
+pub(crate) struct CompilationCache {
+  version: u64,
+  blocks: HashMap<Expression, Block>,
+}
+
+impl CompilationCache {
+  pub(crate) fn get(
+    &mut self,
+    expression: &Expression,
+    version: u64,
+  ) -> Option<Block> {
+    if version != self.version {
+      self.blocks.clear();
+      self.version = version;
+    }
+    self.blocks.get(expression).cloned()
+  }
+  pub(crate) fn insert(
+    &mut self,
+    expression: Expression,
+    version: u64,
+    block: Block,
+  ) {
+    if version == self.version {
+      self.blocks.insert(expression, block);
+    }
+  }
+}
@@ -0,0 +1,55 @@
+use rustyline::{
+  completion::Completer, highlight::Highlighter, hint::Hinter,
+  validate::Validator, Context, Helper,
+};
+
+// Characters that can't appear in a symbol, so a completion starts after the
+// last of them before the cursor
+fn is_symbol_boundary(c: char) -> bool {
+  c.is_whitespace() || "()[]{}\"'@".contains(c)
+}
+
+pub(crate) fn completion_start(line: &str, pos: usize) -> usize {
+  line[..pos]
+    .char_indices()
+    .rev()
+    .find(|(_, c)| is_symbol_boundary(*c))
+    .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+// Completes symbols in the REPL. The candidate names are refreshed from the
+// evaluator after each line is evaluated, since the helper is owned by the
+// editor and can't borrow the evaluator
+#[derive(Default)]
+pub(crate) struct ReplHelper {
+  pub names: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+  type Candidate = String;
+
+  fn complete(
+    &self,
+    line: &str,
+    pos: usize,
+    _ctx: &Context<'_>,
+  ) -> rustyline::Result<(usize, Vec<String>)> {
+    let start = completion_start(line, pos);
+    let prefix = &line[start..pos];
+    Ok((
+      start,
+      self
+        .names
+        .iter()
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect(),
+    ))
+  }
+}
+impl Hinter for ReplHelper {
+  type Hint = String;
+}
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use crate::runtime::{
+  control::Block, coverage::Coverage, data::GenericValue::*,
+  evaluation::Instruction,
+};
+
+// How much of one top-level form's bytecode ran, counting the instructions of
+// the form itself and of every function defined within it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormCoverage {
+  pub form: String,
+  pub executed: usize,
+  pub total: usize,
+}
+
+impl FormCoverage {
+  pub(crate) fn new(form: String, block: &Block, coverage: &Coverage) -> Self {
+    let mut blocks = vec![];
+    function_blocks(block, &mut blocks, &mut HashSet::new());
+    Self {
+      form,
+      executed: blocks.iter().map(|block| coverage.executed(block)).sum(),
+      total: blocks.iter().map(|block| block.instructions.len()).sum(),
+    }
+  }
+}
+
+// The block of a form along with those of the functions defined within it.
+// Functions are compiled the first time they're called, so the ones that
+// never were get compiled here to find out what instructions they have
+pub(crate) fn function_blocks(
+  block: &Block,
+  blocks: &mut Vec<Block>,
+  seen: &mut HashSet<*const Instruction>,
+) {
+  if !seen.insert(block.instructions.as_ptr()) {
+    return;
+  }
+  blocks.push(block.clone());
+  for constant in block.constants.iter() {
+    if let CompositeFn(f) = constant {
+      if let Ok(block) = f.block() {
+        function_blocks(block, blocks, seen);
+      }
+    }
+  }
+}
+
+// The coverage of every form evaluated since coverage was enabled, in the
+// order they were first evaluated
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoverageReport {
+  pub forms: Vec<FormCoverage>,
+}
+
+impl CoverageReport {
+  pub fn executed(&self) -> usize {
+    self.forms.iter().map(|form| form.executed).sum()
+  }
+  pub fn total(&self) -> usize {
+    self.forms.iter().map(|form| form.total).sum()
+  }
+  // The fraction of all instructions that ran, which is 1 when there weren't
+  // any
+  pub fn fraction(&self) -> f64 {
+    match self.total() {
+      0 => 1.,
+      total => self.executed() as f64 / total as f64,
+    }
+  }
+}
@@ -0,0 +1,69 @@
+use crate::runtime::data::{GenericValue::*, Value};
+
+use super::{error::PidginResult, evaluator::Evaluator};
+
+// The results of evaluating a top-level form with the bytecode VM and with
+// the reference interpreter, which runs the form's IR before any of the
+// passes that optimize it and turn it into bytecode
+#[derive(Debug, Clone, PartialEq)]
+pub struct DifferentialResult {
+  pub form: String,
+  pub vm: PidginResult<Value>,
+  pub reference: PidginResult<Value>,
+}
+
+fn is_fn(value: &Value) -> bool {
+  matches!(
+    value,
+    CoreFn(_)
+      | CompositeFn(_)
+      | ExternalFn(_)
+      | PartialApplication(_)
+      | Composition(_)
+  )
+}
+
+// Values that `=` compares by identity can't be equal across the VM and the
+// interpreter, since each makes its own. Functions are taken to agree with any
+// other function, since the interpreter represents them differently, cells
+// agree when their contents do, and coroutines and external objects agree
+// with any of the same kind
+fn values_agree(vm: &Value, reference: &Value) -> bool {
+  match (vm, reference) {
+    (Cell(vm), Cell(reference)) => {
+      values_agree(&vm.borrow(), &reference.borrow())
+    }
+    (List(vm), List(reference)) => {
+      vm.len() == reference.len()
+        && vm
+          .iter()
+          .zip(reference.iter())
+          .all(|(vm, reference)| values_agree(vm, reference))
+    }
+    (Coroutine(_), Coroutine(_)) | (ExternalObject(_), ExternalObject(_)) => {
+      true
+    }
+    (vm, reference) => vm == reference || is_fn(vm) && is_fn(reference),
+  }
+}
+
+impl DifferentialResult {
+  // Errors are compared by kind
+  pub fn agrees(&self) -> bool {
+    match (&self.vm, &self.reference) {
+      (Ok(vm), Ok(reference)) => values_agree(vm, reference),
+      (Err(vm), Err(reference)) => vm == reference,
+      _ => false,
+    }
+  }
+}
+
+// Evaluates a source in a fresh evaluator with both the VM and the reference
+// interpreter, returning the first form they disagree on, if any. Meant for
+// tests that check the compiler's passes don't change what programs do
+pub fn first_disagreement(source: &str) -> Option<DifferentialResult> {
+  Evaluator::default()
+    .differential_eval(source)
+    .into_iter()
+    .find(|result| !result.agrees())
+}
@@ -0,0 +1,56 @@
+use std::fmt::{Display, Write};
+
+// A stage of compilation whose output can be dumped while compiling a source,
+// to inspect what the compiler produces for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompilationStage {
+  Ast,
+  Ir,
+  Bytecode,
+}
+
+impl CompilationStage {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "ast" => Some(Self::Ast),
+      "ir" => Some(Self::Ir),
+      "bytecode" => Some(Self::Bytecode),
+      _ => None,
+    }
+  }
+}
+
+impl Display for CompilationStage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Ast => write!(f, "ast"),
+      Self::Ir => write!(f, "ir"),
+      Self::Bytecode => write!(f, "bytecode"),
+    }
+  }
+}
+
+// Collects the output of the requested stages as the evaluator compiles
+pub(crate) struct EmitLog {
+  stages: Vec<CompilationStage>,
+  pub output: String,
+}
+
+impl EmitLog {
+  pub fn new(stages: Vec<CompilationStage>) -> Self {
+    Self {
+      stages,
+      output: String::new(),
+    }
+  }
+  pub fn record(
+    &mut self,
+    stage: CompilationStage,
+    describe: impl FnOnce() -> String,
+  ) {
+    if self.stages.contains(&stage) {
+      writeln!(self.output, ";; {stage}\n{}", describe())
+        .expect("writing to a string can't fail");
+    }
+  }
+}
@@ -2,7 +2,10 @@ use std::{error::Error, fmt::Display};
 
 use crate::{
   compiler::{
-    ast::error::{ASTError, ASTResult},
+    ast::{
+      error::{ASTError, ASTResult},
+      token::SymbolLedger,
+    },
     intermediate::error::IntermediateCompilationError,
   },
   runtime::error::RuntimeError,
@@ -13,6 +16,22 @@ pub enum PidginError {
   AST(ASTError),
   Compiler(IntermediateCompilationError),
   Runtime(RuntimeError),
+  // An error in one of several top-level forms, such as those of a file loaded
+  // with `load-file`
+  InForm {
+    source_name: String,
+    form_index: usize,
+    form: String,
+    error: Box<PidginError>,
+  },
+  // A compiled artifact that can't be written or loaded
+  InvalidArtifact(String),
+  // A plugin library that can't be loaded
+  InvalidPlugin(String),
+  // A saved evaluator image that can't be loaded
+  InvalidImage(String),
+  // The qualified names of globals that can't be sent to another thread
+  UnportableGlobals(Vec<String>),
 }
 
 impl From<ASTError> for PidginError {
@@ -31,12 +50,67 @@ impl From<RuntimeError> for PidginError {
   }
 }
 
+impl PidginError {
+  pub(crate) fn offending_token(&self) -> Option<&str> {
+    match self {
+      PidginError::AST(err) => err.offending_token(),
+      PidginError::InForm { error, .. } => error.offending_token(),
+      _ => None,
+    }
+  }
+  pub(crate) fn description(
+    &self,
+    symbol_ledger: Option<&SymbolLedger>,
+  ) -> String {
+    match self {
+      PidginError::Runtime(err) => {
+        format!("runtime error: {}", err.description(symbol_ledger))
+      }
+      PidginError::InForm {
+        source_name,
+        form_index,
+        form,
+        error,
+      } => format!(
+        "in form {} of {source_name}, {form}:\n{}",
+        form_index + 1,
+        (**error).description(symbol_ledger)
+      ),
+      other => other.to_string(),
+    }
+  }
+}
+
 impl Display for PidginError {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self {
       PidginError::AST(err) => write!(f, "ast error: {err}"),
       PidginError::Compiler(err) => write!(f, "compiler error: {err}"),
       PidginError::Runtime(err) => write!(f, "runtime error: {err}"),
+      PidginError::InForm {
+        source_name,
+        form_index,
+        form,
+        error,
+      } => write!(
+        f,
+        "in form {} of {source_name}, {form}:\n{error}",
+        form_index + 1
+      ),
+      PidginError::InvalidArtifact(message) => {
+        write!(f, "invalid artifact: {message}")
+      }
+      PidginError::InvalidPlugin(message) => {
+        write!(f, "invalid plugin: {message}")
+      }
+      PidginError::InvalidImage(message) => {
+        write!(f, "invalid image: {message}")
+      }
+      PidginError::UnportableGlobals(names) => write!(
+        f,
+        "globals can't be sent to another thread: {}",
+        names.join(", ")
+      ),
     }
   }
 }
@@ -1,42 +1,587 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+  any::{type_name, Any, TypeId},
+  collections::{HashMap, HashSet},
+  error::Error,
+  path::Path,
+  rc::Rc,
+};
+
+use itertools::Itertools;
 
 use crate::{
   compiler::{
     ast::{
-      expressions::Expression,
-      parse::parse_sexp,
+      error::ASTError,
+      expressions::{Expression, SPECIAL_FORMS},
+      parse::{parse_sexp, parse_sexps},
       to_ir::build_expression_ir,
       token::{SymbolLedger, TokenTree},
+      tree::Tree,
     },
-    intermediate::raw_ir_to_bytecode,
+    intermediate::{deferral::defer_function_constants, raw_ir_to_bytecode},
+    reference::ReferenceInterpreter,
     SSABlock,
   },
   instructions::GenericInstruction,
   runtime::{
-    control::Block,
-    data::Value,
-    error::RuntimeResult,
-    evaluation::{EvaluationState, SymbolIndex},
+    capabilities::Capabilities,
+    control::{Block, FrameView},
+    core_functions::CoreFnId,
+    data::{AritySpecifier, ExternalFnResult, ExternalFunction, Value},
+    edn::{read_edn, write_edn},
+    error::{RuntimeError, RuntimeResult},
+    evaluation::{
+      next_environment_generation, EvaluationState, Register, SymbolIndex,
+    },
+    memory::{HeapStats, HeapWalker},
+    pretty_print::PrettyPrintOptions,
+    protocols::{protocol_fns, TypeKey},
+    records::{record_fns, RecordType},
+    replay::Trace,
   },
 };
 
-use super::error::PidginResult;
+use crate::string_utils::{colored, underline_token, Color};
+
+#[cfg(feature = "serde")]
+use super::{
+  artifact::{
+    read_artifact, write_artifact, Artifact, CompiledDefinition, CompiledForm,
+    EnvironmentSnapshot,
+  },
+  image::{read_image, write_image, Image},
+};
+use super::{
+  compilation_cache::CompilationCache,
+  coverage::{CoverageReport, FormCoverage},
+  differential::DifferentialResult,
+  emit::{CompilationStage, EmitLog},
+  error::{PidginError, PidginResult},
+  export::Export,
+  namespaces::Namespaces,
+  plugin::PidginModule,
+  profile::ProfileReport,
+  testing::{TestReport, TestResult},
+};
+#[cfg(feature = "tokio")]
+use crate::runtime::async_host::{self, AsyncHost};
+#[cfg(feature = "serde")]
+use crate::runtime::portable::is_portable;
+
+type GlobalWatch = Rc<dyn Fn(&str, &Value)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormResult {
+  pub form_index: usize,
+  pub form: String,
+  pub result: PidginResult<Value>,
+}
+
+impl FormResult {
+  pub fn into_result(self, source_name: &str) -> PidginResult<Value> {
+    self.result.map_err(|error| PidginError::InForm {
+      source_name: source_name.to_string(),
+      form_index: self.form_index,
+      form: self.form,
+      error: Box::new(error),
+    })
+  }
+}
 
 #[derive(Default)]
-pub(crate) struct Evaluator {
-  symbol_ledger: SymbolLedger,
+pub struct Evaluator {
+  symbol_ledger: Rc<SymbolLedger>,
   global_environment: HashMap<SymbolIndex, Value>,
+  // Kept between evaluations so that top-level forms reuse its stack and
+  // interned strings rather than starting from scratch
+  state: EvaluationState,
+  pub pretty_print_options: PrettyPrintOptions,
+  capabilities: Capabilities,
+  record_types: HashMap<String, Rc<RecordType>>,
+  namespaces: Namespaces,
+  // Globals named by `(declare ...)`, which can be referred to before they're
+  // defined
+  declared: HashSet<SymbolIndex>,
+  emit_log: Option<EmitLog>,
+  // Changes whenever `global_environment` does, invalidating the globals that
+  // compiled code has cached
+  environment_generation: u64,
+  compilation_cache: CompilationCache,
+  // Changes whenever the set of names that compilation can resolve does,
+  // which a redefinition of an existing global doesn't affect
+  compilation_version: u64,
+  // The globals bound by `deftest`, in the order they were first defined
+  tests: Vec<SymbolIndex>,
+  // While `differential_eval` runs, each form is also run by the reference
+  // interpreter, whose result is kept here until it's compared
+  check_against_reference: bool,
+  reference_result: Option<PidginResult<Value>>,
+  // The frames of the last top-level form whose evaluation failed, innermost
+  // first
+  failed_frames: Vec<FrameView>,
+  // Callbacks for globals being defined, by qualified name
+  global_watches: HashMap<SymbolIndex, Vec<GlobalWatch>>,
+  // The source and bytecode of each top-level form evaluated since coverage
+  // or profiling was enabled, which also keeps the bytecode alive for as long
+  // as it might be looked up
+  recorded_forms: Option<Vec<(String, Block)>>,
+  // The bytecode of each top-level form evaluated while compiling a source
+  // ahead of time
+  #[cfg(feature = "serde")]
+  compiled_forms: Option<Vec<CompiledForm>>,
+  // The names of the modules installed with `install_module`
+  modules: HashSet<String>,
 }
 
 impl Evaluator {
+  pub fn enable_file_io(&mut self) {
+    self.capabilities.file_io = true;
+  }
+  // Binds `await` and the operations in `runtime::async_host`, which functions
+  // run as tasks by the evaluator's `async_host` use to suspend themselves
+  #[cfg(feature = "tokio")]
+  pub fn enable_async(&mut self) {
+    self.define_globals(
+      async_host::bindings()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect(),
+    );
+  }
+  // A host for running functions as tasks on a Tokio `LocalSet`, which sees
+  // the globals as they are when it's made
+  #[cfg(feature = "tokio")]
+  pub fn async_host(&self) -> Rc<AsyncHost> {
+    let mut state = EvaluationState::new(Block::new(vec![], vec![]))
+      .with_capabilities(self.capabilities);
+    state.set_symbol_ledger(self.symbol_ledger.clone());
+    state.share_methods(&self.state);
+    AsyncHost::new(state, self.global_environment.clone())
+  }
+  pub fn current_namespace(&self) -> &str {
+    self.namespaces.current_name()
+  }
+  pub fn read_edn(&mut self, source: &str) -> PidginResult<Value> {
+    Ok(read_edn(source, Rc::make_mut(&mut self.symbol_ledger))?)
+  }
+  pub fn write_edn(&self, value: &Value) -> PidginResult<String> {
+    Ok(write_edn(value, Some(&self.symbol_ledger))?)
+  }
+  // Every bound symbol and builtin starting with `prefix`, in sorted order
+  pub fn completions(&self, prefix: &str) -> Vec<String> {
+    self
+      .namespaces
+      .visible_symbols()
+      .filter_map(|symbol| self.symbol_ledger.symbol_name(&symbol))
+      .map(String::as_str)
+      .chain(
+        CoreFnId::all()
+          .filter(|core_fn_id| !core_fn_id.is_internal())
+          .map(|core_fn_id| core_fn_id.name()),
+      )
+      .chain(self.symbol_ledger.builtin_names().map(String::as_str))
+      .chain(SPECIAL_FORMS.iter().copied())
+      .filter(|name| name.starts_with(prefix))
+      .map(String::from)
+      .sorted()
+      .dedup()
+      .collect()
+  }
   pub fn describe(&self, value: Value) -> String {
     value.description(Some(&self.symbol_ledger))
   }
+  pub fn pretty_describe(&self, value: Value) -> String {
+    value
+      .pretty_description(Some(&self.symbol_ledger), &self.pretty_print_options)
+  }
+  pub fn describe_error(&self, error: PidginError) -> String {
+    let description = error.description(Some(&self.symbol_ledger));
+    if self.pretty_print_options.color {
+      colored(&description, Color::Red)
+    } else {
+      description
+    }
+  }
+  // Describes an error in evaluating `source`, pointing out the part of the
+  // source responsible for it when that can be determined
+  pub fn describe_error_in(&self, source: &str, error: PidginError) -> String {
+    let underline = error.offending_token().and_then(|token| {
+      source.lines().find_map(|line| underline_token(line, token))
+    });
+    let description = self.describe_error(error);
+    match underline {
+      Some(underline) => format!("{underline}\n{description}"),
+      None => description,
+    }
+  }
+
   fn parse(&mut self, expression_string: &str) -> PidginResult<Expression> {
-    Ok(Expression::from_token_tree(
-      TokenTree::try_from(parse_sexp(expression_string))?,
-      &mut self.symbol_ledger,
-    )?)
+    self.parse_tree(parse_sexp(expression_string))
+  }
+  fn parse_tree(&mut self, tree: Tree<String>) -> PidginResult<Expression> {
+    let expression = Expression::from_token_tree(
+      TokenTree::try_from(tree)?,
+      Rc::make_mut(&mut self.symbol_ledger),
+    )?;
+    if let Some(emit_log) = &mut self.emit_log {
+      emit_log.record(CompilationStage::Ast, || format!("{expression:#?}"));
+    }
+    Ok(expression)
+  }
+  // Evaluates the forms of a source, returning the output of the given
+  // compilation stages for each of them. The forms have to be evaluated as
+  // well as compiled, since compiling a form depends on the definitions made
+  // by those before it
+  pub fn emit(
+    &mut self,
+    source: &str,
+    stages: Vec<CompilationStage>,
+  ) -> PidginResult<String> {
+    self.emit_log = Some(EmitLog::new(stages));
+    let result = self.eval_source(source, "source");
+    let output = self.emit_log.take().map(|log| log.output);
+    result.map(|_| output.unwrap_or_default())
+  }
+  // The source of an expression after the built-in expansions, such as those
+  // of `time` and `letrec`, have been applied to it. `macroexpand` lists the
+  // forms that are expanded
+  pub fn macroexpand(&mut self, source: &str) -> PidginResult<String> {
+    let expression = self.parse(source)?;
+    Ok(expression.to_string(&self.symbol_ledger))
+  }
+  // Like `macroexpand`, but only the outermost form is expanded
+  pub fn macroexpand_1(&mut self, source: &str) -> PidginResult<String> {
+    let expression = Expression::from_token_tree_once(
+      TokenTree::try_from(parse_sexp(source))?,
+      Rc::make_mut(&mut self.symbol_ledger),
+    )?;
+    Ok(expression.to_string(&self.symbol_ledger))
+  }
+  // Lazily evaluates each top-level form of the source in order, yielding one
+  // result per form. Evaluation continues past failed forms unless the caller
+  // stops consuming the iterator
+  pub fn eval_forms<'a>(
+    &'a mut self,
+    source: &str,
+  ) -> impl Iterator<Item = FormResult> + 'a {
+    parse_sexps(source).into_iter().enumerate().map(
+      move |(form_index, tree)| {
+        let form = tree.to_string();
+        let result = self
+          .parse_tree(tree)
+          .and_then(|expression| self.eval_expression_form(expression));
+        FormResult {
+          form_index,
+          form,
+          result,
+        }
+      },
+    )
+  }
+  // Evaluates the forms of a source until one fails, returning the value of
+  // the last one. The error records which form it came from
+  fn eval_source(
+    &mut self,
+    source: &str,
+    source_name: &str,
+  ) -> PidginResult<Value> {
+    let mut value = Value::Nil;
+    for form_result in self.eval_forms(source) {
+      value = form_result.into_result(source_name)?;
+    }
+    Ok(value)
+  }
+  pub fn eval_file(&mut self, path: impl AsRef<Path>) -> PidginResult<Value> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", path.display()))
+    })?;
+    self.eval_source(&source, &path.display().to_string())
+  }
+  // Evaluates a source that redefines globals, all at once: if any of its
+  // forms fails, the error is returned and every definition the source made
+  // is undone, though other side effects, like those on atoms or protocols,
+  // aren't. Frames that are already running an old definition, like those of
+  // paused coroutines, finish running the code they started with, but every
+  // call that looks up a redefined global after the reload, including calls
+  // from those frames, runs the new definition
+  pub fn reload(&mut self, source: &str) -> PidginResult<Value> {
+    let global_environment = self.global_environment.clone();
+    let dynamic_globals: HashSet<SymbolIndex> = global_environment
+      .keys()
+      .filter(|global| self.symbol_ledger.is_dynamic(global))
+      .copied()
+      .collect();
+    let namespaces = self.namespaces.clone();
+    let declared = self.declared.clone();
+    let tests = self.tests.clone();
+    let record_types = self.record_types.clone();
+    let result = self.eval_source(source, "reloaded source");
+    if result.is_err() {
+      let reloaded_environment =
+        std::mem::replace(&mut self.global_environment, global_environment);
+      let symbol_ledger = Rc::make_mut(&mut self.symbol_ledger);
+      for global in reloaded_environment.keys() {
+        symbol_ledger.set_dynamic(*global, dynamic_globals.contains(global));
+      }
+      self.namespaces = namespaces;
+      self.declared = declared;
+      self.tests = tests;
+      self.record_types = record_types;
+      // Bytecode compiled and globals cached during the reload could refer to
+      // definitions that no longer exist, so neither can be reused
+      self.compilation_version += 1;
+      self.environment_generation = next_environment_generation();
+    }
+    result
+  }
+  // Evaluates a source while recording the bytecode of its top-level forms,
+  // and returns an artifact that `load_compiled_source` can run without
+  // parsing or compiling anything
+  #[cfg(feature = "serde")]
+  pub fn compile_source(&mut self, source: &str) -> PidginResult<String> {
+    self.compiled_forms = Some(vec![]);
+    let result = self.eval_source(source, "source");
+    let forms = self.compiled_forms.take().unwrap_or_default();
+    result?;
+    let artifact = Artifact {
+      forms,
+      namespace: self.current_namespace().to_string(),
+    };
+    write_artifact(&artifact, &self.symbol_ledger)
+  }
+  #[cfg(feature = "serde")]
+  pub fn compile_file(
+    &mut self,
+    source_path: impl AsRef<Path>,
+    artifact_path: impl AsRef<Path>,
+  ) -> PidginResult<()> {
+    let source_path = source_path.as_ref();
+    let source = std::fs::read_to_string(source_path).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", source_path.display()))
+    })?;
+    let artifact = self.compile_source(&source)?;
+    let artifact_path = artifact_path.as_ref();
+    std::fs::write(artifact_path, artifact).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", artifact_path.display()))
+    })?;
+    Ok(())
+  }
+  // Runs the forms of an artifact made by `compile_source`, returning the
+  // value of the last one
+  #[cfg(feature = "serde")]
+  pub fn load_compiled_source(&mut self, source: &str) -> PidginResult<Value> {
+    let artifact =
+      read_artifact(source, Rc::make_mut(&mut self.symbol_ledger))?;
+    self.run_artifact(artifact)
+  }
+  #[cfg(feature = "serde")]
+  fn run_artifact(&mut self, artifact: Artifact) -> PidginResult<Value> {
+    let mut value = Value::Nil;
+    for form in artifact.forms {
+      self.enter_namespace(form.namespace);
+      value = self.eval_bytecode(form.block)?;
+      if let Some(definition) = form.definition {
+        let name =
+          Rc::make_mut(&mut self.symbol_ledger).symbol_index(definition.name);
+        self.bind_definition(
+          name,
+          value.clone(),
+          definition.private,
+          definition.dynamic,
+        );
+      }
+    }
+    self.enter_namespace(artifact.namespace);
+    Ok(value)
+  }
+  #[cfg(feature = "serde")]
+  pub fn load_compiled(
+    &mut self,
+    path: impl AsRef<Path>,
+  ) -> PidginResult<Value> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", path.display()))
+    })?;
+    self.load_compiled_source(&source)
+  }
+  // Only globals that an artifact can represent can be sent to other threads,
+  // so if any global holds a host function or object, a record, or a
+  // protocol, this fails with the names of all such globals
+  #[cfg(feature = "serde")]
+  pub fn snapshot(&self) -> PidginResult<EnvironmentSnapshot> {
+    let (artifact, left_out) = self.globals_artifact();
+    if !left_out.is_empty() {
+      return Err(PidginError::UnportableGlobals(left_out));
+    }
+    Ok(EnvironmentSnapshot {
+      artifact: write_artifact(&artifact, &self.symbol_ledger)?.into(),
+    })
+  }
+  // An artifact that defines each global that an artifact can represent,
+  // and the qualified names of the globals that were left out of it
+  #[cfg(feature = "serde")]
+  fn globals_artifact(&self) -> (Artifact, Vec<String>) {
+    let mut left_out = vec![];
+    let forms = self
+      .global_environment
+      .iter()
+      .filter(|(global, value)| {
+        let portable = is_portable(value, Some(&self.symbol_ledger));
+        if !portable {
+          left_out.extend(self.symbol_ledger.symbol_name(global).cloned());
+        }
+        portable
+      })
+      .filter_map(|(global, value)| {
+        let (namespace, name) =
+          self.symbol_ledger.symbol_name(global)?.split_once('/')?;
+        Some(CompiledForm {
+          namespace: namespace.to_string(),
+          definition: Some(CompiledDefinition {
+            name: name.to_string(),
+            private: self.namespaces.is_private(*global),
+            dynamic: self.symbol_ledger.is_dynamic(global),
+          }),
+          block: Block::new(
+            vec![
+              GenericInstruction::Const(0, 0),
+              GenericInstruction::Return(0),
+            ],
+            vec![value.clone()],
+          ),
+        })
+      })
+      .collect();
+    left_out.sort();
+    let artifact = Artifact {
+      forms,
+      namespace: self.current_namespace().to_string(),
+    };
+    (artifact, left_out)
+  }
+  #[cfg(feature = "serde")]
+  pub fn from_snapshot(snapshot: &EnvironmentSnapshot) -> PidginResult<Self> {
+    let mut evaluator = Self::default();
+    evaluator.load_compiled_source(&snapshot.artifact)?;
+    Ok(evaluator)
+  }
+  // Saves the state of the whole session, which `load_image_source` can
+  // restore after a restart. Unlike with `snapshot`, globals holding host
+  // values are quietly left out
+  #[cfg(feature = "serde")]
+  pub fn image(&self) -> PidginResult<String> {
+    let name =
+      |global: &SymbolIndex| self.symbol_ledger.symbol_name(global).cloned();
+    let image = Image {
+      artifact: self.globals_artifact().0,
+      namespaces: self
+        .namespaces
+        .requirements()
+        .map(|(name, required)| (name.to_string(), required.to_vec()))
+        .collect(),
+      declared: self.declared.iter().filter_map(name).collect(),
+      tests: self.tests.iter().filter_map(name).collect(),
+      rng: self.state.rng(),
+    };
+    write_image(&image, &self.symbol_ledger)
+  }
+  #[cfg(feature = "serde")]
+  pub fn save_image(&self, path: impl AsRef<Path>) -> PidginResult<()> {
+    let image = self.image()?;
+    let path = path.as_ref();
+    std::fs::write(path, image).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", path.display()))
+    })?;
+    Ok(())
+  }
+  // Restores a session saved with `image` into this evaluator, on top of
+  // whatever it has already defined
+  #[cfg(feature = "serde")]
+  pub fn load_image_source(&mut self, source: &str) -> PidginResult<()> {
+    let image = read_image(source, Rc::make_mut(&mut self.symbol_ledger))?;
+    // every namespace has to exist before any of them can require it
+    for (name, _) in &image.namespaces {
+      self.enter_namespace(name.clone());
+    }
+    for (name, required) in image.namespaces {
+      self.enter_namespace(name);
+      for required in required {
+        self.namespaces.require(required)?;
+      }
+    }
+    for declared in image.declared {
+      let (namespace, name) = declared.split_once('/').ok_or_else(|| {
+        PidginError::InvalidImage(format!("unqualified name {declared}"))
+      })?;
+      self.enter_namespace(namespace.to_string());
+      let symbol_ledger = Rc::make_mut(&mut self.symbol_ledger);
+      let name = symbol_ledger.symbol_index(name.to_string());
+      let global = self.namespaces.define(name, false, symbol_ledger);
+      self.declared.insert(global);
+    }
+    self.compilation_version += 1;
+    self.run_artifact(image.artifact)?;
+    for test in image.tests {
+      let global =
+        Rc::make_mut(&mut self.symbol_ledger).symbol_index(test.clone());
+      if !self.global_environment.contains_key(&global) {
+        return Err(PidginError::InvalidImage(format!(
+          "test {test} has no definition"
+        )));
+      }
+      if !self.tests.contains(&global) {
+        self.tests.push(global);
+      }
+    }
+    self.state.seed_rng(image.rng.state());
+    Ok(())
+  }
+  #[cfg(feature = "serde")]
+  pub fn load_image(&mut self, path: impl AsRef<Path>) -> PidginResult<()> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|error| {
+      RuntimeError::FileIO(format!("{}: {error}", path.display()))
+    })?;
+    self.load_image_source(&source)
+  }
+  #[cfg(feature = "serde")]
+  fn record_compiled_form(
+    &mut self,
+    definition: Option<CompiledDefinition>,
+    block: &Block,
+  ) {
+    let namespace = self.current_namespace().to_string();
+    if let Some(forms) = &mut self.compiled_forms {
+      forms.push(CompiledForm {
+        namespace,
+        definition,
+        block: block.clone(),
+      });
+    }
+  }
+  // Records, protocols, and type extensions are built out of host functions,
+  // which an artifact has no way to hold
+  #[cfg(feature = "serde")]
+  fn check_compilable(&self, form: &str) -> PidginResult<()> {
+    if self.compiled_forms.is_some() {
+      return Err(PidginError::InvalidArtifact(format!(
+        "`{form}` can't be compiled ahead of time"
+      )));
+    }
+    Ok(())
+  }
+  // Handles `(load-file path)`. Unlike `eval_file`, this is only available
+  // when file IO has been enabled, like the other functions that read files
+  fn load_file(&mut self, path_expression: Expression) -> PidginResult<Value> {
+    if !self.capabilities.file_io {
+      return Err(RuntimeError::FileIODisabled("load-file".to_string()).into());
+    }
+    match self.eval_expression(path_expression)? {
+      Value::Str(path) => self.eval_file(path.as_str()),
+      _ => Err(RuntimeError::ArgumentNotString.into()),
+    }
   }
   fn compile_ast_to_ir(
     &mut self,
@@ -46,46 +591,726 @@ impl Evaluator {
     let mut constants = vec![];
     let last_register = build_expression_ir(
       expression,
-      &|symbol| self.global_environment.contains_key(&symbol),
+      &|symbol| {
+        self.namespaces.resolve(symbol).filter(|global| {
+          self.global_environment.contains_key(global)
+            || self.declared.contains(global)
+        })
+      },
       &HashMap::new(),
-      &mut self.symbol_ledger,
+      Rc::make_mut(&mut self.symbol_ledger),
       &mut 0,
       &mut instructions,
       &mut constants,
     )?;
     instructions.push(GenericInstruction::Return(last_register));
-    Ok(SSABlock::new(instructions, constants))
+    let ir = SSABlock::new(instructions, constants);
+    if let Some(emit_log) = &mut self.emit_log {
+      emit_log.record(CompilationStage::Ir, || format!("{ir:#?}"));
+    }
+    Ok(ir)
   }
   fn compile_ir_to_bytecode(
     &mut self,
     ir: SSABlock<()>,
   ) -> PidginResult<Block> {
-    Ok(raw_ir_to_bytecode(ir)?)
+    // functions in the form are only compiled once they're first called
+    let bytecode = raw_ir_to_bytecode(defer_function_constants(ir))?;
+    if let Some(emit_log) = &mut self.emit_log {
+      emit_log.record(CompilationStage::Bytecode, || format!("{bytecode:#?}"));
+    }
+    Ok(bytecode)
   }
-  fn eval_bytecode(&self, block: Block) -> RuntimeResult<Value> {
-    EvaluationState::new(block)
-      .evaluate(&self.global_environment)
-      .map(|value| value.unwrap_or(Value::Nil))
+  // Compiles an expression whose `comptime` forms have already been expanded,
+  // reusing the bytecode from the last time the same expression was compiled
+  // if nothing it could refer to has changed since. The cache is skipped while
+  // emitting, so that every stage gets recorded
+  fn compile(&mut self, expression: Expression) -> PidginResult<Block> {
+    let cacheable = self.emit_log.is_none();
+    if cacheable {
+      if let Some(block) = self
+        .compilation_cache
+        .get(&expression, self.compilation_version)
+      {
+        return Ok(block);
+      }
+    }
+    let lifted = expression
+      .clone()
+      .lift_lambdas(&HashSet::new(), Rc::make_mut(&mut self.symbol_ledger))?;
+    let ir = self.compile_ast_to_ir(lifted)?;
+    let bytecode = self.compile_ir_to_bytecode(ir)?;
+    if cacheable {
+      self.compilation_cache.insert(
+        expression,
+        self.compilation_version,
+        bytecode.clone(),
+      );
+    }
+    Ok(bytecode)
+  }
+  fn eval_bytecode(&mut self, block: Block) -> RuntimeResult<Value> {
+    let state = &mut self.state;
+    state.reset(block);
+    state.set_symbol_ledger(std::mem::take(&mut self.symbol_ledger));
+    state.set_capabilities(self.capabilities);
+    state.set_environment_generation(self.environment_generation);
+    let result = state.evaluate(&self.global_environment);
+    self.symbol_ledger = state
+      .take_symbol_ledger()
+      .expect("evaluation state lost the symbol ledger");
+    if result.is_err() {
+      self.failed_frames = state.frames();
+    }
+    result.map(|value| value.unwrap_or(Value::Nil))
+  }
+  // Evaluates an expression by interpreting its raw IR, without compiling it
+  // to bytecode
+  fn eval_reference(&mut self, expression: &Expression) -> PidginResult<Value> {
+    let lifted = expression
+      .clone()
+      .lift_lambdas(&HashSet::new(), Rc::make_mut(&mut self.symbol_ledger))?;
+    let ir = self.compile_ast_to_ir(lifted)?;
+    let interpreter = ReferenceInterpreter::new(
+      self.global_environment.clone(),
+      self.capabilities,
+      std::mem::take(&mut self.symbol_ledger),
+    );
+    let result = interpreter.evaluate(&ir);
+    self.symbol_ledger = interpreter
+      .take_symbol_ledger()
+      .expect("reference interpreter lost the symbol ledger");
+    Ok(result?)
+  }
+  // Evaluates each top-level form of the source with the bytecode VM, and
+  // also by interpreting its raw IR, returning both results for every form
+  // that was compiled. Forms are run twice, so those with side effects other
+  // than definitions can disagree
+  pub fn differential_eval(&mut self, source: &str) -> Vec<DifferentialResult> {
+    self.check_against_reference = true;
+    let results = parse_sexps(source)
+      .into_iter()
+      .filter_map(|tree| {
+        let form = tree.to_string();
+        let vm = self
+          .parse_tree(tree)
+          .and_then(|expression| self.eval_expression_form(expression));
+        let reference = self.reference_result.take()?;
+        Some(DifferentialResult {
+          form,
+          vm,
+          reference,
+        })
+      })
+      .collect();
+    self.check_against_reference = false;
+    results
   }
   pub fn get_binding(&mut self, name: &str) -> Option<&Value> {
-    let symbol_index = self.symbol_ledger.symbol_index(name.to_string());
-    self.global_environment.get(&symbol_index)
+    let symbol_index =
+      Rc::make_mut(&mut self.symbol_ledger).symbol_index(name.to_string());
+    self
+      .namespaces
+      .resolve(symbol_index)
+      .and_then(|global| self.global_environment.get(&global))
+  }
+  // Binds a value in the current namespace, returning the qualified symbol
+  // that it's stored under
+  fn define(
+    &mut self,
+    name: SymbolIndex,
+    value: Value,
+    private: bool,
+  ) -> SymbolIndex {
+    let global = self.namespaces.define(
+      name,
+      private,
+      Rc::make_mut(&mut self.symbol_ledger),
+    );
+    if let Some(callbacks) = self.global_watches.get(&global) {
+      let qualified_name = self
+        .symbol_ledger
+        .symbol_name(&global)
+        .expect("unregistered global");
+      for callback in callbacks {
+        callback(qualified_name, &value);
+      }
+    }
+    if self.global_environment.insert(global, value).is_none() || private {
+      self.compilation_version += 1;
+    }
+    self.environment_generation = next_environment_generation();
+    global
+  }
+  // The qualified symbol that a definition of `name` would be stored under,
+  // whether or not it's been defined yet
+  fn qualify(&mut self, name: &str) -> SymbolIndex {
+    let symbol_ledger = Rc::make_mut(&mut self.symbol_ledger);
+    let symbol_index = symbol_ledger.symbol_index(name.to_string());
+    self.namespaces.resolve(symbol_index).unwrap_or_else(|| {
+      symbol_ledger
+        .symbol_index(format!("{}/{name}", self.namespaces.current_name()))
+    })
+  }
+  // Calls `callback` with the qualified name and new value whenever the
+  // global that `name` refers to is defined or redefined
+  pub fn watch_global(
+    &mut self,
+    name: &str,
+    callback: impl Fn(&str, &Value) + 'static,
+  ) {
+    let global = self.qualify(name);
+    self
+      .global_watches
+      .entry(global)
+      .or_default()
+      .push(Rc::new(callback));
+  }
+  pub fn unwatch_global(&mut self, name: &str) {
+    let global = self.qualify(name);
+    self.global_watches.remove(&global);
+  }
+  // Calls `callback` whenever a register is written while the evaluator runs
+  // code. Registers are numbered within each function's frame, so this fires
+  // for the register in every frame
+  pub fn watch_register(
+    &mut self,
+    register: Register,
+    callback: impl Fn(Register, &Value) + 'static,
+  ) {
+    self.state.watch_register(register, callback);
+  }
+  pub fn unwatch_registers(&mut self) {
+    self.state.unwatch_registers();
+  }
+  // Calls `output` with each line that `print`, `pprint`, and `time` write,
+  // rather than writing them to standard output
+  pub fn set_output(&mut self, output: impl Fn(&str) + 'static) {
+    self.state.set_output(output);
+  }
+  // Binds the value of a `def`, along with whether it's dynamic
+  fn bind_definition(
+    &mut self,
+    name: SymbolIndex,
+    value: Value,
+    private: bool,
+    dynamic: bool,
+  ) {
+    // A redefinition replaces everything about the old definition, so a
+    // global that was dynamic stops being so unless it's marked again
+    let global = self.define(name, value, private);
+    if self.symbol_ledger.is_dynamic(&global) != dynamic {
+      self.compilation_version += 1;
+    }
+    Rc::make_mut(&mut self.symbol_ledger).set_dynamic(global, dynamic);
+  }
+  fn enter_namespace(&mut self, name: String) {
+    if name != self.current_namespace() {
+      self.namespaces.enter(name);
+      self.compilation_version += 1;
+    }
+  }
+  // Binds a host value as a global named `name`
+  pub fn define_value(&mut self, name: &str, value: impl Into<Value>) {
+    self.define_globals(vec![(name.to_string(), value.into())]);
+  }
+  // Exposes a Rust function to source code as a global named `name`
+  pub fn define_fn(
+    &mut self,
+    name: &str,
+    args: impl Into<AritySpecifier>,
+    f: impl Fn(Vec<Value>) -> ExternalFnResult + 'static,
+  ) {
+    let value = ExternalFunction::named(name, args, f).into();
+    self.define_globals(vec![(name.to_string(), value)]);
+  }
+  // Registers a Rust function as a builtin named `name`. Unlike a function
+  // bound with `define_fn`, the compiler resolves it like a core function,
+  // so calls to it skip the global lookup, and it can't be shadowed
+  pub fn define_builtin(
+    &mut self,
+    name: &str,
+    args: impl Into<AritySpecifier>,
+    f: impl Fn(Vec<Value>) -> ExternalFnResult + 'static,
+  ) {
+    self.register_builtin(ExternalFunction::named(name, args, f));
+  }
+  // Like `define_builtin`, but for a function without side effects whose
+  // result depends only on its arguments, so that calls with constant
+  // arguments can be made during compilation
+  pub fn define_pure_builtin(
+    &mut self,
+    name: &str,
+    args: impl Into<AritySpecifier>,
+    f: impl Fn(Vec<Value>) -> ExternalFnResult + 'static,
+  ) {
+    self.register_builtin(ExternalFunction {
+      pure: true,
+      ..ExternalFunction::named(name, args, f)
+    });
+  }
+  fn register_builtin(&mut self, f: ExternalFunction) {
+    Rc::make_mut(&mut self.symbol_ledger).define_builtin(f);
+    self.compilation_version += 1;
+  }
+  // Binds a host object as a global, so that scripts can pass it to the
+  // methods registered for its type with `define_method`
+  pub fn define_object<T: Any>(&mut self, name: &str, object: T) {
+    self.define_globals(vec![(name.to_string(), Value::external(object))]);
+  }
+  // Binds a host resource as a global, like `define_object`. `finalizer` runs
+  // once the last value holding it is dropped, or once the evaluator is,
+  // whichever comes first
+  pub fn define_resource<T: Any>(
+    &mut self,
+    name: &str,
+    resource: T,
+    finalizer: impl FnOnce(&T) + 'static,
+  ) {
+    let value = Value::external_with_finalizer(resource, finalizer);
+    self.state.track_finalizer(&value);
+    self.define_globals(vec![(name.to_string(), value)]);
+  }
+  // Exposes a method of a host type, which scripts can call with
+  // `(. object name args...)`, where there are `args` other arguments. It's
+  // also bound as a global function that takes the object as its first
+  // argument
+  pub fn define_method<T: Any>(
+    &mut self,
+    name: &str,
+    args: u8,
+    f: impl Fn(&T, Vec<Value>) -> ExternalFnResult + 'static,
+  ) {
+    let method = ExternalFunction::named(name, args + 1, move |mut args| {
+      let object = args.remove(0).casted_external::<T>().ok_or_else(|| {
+        Rc::new(RuntimeError::ArgumentNotExternal(
+          type_name::<T>().to_string(),
+        )) as Rc<dyn Error>
+      })?;
+      f(&object, args)
+    });
+    self.state.define_method(
+      TypeId::of::<T>(),
+      name.to_string(),
+      method.clone(),
+    );
+    self.define_globals(vec![(name.to_string(), method.into())]);
+  }
+  // Registers the methods of a type annotated with `#[export]`
+  pub fn export<T: Export>(&mut self) {
+    T::export_methods(self);
+  }
+  // Lets a module bind its functions and types, unless a module with the same
+  // name has already been installed. Returns whether it was installed
+  pub fn install_module(&mut self, module: &dyn PidginModule) -> bool {
+    if !self.modules.insert(module.name().to_string()) {
+      return false;
+    }
+    module.register(self);
+    true
+  }
+  // Loads the module declared by a plugin library, and installs it
+  //
+  // Safety: see `plugin::dynamic::load_module`
+  #[cfg(feature = "plugins")]
+  pub unsafe fn load_plugin(
+    &mut self,
+    path: impl AsRef<Path>,
+  ) -> PidginResult<bool> {
+    let module = super::plugin::dynamic::load_module(path.as_ref())
+      .map_err(PidginError::InvalidPlugin)?;
+    Ok(self.install_module(module.as_ref()))
+  }
+  // Binds the constructor, predicate, and field accessors of a new record
+  // type, returning the constructor
+  fn define_record(&mut self, name: String, field_names: Vec<String>) -> Value {
+    let record_type = Rc::new(RecordType::new(name.clone(), field_names));
+    self.record_types.insert(name, record_type.clone());
+    let record_fns = record_fns(record_type);
+    let constructor = record_fns[0].1.clone();
+    self.define_globals(record_fns);
+    constructor
+  }
+  fn define_globals(&mut self, bindings: Vec<(String, Value)>) {
+    for (name, value) in bindings {
+      let symbol_index =
+        Rc::make_mut(&mut self.symbol_ledger).symbol_index(name);
+      self.define(symbol_index, value, false);
+    }
+  }
+  fn type_key(&self, type_name: &str) -> PidginResult<TypeKey> {
+    self
+      .record_types
+      .get(type_name)
+      .map(|record_type| TypeKey::Record(record_type.clone()))
+      .or_else(|| TypeKey::builtin(type_name))
+      .ok_or_else(|| ASTError::UnknownType(type_name.to_string()).into())
+  }
+  // Adds implementations of protocol methods for a type, returning nil
+  fn extend_type(
+    &mut self,
+    type_name: String,
+    implementations: Vec<(String, Expression)>,
+  ) -> PidginResult<Value> {
+    let type_key = self.type_key(&type_name)?;
+    for (method_name, expression) in implementations {
+      let protocol_fn = match self.get_binding(&method_name) {
+        Some(Value::ProtocolFn(protocol_fn)) => protocol_fn.clone(),
+        _ => return Err(ASTError::NotAProtocolMethod(method_name).into()),
+      };
+      let implementation = self.eval_expression(expression)?;
+      protocol_fn.implement(type_key.clone(), implementation);
+    }
+    Ok(Value::Nil)
+  }
+  // Binds a test's body as a function, so that it can be run later
+  fn define_test(
+    &mut self,
+    name: SymbolIndex,
+    body: Expression,
+  ) -> PidginResult<()> {
+    let ignored =
+      Rc::make_mut(&mut self.symbol_ledger).generate_unique_symbol();
+    let test = self.eval_expression(Expression::Function {
+      arg_names: vec![ignored],
+      body: vec![body],
+    })?;
+    let global = self.define(name, test, false);
+    if !self.tests.contains(&global) {
+      self.tests.push(global);
+    }
+    Ok(())
+  }
+  // Runs every test defined with `deftest`, in the order they were defined
+  pub fn run_tests(&mut self) -> TestReport {
+    let results = self
+      .tests
+      .clone()
+      .into_iter()
+      .filter_map(|global| {
+        let test = self.global_environment.get(&global)?.clone();
+        let name = self.symbol_ledger.symbol_name(&global)?.clone();
+        let result = self
+          .eval_bytecode(Block::for_call(test, vec![Value::Nil]))
+          .map_err(PidginError::from);
+        Some(TestResult { name, result })
+      })
+      .collect();
+    TestReport { results }
+  }
+  // Handles `(run-tests)`, printing each failure and returning a map of how
+  // many tests passed and failed
+  fn run_tests_and_print(&mut self) -> Value {
+    let report = self.run_tests();
+    for failure in report.failures() {
+      if let Err(error) = &failure.result {
+        println!(
+          "FAIL in {}: {}",
+          failure.name,
+          self.describe_error(error.clone())
+        );
+      }
+    }
+    let failed = report.results.len() - report.passed();
+    println!("Ran {} tests, {failed} failed", report.results.len());
+    let ledger = Rc::make_mut(&mut self.symbol_ledger);
+    [(":passed", report.passed()), (":failed", failed)]
+      .into_iter()
+      .map(|(key, count)| {
+        (
+          Value::Symbol(ledger.symbol_index(key.to_string())),
+          Value::from(count as i64),
+        )
+      })
+      .collect::<HashMap<_, _>>()
+      .into()
+  }
+  // Evaluates each `(comptime expr)` in the expression, replacing it with the
+  // resulting value as a constant
+  fn expand_comptime(
+    &mut self,
+    expression: Expression,
+  ) -> PidginResult<Expression> {
+    if let Some(form) = expression.as_comptime(&self.symbol_ledger)? {
+      let value = self.eval_expression(form.clone())?;
+      return Ok(Expression::constant(value)?);
+    }
+    Ok(match expression {
+      Expression::Application(subexpressions) => Expression::Application(
+        subexpressions
+          .into_iter()
+          .map(|subexpression| self.expand_comptime(subexpression))
+          .collect::<Result<_, _>>()?,
+      ),
+      Expression::Function { arg_names, body } => Expression::Function {
+        arg_names,
+        body: body
+          .into_iter()
+          .map(|expression| self.expand_comptime(expression))
+          .collect::<Result<_, _>>()?,
+      },
+      other => other,
+    })
+  }
+  fn eval_expression(&mut self, expression: Expression) -> PidginResult<Value> {
+    let expression = self.expand_comptime(expression)?;
+    let bytecode = self.compile(expression)?;
+    Ok(self.eval_bytecode(bytecode)?)
+  }
+  // Evaluates a line typed into the REPL, binding the last three results to
+  // `*1`, `*2`, and `*3` and the last error to `*e`
+  pub fn repl_eval(&mut self, expression_string: &str) -> PidginResult<Value> {
+    self.failed_frames.clear();
+    let result = self.eval(expression_string);
+    match &result {
+      Ok(value) => {
+        let history = ["*1", "*2"]
+          .map(|name| self.get_binding(name).cloned().unwrap_or(Value::Nil));
+        self.define_globals(vec![
+          ("*3".to_string(), history[1].clone()),
+          ("*2".to_string(), history[0].clone()),
+          ("*1".to_string(), value.clone()),
+        ]);
+      }
+      Err(error) => {
+        let error_value = match error {
+          PidginError::Runtime(runtime_error) => runtime_error.clone().into(),
+          other => self.describe_error(other.clone()).into(),
+        };
+        self.define_globals(vec![("*e".to_string(), error_value)]);
+      }
+    }
+    result
+  }
+  // Starts recording which instructions run, so that a report can be made of
+  // how much of each top-level form evaluated from then on was covered
+  pub fn enable_coverage(&mut self) {
+    self.state.enable_coverage();
+    self.recorded_forms.get_or_insert_with(Vec::new);
+  }
+  fn record_form(&mut self, form: Option<String>, block: &Block) {
+    if let (Some(form), Some(recorded_forms)) = (form, &mut self.recorded_forms)
+    {
+      // forms that were compiled before reuse their cached bytecode
+      if !recorded_forms.iter().any(|(_, recorded)| {
+        Rc::ptr_eq(&recorded.instructions, &block.instructions)
+      }) {
+        recorded_forms.push((form, block.clone()));
+      }
+    }
+  }
+  // Starts recording how often each instruction of the top-level forms
+  // evaluated from then on runs, and how long it takes
+  pub fn enable_profiling(&mut self) {
+    self.state.enable_profiling();
+    self.recorded_forms.get_or_insert_with(Vec::new);
+  }
+  // Makes the random functions give the same results every time, outside of
+  // `with-seed` forms, which use their own seeds
+  pub fn seed_rng(&mut self, seed: u64) {
+    self.state.seed_rng(seed);
+  }
+  // Records the random seed, times, and host function results that the
+  // following evaluations see, until `stop_tracing` returns them as a trace
+  pub fn start_recording(&mut self) {
+    self.state.start_recording();
+  }
+  // Makes the following evaluations see the inputs recorded in a trace rather
+  // than live ones, so that they do exactly what the recorded run did
+  pub fn start_replay(&mut self, trace: Trace) {
+    self.state.start_replay(trace);
+  }
+  // Returns the trace being recorded, or the part of the one being replayed
+  // that hasn't been used yet
+  pub fn stop_tracing(&mut self) -> Option<Trace> {
+    self.state.stop_tracing()
+  }
+  // Writes a trace as EDN. Values that EDN can't represent, like host
+  // objects, can't be written
+  pub fn write_trace(&self, trace: &Trace) -> PidginResult<String> {
+    let trace =
+      trace.encode(|error| error.description(Some(&self.symbol_ledger)));
+    self.write_edn(&trace)
+  }
+  pub fn read_trace(&mut self, source: &str) -> PidginResult<Trace> {
+    let trace = self.read_edn(source)?;
+    Ok(Trace::decode(&trace)?)
+  }
+  pub fn profile_report(&self) -> ProfileReport {
+    let (Some(recorded_forms), Some(profile)) =
+      (&self.recorded_forms, self.state.profile())
+    else {
+      return ProfileReport::default();
+    };
+    let function_names = self
+      .global_environment
+      .iter()
+      .filter_map(|(global, value)| match value {
+        Value::CompositeFn(f) => Some((
+          f.block().ok()?.instructions.as_ptr(),
+          self.symbol_ledger.symbol_name(global)?.clone(),
+        )),
+        _ => None,
+      })
+      .collect();
+    ProfileReport::new(recorded_forms, profile, function_names)
+  }
+  pub fn coverage_report(&self) -> CoverageReport {
+    let (Some(recorded_forms), Some(coverage)) =
+      (&self.recorded_forms, self.state.coverage())
+    else {
+      return CoverageReport::default();
+    };
+    CoverageReport {
+      forms: recorded_forms
+        .iter()
+        .map(|(form, block)| FormCoverage::new(form.clone(), block, coverage))
+        .collect(),
+    }
+  }
+  // Counts the values reachable from the evaluator's globals and its machine
+  pub fn heap_stats(&self) -> HeapStats {
+    let mut walker = HeapWalker::default();
+    walker.values(self.global_environment.values());
+    self.state.walk_heap(&mut walker);
+    walker.stats
+  }
+  // The stack frames left by the last evaluation that failed at runtime,
+  // innermost first. The REPL clears them before each line, so after a line
+  // fails these show where it went wrong, unless it failed before running
+  pub fn failed_frames(&self) -> &[FrameView] {
+    &self.failed_frames
   }
   pub fn eval(&mut self, expression_string: &str) -> PidginResult<Value> {
-    let expression = self
-      .parse(expression_string)?
-      .lift_lambdas(&HashSet::new(), &mut self.symbol_ledger)?;
-    if let Some((name, value_expression)) =
-      expression.as_definition(&self.symbol_ledger)?
+    let expression = self.parse(expression_string)?;
+    self.eval_expression_form(expression)
+  }
+  fn eval_expression_form(
+    &mut self,
+    expression: Expression,
+  ) -> PidginResult<Value> {
+    if let Some(path_expression) =
+      expression.as_load_file(&self.symbol_ledger)?
     {
-      let ir = self.compile_ast_to_ir(value_expression)?;
-      let bytecode = self.compile_ir_to_bytecode(ir)?;
+      return self.load_file(path_expression);
+    }
+    if let Some(name) =
+      expression.as_namespace_declaration(&self.symbol_ledger)?
+    {
+      self.enter_namespace(name);
+      return Ok(Value::Nil);
+    }
+    if let Some(value) =
+      expression.as_macroexpansion(Rc::make_mut(&mut self.symbol_ledger))?
+    {
+      #[cfg(feature = "serde")]
+      self.record_compiled_form(
+        None,
+        &Block::new(
+          vec![
+            GenericInstruction::Const(0, 0),
+            GenericInstruction::Return(0),
+          ],
+          vec![value.clone()],
+        ),
+      );
+      return Ok(value);
+    }
+    if let Some(names) = expression.as_declaration(&self.symbol_ledger)? {
+      for name in names {
+        let global = self.namespaces.define(
+          name,
+          false,
+          Rc::make_mut(&mut self.symbol_ledger),
+        );
+        if self.declared.insert(global) {
+          self.compilation_version += 1;
+        }
+      }
+      return Ok(Value::Nil);
+    }
+    if let Some(names) = expression.as_require(&self.symbol_ledger)? {
+      for name in names {
+        self.namespaces.require(name)?;
+        self.compilation_version += 1;
+      }
+      return Ok(Value::Nil);
+    }
+    if let Some((name, field_names)) =
+      expression.as_record_definition(&self.symbol_ledger)?
+    {
+      #[cfg(feature = "serde")]
+      self.check_compilable("defrecord")?;
+      return Ok(self.define_record(name, field_names));
+    }
+    if let Some((name, method_names)) =
+      expression.as_protocol_definition(&self.symbol_ledger)?
+    {
+      #[cfg(feature = "serde")]
+      self.check_compilable("defprotocol")?;
+      self.define_globals(protocol_fns(name, method_names));
+      return Ok(Value::Nil);
+    }
+    if let Some((type_name, implementations)) =
+      expression.as_type_extension(&self.symbol_ledger)?
+    {
+      #[cfg(feature = "serde")]
+      self.check_compilable("extend-type")?;
+      return self.extend_type(type_name, implementations);
+    }
+    if let Some((name, body)) =
+      expression.as_test_definition(&self.symbol_ledger)?
+    {
+      #[cfg(feature = "serde")]
+      self.check_compilable("deftest")?;
+      self.define_test(name, body)?;
+      return Ok(Value::Nil);
+    }
+    if expression.is_test_run(&self.symbol_ledger)? {
+      #[cfg(feature = "serde")]
+      self.check_compilable("run-tests")?;
+      return Ok(self.run_tests_and_print());
+    }
+    let expression = self.expand_comptime(expression)?;
+    let form = self
+      .recorded_forms
+      .is_some()
+      .then(|| expression.to_string(&self.symbol_ledger));
+    if let Some(definition) = expression.as_definition(&self.symbol_ledger)? {
+      if self.check_against_reference {
+        self.reference_result = Some(self.eval_reference(&definition.value));
+      }
+      let bytecode = self.compile(definition.value)?;
+      #[cfg(feature = "serde")]
+      self.record_compiled_form(
+        Some(CompiledDefinition {
+          name: self
+            .symbol_ledger
+            .symbol_name(&definition.name)
+            .cloned()
+            .unwrap_or_default(),
+          private: definition.private,
+          dynamic: definition.dynamic,
+        }),
+        &bytecode,
+      );
+      self.record_form(form, &bytecode);
       let value = self.eval_bytecode(bytecode)?;
-      self.global_environment.insert(name, value.clone());
+      self.bind_definition(
+        definition.name,
+        value.clone(),
+        definition.private,
+        definition.dynamic,
+      );
       Ok(value)
     } else {
-      let ir = self.compile_ast_to_ir(expression)?;
-      let bytecode = self.compile_ir_to_bytecode(ir)?;
+      if self.check_against_reference {
+        self.reference_result = Some(self.eval_reference(&expression));
+      }
+      let bytecode = self.compile(expression)?;
+      #[cfg(feature = "serde")]
+      self.record_compiled_form(None, &bytecode);
+      self.record_form(form, &bytecode);
       Ok(self.eval_bytecode(bytecode)?)
     }
   }
@@ -0,0 +1,21 @@
+use std::{any::Any, error::Error, rc::Rc};
+
+use crate::runtime::data::Value;
+
+use super::evaluator::Evaluator;
+
+// Implemented by the `#[export]` attribute for types whose methods can be
+// called from scripts on external objects of that type
+pub trait Export: Any {
+  fn export_methods(evaluator: &mut Evaluator);
+}
+
+// Converts an argument passed to an exported method into the Rust type that
+// the method expects
+pub fn extract_arg<T>(value: Value) -> Result<T, Rc<dyn Error>>
+where
+  T: TryFrom<Value>,
+  T::Error: Error + 'static,
+{
+  T::try_from(value).map_err(|error| Rc::new(error) as Rc<dyn Error>)
+}
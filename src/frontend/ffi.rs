@@ -0,0 +1,227 @@
+// A C API, so that hosts written in other languages can embed the VM. Every
+// pointer that one of these functions returns is owned by the caller, and has
+// to be released with the matching `pidgin_*_free` function. Pointer arguments
+// have to be live objects returned by these functions, and strings have to be
+// nul-terminated. `include/pidgin.h` is generated from this file with
+// `cbindgen --config cbindgen.toml --output include/pidgin.h`
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+  ffi::{c_char, c_void, CStr, CString},
+  fmt::Display,
+  ptr,
+  rc::Rc,
+};
+
+use crate::runtime::data::{ExternalFunction, GenericValue::*, Num, Value};
+
+use super::evaluator::Evaluator;
+
+pub struct PidginEvaluator(Evaluator);
+
+// The result of an evaluation, which is either a value or the description of
+// the error that evaluation failed with
+pub enum PidginValue {
+  Value(Value),
+  Error(String),
+}
+
+// Called with the `user_data` pointer it was registered with and the arguments
+// the function was called with, which are only valid for the duration of the
+// call. Returning null returns nil, and otherwise ownership of the returned
+// value passes back to the evaluator
+pub type PidginCallback = extern "C" fn(
+  user_data: *mut c_void,
+  args: *const *const PidginValue,
+  arg_count: usize,
+) -> *mut PidginValue;
+
+#[derive(Debug)]
+struct HostError(String);
+impl Display for HostError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+impl std::error::Error for HostError {}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+  if s.is_null() {
+    None
+  } else {
+    CStr::from_ptr(s).to_str().ok()
+  }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+  // interior nul bytes would cut the string short, so they're dropped
+  CString::new(s.replace('\0', ""))
+    .expect("string still contains a nul byte")
+    .into_raw()
+}
+
+fn into_ptr(value: PidginValue) -> *mut PidginValue {
+  Box::into_raw(Box::new(value))
+}
+
+#[no_mangle]
+pub extern "C" fn pidgin_evaluator_new() -> *mut PidginEvaluator {
+  Box::into_raw(Box::new(PidginEvaluator(Evaluator::default())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_evaluator_free(
+  evaluator: *mut PidginEvaluator,
+) {
+  if !evaluator.is_null() {
+    drop(Box::from_raw(evaluator));
+  }
+}
+
+// Evaluates a nul-terminated UTF-8 string of source code. Never returns null;
+// failures are returned as error values
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_eval(
+  evaluator: *mut PidginEvaluator,
+  source: *const c_char,
+) -> *mut PidginValue {
+  let evaluator = &mut (*evaluator).0;
+  let Some(source) = str_arg(source) else {
+    return into_ptr(PidginValue::Error(
+      "source isn't valid UTF-8".to_string(),
+    ));
+  };
+  into_ptr(match evaluator.eval(source) {
+    Ok(value) => PidginValue::Value(value),
+    Err(error) => PidginValue::Error(evaluator.describe_error(error)),
+  })
+}
+
+// Binds a host function as a global named `name`, which accepts any number of
+// arguments. `user_data` has to stay valid for as long as the evaluator does.
+// Returns false if `name` isn't valid UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_define_fn(
+  evaluator: *mut PidginEvaluator,
+  name: *const c_char,
+  callback: PidginCallback,
+  user_data: *mut c_void,
+) -> bool {
+  let Some(name) = str_arg(name) else {
+    return false;
+  };
+  let name = name.to_string();
+  let f = move |args: Vec<Value>| {
+    let args: Vec<PidginValue> =
+      args.into_iter().map(PidginValue::Value).collect();
+    let arg_pointers: Vec<*const PidginValue> =
+      args.iter().map(|arg| arg as *const PidginValue).collect();
+    let result = callback(user_data, arg_pointers.as_ptr(), arg_pointers.len());
+    if result.is_null() {
+      return Ok(Nil);
+    }
+    match *Box::from_raw(result) {
+      PidginValue::Value(value) => Ok(value),
+      PidginValue::Error(message) => Err(Rc::new(HostError(message)) as _),
+    }
+  };
+  (*evaluator).0.define_value(
+    &name,
+    ExternalFunction {
+      name: Some(name.clone()),
+      args: None,
+      f: Rc::new(f),
+      pure: false,
+    },
+  );
+  true
+}
+
+#[no_mangle]
+pub extern "C" fn pidgin_value_nil() -> *mut PidginValue {
+  into_ptr(PidginValue::Value(Nil))
+}
+
+// Always a float, even when `n` is whole, so integers have to come from
+// `pidgin_value_from_int`
+#[no_mangle]
+pub extern "C" fn pidgin_value_from_number(n: f64) -> *mut PidginValue {
+  into_ptr(PidginValue::Value(n.into()))
+}
+
+#[no_mangle]
+pub extern "C" fn pidgin_value_from_int(n: i64) -> *mut PidginValue {
+  into_ptr(PidginValue::Value(n.into()))
+}
+
+// Returns null if `s` isn't valid UTF-8
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_from_string(
+  s: *const c_char,
+) -> *mut PidginValue {
+  match str_arg(s) {
+    Some(s) => into_ptr(PidginValue::Value(s.into())),
+    None => ptr::null_mut(),
+  }
+}
+
+// An error value, for callbacks to fail with
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_error(
+  message: *const c_char,
+) -> *mut PidginValue {
+  into_ptr(PidginValue::Error(
+    str_arg(message).unwrap_or_default().to_string(),
+  ))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_is_error(
+  value: *const PidginValue,
+) -> bool {
+  matches!(*value, PidginValue::Error(_))
+}
+
+// Writes the value to `out` and returns true if it's a number
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_as_number(
+  value: *const PidginValue,
+  out: *mut f64,
+) -> bool {
+  let n = match &*value {
+    PidginValue::Value(Number(Num::Int(i))) => *i as f64,
+    PidginValue::Value(Number(Num::Float(f))) => **f,
+    _ => return false,
+  };
+  *out = n;
+  true
+}
+
+// The value as Pidgin would print it, or the error's description. Strings are
+// returned without quotes. The result has to be released with
+// `pidgin_string_free`
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_to_string(
+  evaluator: *const PidginEvaluator,
+  value: *const PidginValue,
+) -> *mut c_char {
+  into_c_string(match &*value {
+    PidginValue::Value(Str(s)) => s.to_string(),
+    PidginValue::Value(value) => (*evaluator).0.describe(value.clone()),
+    PidginValue::Error(message) => message.clone(),
+  })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_value_free(value: *mut PidginValue) {
+  if !value.is_null() {
+    drop(Box::from_raw(value));
+  }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pidgin_string_free(s: *mut c_char) {
+  if !s.is_null() {
+    drop(CString::from_raw(s));
+  }
+}
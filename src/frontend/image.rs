@@ -0,0 +1,106 @@
+// Images of a whole evaluator, so that a session's state can be saved to disk
+// and picked up again after a restart. An image is an EDN map holding an
+// artifact that defines each global, like the one in an `EnvironmentSnapshot`,
+// along with the rest of what the evaluator has built up: its namespaces and
+// the namespaces each of them requires, the names it has declared, its tests,
+// and the state of its random number generator.
+//
+// Unlike snapshots, which refuse them, images leave out globals that an
+// artifact can't represent, such as host functions and objects, records, and
+// protocols. A host has to define its globals again before it loads an image
+// that refers to them
+
+use crate::{
+  compiler::ast::token::SymbolLedger,
+  runtime::{
+    data::Value,
+    edn::{read_edn, write_edn},
+    portable::{field, list, string_map as map},
+    random::Rng,
+  },
+};
+
+use super::{
+  artifact::{decode_artifact, encode_artifact, Artifact},
+  error::{PidginError, PidginResult},
+};
+
+const IMAGE_VERSION: i64 = 1;
+
+pub(crate) struct Image {
+  pub artifact: Artifact,
+  pub namespaces: Vec<(String, Vec<String>)>,
+  // Qualified names
+  pub declared: Vec<String>,
+  pub tests: Vec<String>,
+  pub rng: Rng,
+}
+
+fn strings(value: Value) -> PidginResult<Vec<String>> {
+  list(value)?
+    .into_iter()
+    .map(|name| Ok(String::try_from(name)?))
+    .collect()
+}
+
+fn string_list(names: &[String]) -> Value {
+  names
+    .iter()
+    .map(|name| Value::from(name.as_str()))
+    .collect()
+}
+
+pub(crate) fn write_image(
+  image: &Image,
+  symbol_ledger: &SymbolLedger,
+) -> PidginResult<String> {
+  let namespaces = image
+    .namespaces
+    .iter()
+    .map(|(name, required)| {
+      map(vec![
+        ("name", name.as_str().into()),
+        ("requires", string_list(required)),
+      ])
+    })
+    .collect();
+  let image = map(vec![
+    ("version", IMAGE_VERSION.into()),
+    ("globals", encode_artifact(&image.artifact, symbol_ledger)?),
+    ("namespaces", namespaces),
+    ("declared", string_list(&image.declared)),
+    ("tests", string_list(&image.tests)),
+    // EDN integers are signed, so the state is stored with its bits as is
+    ("rng", (image.rng.state() as i64).into()),
+  ]);
+  Ok(write_edn(&image, Some(symbol_ledger))?)
+}
+
+pub(crate) fn read_image(
+  source: &str,
+  symbol_ledger: &mut SymbolLedger,
+) -> PidginResult<Image> {
+  let image = read_edn(source, symbol_ledger)?;
+  let version = i64::try_from(field(&image, "version")?)?;
+  if version != IMAGE_VERSION {
+    return Err(PidginError::InvalidImage(format!(
+      "unsupported version {version}"
+    )));
+  }
+  let namespaces = list(field(&image, "namespaces")?)?
+    .into_iter()
+    .map(|namespace| {
+      Ok((
+        String::try_from(field(&namespace, "name")?)?,
+        strings(field(&namespace, "requires")?)?,
+      ))
+    })
+    .collect::<PidginResult<Vec<_>>>()?;
+  Ok(Image {
+    artifact: decode_artifact(&field(&image, "globals")?, symbol_ledger)?,
+    namespaces,
+    declared: strings(field(&image, "declared")?)?,
+    tests: strings(field(&image, "tests")?)?,
+    rng: Rng::seeded(i64::try_from(field(&image, "rng")?)? as u64),
+  })
+}
@@ -1,21 +1,97 @@
+#[cfg(feature = "serde")]
+pub mod artifact;
+pub mod compilation_cache;
+#[cfg(feature = "cli")]
+pub mod completion;
+pub mod coverage;
+pub mod differential;
+pub mod emit;
 pub mod error;
 pub mod evaluator;
+pub mod export;
+#[cfg(feature = "capi")]
+pub mod ffi;
+#[cfg(feature = "serde")]
+pub mod image;
+pub mod namespaces;
+pub mod plugin;
+pub mod profile;
+#[cfg(feature = "cli")]
+pub mod repl;
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 #[cfg(test)]
 mod tests {
+  use block_macros::block;
+
   use crate::{
     compiler::ast::error::ASTError,
     frontend::error::PidginError,
-    runtime::{data::Value, evaluation},
+    instructions::{Argument::StealArgument, GenericInstruction::*},
+    runtime::{
+      control::{Block, CompositeFunction, FunctionBody},
+      data::{Num, Value},
+      error::RuntimeError,
+      evaluation,
+      external::ExternalValue,
+    },
+  };
+
+  use std::{
+    cell::{Cell, RefCell},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
   };
 
-  use super::evaluator::Evaluator;
+  #[cfg(feature = "cli")]
+  use super::{
+    completion::completion_start,
+    repl::{session_command, watch_command, PostMortem, ReplConfig, WatchLog},
+  };
+  use super::{
+    emit::CompilationStage, evaluator::Evaluator, plugin::PidginModule,
+  };
 
   fn assert_eval_eq<V: Into<Value>>(expr: &str, expected_value: V) {
     let mut evaluator = Evaluator::default();
     assert_eq!(evaluator.eval(expr), Ok(expected_value.into()))
   }
 
+  // A directory for a test's files that no other test or concurrent run
+  // shares, which is removed when it's dropped, even if the test fails
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      static COUNT: AtomicUsize = AtomicUsize::new(0);
+      let dir = std::env::temp_dir().join(format!(
+        "pidgin_{name}_{}_{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+      ));
+      std::fs::create_dir_all(&dir).unwrap();
+      Self(dir)
+    }
+  }
+
+  impl Deref for TempDir {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+      &self.0
+    }
+  }
+
+  impl std::ops::Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.0);
+    }
+  }
+
   #[test]
   fn evaluate_int() {
     assert_eval_eq("1", 1);
@@ -31,6 +107,958 @@ mod tests {
     assert_eval_eq("(+ 1 2)", 3);
   }
 
+  #[test]
+  fn evaluate_big_int_literal() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator
+        .eval("(+ 100000000000000000000 1)")
+        .map(|value| evaluator.describe(value)),
+      Ok("100000000000000000001".to_string())
+    );
+  }
+
+  #[test]
+  fn evaluate_bitwise_functions() {
+    assert_eval_eq("(bit-and 12 10 8)", 8);
+    assert_eval_eq("(bit-shift-right (bit-or 1 2 4) 1)", 3);
+    for shift in ["(bit-shift-left 1 -1)", "(bit-shift-right 1 64)"] {
+      assert_eq!(
+        Evaluator::default().eval(shift),
+        Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+      );
+    }
+  }
+
+  #[cfg(feature = "regex")]
+  #[test]
+  fn evaluate_regex_functions() {
+    assert_eval_eq(r#"(re-find (re-pattern "\d+") "abc123def45")"#, "123");
+    assert_eval_eq(
+      r#"(re-seq "\d+" "abc123def45")"#,
+      Value::from(vec!["123".into(), "45".into()]),
+    );
+    assert_eval_eq(r#"(re-matches "\w+@\w+" "me@host")"#, "me@host");
+    assert_eval_eq(r#"(re-matches "a|ab" "ab")"#, "ab");
+    assert_eval_eq(r#"(re-matches "\d+" "123abc")"#, Value::Nil);
+    assert_eval_eq(r#"(replace "a-b-c" (re-pattern "-") "+")"#, "a+b+c");
+    assert_eval_eq(r#"(replace "a-b-c" "-" "")"#, "abc");
+  }
+
+  #[test]
+  fn file_io_is_disabled_by_default() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval(r#"(slurp "Cargo.toml")"#),
+      Err(PidginError::Runtime(RuntimeError::FileIODisabled(
+        "slurp".to_string()
+      )))
+    );
+  }
+
+  #[test]
+  fn evaluate_file_io_functions() {
+    let dir = TempDir::new("file_io_test");
+    let path = dir.join("lines.txt");
+    let path = path.to_str().unwrap();
+    let mut evaluator = Evaluator::default();
+    evaluator.enable_file_io();
+    evaluator
+      .eval(&format!(r#"(spit "{path}" "first")"#))
+      .unwrap();
+    assert_eq!(
+      evaluator.eval(&format!(r#"(slurp "{path}")"#)),
+      Ok("first".into())
+    );
+    std::fs::write(path, "first\nsecond").unwrap();
+    assert_eq!(
+      evaluator.eval(&format!(r#"(read-lines "{path}")"#)),
+      Ok(Value::from(vec!["first".into(), "second".into()]))
+    );
+    assert_eq!(
+      evaluator.eval(&format!(r#"(list-dir "{}")"#, dir.to_str().unwrap())),
+      Ok(Value::from(vec![path.into()]))
+    );
+  }
+
+  #[test]
+  fn evaluate_records() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(defrecord Point (x y))").unwrap();
+    evaluator.eval("(def p (->Point 1 2))").unwrap();
+    assert_eq!(evaluator.eval("(Point-y p)"), Ok(2.into()));
+    assert_eq!(evaluator.eval("(Point? p)"), Ok(true.into()));
+    assert_eq!(evaluator.eval("(Point? 5)"), Ok(false.into()));
+    assert_eq!(evaluator.eval("p"), evaluator.eval("(->Point 1 2)"));
+    let p = evaluator.eval("p").unwrap();
+    assert_eq!(evaluator.describe(p), "#Point{x 1, y 2}");
+    evaluator.eval("(defrecord Other (x))").unwrap();
+    assert_eq!(
+      evaluator.eval("(Other-x p)"),
+      Err(RuntimeError::ArgumentNotRecord("Other".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_protocols() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(defrecord Point (x y))").unwrap();
+    evaluator
+      .eval("(defprotocol Describe kind double)")
+      .unwrap();
+    evaluator
+      .eval(r#"(extend-type Point (kind (fn (p) "point")))"#)
+      .unwrap();
+    evaluator
+      .eval(
+        r#"(extend-type Number
+             (kind (fn (n) "number"))
+             (double (fn (n) (* n 2))))"#,
+      )
+      .unwrap();
+    evaluator
+      .eval(r#"(extend-type default (kind (fn (x) "other")))"#)
+      .unwrap();
+    assert_eq!(evaluator.eval("(kind (->Point 1 2))"), Ok("point".into()));
+    assert_eq!(evaluator.eval("(kind 5)"), Ok("number".into()));
+    assert_eq!(evaluator.eval(r#"(kind "s")"#), Ok("other".into()));
+    assert_eq!(evaluator.eval("(double 5)"), Ok(10.into()));
+    assert_eq!(
+      evaluator.eval(r#"(double "s")"#),
+      Err(
+        RuntimeError::NoProtocolImplementation(
+          "double".to_string(),
+          "String".to_string()
+        )
+        .into()
+      )
+    );
+  }
+
+  #[test]
+  fn evaluate_metadata() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def f (fn (x) (* x 2)))").unwrap();
+    evaluator
+      .eval(r#"(def g (with-meta f "doubles-x"))"#)
+      .unwrap();
+    assert_eq!(evaluator.eval("(meta g)"), Ok("doubles-x".into()));
+    assert_eq!(evaluator.eval("(meta f)"), Ok(Value::Nil));
+    assert_eq!(evaluator.eval("(g 5)"), Ok(10.into()));
+    evaluator.eval("(defrecord Point (x y))").unwrap();
+    evaluator.eval("(def p (->Point 1 2))").unwrap();
+    evaluator
+      .eval(r#"(def q (with-meta p "annotated"))"#)
+      .unwrap();
+    assert_eq!(evaluator.eval("(meta q)"), Ok("annotated".into()));
+    assert_eq!(evaluator.eval("q"), evaluator.eval("p"));
+    assert_eq!(
+      evaluator.eval(r#"(with-meta 5 "number")"#),
+      Err(RuntimeError::CantAttachMetadata(5.into()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_atoms() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def a (atom 1))").unwrap();
+    assert_eq!(evaluator.eval("@a"), Ok(1.into()));
+    assert_eq!(evaluator.eval("(reset! a 5)"), Ok(5.into()));
+    assert_eq!(evaluator.eval("(deref a)"), Ok(5.into()));
+    assert_eq!(evaluator.eval("(swap! a inc)"), Ok(6.into()));
+    assert_eq!(evaluator.eval("(swap! a + 10 4)"), Ok(20.into()));
+    assert_eq!(evaluator.eval("(swap! a (fn (x) (* x 2)))"), Ok(40.into()));
+    assert_eq!(evaluator.eval("(+ @a 2)"), Ok(42.into()));
+    assert_eq!(
+      evaluator.eval("(deref 5)"),
+      Err(RuntimeError::ArgumentNotCell.into())
+    );
+  }
+
+  #[test]
+  fn evaluate_dynamic_binding() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def ^:dynamic *x* 1)").unwrap();
+    assert_eq!(evaluator.eval("*x*"), Ok(1.into()));
+    assert_eq!(evaluator.eval("(binding (*x* 2) (+ *x* 1))"), Ok(3.into()));
+    assert_eq!(
+      evaluator.eval("(binding (*x* 2) (binding (*x* 5) *x*))"),
+      Ok(5.into())
+    );
+    assert_eq!(
+      evaluator.eval("(+ (binding (*x* 10) *x*) *x*)"),
+      Ok(11.into())
+    );
+    evaluator.eval("(def y 1)").unwrap();
+    assert_eq!(
+      evaluator.eval("(binding (y 2) y)"),
+      Err(ASTError::NonDynamicBinding("y".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_namespaces() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(ns geometry)").unwrap();
+    evaluator.eval("(def scale 10)").unwrap();
+    evaluator.eval("(def ^:private secret 1)").unwrap();
+    assert_eq!(evaluator.eval("(+ scale secret)"), Ok(11.into()));
+    evaluator.eval("(ns my.app)").unwrap();
+    assert_eq!(evaluator.current_namespace(), "my.app");
+    assert_eq!(
+      evaluator.eval("geometry/scale"),
+      Err(ASTError::UnboundSymbol("geometry/scale".to_string()).into())
+    );
+    evaluator.eval("(require 'geometry)").unwrap();
+    assert_eq!(evaluator.eval("(* geometry/scale 2)"), Ok(20.into()));
+    assert_eq!(
+      evaluator.eval("scale"),
+      Err(ASTError::UnboundSymbol("scale".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("geometry/secret"),
+      Err(ASTError::UnboundSymbol("geometry/secret".to_string()).into())
+    );
+    evaluator.eval("(def scale 3)").unwrap();
+    assert_eq!(evaluator.eval("(+ scale geometry/scale)"), Ok(13.into()));
+    assert_eq!(
+      evaluator.eval("(require 'missing)"),
+      Err(ASTError::UnknownNamespace("missing".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_files() {
+    let dir = TempDir::new("eval_file_test");
+    let path = dir.join("forms.pgn");
+    std::fs::write(&path, "(def x 5)\n(def y (* x 2))\n(+ x y)").unwrap();
+    let mut evaluator = Evaluator::default();
+    assert_eq!(evaluator.eval_file(&path), Ok(15.into()));
+    assert_eq!(evaluator.get_binding("y"), Some(&10.into()));
+    let path = path.to_str().unwrap();
+    assert_eq!(
+      evaluator.eval(&format!(r#"(load-file "{path}")"#)),
+      Err(RuntimeError::FileIODisabled("load-file".to_string()).into())
+    );
+    evaluator.enable_file_io();
+    assert_eq!(
+      evaluator.eval(&format!(r#"(load-file "{path}")"#)),
+      Ok(15.into())
+    );
+    std::fs::write(path, "(def z 1)\n(+ z missing)\n(def w 2)").unwrap();
+    assert_eq!(
+      evaluator.eval_file(path),
+      Err(PidginError::InForm {
+        source_name: path.to_string(),
+        form_index: 1,
+        form: "(+ z missing)".to_string(),
+        error: Box::new(ASTError::UnboundSymbol("missing".to_string()).into()),
+      })
+    );
+    assert_eq!(evaluator.get_binding("z"), Some(&1.into()));
+    assert_eq!(evaluator.get_binding("w"), None);
+  }
+
+  #[test]
+  fn evaluate_form_stream() {
+    let mut evaluator = Evaluator::default();
+    let results: Vec<_> = evaluator
+      .eval_forms("(def x 2) (+ x missing)\n(* x 3)")
+      .map(|form_result| {
+        (form_result.form_index, form_result.form, form_result.result)
+      })
+      .collect();
+    assert_eq!(
+      results,
+      vec![
+        (0, "(def x 2)".to_string(), Ok(2.into())),
+        (
+          1,
+          "(+ x missing)".to_string(),
+          Err(ASTError::UnboundSymbol("missing".to_string()).into())
+        ),
+        (2, "(* x 3)".to_string(), Ok(6.into())),
+      ]
+    );
+    let mut forms = evaluator.eval_forms("(def y 1) (def z 2)");
+    assert_eq!(
+      forms.next().map(|form_result| form_result.result),
+      Some(Ok(1.into()))
+    );
+    drop(forms);
+    assert_eq!(evaluator.get_binding("y"), Some(&1.into()));
+    assert_eq!(evaluator.get_binding("z"), None);
+  }
+
+  #[test]
+  fn evaluate_external_fns() {
+    let mut evaluator = Evaluator::default();
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    evaluator.define_fn("add3", 3, move |args| {
+      counter.set(counter.get() + 1);
+      let sum = args[0].as_num()? + args[1].as_num()?;
+      Ok((&sum + args[2].as_num()?).into())
+    });
+    assert_eq!(evaluator.eval("(add3 1 2 3)"), Ok(6.into()));
+    assert_eq!(evaluator.eval("(* 2 (add3 1 2 3))"), Ok(12.into()));
+    assert_eq!(evaluator.eval("(def x (add3 1 1 1))"), Ok(3.into()));
+    assert_eq!(calls.get(), 3);
+    assert_eq!(
+      evaluator.eval("(add3 1 2)"),
+      Err(RuntimeError::InvalidArity.into())
+    );
+    assert_eq!(calls.get(), 3);
+    assert_eq!(
+      evaluator
+        .eval("add3")
+        .map(|value| evaluator.describe(value)),
+      Ok("external_fn( add3 )".to_string())
+    );
+  }
+
+  #[test]
+  fn evaluate_external_objects() {
+    struct Counter {
+      count: Cell<i64>,
+    }
+    let mut evaluator = Evaluator::default();
+    evaluator.define_object(
+      "counter",
+      Counter {
+        count: Cell::new(0),
+      },
+    );
+    evaluator.define_method("increment!", 1, |counter: &Counter, args| {
+      let amount = args[0].as_num()?.as_int_lossless()?;
+      counter.count.set(counter.count.get() + amount);
+      Ok(counter.count.get().into())
+    });
+    evaluator.define_method("total", 0, |counter: &Counter, _| {
+      Ok(counter.count.get().into())
+    });
+    assert_eq!(evaluator.eval("(increment! counter 5)"), Ok(5.into()));
+    assert_eq!(evaluator.eval("(increment! counter 2)"), Ok(7.into()));
+    assert_eq!(evaluator.eval("(total counter)"), Ok(7.into()));
+    assert_eq!(
+      evaluator.eval("(total counter 1)"),
+      Err(RuntimeError::InvalidArity.into())
+    );
+    let counter = evaluator
+      .get_binding("counter")
+      .cloned()
+      .and_then(|value| value.casted_external::<Counter>())
+      .unwrap();
+    assert_eq!(counter.count.get(), 7);
+  }
+
+  #[test]
+  fn evaluate_external_errors() {
+    #[derive(Debug)]
+    struct OutOfFuel;
+    impl std::fmt::Display for OutOfFuel {
+      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "out of fuel")
+      }
+    }
+    impl std::error::Error for OutOfFuel {}
+    let mut evaluator = Evaluator::default();
+    evaluator.define_fn("burn", 1, |args| {
+      if args[0].as_num()?.as_int_lossless()? > 10 {
+        Err(Rc::new(OutOfFuel))
+      } else {
+        Ok(Value::Nil)
+      }
+    });
+    evaluator.define_object("tank", 100i64);
+    evaluator.define_method("level", 0, |level: &i64, _| Ok((*level).into()));
+    assert_eq!(evaluator.eval("(burn 5)"), Ok(Value::Nil));
+    let error = evaluator.eval("(burn 50)").unwrap_err();
+    assert!(matches!(
+      error,
+      PidginError::Runtime(RuntimeError::ExternalError(_))
+    ));
+    assert_eq!(
+      evaluator.describe_error(error),
+      "runtime error: external error: \"out of fuel\"".to_string()
+    );
+    assert_eq!(
+      evaluator.eval(r#"(burn "x")"#),
+      Err(RuntimeError::CantCastToNum("x".into()).into())
+    );
+    assert_eq!(evaluator.eval("(level tank)"), Ok(100.into()));
+    assert_eq!(
+      evaluator.eval("(level 100)"),
+      Err(RuntimeError::ArgumentNotExternal("i64".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn resources() {
+    let closed = Rc::new(Cell::new(false));
+    let finalized = closed.clone();
+    let mut evaluator = Evaluator::default();
+    evaluator.define_resource("file", "notes.txt".to_string(), move |_| {
+      finalized.set(true)
+    });
+    evaluator
+      .define_method("path", 0, |path: &String, _| Ok(path.as_str().into()));
+    assert_eq!(evaluator.eval("(. file path)"), Ok("notes.txt".into()));
+    let file = evaluator.eval("file").unwrap();
+    assert!(!closed.get());
+    drop(evaluator);
+    assert!(closed.get());
+    drop(file);
+  }
+
+  #[test]
+  fn method_calls() {
+    struct Square(i64);
+    struct Rectangle(i64, i64);
+    let mut evaluator = Evaluator::default();
+    evaluator.define_object("square", Square(3));
+    evaluator.define_object("rectangle", Rectangle(2, 5));
+    evaluator.define_method("area", 0, |square: &Square, _| {
+      Ok((square.0 * square.0).into())
+    });
+    evaluator.define_method("area", 0, |rectangle: &Rectangle, _| {
+      Ok((rectangle.0 * rectangle.1).into())
+    });
+    evaluator.define_method("scaled-area", 1, |square: &Square, args| {
+      let factor = args[0].as_num()?.as_int_lossless()?;
+      Ok((square.0 * square.0 * factor).into())
+    });
+    assert_eq!(evaluator.eval("(. square area)"), Ok(9.into()));
+    assert_eq!(evaluator.eval("(. rectangle area)"), Ok(10.into()));
+    assert_eq!(evaluator.eval("(. square scaled-area 2)"), Ok(18.into()));
+    assert_eq!(
+      evaluator.eval(r#"(call-method rectangle "area")"#),
+      Ok(10.into())
+    );
+    assert_eq!(
+      evaluator.eval("(call-method square (quote area))"),
+      Ok(9.into())
+    );
+    assert_eq!(
+      evaluator.eval("(. rectangle scaled-area 2)"),
+      Err(RuntimeError::NoMethod("scaled-area".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(. 5 area)"),
+      Err(RuntimeError::ArgumentNotExternal("object".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(. square scaled-area)"),
+      Err(RuntimeError::InvalidArity.into())
+    );
+    assert!(matches!(
+      evaluator.eval("(. square (area))"),
+      Err(PidginError::AST(ASTError::InvalidMethodCall(_)))
+    ));
+  }
+
+  #[test]
+  fn evaluate_exported_methods() {
+    struct Inventory {
+      items: RefCell<Vec<String>>,
+    }
+    #[pidgin::export]
+    impl Inventory {
+      pub fn add_item(&self, name: String) -> i64 {
+        self.items.borrow_mut().push(name);
+        self.items.borrow().len() as i64
+      }
+      pub fn item_at(&self, index: i64) -> Result<String, RuntimeError> {
+        self
+          .items
+          .borrow()
+          .get(index as usize)
+          .cloned()
+          .ok_or(RuntimeError::InvalidArity)
+      }
+      pub fn clear(&self) {
+        self.items.borrow_mut().clear()
+      }
+      #[allow(dead_code)]
+      fn hidden(&self) {}
+    }
+    let mut evaluator = Evaluator::default();
+    evaluator.define_object(
+      "inventory",
+      Inventory {
+        items: RefCell::new(vec![]),
+      },
+    );
+    evaluator.export::<Inventory>();
+    assert_eq!(
+      evaluator.eval(r#"(add-item inventory "sword")"#),
+      Ok(1.into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(add-item inventory "shield")"#),
+      Ok(2.into())
+    );
+    assert_eq!(evaluator.eval("(item-at inventory 1)"), Ok("shield".into()));
+    assert_eq!(evaluator.eval("(clear inventory)"), Ok(Value::Nil));
+    assert_eq!(
+      evaluator.eval("(hidden inventory)"),
+      Err(ASTError::UnboundSymbol("hidden".to_string()).into())
+    );
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn evaluate_json() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval(r#"(json/parse "[1,2.5,null,[]]")"#),
+      Ok(
+        vec![1.into(), 2.5.into(), Value::Nil, Vec::<Value>::new().into()]
+          .into()
+      )
+    );
+    assert_eq!(
+      evaluator.eval(r#"(json/write (list 1 2.5 nil (list "a")))"#),
+      Ok(r#"[1,2.5,null,["a"]]"#.into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(json/parse "[1,")"#),
+      Err(RuntimeError::InvalidJson(String::new()).into())
+    );
+    assert_eq!(
+      evaluator
+        .eval(r#"(json/parse "[-9223372036854775808,18446744073709551617]")"#),
+      evaluator.eval("(list -9223372036854775808 18446744073709551617)")
+    );
+    assert_eq!(
+      evaluator.eval(r#"(json/write (json/parse "[18446744073709551617]"))"#),
+      Ok("[18446744073709551617]".into())
+    );
+  }
+
+  #[test]
+  fn evaluate_edn() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval(r#"(edn/write (list 1 2.5 "a" nil (list)))"#),
+      Ok(r#"(1 2.5 "a" nil ())"#.into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(edn/write (edn/read "[1,new-symbol,#_2,3]"))"#),
+      Ok("(1 new-symbol 3)".into())
+    );
+    let value = evaluator.read_edn("{:a [1 2] :b #{c}}").unwrap();
+    let written = evaluator.write_edn(&value).unwrap();
+    assert_eq!(evaluator.read_edn(&written), Ok(value));
+    assert_eq!(
+      evaluator.eval(r#"(edn/read "[1")"#),
+      Err(RuntimeError::InvalidEdn(String::new()).into())
+    );
+  }
+
+  #[test]
+  fn pprint() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval("(pprint (list 1 (list 2 3)))"),
+      Ok(Value::Nil)
+    );
+    assert_eq!(evaluator.eval("(pprint (list 1 2) 5)"), Ok(Value::Nil));
+    assert_eq!(
+      evaluator.eval(r#"(pprint (list 1 2) "wide")"#),
+      Err(RuntimeError::ArgumentNotInt.into())
+    );
+  }
+
+  #[test]
+  fn format() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval(r#"(format "x=%d,name=%s" 3 "bob")"#),
+      Ok("x=3,name=bob".into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(format "[%5d|%-5s|%05d|%x]" 42 "ab" -42 255)"#),
+      Ok("[   42|ab   |-0042|ff]".into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(format "%.2f%%,%v,%s" 2.345 "a" (list 1 "b"))"#),
+      Ok(r#"2.35%,"a",[1, "b"]"#.into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(format "%d,%d" 1)"#),
+      Err(RuntimeError::InvalidFormat(String::new()).into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(format "%q" 1)"#),
+      Err(RuntimeError::InvalidFormat(String::new()).into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(format "%d" "a")"#),
+      Err(RuntimeError::InvalidFormat(String::new()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(format 1)"),
+      Err(RuntimeError::ArgumentNotString.into())
+    );
+  }
+
+  #[test]
+  fn number_conversions() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(evaluator.eval(r#"(parse-int "-42")"#), Ok(Value::from(-42)));
+    assert_eq!(
+      evaluator.eval(r#"(parse-int "ff" 16)"#),
+      Ok(Value::from(255))
+    );
+    assert_eq!(
+      evaluator.eval(r#"(parse-int "123456789012345678901234567890")"#),
+      Ok(Value::from(
+        "123456789012345678901234567890"
+          .parse::<num_bigint::BigInt>()
+          .unwrap()
+      ))
+    );
+    assert_eq!(evaluator.eval(r#"(parse-int "12x")"#), Ok(Value::Nil));
+    assert_eq!(evaluator.eval(r#"(parse-int "1_000")"#), Ok(Value::Nil));
+    assert_eq!(
+      evaluator.eval(r#"(parse-int "10" 37)"#),
+      Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(parse-float "2.5e3")"#),
+      Ok(2500.0.into())
+    );
+    assert_eq!(evaluator.eval(r#"(parse-float "two")"#), Ok(Value::Nil));
+    assert_eq!(
+      evaluator.eval("(format-float 3.14159 2)"),
+      Ok("3.14".into())
+    );
+    assert_eq!(evaluator.eval("(format-float 2 1)"), Ok("2.0".into()));
+    assert_eq!(
+      evaluator.eval("(format-float 2 -1)"),
+      Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+    );
+    assert_eq!(evaluator.eval("(to-string 255 16)"), Ok("ff".into()));
+    assert_eq!(evaluator.eval("(to-string -5 2)"), Ok("-101".into()));
+    assert_eq!(evaluator.eval("(to-string 42)"), Ok("42".into()));
+    assert_eq!(
+      evaluator.eval("(to-string 2.5 16)"),
+      Err(RuntimeError::ArgumentNotInt.into())
+    );
+  }
+
+  #[test]
+  fn string_predicates() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(evaluator.eval(r#"(digit? "2024")"#), Ok(true.into()));
+    assert_eq!(evaluator.eval(r#"(alpha? "abc1")"#), Ok(false.into()));
+    assert_eq!(evaluator.eval(r#"(upper-case? "OK")"#), Ok(true.into()));
+    assert_eq!(evaluator.eval(r#"(blank? "")"#), Ok(true.into()));
+  }
+
+  #[test]
+  fn ordering_values() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(evaluator.eval("(compare 1 2)"), Ok((-1).into()));
+    assert_eq!(evaluator.eval(r#"(compare "b" 2)"#), Ok(1.into()));
+    assert_eq!(
+      evaluator.eval("(compare (list 1 2) (list 1 2))"),
+      Ok(0.into())
+    );
+    assert_eq!(
+      evaluator.eval(r#"(sort (list "b" 3 nil "a" 1.5))"#),
+      Ok(Value::from(vec![
+        Value::Nil,
+        1.5.into(),
+        3.into(),
+        "a".into(),
+        "b".into()
+      ]))
+    );
+    assert_eq!(
+      evaluator.eval("(sort-by (fn (x) (- 0 x)) (list 1 3 2))"),
+      Ok(Value::from(vec![3.into(), 2.into(), 1.into()]))
+    );
+    assert_eq!(
+      evaluator.eval("(min-key (fn (x) (* x x)) (list 3 -1 2))"),
+      Ok((-1).into())
+    );
+    assert_eq!(
+      evaluator.eval("(max-key (fn (x) (* x x)) (list 3 -1 2))"),
+      Ok(3.into())
+    );
+    assert_eq!(
+      evaluator.eval("(min-key (fn (x) x) (list))"),
+      Ok(Value::Nil)
+    );
+    let value = evaluator.read_edn("{:b 2 :a 1 :c 3}").unwrap();
+    assert_eq!(evaluator.describe(value), "{:a 1, :b 2, :c 3}");
+  }
+
+  #[test]
+  fn identical() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def xs (list 1 2))").unwrap();
+    assert_eq!(evaluator.eval("(identical? xs xs)"), Ok(true.into()));
+    assert_eq!(
+      evaluator.eval("(identical? xs (list 1 2))"),
+      Ok(false.into())
+    );
+    assert_eq!(evaluator.eval("(= xs (list 1 2))"), Ok(true.into()));
+    assert_eq!(evaluator.eval("(= (atom 1) (atom 1))"), Ok(false.into()));
+    assert_eq!(evaluator.eval("(identical? 2 2)"), Ok(true.into()));
+    assert_eq!(
+      super::differential::first_disagreement(
+        "(def a (atom 1)) (= a a) (= a (atom 1)) (identical? (list 1) (list 1))"
+      ),
+      None
+    );
+  }
+
+  #[test]
+  fn hash() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval(r#"(= (hash (list 1 "a")) (hash (list 1 "a")))"#),
+      Ok(true.into())
+    );
+    assert_eq!(
+      evaluator.eval("(hash 5)"),
+      Ok(Value::from(5).hash_code().into())
+    );
+  }
+
+  #[test]
+  fn builtins() {
+    let mut evaluator = Evaluator::default();
+    evaluator.define_builtin("triple", 1, |args| {
+      Ok((args[0].as_num()? * &3.into()).into())
+    });
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+    evaluator.define_pure_builtin("square", 1, move |args| {
+      counter.set(counter.get() + 1);
+      let n = args[0].as_num()?;
+      Ok((n * n).into())
+    });
+    assert_eq!(evaluator.eval("(triple 4)"), Ok(12.into()));
+    evaluator.eval("(def triple 0)").unwrap();
+    assert_eq!(evaluator.eval("(triple 4)"), Ok(12.into()));
+    assert_eq!(
+      evaluator.eval("(fn (triple) triple)"),
+      Err(ASTError::ShadowedBinding("triple".to_string()).into())
+    );
+    // calls with constant arguments happen once, when the code around them is
+    // compiled
+    evaluator
+      .eval("(def nine (fn (unused) (square 3)))")
+      .unwrap();
+    assert_eq!(evaluator.eval("(+ (nine 0) (nine 0))"), Ok(18.into()));
+    assert_eq!(calls.get(), 1);
+    assert_eq!(
+      evaluator.eval("((fn (x) (square (square x))) 2)"),
+      Ok(16.into())
+    );
+    assert_eq!(calls.get(), 3);
+    assert_eq!(evaluator.completions("squ"), vec!["square".to_string()]);
+  }
+
+  #[derive(Debug)]
+  struct Point(i64, i64);
+
+  impl ExternalValue for Point {
+    fn equals(&self, other: &Self) -> bool {
+      (self.0, self.1) == (other.0, other.1)
+    }
+    fn hash_value(&self, mut state: &mut dyn Hasher) {
+      (self.0, self.1).hash(&mut state);
+    }
+    fn describe(&self) -> String {
+      format!("#point[{} {}]", self.0, self.1)
+    }
+  }
+
+  #[test]
+  fn external_values() {
+    let mut evaluator = Evaluator::default();
+    evaluator.define_value("a", Value::external_value(Point(1, 2)));
+    evaluator.define_value("b", Value::external_value(Point(1, 2)));
+    evaluator.define_value("c", Value::external_value(Point(3, 4)));
+    evaluator.define_object("opaque", Point(1, 2));
+    assert_eq!(evaluator.eval("(= a b)"), Ok(true.into()));
+    assert_eq!(evaluator.eval("(= a c)"), Ok(false.into()));
+    assert_eq!(evaluator.eval("(= a opaque)"), Ok(false.into()));
+    assert_eq!(evaluator.eval("(= (hash a) (hash b))"), Ok(true.into()));
+    let a = evaluator.eval("a").unwrap();
+    let set: im_rc::HashSet<Value> = [a.clone(), evaluator.eval("b").unwrap()]
+      .into_iter()
+      .collect();
+    assert_eq!(set.len(), 1);
+    assert_eq!(evaluator.describe(a.clone()), "#point[1 2]");
+    assert_eq!(a.casted_external::<Point>().map(|point| point.1), Some(2));
+    let opaque = evaluator.eval("opaque").unwrap();
+    assert_eq!(evaluator.describe(opaque), "external_object");
+  }
+
+  struct Geometry;
+
+  impl PidginModule for Geometry {
+    fn name(&self) -> &str {
+      "geometry"
+    }
+    fn register(&self, evaluator: &mut Evaluator) {
+      evaluator.define_value("tau", 6.5);
+      evaluator.define_fn("square", 1, |args| {
+        let n = args[0].as_num()?;
+        Ok((n * n).into())
+      });
+    }
+  }
+
+  #[test]
+  fn modules() {
+    let mut evaluator = Evaluator::default();
+    assert!(evaluator.install_module(&Geometry));
+    assert_eq!(evaluator.eval("(square 7)"), Ok(49.into()));
+    evaluator.eval("(def tau 6.25)").unwrap();
+    assert!(!evaluator.install_module(&Geometry));
+    assert_eq!(evaluator.eval("tau"), Ok(6.25.into()));
+  }
+
+  #[cfg(feature = "plugins")]
+  #[test]
+  fn missing_plugin() {
+    let mut evaluator = Evaluator::default();
+    let result = unsafe { evaluator.load_plugin("no-such-plugin.so") };
+    assert!(matches!(result, Err(PidginError::InvalidPlugin(_))));
+  }
+
+  #[test]
+  fn rounding_and_clamping() {
+    let mut evaluator = Evaluator::default();
+    let float = |value: Result<Value, PidginError>| match value {
+      Ok(Value::Number(Num::Float(f))) => *f,
+      other => panic!("expected a float, got {other:?}"),
+    };
+    assert_eq!(float(evaluator.eval("(round 2.5)")), 3.0);
+    assert_eq!(float(evaluator.eval("(round -2.5)")), -3.0);
+    assert_eq!(float(evaluator.eval(r#"(round 2.5 "half-up")"#)), 3.0);
+    assert_eq!(float(evaluator.eval(r#"(round -2.5 "half-up")"#)), -2.0);
+    assert_eq!(float(evaluator.eval(r#"(round -2.6 "half-up")"#)), -3.0);
+    assert_eq!(
+      float(evaluator.eval(r#"(round 0.49999999999999994 "half-up")"#)),
+      0.0
+    );
+    assert_eq!(float(evaluator.eval(r#"(round 2.5 "half-even")"#)), 2.0);
+    assert_eq!(float(evaluator.eval(r#"(round 3.5 "half-even")"#)), 4.0);
+    assert_eq!(evaluator.eval("(round 7)"), Ok(7.into()));
+    assert_eq!(
+      evaluator.eval(r#"(round 2.5 "nearest")"#),
+      Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+    );
+    assert_eq!(float(evaluator.eval("(trunc -2.7)")), -2.0);
+    assert_eq!(evaluator.eval("(clamp 12 0 10)"), Ok(10.into()));
+    assert_eq!(evaluator.eval("(clamp -3 0 10)"), Ok(0.into()));
+    assert_eq!(float(evaluator.eval("(clamp 0.5 0 10)")), 0.5);
+    assert_eq!(
+      evaluator.eval("(clamp 5 10 0)"),
+      Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+    );
+    assert_eq!(evaluator.eval("(lerp 10 20 0)"), Ok(10.into()));
+    assert_eq!(float(evaluator.eval("(lerp 10 20 0.25)")), 12.5);
+    assert_eq!(evaluator.eval("(signum -5)"), Ok((-1).into()));
+    assert_eq!(float(evaluator.eval("(signum 0.0)")), 0.0);
+    assert_eq!(float(evaluator.eval("(signum 3.5)")), 1.0);
+  }
+
+  #[test]
+  fn seeded_randomness() {
+    let mut evaluator = Evaluator::default();
+    let seeded =
+      "(with-seed 42 (list (rand-int 100) (rand) (rand-nth (list 1 2 3))))";
+    let first = evaluator.eval(seeded).unwrap();
+    assert_eq!(evaluator.eval(seeded), Ok(first));
+    assert_eq!(
+      evaluator.eval("(sort (with-seed 1 (shuffle (list 5 3 1 4 2))))"),
+      Ok(Value::from(vec![
+        1.into(),
+        2.into(),
+        3.into(),
+        4.into(),
+        5.into()
+      ]))
+    );
+    let Ok(Value::List(sample)) = evaluator.eval("(sample 3 (list 1 2 3 4 5))")
+    else {
+      panic!("expected a list")
+    };
+    assert_eq!(sample.len(), 3);
+    assert_eq!(
+      evaluator.eval("(sample 9 (list 1))"),
+      Ok(Value::from(vec![1.into()]))
+    );
+    // a `with-seed` form doesn't disturb the generator around it
+    evaluator.seed_rng(7);
+    let unseeded = evaluator.eval("(rand)").unwrap();
+    evaluator.seed_rng(7);
+    evaluator.eval("(with-seed 1 (rand))").unwrap();
+    assert_eq!(evaluator.eval("(rand)"), Ok(unseeded));
+    assert_eq!(
+      evaluator.eval("(rand-int 0)"),
+      Err(RuntimeError::ArgumentOutOfRange(String::new()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(with-seed 1)"),
+      Err(ASTError::InvalidWithSeedForm(2).into())
+    );
+  }
+
+  #[test]
+  fn completions() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def deflection 1)").unwrap();
+    assert_eq!(
+      evaluator.completions("def"),
+      vec!["def", "deflection", "defprotocol", "defrecord", "deftest"]
+    );
+    assert!(evaluator.completions("").contains(&"bit-and".to_string()));
+    evaluator.eval("(ns other)").unwrap();
+    assert_eq!(evaluator.completions("defl"), Vec::<String>::new());
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn completion_starts() {
+    assert_eq!(completion_start("(+ (inc x", 9), 8);
+    assert_eq!(completion_start("@my-atom", 8), 1);
+    assert_eq!(completion_start("print", 3), 0);
+  }
+
+  #[test]
+  fn evaluate_result_history() {
+    let mut evaluator = Evaluator::default();
+    evaluator.repl_eval("1").unwrap();
+    evaluator.repl_eval("2").unwrap();
+    assert_eq!(evaluator.repl_eval("(+ *1 *2)"), Ok(3.into()));
+    assert_eq!(
+      evaluator.repl_eval("(list *1 *2 *3)"),
+      Ok(vec![3.into(), 2.into(), 1.into()].into())
+    );
+    assert_eq!(evaluator.get_binding("*e"), None);
+    assert!(evaluator.repl_eval("(edn/read 1)").is_err());
+    assert!(matches!(evaluator.get_binding("*e"), Some(Value::Error(_))));
+    assert!(evaluator.repl_eval("missing").is_err());
+    assert_eq!(
+      evaluator.get_binding("*e"),
+      Some(&"ast error: encountered unbound symbol missing".into())
+    );
+    assert_eq!(
+      evaluator.get_binding("*1"),
+      Some(&vec![3.into(), 2.into(), 1.into()].into())
+    );
+  }
+
   #[test]
   fn evaluate_def() {
     let mut evaluator = Evaluator::default();
@@ -60,6 +1088,16 @@ mod tests {
     assert_eq!(evaluator.eval("((compose inc inc inc) 0)"), Ok(3.into()))
   }
 
+  #[test]
+  fn runtime_error_describes_symbol_names() {
+    let mut evaluator = Evaluator::default();
+    let error = evaluator.eval("((quote foo) 1)").unwrap_err();
+    assert_eq!(
+      evaluator.describe_error(error),
+      "runtime error: can't apply value foo"
+    )
+  }
+
   #[test]
   fn shadowing_local_causes_error() {
     let mut evaluator = Evaluator::default();
@@ -79,4 +1117,1019 @@ mod tests {
       )))
     )
   }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn repl_config() {
+    let mut config = ReplConfig::new()
+      .prompt("pidgin {ns}=> ")
+      .no_history()
+      .bind("answer", 42)
+      .evaluator(Evaluator::default());
+    let mut evaluator = config.build_evaluator();
+    assert_eq!(evaluator.eval("(+ answer 1)"), Ok(43.into()));
+    assert_eq!(config.prompt_for(&evaluator), "pidgin user=> ");
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn post_mortem_debugging() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def f (fn (x) (first x)))").unwrap();
+    assert!(evaluator.repl_eval("(f 5)").is_err());
+    let mut post_mortem =
+      PostMortem::new(evaluator.failed_frames().to_vec()).unwrap();
+    let mut run = |line| post_mortem.command(line, &evaluator);
+    assert_eq!(
+      run(":frames").unwrap(),
+      "*0: at instruction 0, First(0, 0)\n \
+       1: at instruction 2, Call(0, 1, [StealArgument(0)])"
+    );
+    assert_eq!(run(":locals").unwrap(), "r0 = 5");
+    assert_eq!(
+      run(":up").unwrap(),
+      "*1: at instruction 2, Call(0, 1, [StealArgument(0)])"
+    );
+    assert_eq!(run(":up").unwrap(), "already at the outermost frame");
+    assert_eq!(run(":locals 0").unwrap(), "r0 = 5");
+    assert_eq!(run(":locals 7").unwrap(), "no frame 7");
+    assert!(run(":step").unwrap().starts_with("debugger commands"));
+    assert_eq!(run(":continue"), None);
+  }
+
+  #[test]
+  fn watchpoints() {
+    let mut evaluator = Evaluator::default();
+    let definitions = Rc::new(RefCell::new(vec![]));
+    let log = definitions.clone();
+    evaluator.watch_global("x", move |name, value| {
+      log.borrow_mut().push((name.to_string(), value.clone()))
+    });
+    evaluator.eval("(def x 1)").unwrap();
+    evaluator.eval("(def y 2)").unwrap();
+    evaluator.eval("(def x (+ x y))").unwrap();
+    assert_eq!(
+      *definitions.borrow(),
+      vec![
+        ("user/x".to_string(), 1.into()),
+        ("user/x".to_string(), 3.into())
+      ]
+    );
+    evaluator.unwatch_global("x");
+    evaluator.eval("(def x 4)").unwrap();
+    assert_eq!(definitions.borrow().len(), 2);
+    let writes = Rc::new(RefCell::new(vec![]));
+    let log = writes.clone();
+    evaluator.watch_register(1, move |register, value| {
+      log.borrow_mut().push((register, value.clone()))
+    });
+    evaluator.eval("((fn (a) (* a 3)) 5)").unwrap();
+    // the top-level frame puts the function in register 1, and the function's
+    // own frame puts the constant there
+    assert_eq!(writes.borrow().len(), 2);
+    assert_eq!(writes.borrow()[1], (1, 3.into()));
+    evaluator.unwatch_registers();
+    evaluator.eval("((fn (a) (* a 3)) 5)").unwrap();
+    assert_eq!(writes.borrow().len(), 2);
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn repl_watch_command() {
+    let mut evaluator = Evaluator::default();
+    let watch_log = WatchLog::default();
+    assert_eq!(
+      watch_command(":watch x", &mut evaluator, &watch_log),
+      Some("watching x".to_string())
+    );
+    assert_eq!(watch_command("(def x 1)", &mut evaluator, &watch_log), None);
+    evaluator.eval("(def x 1)").unwrap();
+    assert_eq!(*watch_log.borrow(), vec![("user/x".to_string(), 1.into())]);
+    assert_eq!(
+      watch_command(":unwatch x", &mut evaluator, &watch_log),
+      Some("stopped watching x".to_string())
+    );
+    evaluator.eval("(def x 2)").unwrap();
+    assert_eq!(watch_log.borrow().len(), 1);
+  }
+
+  #[cfg(feature = "cli")]
+  #[test]
+  fn repl_sessions() {
+    let dir = TempDir::new("repl_session_test");
+    let image = dir.join("session.pgn-image");
+    let save = format!(":save {}", image.display());
+    let restore = format!(":restore {}", image.display());
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def x 5)").unwrap();
+    assert_eq!(
+      session_command(&save, &mut evaluator),
+      Some(format!("saved the session to {}", image.display()))
+    );
+    assert_eq!(session_command("(def x 1)", &mut evaluator), None);
+    let mut restored = Evaluator::default();
+    assert_eq!(
+      session_command(&restore, &mut restored),
+      Some(format!("restored the session from {}", image.display()))
+    );
+    assert_eq!(restored.eval("(* x 2)"), Ok(10.into()));
+    assert!(session_command(":restore missing.pgn-image", &mut restored)
+      .unwrap()
+      .starts_with("runtime error"));
+
+    // a session that's saved for recovery but never finished is restored
+    // by the next REPL
+    let recovery = dir.join("recovery.pgn-image");
+    let config = ReplConfig::new().no_history().recovery_path(&recovery);
+    let mut evaluator = Evaluator::default();
+    assert_eq!(config.recover(&mut evaluator), None);
+    evaluator.eval("(def y 3)").unwrap();
+    assert_eq!(config.save_recovery(&evaluator), None);
+    let mut recovered = Evaluator::default();
+    assert_eq!(
+      config.recover(&mut recovered),
+      Some(format!(
+        "recovered the last session from {}",
+        recovery.display()
+      ))
+    );
+    assert_eq!(recovered.eval("y"), Ok(3.into()));
+    config.finish_session();
+    assert!(!recovery.exists());
+  }
+
+  #[test]
+  fn coverage_report() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def unrecorded 1)").unwrap();
+    evaluator.enable_coverage();
+    evaluator.eval("(def f (fn (x) (* x 2)))").unwrap();
+    evaluator.eval("(def g (fn (x) (+ x 1)))").unwrap();
+    evaluator.eval("(f 3)").unwrap();
+    evaluator.eval("(f 3)").unwrap();
+    let report = evaluator.coverage_report();
+    // the repeated form reuses its cached bytecode, so it's only reported once
+    assert_eq!(
+      report
+        .forms
+        .iter()
+        .map(|form| (form.form.as_str(), form.executed, form.total))
+        .collect::<Vec<_>>(),
+      vec![
+        ("(def f (fn (x) (* x 2)))", 5, 5),
+        ("(def g (fn (x) (+ x 1)))", 2, 4),
+        ("(f 3)", 4, 4)
+      ]
+    );
+    assert_eq!(report.executed(), 11);
+    assert_eq!(report.total(), 13);
+  }
+
+  #[test]
+  fn profile_report() {
+    let mut evaluator = Evaluator::default();
+    evaluator.enable_profiling();
+    evaluator.eval("(def f (fn (x) (* x 2)))").unwrap();
+    evaluator.eval("(f (f 3))").unwrap();
+    let report = evaluator.profile_report();
+    // the instructions of `f` count toward the form that defines it, rather
+    // than the one that calls it
+    assert_eq!(
+      report
+        .forms
+        .iter()
+        .map(|form| (form.form.as_str(), form.hits))
+        .collect::<Vec<_>>(),
+      vec![("(def f (fn (x) (* x 2)))", 8), ("(f (f 3))", 6)]
+    );
+    assert_eq!(
+      report.stacks.keys().cloned().collect::<Vec<_>>(),
+      vec![
+        vec!["(def f (fn (x) (* x 2)))".to_string()],
+        vec!["(f (f 3))".to_string()],
+        vec!["(f (f 3))".to_string(), "user/f".to_string()],
+      ]
+    );
+    let folded = report.folded_stacks();
+    assert_eq!(folded.lines().count(), 3);
+    assert!(folded
+      .lines()
+      .any(|line| line.starts_with("(f (f 3));user/f ")));
+  }
+
+  #[test]
+  fn describe_error_in_source() {
+    let mut evaluator = Evaluator::default();
+    let source = "(+ 1 (* xs 2))";
+    let error = evaluator.eval(source).unwrap_err();
+    assert_eq!(
+      evaluator.describe_error_in(source, error),
+      "(+ 1 (* xs 2))\n        ^~\nast error: encountered unbound symbol xs"
+    );
+  }
+
+  #[test]
+  fn evaluate_multiple_forms() {
+    let results =
+      crate::evaluate_pidgin_forms("(def x 2) (+ x missing) (* x 3)");
+    assert_eq!(results.len(), 3);
+    assert!(results[1].is_err());
+    assert_eq!(results[2], Ok(6.into()));
+  }
+
+  #[test]
+  fn emit_compilation_stages() {
+    let mut evaluator = Evaluator::default();
+    let output = evaluator
+      .emit("(def x 2) (+ x 1)", vec![CompilationStage::Bytecode])
+      .unwrap();
+    assert_eq!(output.matches(";; bytecode").count(), 2);
+    assert!(!output.contains(";; ast"));
+    assert_eq!(evaluator.eval("x"), Ok(2.into()));
+  }
+
+  #[test]
+  fn evaluate_time() {
+    assert_eval_eq("(time (+ 1 2))", 3);
+    assert_eval_eq("((fn (x) (time (* x 2))) 5)", 10);
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval("(time 1 2)"),
+      Err(ASTError::InvalidTimeForm(3).into())
+    );
+    let output = Rc::new(RefCell::new(vec![]));
+    let lines = output.clone();
+    evaluator.set_output(move |line| lines.borrow_mut().push(line.to_string()));
+    assert_eq!(evaluator.eval("(time (+ 1 2))"), Ok(3.into()));
+    evaluator.eval("(pprint 5)").unwrap();
+    let output = output.borrow();
+    assert_eq!(output.len(), 2);
+    assert!(output[0].starts_with("Elapsed time: "));
+    assert_eq!(output[1], "5");
+    // the functions that `time` expands to are only reachable through it
+    assert!(evaluator.eval("(nano-time)").is_err());
+    assert!(evaluator.completions("nano").is_empty());
+  }
+  #[test]
+  fn redefine_globals() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def x 5)").unwrap();
+    evaluator.eval("(def x 6)").unwrap();
+    assert_eq!(evaluator.eval("(+ x 1)"), Ok(7.into()));
+    evaluator.eval("(def x (fn (a) (* a 2)))").unwrap();
+    assert_eq!(evaluator.eval("(x 3)"), Ok(6.into()));
+    evaluator.eval("(def ^:dynamic y 1)").unwrap();
+    evaluator.eval("(def y 2)").unwrap();
+    assert_eq!(
+      evaluator.eval("(binding (y 3) y)"),
+      Err(ASTError::NonDynamicBinding("y".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn late_bound_globals() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def helper (fn (x) (+ x 1)))").unwrap();
+    evaluator
+      .eval("(def caller (fn (x) (* (helper x) 2)))")
+      .unwrap();
+    assert_eq!(evaluator.eval("(caller 1)"), Ok(4.into()));
+    evaluator.eval("(def helper (fn (x) (+ x 10)))").unwrap();
+    assert_eq!(evaluator.eval("(caller 1)"), Ok(22.into()));
+    assert_eq!(
+      evaluator.eval("(fn (x) (+ x missing))"),
+      Err(ASTError::UnboundSymbol("missing".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_declare() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.eval("(def f (fn (x) (g x)))"),
+      Err(ASTError::UnboundSymbol("g".to_string()).into())
+    );
+    evaluator.eval("(declare g)").unwrap();
+    evaluator.eval("(def f (fn (x) (g x)))").unwrap();
+    let error = evaluator.eval("(f 3)").unwrap_err();
+    assert_eq!(
+      evaluator.describe_error(error),
+      "runtime error: user/g was declared but never defined"
+    );
+    evaluator.eval("(def g (fn (x) (* x 2)))").unwrap();
+    assert_eq!(evaluator.eval("(f 3)"), Ok(6.into()));
+  }
+
+  #[test]
+  fn evaluate_letrec() {
+    assert_eval_eq("(letrec (x 5) (+ x 1))", 6);
+    assert_eval_eq(
+      "(letrec (twice (fn (n) (add n n)) add (fn (a b) (+ a b))) (twice 4))",
+      8,
+    );
+    let mut evaluator = Evaluator::default();
+    let curried_sum = evaluator
+      .eval("(letrec (f (fn (n) (fn (m) (f (+ n m))))) (((f 1) 2) 3))");
+    assert!(matches!(curried_sum, Ok(Value::PartialApplication(_))));
+    assert_eq!(
+      evaluator.eval("(letrec (x) x)"),
+      Err(ASTError::InvalidLetrec("(letrec (x) x)".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(letrec (f 1) ((fn (f) f) 2))"),
+      Err(ASTError::ShadowedBinding("f".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(letrec (f (fn (n) (fn (f) f))) ((f 1) 2))"),
+      Err(ASTError::ShadowedBinding("f".to_string()).into())
+    );
+  }
+
+  #[test]
+  fn evaluate_after_failed_call() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def g (fn (y) (first y)))").unwrap();
+    evaluator.eval("(def f (fn (x) (g (+ x 1))))").unwrap();
+    // the error leaves the frames of `f` and `g` behind, which the next form
+    // mustn't see
+    assert!(evaluator.eval("(f 1)").is_err());
+    assert_eq!(evaluator.eval("(+ 1 2)"), Ok(3.into()));
+    assert_eq!(evaluator.eval("(g (list 5))"), Ok(5.into()));
+  }
+
+  #[test]
+  fn evaluate_gensym() {
+    let mut evaluator = Evaluator::default();
+    let first = evaluator.eval("(gensym)").unwrap();
+    let second = evaluator.eval("(gensym)").unwrap();
+    assert_ne!(first, second);
+    assert!(evaluator.describe(first).starts_with("G__"));
+    evaluator.eval("(def foo0 1)").unwrap();
+    let prefixed = evaluator.eval("(gensym \"foo\")").unwrap();
+    let name = evaluator.describe(prefixed);
+    assert!(name.starts_with("foo") && name != "foo0");
+  }
+
+  #[test]
+  fn evaluate_macroexpand() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.macroexpand("(time @x)"),
+      Ok("(report-elapsed (nano-time) (deref x))".to_string())
+    );
+    let expected = evaluator.read_edn("(report-elapsed (nano-time) x)");
+    assert_eq!(evaluator.eval("(macroexpand '(time x))"), expected);
+    let expected = evaluator
+      .read_edn("(def f (fn (x) (report-elapsed (nano-time) (quote x))))");
+    assert_eq!(
+      evaluator.eval("(macroexpand '(def f (fn (x) (time 'x))))"),
+      expected
+    );
+  }
+
+  #[test]
+  fn evaluate_macroexpand_1() {
+    let mut evaluator = Evaluator::default();
+    assert_eq!(
+      evaluator.macroexpand_1("(time (time x))"),
+      Ok("(report-elapsed (nano-time) (time x))".to_string())
+    );
+    let expected = evaluator.read_edn("(report-elapsed (nano-time) (is x))");
+    assert_eq!(evaluator.eval("(macroexpand-1 '(time (is x)))"), expected);
+    let expected = evaluator.read_edn("(def f (time x))");
+    assert_eq!(
+      evaluator.eval("(macroexpand-1 '(def f (time x)))"),
+      expected
+    );
+    assert_eq!(
+      evaluator.macroexpand_1("(with-open (f (open)) (time f))"),
+      Ok("((fn (f) (try (time f) (finally (close f)))) (open))".to_string())
+    );
+  }
+
+  #[test]
+  fn compile_functions_on_first_call() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def f (fn (x) (fn (y) (+ y 1))))").unwrap();
+    let is_compiled = |value: &Value| match value {
+      Value::CompositeFn(f) => match &f.body {
+        FunctionBody::Compiled(_) => true,
+        FunctionBody::Deferred(deferred) => deferred.is_compiled(),
+      },
+      _ => panic!("expected a function"),
+    };
+    assert!(!is_compiled(evaluator.get_binding("f").unwrap()));
+    let inner = evaluator.eval("(f 1)").unwrap();
+    assert!(is_compiled(evaluator.get_binding("f").unwrap()));
+    assert!(!is_compiled(&inner));
+  }
+
+  #[test]
+  fn reuse_compiled_forms() {
+    let mut evaluator = Evaluator::default();
+    // a function's deferred block comes from the form's constants, so getting
+    // the same one back means the form's bytecode was reused
+    let deferred_block =
+      |evaluator: &mut Evaluator| match evaluator.eval("(fn (x) x)") {
+        Ok(Value::CompositeFn(f)) => match &f.body {
+          FunctionBody::Deferred(deferred) => deferred.clone(),
+          FunctionBody::Compiled(_) => panic!("expected a deferred function"),
+        },
+        _ => panic!("expected a function"),
+      };
+    let first = deferred_block(&mut evaluator);
+    assert!(Rc::ptr_eq(&first, &deferred_block(&mut evaluator)));
+    evaluator.eval("(def v 1)").unwrap();
+    assert!(!Rc::ptr_eq(&first, &deferred_block(&mut evaluator)));
+    assert_eq!(evaluator.eval("(+ v 1)"), Ok(2.into()));
+    evaluator.eval("(def v 5)").unwrap();
+    assert_eq!(evaluator.eval("(+ v 1)"), Ok(6.into()));
+  }
+
+  #[test]
+  fn record_and_replay() {
+    let readings = Rc::new(Cell::new(0));
+    let sensor = |evaluator: &mut Evaluator, readings: &Rc<Cell<i64>>| {
+      let readings = readings.clone();
+      evaluator.define_fn("sensor", 1, move |args| {
+        readings.set(readings.get() + 1);
+        match &args[..] {
+          [Value::Number(Num::Int(0))] => {
+            Err(RuntimeError::ArgumentOutOfRange("offline".to_string()).into())
+          }
+          _ => Ok((readings.get() * 10).into()),
+        }
+      });
+    };
+    let output = Rc::new(RefCell::new(vec![]));
+    let run = |evaluator: &mut Evaluator| {
+      let lines = output.clone();
+      evaluator
+        .set_output(move |line| lines.borrow_mut().push(line.to_string()));
+      evaluator
+        .eval("(def x (+ (rand-int 1000) (sensor 1)))")
+        .unwrap();
+      evaluator.eval("(def t (time 1))").unwrap();
+      assert!(evaluator.eval("(sensor 0)").is_err());
+      evaluator
+        .eval("(list x t (shuffle (list 1 2 3 4 5)))")
+        .unwrap()
+    };
+    let mut evaluator = Evaluator::default();
+    sensor(&mut evaluator, &readings);
+    evaluator.start_recording();
+    let recorded = run(&mut evaluator);
+    let trace = evaluator.stop_tracing().unwrap();
+    assert_eq!(trace.events.len(), 4);
+    let written = evaluator.write_trace(&trace).unwrap();
+    assert_eq!(readings.get(), 2);
+
+    let mut evaluator = Evaluator::default();
+    sensor(&mut evaluator, &readings);
+    evaluator.eval("(sensor 1)").unwrap();
+    let trace = evaluator.read_trace(&written).unwrap();
+    evaluator.start_replay(trace);
+    assert_eq!(run(&mut evaluator), recorded);
+    // the clock is replayed too, so `time` reports the same elapsed time
+    assert_eq!(output.borrow()[0], output.borrow()[1]);
+    // replayed host functions aren't called
+    assert_eq!(readings.get(), 3);
+    let error = evaluator.eval("(sensor 1)").unwrap_err();
+    assert_eq!(
+      evaluator.describe_error(error),
+      "runtime error: replay diverged: the run asked for a call to sensor, \
+       but the trace recorded nothing"
+    );
+    assert_eq!(evaluator.stop_tracing().unwrap().events, vec![]);
+    assert_eq!(evaluator.stop_tracing(), None);
+  }
+
+  #[test]
+  fn hot_reload() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def helper (fn (x) (+ x 1)))").unwrap();
+    let call_helper = evaluator.eval("(fn (x) (helper x))").unwrap();
+    // yields halfway through, and then calls `helper`
+    let worker = CompositeFunction::new(
+      0,
+      block![
+        Const(0, "first half"),
+        Yield(0),
+        Const(1, call_helper),
+        Const(2, 5),
+        Call(1, 1, [StealArgument(2)]),
+        Return(1)
+      ],
+    );
+    evaluator.define_value("worker", Value::CompositeFn(worker.clone().into()));
+    evaluator.define_value("task", Value::fn_coroutine(worker).unwrap());
+    assert_eq!(evaluator.eval("(task)"), Ok("first half".into()));
+    evaluator
+      .reload(
+        "(def helper (fn (x) (* x 100)))
+         (def worker (fn (x) (helper x)))",
+      )
+      .unwrap();
+    assert_eq!(evaluator.eval("(worker 2)"), Ok(200.into()));
+    // the paused frame keeps its old code, but calls the new `helper`
+    assert_eq!(evaluator.eval("(task)"), Ok(500.into()));
+
+    // a reload that fails leaves every definition as it was
+    let error = evaluator
+      .reload(
+        "(def helper (fn (x) (- x 1)))
+         (def ^:dynamic extra 1)
+         (first 1)",
+      )
+      .unwrap_err();
+    assert!(matches!(error, PidginError::InForm { form_index: 2, .. }));
+    assert_eq!(evaluator.eval("(worker 2)"), Ok(200.into()));
+    assert_eq!(
+      evaluator.eval("extra"),
+      Err(ASTError::UnboundSymbol("extra".to_string()).into())
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn evaluate_snapshots_in_parallel() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def square (fn (x) (* x x)))").unwrap();
+    evaluator.eval("(def ^:private offset 1)").unwrap();
+    let snapshot = evaluator.snapshot().unwrap();
+    let results: Vec<String> = std::thread::scope(|scope| {
+      let threads: Vec<_> = (0..4)
+        .map(|i| {
+          let snapshot = snapshot.clone();
+          scope.spawn(move || {
+            let mut evaluator = Evaluator::from_snapshot(&snapshot).unwrap();
+            let value =
+              evaluator.eval(&format!("(+ (square {i}) offset)")).unwrap();
+            evaluator.describe(value)
+          })
+        })
+        .collect();
+      threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .collect()
+    });
+    assert_eq!(results, ["1", "2", "5", "10"]);
+    evaluator.define_fn("host", 0, |_| Ok(Value::Nil));
+    evaluator.eval("(def wrapped (list host))").unwrap();
+    assert_eq!(
+      evaluator.snapshot().unwrap_err(),
+      PidginError::UnportableGlobals(vec![
+        "user/host".to_string(),
+        "user/wrapped".to_string()
+      ])
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn images() {
+    let dir = TempDir::new("image_test");
+    let path = dir.join("image.edn");
+    let mut evaluator = Evaluator::default();
+    evaluator.seed_rng(3);
+    evaluator.eval("(ns geometry)").unwrap();
+    evaluator.eval("(def scale 10)").unwrap();
+    evaluator.eval("(ns my.app)").unwrap();
+    evaluator.eval("(require 'geometry)").unwrap();
+    evaluator.eval("(declare g)").unwrap();
+    evaluator
+      .eval("(def f (fn (x) (+ (g x) geometry/scale)))")
+      .unwrap();
+    evaluator
+      .eval("(deftest scaling (is (= geometry/scale 10)))")
+      .unwrap();
+    evaluator
+      .eval("(def timed (fn (x) (time (* x 2))))")
+      .unwrap();
+    evaluator.eval("(timed 1)").unwrap();
+    evaluator.define_fn("host", 0, |_| Ok(Value::Nil));
+    evaluator.save_image(&path).unwrap();
+    let roll = evaluator.eval("(rand-int 1000)").unwrap();
+    drop(evaluator);
+
+    let mut evaluator = Evaluator::default();
+    evaluator.load_image(&path).unwrap();
+    assert_eq!(evaluator.current_namespace(), "my.app");
+    assert_eq!(evaluator.eval("(rand-int 1000)"), Ok(roll));
+    assert!(evaluator.get_binding("host").is_none());
+    assert_eq!(evaluator.eval("(timed 4)"), Ok(8.into()));
+    let error = evaluator.eval("(f 1)").unwrap_err();
+    assert_eq!(
+      evaluator.describe_error(error),
+      "runtime error: my.app/g was declared but never defined"
+    );
+    evaluator.eval("(def g (fn (x) (* x 2)))").unwrap();
+    assert_eq!(evaluator.eval("(f 1)"), Ok(12.into()));
+    let report = evaluator.run_tests();
+    assert_eq!(report.results.len(), 1);
+    assert!(report.all_passed());
+    assert_eq!(
+      evaluator.load_image_source("{\"version\" 0}"),
+      Err(PidginError::InvalidImage(
+        "unsupported version 0".to_string()
+      ))
+    );
+  }
+
+  #[test]
+  fn evaluate_pmap() {
+    let numbers = |n: i64| {
+      let numbers: Vec<String> = (0..n).map(|i| i.to_string()).collect();
+      format!("(list {})", numbers.join(" "))
+    };
+    let mut evaluator = Evaluator::default();
+    let squares = evaluator
+      .eval(&format!("(pmap (fn (x) (* x x)) {})", numbers(40)))
+      .unwrap();
+    assert_eq!(
+      squares,
+      (0..40i64).map(|x| Value::from(x * x)).collect::<Value>()
+    );
+    evaluator.eval("(def offset 100)").unwrap();
+    assert_eq!(
+      evaluator.eval(&format!("(pmap (fn (x) (+ x offset)) {})", numbers(20))),
+      Ok((100..120i64).map(Value::from).collect::<Value>())
+    );
+    assert_eq!(
+      evaluator.eval(&format!("(pmap (fn (x) (deref x)) {})", numbers(20))),
+      Err(RuntimeError::ArgumentNotCell.into())
+    );
+    assert_eval_eq("(pmap (fn (x) (inc x)) (list))", Value::from(vec![]));
+  }
+
+  #[test]
+  fn pmap_side_effects() {
+    let dir = TempDir::new("pmap_side_effects_test");
+    let path = |i: usize| dir.join(i.to_string());
+    // every item is a file whose contents get wrapped in a list each time the
+    // function runs on it, except the eighth, which fails. Each item before it
+    // must have run exactly once, and none after it
+    let items: Vec<String> = (0..40)
+      .map(|i| {
+        std::fs::write(path(i), "x").unwrap();
+        match i {
+          7 => "7".to_string(),
+          _ => format!("\"{}\"", path(i).display()),
+        }
+      })
+      .collect();
+    let mut evaluator = Evaluator::default();
+    evaluator.enable_file_io();
+    let result = evaluator.eval(&format!(
+      "(pmap (fn (p) (spit p (list (slurp p)))) (list {}))",
+      items.join(" ")
+    ));
+    assert_eq!(result, Err(RuntimeError::ArgumentNotString.into()));
+    for i in 0..40 {
+      let contents = std::fs::read_to_string(path(i)).unwrap();
+      assert_eq!(contents, if i < 7 { "[\"x\"]" } else { "x" });
+    }
+  }
+
+  #[test]
+  fn evaluate_error_values() {
+    let mut evaluator = Evaluator::default();
+    evaluator
+      .eval(
+        "(def e (error \"out-of-range\" (edn/read \"{:index,5}\")
+                  (error \"bad-input\")))",
+      )
+      .unwrap();
+    assert_eq!(evaluator.eval("(error? e)"), Ok(true.into()));
+    assert_eq!(evaluator.eval("(error? 5)"), Ok(false.into()));
+    assert_eq!(evaluator.eval("(ex-message e)"), Ok("out-of-range".into()));
+    assert_eq!(
+      evaluator.eval("(ex-message (ex-cause e))"),
+      Ok("bad-input".into())
+    );
+    assert_eq!(evaluator.eval("(ex-cause (ex-cause e))"), Ok(Value::Nil));
+    let data = evaluator.eval("(ex-data e)").unwrap();
+    assert_eq!(evaluator.describe(data), "{:index 5}");
+    let Err(PidginError::Runtime(RuntimeError::Custom(message, data, cause))) =
+      evaluator.eval("(throw e)")
+    else {
+      panic!("throw didn't fail with the thrown error")
+    };
+    assert_eq!(message, "out-of-range");
+    assert_eq!(evaluator.describe(data), "{:index 5}");
+    assert!(cause.is_some());
+    assert_eq!(
+      evaluator.eval("(throw \"oops\")"),
+      Err(RuntimeError::ArgumentNotError.into())
+    );
+    assert_eq!(
+      evaluator.eval("(ex-message (error \"a\" nil (error \"b\")))"),
+      Ok("a".into())
+    );
+  }
+
+  #[test]
+  fn evaluate_try() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def log (atom nil))").unwrap();
+    assert_eq!(evaluator.eval("(try (+ 1 2))"), Ok(3.into()));
+    assert_eq!(
+      evaluator.eval("(try (throw (error \"boom\")) (catch e (ex-message e)))"),
+      Ok("boom".into())
+    );
+    assert_eq!(
+      evaluator.eval("(try (deref 5) (catch e (error? e)))"),
+      Ok(true.into())
+    );
+    assert_eq!(
+      evaluator.eval(
+        "((fn (x) (try (throw (error \"boom\")) (finally (reset! log x)))) 4)"
+      ),
+      Err(RuntimeError::Custom("boom".to_string(), Value::Nil, None).into())
+    );
+    assert_eq!(evaluator.eval("(deref log)"), Ok(4.into()));
+    assert_eq!(
+      evaluator.eval(
+        "(try (throw (error \"boom\"))
+              (catch e 7)
+              (finally (reset! log 5)))"
+      ),
+      Ok(7.into())
+    );
+    assert_eq!(evaluator.eval("(deref log)"), Ok(5.into()));
+    assert_eq!(
+      evaluator.eval("(try 1 (catch 2 3))"),
+      Err(ASTError::InvalidTry("(try 1 (catch 2 3))".to_string()).into())
+    );
+    assert_eq!(
+      evaluator.eval("(try 1 (finally 2) (catch e 3))"),
+      Err(
+        ASTError::InvalidTry("(try 1 (finally 2) (catch e 3))".to_string())
+          .into()
+      )
+    );
+    evaluator
+      .eval("(def close (fn (r) (reset! log r)))")
+      .unwrap();
+    assert_eq!(evaluator.eval("(with-open (r 6) (* r 2))"), Ok(12.into()));
+    assert_eq!(evaluator.eval("(deref log)"), Ok(6.into()));
+    assert!(evaluator
+      .eval("(with-open (r 8) (throw (error \"boom\")))")
+      .is_err());
+    assert_eq!(evaluator.eval("(deref log)"), Ok(8.into()));
+  }
+
+  #[test]
+  fn evaluate_deep_equality() {
+    assert_eval_eq("(= (list 1 (list 2 3)) (list 1 (list 2 3)))", true);
+    assert_eval_eq("(= 1 1 2)", false);
+    assert_eval_eq("(not= (list 1) (list 2))", true);
+  }
+
+  #[test]
+  fn run_tests() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def double (fn (x) (* x 2)))").unwrap();
+    evaluator
+      .eval("(deftest doubling (is (= (double 2) 4)))")
+      .unwrap();
+    evaluator
+      .eval("(deftest broken (is (= (double 2) 5)))")
+      .unwrap();
+    evaluator.eval("(deftest crashing (deref 1))").unwrap();
+    evaluator
+      .eval("(deftest doubling (is (= (double 3) 6)))")
+      .unwrap();
+    let report = evaluator.run_tests();
+    assert_eq!(
+      report
+        .results
+        .iter()
+        .map(|result| result.name.as_str())
+        .collect::<Vec<_>>(),
+      vec!["user/doubling", "user/broken", "user/crashing"]
+    );
+    assert_eq!(report.passed(), 1);
+    assert!(!report.all_passed());
+    let Err(PidginError::Runtime(RuntimeError::Custom(message, form, None))) =
+      &report.results[1].result
+    else {
+      panic!("the failed assertion wasn't reported")
+    };
+    assert_eq!(message, "assertion failed");
+    assert_eq!(evaluator.describe(form.clone()), "[=, [double, 2], 5]");
+    assert_eq!(
+      report.results[2].result,
+      Err(RuntimeError::ArgumentNotCell.into())
+    );
+    let summary = evaluator.read_edn("{:passed 1 :failed 2}").unwrap();
+    assert_eq!(evaluator.eval("(run-tests)"), Ok(summary));
+    assert_eq!(
+      evaluator.eval("(deftest 5 true)"),
+      Err(
+        ASTError::InvalidTestDefinition("(deftest 5 true)".to_string()).into()
+      )
+    );
+  }
+
+  #[test]
+  fn differential_evaluation() {
+    assert_eq!(
+      super::differential::first_disagreement(
+        "(def f (fn (x y) (* x (+ y 1))))
+         ((fn (a) (f a (first (list 3 4)))) 4)
+         (def add (fn (x) (fn (y) (+ x y))))
+         ((add 2) 3)
+         (def ^:dynamic scale 2)
+         (def scaled (fn (x) (* x scale)))
+         (binding (scale 10) (scaled 3))
+         (scaled 3)
+         (first 5)"
+      ),
+      None
+    );
+    let mut evaluator = Evaluator::default();
+    let results = evaluator.differential_eval("(def x 5) (+ x 1) (first 5)");
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|result| result.agrees()));
+    assert_eq!(results[1].form, "(+ x 1)");
+    assert_eq!(results[1].reference, Ok(6.into()));
+    assert!(results[2].vm.is_err());
+  }
+
+  #[cfg(feature = "tokio")]
+  #[test]
+  fn evaluate_async_tasks() {
+    use crate::runtime::async_host;
+    let mut evaluator = Evaluator::default();
+    evaluator.enable_async();
+    let (channel, mut bridge) = async_host::channel();
+    evaluator.define_value("events", channel);
+    evaluator
+      .eval("(def worker (fn (x) ((fn (_) (* x 7)) (await (sleep 5)))))")
+      .unwrap();
+    evaluator
+      .eval(
+        "(def finish
+           (fn (task n) (await (send events (+ n (await (join task)))))))",
+      )
+      .unwrap();
+    evaluator
+      .eval(
+        "(def main
+           (fn (x) (finish (await (spawn worker x)) (await (recv events)))))",
+      )
+      .unwrap();
+    let main = evaluator.get_binding("main").unwrap().clone();
+    let host = evaluator.async_host();
+    let sender = bridge.sender.clone();
+    std::thread::spawn(move || sender.send("100".to_string()).unwrap());
+    let runtime = tokio::runtime::Builder::new_current_thread()
+      .enable_time()
+      .build()
+      .unwrap();
+    let result = runtime.block_on(
+      tokio::task::LocalSet::new().run_until(host.run(main, vec![6.into()])),
+    );
+    assert_eq!(result, Ok(true.into()));
+    assert_eq!(bridge.receiver.try_recv(), Ok("142".to_string()));
+  }
+
+  #[cfg(feature = "wasm")]
+  #[test]
+  fn wasm_numbers() {
+    use super::wasm::{from_js_number, to_js_number, JsNumber};
+    use num_bigint::BigInt as BigInteger;
+    let safe = 9007199254740991;
+    for n in [
+      Num::Int(0),
+      Num::Int(-safe),
+      Num::Int(safe),
+      Num::Int(safe + 1),
+      Num::Int(-safe - 1),
+      Num::Int(i64::MAX),
+      Num::Int(i64::MIN),
+      Num::Float(2.5.into()),
+      Num::from_big_int(BigInteger::from(i64::MAX) * 4),
+    ] {
+      let value = Value::Number(n.clone());
+      assert_eq!(from_js_number(to_js_number(&n)).ok(), Some(value));
+    }
+    assert_eq!(
+      to_js_number(&Num::Int(safe + 1)),
+      JsNumber::BigInt("9007199254740992".to_string())
+    );
+  }
+
+  #[cfg(feature = "capi")]
+  #[test]
+  fn c_api() {
+    use super::ffi::*;
+    use std::ffi::{c_void, CStr};
+    extern "C" fn twice(
+      _user_data: *mut c_void,
+      args: *const *const PidginValue,
+      arg_count: usize,
+    ) -> *mut PidginValue {
+      let mut n = 0.;
+      unsafe {
+        if arg_count != 1 || !pidgin_value_as_number(*args, &mut n) {
+          return pidgin_value_error(c"expected a number".as_ptr());
+        }
+      }
+      pidgin_value_from_number(n * 2.)
+    }
+    unsafe {
+      let evaluator = pidgin_evaluator_new();
+      assert!(pidgin_define_fn(
+        evaluator,
+        c"twice".as_ptr(),
+        twice,
+        std::ptr::null_mut()
+      ));
+      let value = pidgin_eval(evaluator, c"(+ (twice 20) 2)".as_ptr());
+      let mut n = 0.;
+      assert!(pidgin_value_as_number(value, &mut n));
+      assert_eq!(n, 42.);
+      pidgin_value_free(value);
+      let value = pidgin_eval(evaluator, c"(twice \"a\")".as_ptr());
+      assert!(pidgin_value_is_error(value));
+      let description = pidgin_value_to_string(evaluator, value);
+      assert!(CStr::from_ptr(description)
+        .to_str()
+        .unwrap()
+        .contains("expected a number"));
+      pidgin_string_free(description);
+      pidgin_value_free(value);
+      for (value, expected) in [
+        (pidgin_value_from_number(2.), "2."),
+        (pidgin_value_from_number(-0.), "-0."),
+        (pidgin_value_from_int(2), "2"),
+      ] {
+        let description = pidgin_value_to_string(evaluator, value);
+        assert_eq!(CStr::from_ptr(description).to_str(), Ok(expected));
+        pidgin_string_free(description);
+        pidgin_value_free(value);
+      }
+      pidgin_evaluator_free(evaluator);
+    }
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn load_compiled_artifact() {
+    let source = "(ns geometry)
+                  (def ^:dynamic *scale* 2)
+                  (def scaled (fn (x) (* x *scale*)))
+                  (ns user)
+                  (require 'geometry)
+                  (def area (fn (w h) (geometry/scaled (* w h))))
+                  (area 2 3)";
+    let artifact = Evaluator::default().compile_source(source).unwrap();
+    let mut evaluator = Evaluator::default();
+    // symbols the loading evaluator already knows about get different
+    // indexes than they had when the artifact was compiled
+    evaluator.eval("(def unrelated 'geometry/scaled)").unwrap();
+    assert_eq!(evaluator.load_compiled_source(&artifact), Ok(12.into()));
+    assert_eq!(evaluator.current_namespace(), "user");
+    assert_eq!(evaluator.eval("(area 1 5)"), Ok(10.into()));
+    evaluator.eval("(ns geometry)").unwrap();
+    assert_eq!(
+      evaluator.eval("(binding (*scale* 3) (scaled 2))"),
+      Ok(6.into())
+    );
+    assert!(matches!(
+      Evaluator::default().compile_source("(defrecord Point (x y))"),
+      Err(PidginError::InForm { error, .. })
+        if matches!(*error, PidginError::InvalidArtifact(_))
+    ));
+  }
+
+  #[test]
+  fn evaluate_comptime() {
+    let mut evaluator = Evaluator::default();
+    evaluator.eval("(def counter (atom 0))").unwrap();
+    evaluator
+      .eval("(def f (fn (x) (+ x (comptime (reset! counter (+ @counter 1))))))")
+      .unwrap();
+    assert_eq!(evaluator.eval("@counter"), Ok(1.into()));
+    assert_eq!(evaluator.eval("(f 10)"), Ok(11.into()));
+    assert_eq!(evaluator.eval("(f 20)"), Ok(21.into()));
+    assert_eq!(evaluator.eval("@counter"), Ok(1.into()));
+    assert_eq!(
+      evaluator.eval("(comptime (quote sym))"),
+      evaluator.read_edn("sym")
+    );
+    // functions defined in source are still IR until they're called, so they
+    // can be embedded, but a function that was handed over already compiled
+    // can't be
+    assert_eq!(evaluator.eval("((comptime (fn (x) x)) 5)"), Ok(5.into()));
+    evaluator
+      .define_value("compiled", Value::composite_fn(1, block![Return(0)]));
+    assert!(matches!(
+      evaluator.eval("(comptime compiled)"),
+      Err(PidginError::Runtime(RuntimeError::CantConvert(_, _)))
+    ));
+  }
 }
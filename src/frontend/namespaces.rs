@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+  compiler::ast::{error::ASTError, token::SymbolLedger},
+  runtime::evaluation::SymbolIndex,
+};
+
+const DEFAULT_NAMESPACE: &str = "user";
+
+#[derive(Debug, Clone, Default)]
+struct Namespace {
+  // Maps the unqualified names defined in this namespace to the qualified
+  // symbols (`ns/name`) that their values are stored under
+  definitions: HashMap<SymbolIndex, SymbolIndex>,
+  qualified_names: HashSet<SymbolIndex>,
+  // The qualified names of definitions that aren't marked `^:private`, which
+  // other namespaces can refer to once they require this one
+  exports: HashSet<SymbolIndex>,
+  required: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Namespaces {
+  namespaces: HashMap<String, Namespace>,
+  current: String,
+}
+
+impl Default for Namespaces {
+  fn default() -> Self {
+    Self {
+      namespaces: HashMap::from([(
+        DEFAULT_NAMESPACE.to_string(),
+        Namespace::default(),
+      )]),
+      current: DEFAULT_NAMESPACE.to_string(),
+    }
+  }
+}
+
+impl Namespaces {
+  fn current(&self) -> &Namespace {
+    &self.namespaces[&self.current]
+  }
+  fn current_mut(&mut self) -> &mut Namespace {
+    self.namespaces.get_mut(&self.current).unwrap()
+  }
+  pub fn current_name(&self) -> &str {
+    &self.current
+  }
+  // Switches to the given namespace, creating it if it doesn't exist yet
+  pub fn enter(&mut self, name: String) {
+    self.namespaces.entry(name.clone()).or_default();
+    self.current = name;
+  }
+  pub fn require(&mut self, name: String) -> Result<(), ASTError> {
+    if !self.namespaces.contains_key(&name) {
+      return Err(ASTError::UnknownNamespace(name));
+    }
+    let current = self.current_mut();
+    if !current.required.contains(&name) {
+      current.required.push(name);
+    }
+    Ok(())
+  }
+  // Returns the qualified symbol that a definition of `name` in the current
+  // namespace should be stored under
+  pub fn define(
+    &mut self,
+    name: SymbolIndex,
+    private: bool,
+    symbol_ledger: &mut SymbolLedger,
+  ) -> SymbolIndex {
+    let qualified_name = symbol_ledger.symbol_index(format!(
+      "{}/{}",
+      self.current,
+      symbol_ledger
+        .symbol_name(&name)
+        .expect("unregistered symbol passed to Namespaces::define")
+    ));
+    let current = self.current_mut();
+    current.definitions.insert(name, qualified_name);
+    current.qualified_names.insert(qualified_name);
+    if private {
+      current.exports.remove(&qualified_name);
+    } else {
+      current.exports.insert(qualified_name);
+    }
+    qualified_name
+  }
+  // Each namespace, along with the namespaces it requires
+  #[cfg(feature = "serde")]
+  pub fn requirements(&self) -> impl Iterator<Item = (&str, &[String])> {
+    self
+      .namespaces
+      .iter()
+      .map(|(name, namespace)| (name.as_str(), namespace.required.as_slice()))
+  }
+  // Whether a qualified name belongs to a definition marked `^:private`
+  #[cfg(feature = "serde")]
+  pub fn is_private(&self, qualified_name: SymbolIndex) -> bool {
+    self.namespaces.values().any(|namespace| {
+      namespace.qualified_names.contains(&qualified_name)
+        && !namespace.exports.contains(&qualified_name)
+    })
+  }
+  // The symbols that can currently be referred to, either unqualified names
+  // defined in the current namespace or the exports of required namespaces
+  pub fn visible_symbols(&self) -> impl Iterator<Item = SymbolIndex> + '_ {
+    let current = self.current();
+    current.definitions.keys().copied().chain(
+      current
+        .required
+        .iter()
+        .flat_map(|required| self.namespaces[required].exports.iter().copied()),
+    )
+  }
+  // Finds the qualified symbol that `symbol` refers to from within the current
+  // namespace. Unqualified symbols refer to definitions in the current
+  // namespace, while qualified ones can also refer to the exports of any
+  // required namespace
+  pub fn resolve(&self, symbol: SymbolIndex) -> Option<SymbolIndex> {
+    let current = self.current();
+    if let Some(qualified_name) = current.definitions.get(&symbol) {
+      Some(*qualified_name)
+    } else if current.qualified_names.contains(&symbol)
+      || current
+        .required
+        .iter()
+        .any(|required| self.namespaces[required].exports.contains(&symbol))
+    {
+      Some(symbol)
+    } else {
+      None
+    }
+  }
+}
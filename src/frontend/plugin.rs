@@ -0,0 +1,87 @@
+// Modules, which let separately compiled crates add functions and types to an
+// evaluator through `Evaluator::install_module`. With the `plugins` feature, a
+// module can also be built as a dynamic library that names it with
+// `declare_pidgin_module!`, and then loaded at runtime with
+// `Evaluator::load_plugin`.
+//
+// Rust has no stable ABI, so a plugin has to be built by the same compiler and
+// against the same version of this crate as the program that loads it. Only
+// the version can be checked when it's loaded
+
+use super::evaluator::Evaluator;
+
+pub trait PidginModule {
+  // Installing a module with the same name as one that's already installed
+  // does nothing
+  fn name(&self) -> &str;
+  // Binds the module's functions and types, for instance with
+  // `Evaluator::define_fn` and `Evaluator::export`
+  fn register(&self, evaluator: &mut Evaluator);
+}
+
+#[cfg(feature = "plugins")]
+pub mod dynamic {
+  use std::{ffi::CStr, os::raw::c_char, path::Path};
+
+  use libloading::{Library, Symbol};
+
+  use super::PidginModule;
+
+  // Null-terminated, so that a plugin can report the version it was built
+  // against over the C ABI
+  pub const PLUGIN_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+  pub(crate) const VERSION_SYMBOL: &[u8] = b"pidgin_plugin_version";
+  pub(crate) const MODULE_SYMBOL: &[u8] = b"pidgin_plugin_module";
+
+  pub type PluginVersionFn = unsafe extern "C" fn() -> *const c_char;
+  pub type PluginModuleFn = fn() -> Box<dyn PidginModule>;
+
+  // Opens the library at `path` and builds the module it declares. The
+  // library is never unloaded, since the functions it defines may outlive
+  // the evaluator they were installed into
+  //
+  // Safety: the library's initialization code runs when it's opened, and
+  // its module must have been built as described at the top of this file
+  pub(crate) unsafe fn load_module(
+    path: &Path,
+  ) -> Result<Box<dyn PidginModule>, String> {
+    let describe = |error: libloading::Error| {
+      format!("couldn't load {}: {error}", path.display())
+    };
+    let library = Library::new(path).map_err(describe)?;
+    let version: Symbol<PluginVersionFn> =
+      library.get(VERSION_SYMBOL).map_err(describe)?;
+    let version = CStr::from_ptr(version()).to_string_lossy().into_owned();
+    let expected_version = PLUGIN_VERSION.trim_end_matches('\0');
+    if version != expected_version {
+      return Err(format!(
+        "{} was built against pidgin {version}, but this is pidgin \
+         {expected_version}",
+        path.display()
+      ));
+    }
+    let module: Symbol<PluginModuleFn> =
+      library.get(MODULE_SYMBOL).map_err(describe)?;
+    let module = module();
+    std::mem::forget(library);
+    Ok(module)
+  }
+}
+
+// Declares the module that a plugin library provides, given an expression
+// that builds it. Goes at the top level of a `cdylib` crate
+#[cfg(feature = "plugins")]
+#[macro_export]
+macro_rules! declare_pidgin_module {
+  ($module:expr) => {
+    #[no_mangle]
+    pub extern "C" fn pidgin_plugin_version() -> *const ::std::os::raw::c_char {
+      $crate::PLUGIN_VERSION.as_ptr().cast()
+    }
+    #[no_mangle]
+    pub fn pidgin_plugin_module() -> Box<dyn $crate::PidginModule> {
+      Box::new($module)
+    }
+  };
+}
@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::runtime::{
+  control::Block, evaluation::Instruction, profile::Profile,
+};
+
+use super::coverage::function_blocks;
+
+// The hits and time of one top-level form, counting every instruction of the
+// form itself and of the functions defined within it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormProfile {
+  pub form: String,
+  pub hits: u64,
+  pub nanos: u64,
+}
+
+// The profile of every form evaluated since profiling was enabled, in the
+// order they were first evaluated, along with the time spent in each call
+// stack
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ProfileReport {
+  pub forms: Vec<FormProfile>,
+  // Each stack's frames are named, outermost first
+  pub stacks: BTreeMap<Vec<String>, u64>,
+}
+
+// Frame names can't contain the characters that separate frames and counts
+// in folded stacks
+fn frame_name(name: &str) -> String {
+  name.replace(';', ",")
+}
+
+impl ProfileReport {
+  // Functions are named after the globals they're bound to, falling back to
+  // the form they were defined in, and forms are named by their source
+  pub(crate) fn new(
+    recorded_forms: &[(String, Block)],
+    profile: &Profile,
+    function_names: HashMap<*const Instruction, String>,
+  ) -> Self {
+    let mut names = HashMap::new();
+    let forms = recorded_forms
+      .iter()
+      .map(|(form, block)| {
+        let mut blocks = vec![];
+        function_blocks(block, &mut blocks, &mut HashSet::new());
+        let (mut hits, mut nanos) = (0, 0);
+        for (i, function_block) in blocks.iter().enumerate() {
+          let pointer = function_block.instructions.as_ptr();
+          names.entry(pointer).or_insert_with(|| {
+            frame_name(&match function_names.get(&pointer) {
+              Some(name) => name.clone(),
+              None if i == 0 => form.clone(),
+              None => format!("fn in {form}"),
+            })
+          });
+          for instruction_index in 0..function_block.instructions.len() {
+            let instruction =
+              profile.instruction(function_block, instruction_index);
+            hits += instruction.hits;
+            nanos += instruction.nanos;
+          }
+        }
+        FormProfile {
+          form: form.clone(),
+          hits,
+          nanos,
+        }
+      })
+      .collect();
+    let mut stacks = BTreeMap::new();
+    for (stack, nanos) in profile.stacks() {
+      let names = stack
+        .iter()
+        .map(|block| names.get(block).cloned().unwrap_or("unknown".to_string()))
+        .collect();
+      *stacks.entry(names).or_default() += nanos;
+    }
+    Self { forms, stacks }
+  }
+  // The stacks in the folded format that flamegraph tools read, with one line
+  // per stack giving its frames separated by semicolons and then the
+  // nanoseconds spent in it
+  pub fn folded_stacks(&self) -> String {
+    self
+      .stacks
+      .iter()
+      .map(|(stack, nanos)| format!("{} {nanos}\n", stack.join(";")))
+      .collect()
+  }
+}
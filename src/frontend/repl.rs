@@ -0,0 +1,353 @@
+use std::{
+  cell::RefCell,
+  io::IsTerminal,
+  path::{Path, PathBuf},
+  rc::Rc,
+};
+
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+
+use crate::runtime::{control::FrameView, data::Value};
+
+use super::{completion::ReplHelper, evaluator::Evaluator};
+
+const DEBUGGER_HELP: &str = "debugger commands:
+  :frames      list the frames of the failed evaluation, innermost first
+  :locals [n]  show the registers of the selected frame, or of frame n
+  :up          select the frame that called the selected one
+  :down        select the frame that the selected one called
+  :continue    return to the REPL";
+
+// The sub-prompt that the REPL drops into when a line fails at runtime, for
+// inspecting the frames that the failed evaluation left behind. Frame 0 is
+// the one that was running when the error happened
+pub(crate) struct PostMortem {
+  frames: Vec<FrameView>,
+  selected: usize,
+}
+
+impl PostMortem {
+  pub(crate) fn new(frames: Vec<FrameView>) -> Option<Self> {
+    (!frames.is_empty()).then_some(Self {
+      frames,
+      selected: 0,
+    })
+  }
+  fn describe_frame(&self, index: usize) -> String {
+    let frame = &self.frames[index];
+    let marker = if index == self.selected { '*' } else { ' ' };
+    match &frame.instruction {
+      Some(instruction) => format!(
+        "{marker}{index}: at instruction {}, {instruction:?}",
+        frame.instruction_index
+      ),
+      None => format!("{marker}{index}: finished"),
+    }
+  }
+  // Runs one command, returning what to print, or `None` once the user asks
+  // to leave the debugger
+  pub(crate) fn command(
+    &mut self,
+    line: &str,
+    evaluator: &Evaluator,
+  ) -> Option<String> {
+    let mut words = line.split_whitespace();
+    let output = match (words.next(), words.next()) {
+      (Some(":continue"), None) => return None,
+      (Some(":frames"), None) => (0..self.frames.len())
+        .map(|index| self.describe_frame(index))
+        .collect::<Vec<_>>()
+        .join("\n"),
+      (Some(":locals"), index) => {
+        match index.map_or(Ok(self.selected), str::parse::<usize>) {
+          Ok(index) if index < self.frames.len() => {
+            let registers = &self.frames[index].registers;
+            if registers.is_empty() {
+              "no registers".to_string()
+            } else {
+              registers
+                .iter()
+                .enumerate()
+                .map(|(register, value)| {
+                  format!(
+                    "r{register} = {}",
+                    evaluator.pretty_describe(value.clone())
+                  )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+            }
+          }
+          _ => format!("no frame {}", index.unwrap_or_default()),
+        }
+      }
+      (Some(":up"), None) => {
+        if self.selected + 1 < self.frames.len() {
+          self.selected += 1;
+          self.describe_frame(self.selected)
+        } else {
+          "already at the outermost frame".to_string()
+        }
+      }
+      (Some(":down"), None) => {
+        if self.selected > 0 {
+          self.selected -= 1;
+          self.describe_frame(self.selected)
+        } else {
+          "already at the innermost frame".to_string()
+        }
+      }
+      _ => DEBUGGER_HELP.to_string(),
+    };
+    Some(output)
+  }
+}
+
+// The globals that were defined while a line ran and are being watched with
+// `:watch`, to print along with the line's result
+pub(crate) type WatchLog = Rc<RefCell<Vec<(String, Value)>>>;
+
+// Handles `:watch name` and `:unwatch name`, returning what to print, or
+// `None` if the line is neither
+pub(crate) fn watch_command(
+  line: &str,
+  evaluator: &mut Evaluator,
+  watch_log: &WatchLog,
+) -> Option<String> {
+  let mut words = line.split_whitespace();
+  match (words.next()?, words.next(), words.next()) {
+    (":watch", Some(name), None) => {
+      let watch_log = watch_log.clone();
+      evaluator.watch_global(name, move |name, value| {
+        watch_log
+          .borrow_mut()
+          .push((name.to_string(), value.clone()))
+      });
+      Some(format!("watching {name}"))
+    }
+    (":unwatch", Some(name), None) => {
+      evaluator.unwatch_global(name);
+      Some(format!("stopped watching {name}"))
+    }
+    _ => None,
+  }
+}
+
+// Handles `:save path` and `:restore path`, which write the whole session to
+// an image and load one back, returning what to print, or `None` if the line
+// is neither
+pub(crate) fn session_command(
+  line: &str,
+  evaluator: &mut Evaluator,
+) -> Option<String> {
+  let mut words = line.split_whitespace();
+  let result = match (words.next()?, words.next(), words.next()) {
+    (":save", Some(path), None) => evaluator
+      .save_image(path)
+      .map(|()| format!("saved the session to {path}")),
+    (":restore", Some(path), None) => evaluator
+      .load_image(path)
+      .map(|()| format!("restored the session from {path}")),
+    _ => return None,
+  };
+  Some(result.unwrap_or_else(|error| evaluator.describe_error(error)))
+}
+
+// Settings for an interactive REPL session, built up with chained calls and
+// started with `run`. `{ns}` in the prompt is replaced by the current
+// namespace
+pub struct ReplConfig {
+  prompt: String,
+  history_path: Option<PathBuf>,
+  // Where the session is saved after every line, and deleted from once the
+  // REPL exits normally, so that a session that crashed is restored the next
+  // time the REPL starts
+  recovery_path: Option<PathBuf>,
+  banner: Option<String>,
+  bindings: Vec<(String, Value)>,
+  evaluator: Option<Evaluator>,
+}
+
+impl Default for ReplConfig {
+  fn default() -> Self {
+    Self {
+      prompt: "{ns}> ".to_string(),
+      history_path: Some(PathBuf::from("history.txt")),
+      recovery_path: Some(PathBuf::from("recovery.pgn-image")),
+      banner: Some("\nWelcome to Pidgin!! :D\n".to_string()),
+      bindings: vec![],
+      evaluator: None,
+    }
+  }
+}
+
+impl ReplConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+    self.prompt = prompt.into();
+    self
+  }
+  pub fn history_path(mut self, path: impl AsRef<Path>) -> Self {
+    self.history_path = Some(path.as_ref().to_path_buf());
+    self
+  }
+  pub fn no_history(mut self) -> Self {
+    self.history_path = None;
+    self
+  }
+  pub fn recovery_path(mut self, path: impl AsRef<Path>) -> Self {
+    self.recovery_path = Some(path.as_ref().to_path_buf());
+    self
+  }
+  pub fn no_recovery(mut self) -> Self {
+    self.recovery_path = None;
+    self
+  }
+  pub fn banner(mut self, banner: impl Into<String>) -> Self {
+    self.banner = Some(banner.into());
+    self
+  }
+  pub fn no_banner(mut self) -> Self {
+    self.banner = None;
+    self
+  }
+  pub fn bind(mut self, name: &str, value: impl Into<Value>) -> Self {
+    self.bindings.push((name.to_string(), value.into()));
+    self
+  }
+  // Runs the session with an already-configured evaluator. Unlike the
+  // default evaluator, it isn't given file access or colored output unless
+  // it's been set up with them
+  pub fn evaluator(mut self, evaluator: Evaluator) -> Self {
+    self.evaluator = Some(evaluator);
+    self
+  }
+  pub(crate) fn build_evaluator(&mut self) -> Evaluator {
+    let mut evaluator = self.evaluator.take().unwrap_or_else(|| {
+      let mut evaluator = Evaluator::default();
+      // The REPL only runs code typed by its user, so there's no reason to
+      // keep it away from the filesystem
+      evaluator.enable_file_io();
+      evaluator.pretty_print_options.color = std::io::stdout().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none();
+      evaluator
+    });
+    for (name, value) in self.bindings.drain(..) {
+      evaluator.define_value(&name, value);
+    }
+    evaluator
+  }
+  // Restores the session that was left behind by a REPL that didn't exit
+  // normally, returning what to print if there was one
+  pub(crate) fn recover(&self, evaluator: &mut Evaluator) -> Option<String> {
+    let path = self.recovery_path.as_ref().filter(|path| path.exists())?;
+    Some(match evaluator.load_image(path) {
+      Ok(()) => format!("recovered the last session from {}", path.display()),
+      Err(error) => format!(
+        "couldn't recover the last session: {}",
+        evaluator.describe_error(error)
+      ),
+    })
+  }
+  pub(crate) fn save_recovery(&self, evaluator: &Evaluator) -> Option<String> {
+    let path = self.recovery_path.as_ref()?;
+    evaluator.save_image(path).err().map(|error| {
+      format!(
+        "couldn't save the session for recovery: {}",
+        evaluator.describe_error(error)
+      )
+    })
+  }
+  pub(crate) fn finish_session(&self) {
+    if let Some(path) = &self.recovery_path {
+      let _ = std::fs::remove_file(path);
+    }
+  }
+  pub(crate) fn prompt_for(&self, evaluator: &Evaluator) -> String {
+    self.prompt.replace("{ns}", evaluator.current_namespace())
+  }
+  pub fn run(mut self) -> Result<(), ReadlineError> {
+    let mut evaluator = self.build_evaluator();
+    if let Some(banner) = &self.banner {
+      println!("{banner}");
+    }
+    if let Some(output) = self.recover(&mut evaluator) {
+      println!("{output}");
+    }
+    let mut rl = Editor::<ReplHelper, DefaultHistory>::new()?;
+    rl.set_helper(Some(ReplHelper {
+      names: evaluator.completions(""),
+    }));
+    if let Some(history_path) = &self.history_path {
+      if rl.load_history(history_path).is_err() {
+        println!("No previous history.");
+      }
+    }
+    let watch_log = WatchLog::default();
+    loop {
+      let readline = rl.readline(&self.prompt_for(&evaluator));
+      match readline {
+        Ok(line) => {
+          rl.add_history_entry(line.as_str())
+            .expect("failed to add line to history");
+          if let Some(output) = watch_command(&line, &mut evaluator, &watch_log)
+          {
+            println!("{output}");
+            continue;
+          }
+          if let Some(output) = session_command(&line, &mut evaluator) {
+            println!("{output}");
+          } else {
+            let result = evaluator.repl_eval(&line);
+            for (name, value) in watch_log.borrow_mut().drain(..) {
+              println!("{name} set to {}", evaluator.pretty_describe(value));
+            }
+            match result {
+              Ok(value) => println!("{}", evaluator.pretty_describe(value)),
+              Err(error) => {
+                println!("{}", evaluator.describe_error_in(&line, error));
+                if let Some(mut post_mortem) =
+                  PostMortem::new(evaluator.failed_frames().to_vec())
+                {
+                  println!("entering the debugger, :continue to leave");
+                  while let Ok(line) = rl.readline("debug> ") {
+                    match post_mortem.command(&line, &evaluator) {
+                      Some(output) => println!("{output}"),
+                      None => break,
+                    }
+                  }
+                }
+              }
+            }
+          }
+          if let Some(output) = self.save_recovery(&evaluator) {
+            println!("{output}");
+          }
+          if let Some(helper) = rl.helper_mut() {
+            helper.names = evaluator.completions("");
+          }
+        }
+        Err(ReadlineError::Interrupted) => {
+          println!("CTRL-C");
+          break;
+        }
+        Err(ReadlineError::Eof) => {
+          println!("CTRL-D");
+          break;
+        }
+        Err(err) => {
+          println!("Error: {:?}", err);
+          break;
+        }
+      }
+    }
+    if let Some(history_path) = &self.history_path {
+      rl.save_history(history_path)
+        .expect("failed to save history");
+    }
+    self.finish_session();
+    Ok(())
+  }
+}
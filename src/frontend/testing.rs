@@ -0,0 +1,36 @@
+use crate::runtime::data::Value;
+
+use super::error::PidginResult;
+
+// The outcome of one test defined with `deftest`. The result is the value of
+// the test's body, or the error that stopped it, which is an `assertion
+// failed` error when one of its `is` forms was false
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+  pub name: String,
+  pub result: PidginResult<Value>,
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    self.result.is_ok()
+  }
+}
+
+// The outcome of every test, in the order they were defined
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TestReport {
+  pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+  pub fn passed(&self) -> usize {
+    self.results.iter().filter(|result| result.passed()).count()
+  }
+  pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+    self.results.iter().filter(|result| !result.passed())
+  }
+  pub fn all_passed(&self) -> bool {
+    self.failures().next().is_none()
+  }
+}
@@ -0,0 +1,193 @@
+// Bindings that let JavaScript drive an evaluator, for running Pidgin in the
+// browser. Values cross the boundary as their closest JavaScript counterparts:
+// lists become arrays, hashmaps become `Map`s, and hashsets become `Set`s.
+// Values with no such counterpart, like functions, are passed to JavaScript
+// as their descriptions
+
+use std::rc::Rc;
+
+use js_sys::{Array, BigInt, Function, Map, Object, Set};
+use num_bigint::BigInt as BigInteger;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsError, JsValue};
+
+use crate::runtime::data::{GenericValue::*, Num, Value};
+
+use super::evaluator::Evaluator;
+
+// The largest integer that a JavaScript number can hold exactly
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.;
+
+// A number as it crosses the boundary. Integers that a JavaScript number
+// can't hold exactly cross as `BigInt`s, which are passed as their digits
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsNumber {
+  Number(f64),
+  BigInt(String),
+}
+
+pub(crate) fn to_js_number(n: &Num) -> JsNumber {
+  match n {
+    Num::Int(i) if (*i as f64).abs() <= MAX_SAFE_INTEGER => {
+      JsNumber::Number(*i as f64)
+    }
+    Num::Int(i) => JsNumber::BigInt(i.to_string()),
+    Num::Float(f) => JsNumber::Number(**f),
+    Num::BigInt(i) => JsNumber::BigInt(i.to_string()),
+  }
+}
+
+// Whole numbers become integers as long as they're small enough that the
+// conversion to a number didn't round them
+pub(crate) fn from_js_number(n: JsNumber) -> Result<Value, JsError> {
+  match n {
+    JsNumber::Number(n) if n.fract() == 0. && n.abs() <= MAX_SAFE_INTEGER => {
+      Ok((n as i64).into())
+    }
+    JsNumber::Number(n) => Ok(n.into()),
+    JsNumber::BigInt(digits) => digits
+      .parse::<BigInteger>()
+      .map(Value::from)
+      .map_err(|error| JsError::new(&error.to_string())),
+  }
+}
+
+#[wasm_bindgen(js_name = Evaluator)]
+#[derive(Default)]
+pub struct WasmEvaluator {
+  evaluator: Evaluator,
+}
+
+#[wasm_bindgen(js_class = Evaluator)]
+impl WasmEvaluator {
+  #[wasm_bindgen(constructor)]
+  pub fn new() -> Self {
+    Self::default()
+  }
+  pub fn eval(&mut self, source: &str) -> Result<JsValue, JsError> {
+    match self.evaluator.eval(source) {
+      Ok(value) => Ok(self.to_js(&value)),
+      Err(error) => Err(JsError::new(&self.evaluator.describe_error(error))),
+    }
+  }
+  // Like `eval`, but returns the value's description rather than converting
+  // it, as a REPL would print it
+  #[wasm_bindgen(js_name = evalToString)]
+  pub fn eval_to_string(&mut self, source: &str) -> Result<String, JsError> {
+    match self.evaluator.eval(source) {
+      Ok(value) => Ok(self.evaluator.describe(value)),
+      Err(error) => Err(JsError::new(&self.evaluator.describe_error(error))),
+    }
+  }
+  #[wasm_bindgen(js_name = defineValue)]
+  pub fn define_value(
+    &mut self,
+    name: &str,
+    value: JsValue,
+  ) -> Result<(), JsError> {
+    let value = from_js(value)?;
+    self.evaluator.define_value(name, value);
+    Ok(())
+  }
+  #[wasm_bindgen(js_name = currentNamespace)]
+  pub fn current_namespace(&self) -> String {
+    self.evaluator.current_namespace().to_string()
+  }
+  // Calls `callback` with each line that `print`, `pprint`, and `time` write,
+  // since there's no standard output to write them to in the browser
+  #[wasm_bindgen(js_name = setOutput)]
+  pub fn set_output(&mut self, callback: Function) {
+    self.evaluator.set_output(move |line| {
+      let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(line));
+    });
+  }
+}
+
+impl WasmEvaluator {
+  fn to_js(&self, value: &Value) -> JsValue {
+    match value {
+      Nil => JsValue::NULL,
+      Bool(b) => JsValue::from_bool(*b),
+      Char(c) => JsValue::from_str(&c.to_string()),
+      Number(n) => match to_js_number(n) {
+        JsNumber::Number(n) => JsValue::from_f64(n),
+        JsNumber::BigInt(digits) => JsValue::bigint_from_str(&digits),
+      },
+      Str(s) => JsValue::from_str(s),
+      List(list) => list
+        .iter()
+        .map(|value| self.to_js(value))
+        .collect::<Array>()
+        .into(),
+      Hashmap(map) => {
+        let js_map = Map::new();
+        for (key, value) in map.iter() {
+          js_map.set(&self.to_js(key), &self.to_js(value));
+        }
+        js_map.into()
+      }
+      Hashset(set) => {
+        let js_set = Set::new(&JsValue::UNDEFINED);
+        for value in set.iter() {
+          js_set.add(&self.to_js(value));
+        }
+        js_set.into()
+      }
+      other => JsValue::from_str(&self.evaluator.describe(other.clone())),
+    }
+  }
+}
+
+fn from_js(value: JsValue) -> Result<Value, JsError> {
+  if value.is_null() || value.is_undefined() {
+    Ok(Nil)
+  } else if let Some(b) = value.as_bool() {
+    Ok(b.into())
+  } else if let Some(n) = value.as_f64() {
+    from_js_number(JsNumber::Number(n))
+  } else if let Some(s) = value.as_string() {
+    Ok(s.into())
+  } else if value.is_bigint() {
+    let digits = String::from(
+      value
+        .unchecked_into::<BigInt>()
+        .to_string(10)
+        .map_err(|_| JsError::new("can't convert BigInt"))?,
+    );
+    from_js_number(JsNumber::BigInt(digits))
+  } else if Array::is_array(&value) {
+    Array::from(&value).iter().map(from_js).collect()
+  } else if let Some(map) = value.dyn_ref::<Map>() {
+    let mut entries = vec![];
+    map.for_each(&mut |value, key| entries.push((key, value)));
+    map_from_entries(entries)
+  } else if let Some(set) = value.dyn_ref::<Set>() {
+    let mut values = vec![];
+    set.for_each(&mut |value, _, _| values.push(value));
+    Ok(Hashset(Rc::new(
+      values.into_iter().map(from_js).collect::<Result<_, _>>()?,
+    )))
+  } else if value.is_object() {
+    map_from_entries(
+      Object::entries(value.unchecked_ref())
+        .iter()
+        .map(|entry| {
+          let entry = Array::from(&entry);
+          (entry.get(0), entry.get(1))
+        })
+        .collect(),
+    )
+  } else {
+    Err(JsError::new("can't convert value from JavaScript"))
+  }
+}
+
+fn map_from_entries(
+  entries: Vec<(JsValue, JsValue)>,
+) -> Result<Value, JsError> {
+  Ok(Hashmap(Rc::new(
+    entries
+      .into_iter()
+      .map(|(key, value)| Ok((from_js(key)?, from_js(value)?)))
+      .collect::<Result<_, JsError>>()?,
+  )))
+}
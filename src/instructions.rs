@@ -1,6 +1,38 @@
+use std::rc::Rc;
+
 use crate::runtime::evaluation::{ConstIndex, SymbolIndex};
 
+// An argument carried by a call. A stolen argument is moved out of its
+// register rather than copied, for when the caller doesn't need it afterwards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Argument<I> {
+  CopyArgument(I),
+  StealArgument(I),
+}
+use Argument::*;
+
+impl<I> Argument<I> {
+  pub fn register(&self) -> &I {
+    match self {
+      CopyArgument(register) | StealArgument(register) => register,
+    }
+  }
+  pub fn translate<NewI>(
+    self,
+    translator: impl Fn(I) -> NewI,
+  ) -> Argument<NewI> {
+    match self {
+      CopyArgument(register) => CopyArgument(translator(register)),
+      StealArgument(register) => StealArgument(translator(register)),
+    }
+  }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GenericInstruction<I, O, R> {
   DebugPrint(u8),
 
@@ -14,16 +46,21 @@ pub enum GenericInstruction<I, O, R> {
 
   // Function control flow
   Return(I),
-  CopyArgument(I),
-  StealArgument(I),
-  Call(O, I, u8),
+  Call(O, I, Rc<[Argument<I>]>),
   Apply(R, I),
-  CallAndReturn(I, u8),
+  CallAndReturn(I, Rc<[Argument<I>]>),
   ApplyAndReturn(I, I),
   Jump(u16),
+  // Relative to the position of the jump instruction itself
+  RelativeJump(i16),
+  JumpIf(I, u16),
+  JumpIfNot(I, u16),
 
   // Environment manipulation
   Lookup(O, SymbolIndex),
+  LookupDynamic(O, SymbolIndex),
+  PushDynamicBinding(SymbolIndex, I),
+  PopDynamicBindings(u8),
 
   // Control flow
   If(I),
@@ -75,6 +112,12 @@ pub enum GenericInstruction<I, O, R> {
   GreaterThanOrEqual(O, I, I),
   LessThan(O, I, I),
   LessThanOrEqual(O, I, I),
+  BitAnd(O, I, I),
+  BitOr(O, I, I),
+  BitXor(O, I, I),
+  BitNot(O, I),
+  ShiftLeft(O, I, I),
+  ShiftRight(O, I, I),
   Rand(O),
   UpperBoundedRand(O, I),
   LowerUpperBoundedRand(O, I, I),
@@ -164,10 +207,9 @@ pub enum GenericInstruction<I, O, R> {
   BoundedIterate(R, I, I),
 
   // Cells
-  CreateCell(O),
+  CreateCell(O, I),
   GetCellValue(O, I),
-  SetCellValue(O, I),
-  UpdateCell(O, I),
+  SetCellValue(I, I),
 
   // Coroutines
   CreateCoroutine(R),
@@ -205,199 +247,313 @@ pub enum GenericInstruction<I, O, R> {
   ToMap(O, I),
   ToSet(O, I),
   ToError(O, I),
+
+  // Fused instructions. These are only produced by the fusion pass after
+  // register allocation, and never appear in SSA blocks
+  ConstAdd(O, ConstIndex, I),
+  IncAndJumpIfPos(R, u16),
+  GetConstKey(O, I, ConstIndex),
+
+  // List updates that the uniqueness pass has proven to be the only reference
+  // to their list, so the VM can change the list in place rather than copying
+  // it when it's shared
+  PushUnique(R, I),
+  ConsUnique(R, I),
+  RestUnique(R),
+  ButLastUnique(R),
 }
 use GenericInstruction::*;
 
-pub struct RegisterUsages<I, O, R> {
-  pub inputs: Vec<I>,
-  pub outputs: Vec<O>,
-  pub replacements: Vec<R>,
+// The registers an instruction reads from. No instruction has more than three
+// fixed inputs, so they're kept inline, followed by the arguments of a call
+pub struct Inputs<'a, I> {
+  fixed: std::array::IntoIter<Option<&'a I>, 3>,
+  arguments: std::slice::Iter<'a, Argument<I>>,
+}
+impl<I: Clone> Iterator for Inputs<'_, I> {
+  type Item = I;
+  fn next(&mut self) -> Option<I> {
+    self
+      .fixed
+      .by_ref()
+      .flatten()
+      .next()
+      .or_else(|| self.arguments.next().map(Argument::register))
+      .cloned()
+  }
+}
+
+// No instruction writes to more than one register, so usages are gathered
+// without allocating
+pub struct RegisterUsages<'a, I, O, R> {
+  pub inputs: Inputs<'a, I>,
+  pub outputs: std::option::IntoIter<O>,
+  pub replacements: std::option::IntoIter<R>,
 }
 impl<I: Clone, O: Clone, R: Clone> GenericInstruction<I, O, R> {
-  pub fn usages(&self) -> RegisterUsages<I, O, R> {
+  pub fn usages(&self) -> RegisterUsages<'_, I, O, R> {
+    let arguments: &[Argument<I>] = match self {
+      Call(_, _, args) | CallAndReturn(_, args) => args,
+      _ => &[],
+    };
     let (inputs, outputs, replacements) = match self {
-      DebugPrint(_) => (vec![], vec![], vec![]),
-      Clear(to) => (vec![], vec![to], vec![]),
-      Copy(to, from) => (vec![from], vec![to], vec![]),
-      Const(to, _) => (vec![], vec![to], vec![]),
-      Print(from) => (vec![from], vec![], vec![]),
-      Return(from) => (vec![from], vec![], vec![]),
-      CopyArgument(from) => (vec![from], vec![], vec![]),
-      StealArgument(from) => (vec![from], vec![], vec![]),
-      Call(to, from, _) => (vec![from], vec![to], vec![]),
-      Apply(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      CallAndReturn(f, _) => (vec![f], vec![], vec![]),
-      ApplyAndReturn(from, _) => (vec![from], vec![], vec![]),
-      Jump(_) => (vec![], vec![], vec![]),
-      Lookup(to, _) => (vec![], vec![to], vec![]),
-      If(from) => (vec![from], vec![], vec![]),
-      Else => (vec![], vec![], vec![]),
-      ElseIf(from) => (vec![from], vec![], vec![]),
-      EndIf => (vec![], vec![], vec![]),
-      Partial(to, f, arg) => (vec![f, arg], vec![to], vec![]),
-      Compose(to, f_1, f_2) => (vec![f_1, f_2], vec![to], vec![]),
-      FindSome(to, f, collection) => (vec![f, collection], vec![to], vec![]),
+      DebugPrint(_) => ([None, None, None], None, None),
+      Clear(to) => ([None, None, None], Some(to), None),
+      Copy(to, from) => ([Some(from), None, None], Some(to), None),
+      Const(to, _) => ([None, None, None], Some(to), None),
+      Print(from) => ([Some(from), None, None], None, None),
+      Return(from) => ([Some(from), None, None], None, None),
+      Call(to, f, _) => ([Some(f), None, None], Some(to), None),
+      Apply(from_and_to, f) => ([Some(f), None, None], None, Some(from_and_to)),
+      CallAndReturn(f, _) => ([Some(f), None, None], None, None),
+      ApplyAndReturn(from, _) => ([Some(from), None, None], None, None),
+      Jump(_) => ([None, None, None], None, None),
+      RelativeJump(_) => ([None, None, None], None, None),
+      JumpIf(condition, _) => ([Some(condition), None, None], None, None),
+      JumpIfNot(condition, _) => ([Some(condition), None, None], None, None),
+      Lookup(to, _) => ([None, None, None], Some(to), None),
+      LookupDynamic(to, _) => ([None, None, None], Some(to), None),
+      PushDynamicBinding(_, from) => ([Some(from), None, None], None, None),
+      PopDynamicBindings(_) => ([None, None, None], None, None),
+      If(from) => ([Some(from), None, None], None, None),
+      Else => ([None, None, None], None, None),
+      ElseIf(from) => ([Some(from), None, None], None, None),
+      EndIf => ([None, None, None], None, None),
+      Partial(to, f, arg) => ([Some(f), Some(arg), None], Some(to), None),
+      Compose(to, f_1, f_2) => ([Some(f_1), Some(f_2), None], Some(to), None),
+      FindSome(to, f, collection) => {
+        ([Some(f), Some(collection), None], Some(to), None)
+      }
       ReduceWithoutInitialValue(from_and_to, f) => {
-        (vec![f], vec![], vec![from_and_to])
+        ([Some(f), None, None], None, Some(from_and_to))
       }
       ReduceWithInitialValue(from_and_to, f, initial) => {
-        (vec![f, initial], vec![], vec![from_and_to])
-      }
-      Memoize(to, from) => (vec![from], vec![to], vec![]),
-      Constantly(to, from) => (vec![from], vec![to], vec![]),
-      NumericalEqual(to, a, b) => (vec![a, b], vec![to], vec![]),
-      IsZero(to, from) => (vec![from], vec![to], vec![]),
-      IsNan(to, from) => (vec![from], vec![to], vec![]),
-      IsInf(to, from) => (vec![from], vec![to], vec![]),
-      IsEven(to, from) => (vec![from], vec![to], vec![]),
-      IsOdd(to, from) => (vec![from], vec![to], vec![]),
-      IsPos(to, from) => (vec![from], vec![to], vec![]),
-      IsNeg(to, from) => (vec![from], vec![to], vec![]),
-      Inc(to, from) => (vec![from], vec![to], vec![]),
-      Dec(to, from) => (vec![from], vec![to], vec![]),
-      Negate(to, from) => (vec![from], vec![to], vec![]),
-      Abs(to, from) => (vec![from], vec![to], vec![]),
-      Floor(to, from) => (vec![from], vec![to], vec![]),
-      Ceil(to, from) => (vec![from], vec![to], vec![]),
-      Sqrt(to, from) => (vec![from], vec![to], vec![]),
-      Exp(to, from) => (vec![from], vec![to], vec![]),
-      Exp2(to, from) => (vec![from], vec![to], vec![]),
-      Ln(to, from) => (vec![from], vec![to], vec![]),
-      Log2(to, from) => (vec![from], vec![to], vec![]),
-      Add(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Subtract(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Multiply(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Divide(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Pow(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Mod(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Quot(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Min(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Max(to, a, b) => (vec![a, b], vec![to], vec![]),
-      GreaterThan(to, a, b) => (vec![a, b], vec![to], vec![]),
-      GreaterThanOrEqual(to, a, b) => (vec![a, b], vec![to], vec![]),
-      LessThan(to, a, b) => (vec![a, b], vec![to], vec![]),
-      LessThanOrEqual(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Rand(to) => (vec![], vec![to], vec![]),
-      UpperBoundedRand(to, from) => (vec![from], vec![to], vec![]),
-      LowerUpperBoundedRand(to, a, b) => (vec![a, b], vec![to], vec![]),
-      RandInt(to, from) => (vec![from], vec![to], vec![]),
-      LowerBoundedRandInt(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Equal(to, a, b) => (vec![a, b], vec![to], vec![]),
-      NotEqual(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Not(to, from) => (vec![from], vec![to], vec![]),
-      And(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Or(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Xor(to, a, b) => (vec![a, b], vec![to], vec![]),
-      IsEmpty(to, from) => (vec![from], vec![to], vec![]),
-      First(to, from) => (vec![from], vec![to], vec![]),
-      Count(to, from) => (vec![from], vec![to], vec![]),
-      Flatten(to, from) => (vec![from], vec![to], vec![]),
-      Remove(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      Filter(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      Map(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      DoubleMap(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
+        ([Some(f), Some(initial), None], None, Some(from_and_to))
+      }
+      Memoize(to, from) => ([Some(from), None, None], Some(to), None),
+      Constantly(to, from) => ([Some(from), None, None], Some(to), None),
+      NumericalEqual(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      IsZero(to, from) => ([Some(from), None, None], Some(to), None),
+      IsNan(to, from) => ([Some(from), None, None], Some(to), None),
+      IsInf(to, from) => ([Some(from), None, None], Some(to), None),
+      IsEven(to, from) => ([Some(from), None, None], Some(to), None),
+      IsOdd(to, from) => ([Some(from), None, None], Some(to), None),
+      IsPos(to, from) => ([Some(from), None, None], Some(to), None),
+      IsNeg(to, from) => ([Some(from), None, None], Some(to), None),
+      Inc(to, from) => ([Some(from), None, None], Some(to), None),
+      Dec(to, from) => ([Some(from), None, None], Some(to), None),
+      Negate(to, from) => ([Some(from), None, None], Some(to), None),
+      Abs(to, from) => ([Some(from), None, None], Some(to), None),
+      Floor(to, from) => ([Some(from), None, None], Some(to), None),
+      Ceil(to, from) => ([Some(from), None, None], Some(to), None),
+      Sqrt(to, from) => ([Some(from), None, None], Some(to), None),
+      Exp(to, from) => ([Some(from), None, None], Some(to), None),
+      Exp2(to, from) => ([Some(from), None, None], Some(to), None),
+      Ln(to, from) => ([Some(from), None, None], Some(to), None),
+      Log2(to, from) => ([Some(from), None, None], Some(to), None),
+      Add(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Subtract(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Multiply(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Divide(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Pow(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Mod(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Quot(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Min(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Max(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      GreaterThan(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      GreaterThanOrEqual(to, a, b) => {
+        ([Some(a), Some(b), None], Some(to), None)
+      }
+      LessThan(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      LessThanOrEqual(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      BitAnd(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      BitOr(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      BitXor(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      BitNot(to, from) => ([Some(from), None, None], Some(to), None),
+      ShiftLeft(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      ShiftRight(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Rand(to) => ([None, None, None], Some(to), None),
+      UpperBoundedRand(to, from) => ([Some(from), None, None], Some(to), None),
+      LowerUpperBoundedRand(to, a, b) => {
+        ([Some(a), Some(b), None], Some(to), None)
+      }
+      RandInt(to, from) => ([Some(from), None, None], Some(to), None),
+      LowerBoundedRandInt(to, a, b) => {
+        ([Some(a), Some(b), None], Some(to), None)
+      }
+      Equal(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      NotEqual(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Not(to, from) => ([Some(from), None, None], Some(to), None),
+      And(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Or(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Xor(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      IsEmpty(to, from) => ([Some(from), None, None], Some(to), None),
+      First(to, from) => ([Some(from), None, None], Some(to), None),
+      Count(to, from) => ([Some(from), None, None], Some(to), None),
+      Flatten(to, from) => ([Some(from), None, None], Some(to), None),
+      Remove(from_and_to, x) => {
+        ([Some(x), None, None], None, Some(from_and_to))
+      }
+      Filter(from_and_to, f) => {
+        ([Some(f), None, None], None, Some(from_and_to))
+      }
+      Map(from_and_to, f) => ([Some(f), None, None], None, Some(from_and_to)),
+      DoubleMap(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
       MultiCollectionMap(from_and_to, f) => {
-        (vec![f], vec![], vec![from_and_to])
-      }
-      Set(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      SetIn(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      Get(to, a, b) => (vec![a, b], vec![to], vec![]),
-      GetIn(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Update(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      UpdateIn(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      MinKey(to, a, b) => (vec![a, b], vec![to], vec![]),
-      MaxKey(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Push(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      Sort(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      SortBy(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      EmptyList(to) => (vec![], vec![to], vec![]),
-      Last(to, from) => (vec![from], vec![to], vec![]),
-      Rest(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      ButLast(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      Nth(to, a, b) => (vec![a, b], vec![to], vec![]),
-      NthFromLast(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Cons(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      Concat(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      Take(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      Drop(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      Reverse(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      Distinct(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      Sub(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      Partition(to, a, b) => (vec![a, b], vec![to], vec![]),
+        ([Some(f), None, None], None, Some(from_and_to))
+      }
+      Set(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      SetIn(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      Get(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      GetIn(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Update(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      UpdateIn(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      MinKey(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      MaxKey(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Push(from_and_to, f) => ([Some(f), None, None], None, Some(from_and_to)),
+      Sort(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      SortBy(from_and_to, f) => {
+        ([Some(f), None, None], None, Some(from_and_to))
+      }
+      EmptyList(to) => ([None, None, None], Some(to), None),
+      Last(to, from) => ([Some(from), None, None], Some(to), None),
+      Rest(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      ButLast(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      Nth(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      NthFromLast(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Cons(from_and_to, x) => ([Some(x), None, None], None, Some(from_and_to)),
+      Concat(from_and_to, x) => {
+        ([Some(x), None, None], None, Some(from_and_to))
+      }
+      Take(from_and_to, x) => ([Some(x), None, None], None, Some(from_and_to)),
+      Drop(from_and_to, x) => ([Some(x), None, None], None, Some(from_and_to)),
+      Reverse(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      Distinct(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      Sub(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      Partition(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
       SteppedPartition(from_and_to, a, b) => {
-        (vec![a, b], vec![], vec![from_and_to])
-      }
-      Pad(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      EmptyMap(to) => (vec![], vec![to], vec![]),
-      Keys(to, from) => (vec![from], vec![to], vec![]),
-      Values(to, from) => (vec![from], vec![to], vec![]),
-      Zip(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Invert(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      Merge(to, a, b) => (vec![a, b], vec![to], vec![]),
-      MergeWith(from_and_to, a, b) => (vec![a, b], vec![], vec![from_and_to]),
-      MapKeys(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      MapValues(from_and_to, f) => (vec![f], vec![], vec![from_and_to]),
-      SelectKeys(from_and_to, x) => (vec![x], vec![], vec![from_and_to]),
-      EmptySet(to) => (vec![], vec![to], vec![]),
-      Union(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Intersection(to, a, b) => (vec![a, b], vec![to], vec![]),
-      Difference(to, a, b) => (vec![a, b], vec![to], vec![]),
-      SymmetricDifference(to, a, b) => (vec![a, b], vec![to], vec![]),
-      InfiniteRange(to) => (vec![], vec![to], vec![]),
-      UpperBoundedRange(to, from) => (vec![from], vec![to], vec![]),
-      LowerUpperBoundedRange(to, a, b) => (vec![a, b], vec![to], vec![]),
-      InfiniteRepeat(to, from) => (vec![from], vec![to], vec![]),
-      BoundedRepeat(to, a, b) => (vec![a, b], vec![to], vec![]),
-      InfiniteRepeatedly(to, from) => (vec![from], vec![to], vec![]),
-      BoundedRepeatedly(to, a, b) => (vec![a, b], vec![to], vec![]),
-      InfiniteIterate(to, a, b) => (vec![a, b], vec![to], vec![]),
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      Pad(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      EmptyMap(to) => ([None, None, None], Some(to), None),
+      Keys(to, from) => ([Some(from), None, None], Some(to), None),
+      Values(to, from) => ([Some(from), None, None], Some(to), None),
+      Zip(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Invert(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      Merge(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      MergeWith(from_and_to, a, b) => {
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      MapKeys(from_and_to, f) => {
+        ([Some(f), None, None], None, Some(from_and_to))
+      }
+      MapValues(from_and_to, f) => {
+        ([Some(f), None, None], None, Some(from_and_to))
+      }
+      SelectKeys(from_and_to, x) => {
+        ([Some(x), None, None], None, Some(from_and_to))
+      }
+      EmptySet(to) => ([None, None, None], Some(to), None),
+      Union(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Intersection(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      Difference(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      SymmetricDifference(to, a, b) => {
+        ([Some(a), Some(b), None], Some(to), None)
+      }
+      InfiniteRange(to) => ([None, None, None], Some(to), None),
+      UpperBoundedRange(to, from) => ([Some(from), None, None], Some(to), None),
+      LowerUpperBoundedRange(to, a, b) => {
+        ([Some(a), Some(b), None], Some(to), None)
+      }
+      InfiniteRepeat(to, from) => ([Some(from), None, None], Some(to), None),
+      BoundedRepeat(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      InfiniteRepeatedly(to, from) => {
+        ([Some(from), None, None], Some(to), None)
+      }
+      BoundedRepeatedly(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
+      InfiniteIterate(to, a, b) => ([Some(a), Some(b), None], Some(to), None),
       BoundedIterate(from_and_to, a, b) => {
-        (vec![a, b], vec![], vec![from_and_to])
-      }
-      CreateCell(to) => (vec![], vec![to], vec![]),
-      GetCellValue(to, from) => (vec![from], vec![to], vec![]),
-      SetCellValue(to, from) => (vec![from], vec![to], vec![]),
-      UpdateCell(to, from) => (vec![from], vec![to], vec![]),
-      CreateCoroutine(from_and_to) => (vec![], vec![], vec![from_and_to]),
-      IsCoroutineAlive(to, from) => (vec![from], vec![to], vec![]),
-      Yield(from) => (vec![], vec![from], vec![]),
+        ([Some(a), Some(b), None], None, Some(from_and_to))
+      }
+      CreateCell(to, from) => ([Some(from), None, None], Some(to), None),
+      GetCellValue(to, from) => ([Some(from), None, None], Some(to), None),
+      SetCellValue(cell, value) => {
+        ([Some(cell), Some(value), None], None, None)
+      }
+      CreateCoroutine(from_and_to) => {
+        ([None, None, None], None, Some(from_and_to))
+      }
+      IsCoroutineAlive(to, from) => ([Some(from), None, None], Some(to), None),
+      Yield(from) => ([None, None, None], Some(from), None),
       YieldAndAccept(from, arg_count, first_register) => todo!(),
-      IsNil(to, from) => (vec![from], vec![to], vec![]),
-      IsBool(to, from) => (vec![from], vec![to], vec![]),
-      IsChar(to, from) => (vec![from], vec![to], vec![]),
-      IsNum(to, from) => (vec![from], vec![to], vec![]),
-      IsInt(to, from) => (vec![from], vec![to], vec![]),
-      IsFloat(to, from) => (vec![from], vec![to], vec![]),
-      IsSymbol(to, from) => (vec![from], vec![to], vec![]),
-      IsString(to, from) => (vec![from], vec![to], vec![]),
-      IsList(to, from) => (vec![from], vec![to], vec![]),
-      IsMap(to, from) => (vec![from], vec![to], vec![]),
-      IsSet(to, from) => (vec![from], vec![to], vec![]),
-      IsCollection(to, from) => (vec![from], vec![to], vec![]),
-      IsFn(to, from) => (vec![from], vec![to], vec![]),
-      IsError(to, from) => (vec![from], vec![to], vec![]),
-      IsCell(to, from) => (vec![from], vec![to], vec![]),
-      IsCoroutine(to, from) => (vec![from], vec![to], vec![]),
-      ToBool(to, from) => (vec![from], vec![to], vec![]),
-      ToChar(to, from) => (vec![from], vec![to], vec![]),
-      ToNum(to, from) => (vec![from], vec![to], vec![]),
-      ToInt(to, from) => (vec![from], vec![to], vec![]),
-      ToFloat(to, from) => (vec![from], vec![to], vec![]),
-      ToSymbol(to, from) => (vec![from], vec![to], vec![]),
-      ToString(to, from) => (vec![from], vec![to], vec![]),
-      ToList(to, from) => (vec![from], vec![to], vec![]),
-      ToMap(to, from) => (vec![from], vec![to], vec![]),
-      ToSet(to, from) => (vec![from], vec![to], vec![]),
-      ToError(to, from) => (vec![from], vec![to], vec![]),
+      IsNil(to, from) => ([Some(from), None, None], Some(to), None),
+      IsBool(to, from) => ([Some(from), None, None], Some(to), None),
+      IsChar(to, from) => ([Some(from), None, None], Some(to), None),
+      IsNum(to, from) => ([Some(from), None, None], Some(to), None),
+      IsInt(to, from) => ([Some(from), None, None], Some(to), None),
+      IsFloat(to, from) => ([Some(from), None, None], Some(to), None),
+      IsSymbol(to, from) => ([Some(from), None, None], Some(to), None),
+      IsString(to, from) => ([Some(from), None, None], Some(to), None),
+      IsList(to, from) => ([Some(from), None, None], Some(to), None),
+      IsMap(to, from) => ([Some(from), None, None], Some(to), None),
+      IsSet(to, from) => ([Some(from), None, None], Some(to), None),
+      IsCollection(to, from) => ([Some(from), None, None], Some(to), None),
+      IsFn(to, from) => ([Some(from), None, None], Some(to), None),
+      IsError(to, from) => ([Some(from), None, None], Some(to), None),
+      IsCell(to, from) => ([Some(from), None, None], Some(to), None),
+      IsCoroutine(to, from) => ([Some(from), None, None], Some(to), None),
+      ToBool(to, from) => ([Some(from), None, None], Some(to), None),
+      ToChar(to, from) => ([Some(from), None, None], Some(to), None),
+      ToNum(to, from) => ([Some(from), None, None], Some(to), None),
+      ToInt(to, from) => ([Some(from), None, None], Some(to), None),
+      ToFloat(to, from) => ([Some(from), None, None], Some(to), None),
+      ToSymbol(to, from) => ([Some(from), None, None], Some(to), None),
+      ToString(to, from) => ([Some(from), None, None], Some(to), None),
+      ToList(to, from) => ([Some(from), None, None], Some(to), None),
+      ToMap(to, from) => ([Some(from), None, None], Some(to), None),
+      ToSet(to, from) => ([Some(from), None, None], Some(to), None),
+      ToError(to, from) => ([Some(from), None, None], Some(to), None),
+      ConstAdd(to, _, from) => ([Some(from), None, None], Some(to), None),
+      IncAndJumpIfPos(from_and_to, _) => {
+        ([None, None, None], None, Some(from_and_to))
+      }
+      GetConstKey(to, from, _) => ([Some(from), None, None], Some(to), None),
+      PushUnique(from_and_to, x) => {
+        ([Some(x), None, None], None, Some(from_and_to))
+      }
+      ConsUnique(from_and_to, x) => {
+        ([Some(x), None, None], None, Some(from_and_to))
+      }
+      RestUnique(from_and_to) => ([None, None, None], None, Some(from_and_to)),
+      ButLastUnique(from_and_to) => {
+        ([None, None, None], None, Some(from_and_to))
+      }
     };
     RegisterUsages {
-      inputs: inputs.into_iter().cloned().collect(),
-      outputs: outputs.into_iter().cloned().collect(),
-      replacements: replacements.into_iter().cloned().collect(),
+      inputs: Inputs {
+        fixed: inputs.into_iter(),
+        arguments: arguments.iter(),
+      },
+      outputs: outputs.cloned().into_iter(),
+      replacements: replacements.cloned().into_iter(),
     }
   }
 }
-impl<I, O, R> GenericInstruction<I, O, R> {
+impl<I: Clone, O, R> GenericInstruction<I, O, R> {
   pub fn translate<
     NewI,
     NewO,
@@ -418,16 +574,31 @@ impl<I, O, R> GenericInstruction<I, O, R> {
       Const(a, b) => Const(output_translator(a), b),
       Print(a) => Print(input_translator(a)),
       Return(a) => Return(input_translator(a)),
-      CopyArgument(a) => CopyArgument(input_translator(a)),
-      StealArgument(a) => StealArgument(input_translator(a)),
-      Call(a, b, c) => Call(output_translator(a), input_translator(b), c),
+      Call(a, b, c) => Call(
+        output_translator(a),
+        input_translator(b),
+        c.iter()
+          .map(|arg| arg.clone().translate(&input_translator))
+          .collect(),
+      ),
       Apply(a, b) => Apply(replacement_translator(a), input_translator(b)),
-      CallAndReturn(a, b) => CallAndReturn(input_translator(a), b),
+      CallAndReturn(a, b) => CallAndReturn(
+        input_translator(a),
+        b.iter()
+          .map(|arg| arg.clone().translate(&input_translator))
+          .collect(),
+      ),
       ApplyAndReturn(a, b) => {
         ApplyAndReturn(input_translator(a), input_translator(b))
       }
       Jump(a) => Jump(a),
+      RelativeJump(a) => RelativeJump(a),
+      JumpIf(a, b) => JumpIf(input_translator(a), b),
+      JumpIfNot(a, b) => JumpIfNot(input_translator(a), b),
       Lookup(a, b) => Lookup(output_translator(a), b),
+      LookupDynamic(a, b) => LookupDynamic(output_translator(a), b),
+      PushDynamicBinding(a, b) => PushDynamicBinding(a, input_translator(b)),
+      PopDynamicBindings(a) => PopDynamicBindings(a),
       If(a) => If(input_translator(a)),
       Else => Else,
       ElseIf(a) => ElseIf(input_translator(a)),
@@ -546,6 +717,32 @@ impl<I, O, R> GenericInstruction<I, O, R> {
         input_translator(b),
         input_translator(c),
       ),
+      BitAnd(a, b, c) => BitAnd(
+        output_translator(a),
+        input_translator(b),
+        input_translator(c),
+      ),
+      BitOr(a, b, c) => BitOr(
+        output_translator(a),
+        input_translator(b),
+        input_translator(c),
+      ),
+      BitXor(a, b, c) => BitXor(
+        output_translator(a),
+        input_translator(b),
+        input_translator(c),
+      ),
+      BitNot(a, b) => BitNot(output_translator(a), input_translator(b)),
+      ShiftLeft(a, b, c) => ShiftLeft(
+        output_translator(a),
+        input_translator(b),
+        input_translator(c),
+      ),
+      ShiftRight(a, b, c) => ShiftRight(
+        output_translator(a),
+        input_translator(b),
+        input_translator(c),
+      ),
       Rand(a) => Rand(output_translator(a)),
       UpperBoundedRand(a, b) => {
         UpperBoundedRand(output_translator(a), input_translator(b))
@@ -767,14 +964,13 @@ impl<I, O, R> GenericInstruction<I, O, R> {
         input_translator(b),
         input_translator(c),
       ),
-      CreateCell(a) => CreateCell(output_translator(a)),
+      CreateCell(a, b) => CreateCell(output_translator(a), input_translator(b)),
       GetCellValue(a, b) => {
         GetCellValue(output_translator(a), input_translator(b))
       }
       SetCellValue(a, b) => {
-        SetCellValue(output_translator(a), input_translator(b))
+        SetCellValue(input_translator(a), input_translator(b))
       }
-      UpdateCell(a, b) => UpdateCell(output_translator(a), input_translator(b)),
       CreateCoroutine(a) => CreateCoroutine(replacement_translator(a)),
       IsCoroutineAlive(a, b) => {
         IsCoroutineAlive(output_translator(a), input_translator(b))
@@ -814,6 +1010,21 @@ impl<I, O, R> GenericInstruction<I, O, R> {
       ToMap(a, b) => ToMap(output_translator(a), input_translator(b)),
       ToSet(a, b) => ToSet(output_translator(a), input_translator(b)),
       ToError(a, b) => ToError(output_translator(a), input_translator(b)),
+      ConstAdd(a, b, c) => {
+        ConstAdd(output_translator(a), b, input_translator(c))
+      }
+      IncAndJumpIfPos(a, b) => IncAndJumpIfPos(replacement_translator(a), b),
+      GetConstKey(a, b, c) => {
+        GetConstKey(output_translator(a), input_translator(b), c)
+      }
+      PushUnique(a, b) => {
+        PushUnique(replacement_translator(a), input_translator(b))
+      }
+      ConsUnique(a, b) => {
+        ConsUnique(replacement_translator(a), input_translator(b))
+      }
+      RestUnique(a) => RestUnique(replacement_translator(a)),
+      ButLastUnique(a) => ButLastUnique(replacement_translator(a)),
     }
   }
 }
@@ -1,3 +1,5 @@
+extern crate self as pidgin;
+
 mod blocks;
 mod compiler;
 mod frontend;
@@ -5,47 +7,71 @@ mod instructions;
 mod runtime;
 mod string_utils;
 
-use frontend::{error::PidginResult, evaluator::Evaluator};
-use rustyline::{error::ReadlineError, DefaultEditor};
+pub use block_macros::export;
+pub use blocks::{BlockBuilder, GenericBlock, Label};
+#[cfg(feature = "serde")]
+pub use frontend::artifact::EnvironmentSnapshot;
+#[cfg(feature = "plugins")]
+pub use frontend::plugin::dynamic::PLUGIN_VERSION;
+#[cfg(feature = "cli")]
+pub use frontend::repl::ReplConfig;
+pub use frontend::{
+  coverage::{CoverageReport, FormCoverage},
+  differential::{first_disagreement, DifferentialResult},
+  emit::CompilationStage,
+  error::{PidginError, PidginResult},
+  evaluator::Evaluator,
+  export::{extract_arg, Export},
+  plugin::PidginModule,
+  profile::{FormProfile, ProfileReport},
+  testing::{TestReport, TestResult},
+};
+pub use instructions::{Argument, GenericInstruction};
+#[cfg(feature = "tokio")]
+pub use runtime::async_host::{channel, AsyncHost, Bridge};
+#[cfg(feature = "arbitrary")]
+pub use runtime::fuzzing::{run_arbitrary_program, ProgramOutcome};
+#[cfg(feature = "serde")]
+pub use runtime::serde_bridge::{from_value, to_value};
+pub use runtime::{
+  control::{Block, FrameView},
+  coverage::Coverage,
+  data::Value,
+  error::RuntimeError,
+  evaluation::{EvaluationState, Instruction},
+  external::ExternalValue,
+  memory::{inspect_sharing, HeapStats, KindStats, SharingReport},
+  pretty_print::pretty,
+  profile::{InstructionProfile, Profile},
+  replay::{Trace, TraceEvent},
+  validation::ValidationError,
+};
+#[cfg(feature = "cli")]
+use rustyline::error::ReadlineError;
 
 pub fn evaluate_pidgin_sexp(sexp: String) -> PidginResult<String> {
   let mut evaluator = Evaluator::default();
   evaluator.eval(&sexp).map(|value| evaluator.describe(value))
 }
 
-pub fn repl() -> Result<(), ReadlineError> {
+// Evaluates every top-level form of the source with a fresh evaluator,
+// keeping going after failed forms
+pub fn evaluate_pidgin_forms(source: &str) -> Vec<PidginResult<Value>> {
   let mut evaluator = Evaluator::default();
-  println!("\nWelcome to Pidgin!! :D\n");
-  let mut rl = DefaultEditor::new()?;
-  if rl.load_history("history.txt").is_err() {
-    println!("No previous history.");
-  }
-  loop {
-    let readline = rl.readline("> ");
-    match readline {
-      Ok(line) => {
-        rl.add_history_entry(line.as_str())
-          .expect("failed to add line to history");
-        match evaluator.eval(&line) {
-          Ok(value) => println!("{value}"),
-          Err(error) => println!("{error}"),
-        }
-      }
-      Err(ReadlineError::Interrupted) => {
-        println!("CTRL-C");
-        break;
-      }
-      Err(ReadlineError::Eof) => {
-        println!("CTRL-D");
-        break;
-      }
-      Err(err) => {
-        println!("Error: {:?}", err);
-        break;
-      }
-    }
-  }
-  rl.save_history("history.txt")
-    .expect("failed to save history");
-  Ok(())
+  evaluator
+    .eval_forms(source)
+    .map(|form_result| form_result.result)
+    .collect()
+}
+
+#[cfg(feature = "cli")]
+pub fn repl() -> Result<(), ReadlineError> {
+  ReplConfig::default().run()
+}
+
+// Starts the REPL with an evaluator that's already been set up, for instance
+// with host functions or a preloaded prelude
+#[cfg(feature = "cli")]
+pub fn repl_with(evaluator: Evaluator) -> Result<(), ReadlineError> {
+  ReplConfig::default().evaluator(evaluator).run()
 }
@@ -1,33 +1,102 @@
-use pidgin::repl;
+use std::process::ExitCode;
 
-fn dec_loop_benchmark() {
-  /*use block_macros::block;
-  use pidgin::{
-    runtime::control::Block, EvaluationState, GenericInstruction::*, Value,
+use pidgin::{repl, CompilationStage, Evaluator, Value};
+
+const USAGE: &str = "usage:
+  pidgin run <file> [args...]  evaluate a script, binding args to \
+*command-line-args*
+  pidgin compile <file> [--emit=ast,ir,bytecode]
+                               print the output of compilation stages. the
+                               file's top-level forms are evaluated too, but
+                               without file io
+  pidgin repl                  start an interactive session (the default)";
+
+fn run_script(path: &str, args: Vec<String>) -> ExitCode {
+  let mut evaluator = Evaluator::default();
+  evaluator.enable_file_io();
+  evaluator
+    .define_value("*command-line-args*", args.into_iter().collect::<Value>());
+  match evaluator.eval_file(path) {
+    Ok(_) => ExitCode::SUCCESS,
+    Err(error) => {
+      eprintln!("{}", evaluator.describe_error(error));
+      ExitCode::FAILURE
+    }
+  }
+}
+
+fn compile(path: &str, options: Vec<String>) -> ExitCode {
+  let mut stages = vec![CompilationStage::Bytecode];
+  for option in options {
+    let Some(names) = option.strip_prefix("--emit=") else {
+      eprintln!("unknown option {option}\n{USAGE}");
+      return ExitCode::from(2);
+    };
+    match names.split(',').map(CompilationStage::from_name).collect() {
+      Some(requested) => stages = requested,
+      None => {
+        eprintln!("unknown stage in {names}, expected ast, ir, or bytecode");
+        return ExitCode::from(2);
+      }
+    }
+  }
+  let source = match std::fs::read_to_string(path) {
+    Ok(source) => source,
+    Err(error) => {
+      eprintln!("{path}: {error}");
+      return ExitCode::FAILURE;
+    }
   };
-  let time = std::time::Instant::now();
-  let program = block![
-    Const(0, 100000000),
-    Const(
-      1,
-      Value::composite_fn(
-        1,
-        block![IsPos(1, 0), If(1), Dec(0, 0), Jump(0), EndIf, Return(0)]
-      )
-    ),
-    Call(0, 1, 1),
-    StealArgument(0),
-  ];
-  let mut state = EvaluationState::new(program);
-  state.evaluate().unwrap();
-  println!("{}", time.elapsed().as_secs_f64())*/
+  // compiling a form means evaluating it, so file io is left disabled to
+  // keep compilation from writing files
+  let mut evaluator = Evaluator::default();
+  match evaluator.emit(&source, stages) {
+    Ok(output) => {
+      print!("{output}");
+      ExitCode::SUCCESS
+    }
+    Err(error) => {
+      eprintln!("{}", evaluator.describe_error(error));
+      ExitCode::FAILURE
+    }
+  }
 }
 
-fn start_repl() {
-  repl().unwrap()
+fn start_repl() -> ExitCode {
+  match repl() {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(error) => {
+      eprintln!("{error}");
+      ExitCode::FAILURE
+    }
+  }
 }
 
-fn main() {
-  //dec_loop_benchmark()
-  start_repl()
+fn main() -> ExitCode {
+  let mut args = std::env::args().skip(1);
+  match args.next().as_deref() {
+    None | Some("repl") => start_repl(),
+    Some("run") => match args.next() {
+      Some(path) => run_script(&path, args.collect()),
+      None => {
+        eprintln!("{USAGE}");
+        ExitCode::from(2)
+      }
+    },
+    Some("compile") => match args.next() {
+      Some(path) => compile(&path, args.collect()),
+      None => {
+        eprintln!("{USAGE}");
+        ExitCode::from(2)
+      }
+    },
+    Some("-h" | "--help" | "help") => {
+      println!("{USAGE}");
+      ExitCode::SUCCESS
+    }
+    Some(other) => {
+      eprintln!("unknown command {other}\n{USAGE}");
+      ExitCode::from(2)
+    }
+  }
 }
@@ -0,0 +1,272 @@
+// Runs functions as tasks on a Tokio `LocalSet`. Each task runs in a coroutine
+// of its own, and suspends by passing one of the operations made by `sleep`,
+// `(spawn f & args)`, `join`, `send`, or `recv` to `await`, which yields the
+// operation to the host and evaluates to its result once the host has carried
+// it out. Awaiting anything else just lets the other tasks run. Values can't
+// leave the thread that made them, so channels carry EDN between Pidgin and
+// Tokio tasks on other threads
+
+use std::{
+  any::{type_name, Any},
+  cell::RefCell,
+  collections::HashMap,
+  error::Error,
+  rc::Rc,
+  time::Duration,
+};
+
+use tokio::sync::{
+  mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+  Mutex, Notify,
+};
+
+use crate::instructions::GenericInstruction::*;
+
+use super::{
+  control::Block,
+  data::{AritySpecifier, ExternalFunction, GenericValue::*, Value},
+  edn::{read_edn, write_edn},
+  error::{RuntimeError, RuntimeResult},
+  evaluation::{EvaluationState, SymbolIndex},
+  external::ExternalHandle,
+};
+
+enum Operation {
+  Sleep(Duration),
+  Spawn(Value, Vec<Value>),
+  Join(Rc<Task>),
+  Send(Rc<Channel>, Value),
+  Receive(Rc<Channel>),
+}
+
+#[derive(Default)]
+struct Task {
+  result: RefCell<Option<Value>>,
+  finished: Notify,
+}
+impl Task {
+  async fn join(&self) -> Value {
+    loop {
+      let finished = self.finished.notified();
+      if let Some(result) = &*self.result.borrow() {
+        return result.clone();
+      }
+      finished.await;
+    }
+  }
+}
+
+// The Pidgin end of a channel, which `send` and `recv` operate on
+pub struct Channel {
+  incoming: Mutex<UnboundedReceiver<String>>,
+  outgoing: UnboundedSender<String>,
+}
+
+// The host's end of a channel. Values are sent and received as EDN, so this
+// end can be moved to other threads
+pub struct Bridge {
+  pub sender: UnboundedSender<String>,
+  pub receiver: UnboundedReceiver<String>,
+}
+
+// Makes a channel, returning the value to bind for Pidgin code along with the
+// host's end of it
+pub fn channel() -> (Value, Bridge) {
+  let (to_pidgin, incoming) = unbounded_channel();
+  let (outgoing, from_pidgin) = unbounded_channel();
+  let channel = Channel {
+    incoming: Mutex::new(incoming),
+    outgoing,
+  };
+  (
+    Value::external(channel),
+    Bridge {
+      sender: to_pidgin,
+      receiver: from_pidgin,
+    },
+  )
+}
+
+// `args` is the number of arguments the operation takes, or `None` if it
+// checks them itself
+fn operation(
+  name: &str,
+  args: Option<u8>,
+  f: impl Fn(Vec<Value>) -> RuntimeResult<Operation> + 'static,
+) -> (&str, Value) {
+  let f = move |args| {
+    f(args)
+      .map(Value::external)
+      .map_err(|error| Rc::new(error) as Rc<dyn Error>)
+  };
+  let f = ExternalFunction {
+    name: Some(name.to_string()),
+    args: args.map(AritySpecifier::from),
+    f: Rc::new(f),
+    pure: false,
+  };
+  (name, f.into())
+}
+
+fn external_arg<T: Any>(value: Value) -> RuntimeResult<Rc<T>> {
+  value.casted_external::<T>().ok_or_else(|| {
+    RuntimeError::ArgumentNotExternal(type_name::<T>().to_string())
+  })
+}
+
+// The functions that tasks use to suspend themselves, which have to be bound
+// as globals before any code that calls them is compiled
+pub fn bindings() -> Vec<(&'static str, Value)> {
+  vec![
+    (
+      "await",
+      Value::composite_fn(
+        1,
+        Block::new_with_metadata(
+          vec![YieldAndAccept(0, 1, 0), Return(0)],
+          vec![],
+          0,
+        ),
+      ),
+    ),
+    operation("sleep", Some(1), |mut args| {
+      let millis = f64::try_from(args.remove(0))?;
+      Ok(Operation::Sleep(Duration::from_secs_f64(
+        millis.max(0.) / 1000.,
+      )))
+    }),
+    operation("spawn", None, |mut args| {
+      if args.is_empty() {
+        return Err(RuntimeError::InvalidArity);
+      }
+      Ok(Operation::Spawn(args.remove(0), args))
+    }),
+    operation("join", Some(1), |mut args| {
+      Ok(Operation::Join(external_arg(args.remove(0))?))
+    }),
+    operation("send", Some(2), |mut args| {
+      let value = args.pop().unwrap();
+      Ok(Operation::Send(external_arg(args.remove(0))?, value))
+    }),
+    operation("recv", Some(1), |mut args| {
+      Ok(Operation::Receive(external_arg(args.remove(0))?))
+    }),
+  ]
+}
+
+enum TaskStatus {
+  Awaiting,
+  Failed,
+  Finished,
+}
+
+// Errors inside a coroutine stop it by yielding the error, leaving it paused
+// without expecting an argument, while `await` always expects one
+fn status(coroutine: &Value) -> TaskStatus {
+  let Coroutine(coroutine) = coroutine else {
+    return TaskStatus::Finished;
+  };
+  match (**coroutine)
+    .as_ref()
+    .map(|paused| paused.borrow())
+    .as_deref()
+  {
+    Some(Some(paused)) if paused.args.can_accept(1) => TaskStatus::Awaiting,
+    Some(Some(_)) => TaskStatus::Failed,
+    _ => TaskStatus::Finished,
+  }
+}
+
+pub struct AsyncHost {
+  // Tasks only run between suspensions, so they can all share one machine
+  state: RefCell<EvaluationState>,
+  global_bindings: HashMap<SymbolIndex, Value>,
+}
+impl AsyncHost {
+  pub fn new(
+    state: EvaluationState,
+    global_bindings: HashMap<SymbolIndex, Value>,
+  ) -> Rc<Self> {
+    Rc::new(Self {
+      state: RefCell::new(state),
+      global_bindings,
+    })
+  }
+  // Runs `f` as a task until it returns, along with any tasks it spawns. Has
+  // to be polled from within a `LocalSet`
+  pub async fn run(
+    self: Rc<Self>,
+    f: Value,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    let CompositeFn(f) = f else {
+      return Err(RuntimeError::CantCreateCoroutine(
+        "tasks can only be made from composite functions".to_string(),
+      ));
+    };
+    let coroutine = Value::fn_coroutine(Rc::unwrap_or_clone(f))?;
+    let mut value = self.resume(&coroutine, args)?;
+    loop {
+      match (status(&coroutine), value) {
+        (TaskStatus::Awaiting, awaited) => {
+          let result = self.perform(awaited).await;
+          value = self.resume(&coroutine, vec![result])?;
+        }
+        (TaskStatus::Failed, Error(error)) => {
+          return Err(Rc::unwrap_or_clone(error))
+        }
+        (_, value) => return Ok(value),
+      }
+    }
+  }
+  fn resume(
+    &self,
+    coroutine: &Value,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    let mut state = self.state.borrow_mut();
+    state.reset(Block::for_call(coroutine.clone(), args));
+    Ok(state.evaluate(&self.global_bindings)?.unwrap_or(Nil))
+  }
+  async fn perform(self: &Rc<Self>, value: Value) -> Value {
+    let Some(operation) = value.clone().casted_external::<Operation>() else {
+      tokio::task::yield_now().await;
+      return value;
+    };
+    match &*operation {
+      Operation::Sleep(duration) => {
+        tokio::time::sleep(*duration).await;
+        Nil
+      }
+      Operation::Spawn(f, args) => {
+        let task = Rc::new(Task::default());
+        let (host, f, args) = (self.clone(), f.clone(), args.clone());
+        let spawned_task = task.clone();
+        tokio::task::spawn_local(async move {
+          let result = host.run(f, args).await.unwrap_or_else(Value::from);
+          *spawned_task.result.borrow_mut() = Some(result);
+          spawned_task.finished.notify_waiters();
+        });
+        ExternalObject(Rc::new(ExternalHandle::opaque(task)))
+      }
+      Operation::Join(task) => task.join().await,
+      Operation::Send(channel, value) => {
+        let symbol_ledger = self.state.borrow().symbol_ledger.clone();
+        match write_edn(value, symbol_ledger.as_deref()) {
+          Ok(edn) => channel.outgoing.send(edn).is_ok().into(),
+          Err(error) => error.into(),
+        }
+      }
+      Operation::Receive(channel) => {
+        let Some(edn) = channel.incoming.lock().await.recv().await else {
+          return Nil;
+        };
+        let mut state = self.state.borrow_mut();
+        let symbol_ledger = Rc::make_mut(
+          state.symbol_ledger.get_or_insert_with(Default::default),
+        );
+        read_edn(&edn, symbol_ledger).unwrap_or_else(Value::from)
+      }
+    }
+  }
+}
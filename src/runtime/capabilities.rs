@@ -0,0 +1,6 @@
+// Host access that the embedder has to opt in to. Everything is disabled by
+// default, so scripts are sandboxed unless explicitly given more access
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+  pub file_io: bool,
+}
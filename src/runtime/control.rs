@@ -1,32 +1,110 @@
-use std::rc::Rc;
+use std::{cell::OnceCell, fmt::Debug, rc::Rc};
 
 use crate::{
   blocks::GenericBlock,
-  compiler::intermediate::register_allocation::get_max_register,
+  compiler::{
+    intermediate::{
+      deferral::deferred_fn_to_bytecode, register_allocation::get_max_register,
+    },
+    SSABlock,
+  },
+  instructions::{Argument::StealArgument, GenericInstruction::*},
   runtime::{
     data::{AritySpecifier, Value},
-    evaluation::{Instruction, Register, StackIndex},
+    error::{RuntimeError, RuntimeResult},
+    evaluation::{ConstIndex, Instruction, Register, StackIndex, SymbolIndex},
   },
 };
 
-const STACK_CAPACITY: usize = 1000; //u16::MAX as usize + 1;
+// Stacks start out this large unless configured otherwise, and grow on demand
+// up to the most that a `StackIndex` can address
+pub const DEFAULT_STACK_CAPACITY: usize = 256;
+pub const MAX_STACK_SIZE: usize = StackIndex::MAX as usize + 1;
 
 pub type Block = GenericBlock<Register, Register, Register, Register>;
 impl Block {
   pub fn new(instructions: Vec<Instruction>, constants: Vec<Value>) -> Self {
     let max_register = get_max_register(&instructions);
-    Block {
-      instructions: instructions.into(),
-      constants: constants.into(),
-      metadata: max_register,
+    Block::new_with_metadata(instructions, constants, max_register)
+  }
+  // A block that calls `f` with `args` and returns the result. The function
+  // and arguments are loaded from the block's constants, so the function's
+  // frame is set up like any other call's. The result gets a register of its
+  // own, since a coroutine that fails replaces the register holding it
+  pub(crate) fn for_call(f: Value, args: Vec<Value>) -> Self {
+    let result = args.len() as Register + 1;
+    let mut instructions: Vec<_> =
+      (0..result).map(|i| Const(i, i as ConstIndex)).collect();
+    instructions.push(Call(
+      result,
+      0,
+      (1..result).map(StealArgument).collect(),
+    ));
+    instructions.push(Return(result));
+    Block::new(instructions, std::iter::once(f).chain(args).collect())
+  }
+}
+
+// A function's IR that's left uncompiled until the function is first called,
+// so that programs with many functions don't pay to compile the ones they
+// never use. The compiled block is kept for later calls
+pub struct DeferredBlock {
+  args: AritySpecifier,
+  ir: SSABlock<()>,
+  block: OnceCell<Block>,
+}
+impl DeferredBlock {
+  pub fn new(args: AritySpecifier, ir: SSABlock<()>) -> Self {
+    Self {
+      args,
+      ir,
+      block: OnceCell::new(),
+    }
+  }
+  pub fn is_compiled(&self) -> bool {
+    self.block.get().is_some()
+  }
+  pub fn block(&self) -> RuntimeResult<&Block> {
+    if let Some(block) = self.block.get() {
+      return Ok(block);
+    }
+    let block = deferred_fn_to_bytecode(self.args.clone(), self.ir.clone())
+      .map_err(|error| RuntimeError::DeferredCompilation(error.to_string()))?;
+    Ok(self.block.get_or_init(|| block))
+  }
+}
+impl Debug for DeferredBlock {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.block.get() {
+      Some(block) => block.fmt(f),
+      None => f.debug_tuple("Uncompiled").field(&self.ir).finish(),
     }
   }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
+pub enum FunctionBody<I, O, R, M> {
+  Compiled(GenericBlock<I, O, R, M>),
+  Deferred(Rc<DeferredBlock>),
+}
+
+#[derive(Clone)]
 pub struct GenericCompositeFunction<I, O, R, M> {
   pub args: AritySpecifier,
-  pub block: GenericBlock<I, O, R, M>,
+  pub body: FunctionBody<I, O, R, M>,
+}
+impl<I: Clone + Debug, O: Clone + Debug, R: Clone + Debug, M: Clone + Debug>
+  Debug for GenericCompositeFunction<I, O, R, M>
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut debug = f.debug_struct("GenericCompositeFunction");
+    debug.field("args", &self.args);
+    match &self.body {
+      FunctionBody::Compiled(block) => debug.field("block", block),
+      FunctionBody::Deferred(deferred) => debug.field("block", deferred),
+    };
+    debug.finish()
+  }
 }
 
 impl<I, O, R, M> GenericCompositeFunction<I, O, R, M> {
@@ -36,13 +114,29 @@ impl<I, O, R, M> GenericCompositeFunction<I, O, R, M> {
   ) -> Self {
     Self {
       args: args.into(),
-      block: block.into(),
+      body: FunctionBody::Compiled(block.into()),
+    }
+  }
+  pub fn deferred<A: Into<AritySpecifier>>(args: A, ir: SSABlock<()>) -> Self {
+    let args = args.into();
+    Self {
+      args: args.clone(),
+      body: FunctionBody::Deferred(Rc::new(DeferredBlock::new(args, ir))),
     }
   }
 }
 
 pub type CompositeFunction =
   GenericCompositeFunction<Register, Register, Register, Register>;
+impl CompositeFunction {
+  // The function's block, compiling it first if it was deferred
+  pub fn block(&self) -> RuntimeResult<&Block> {
+    match &self.body {
+      FunctionBody::Compiled(block) => Ok(block),
+      FunctionBody::Deferred(deferred) => deferred.block(),
+    }
+  }
+}
 
 #[derive(Debug)]
 pub struct CoroutineState {
@@ -50,16 +144,39 @@ pub struct CoroutineState {
   pub paused_frames: Vec<StackFrame>,
 }
 impl CoroutineState {
-  pub fn new() -> Self {
+  pub fn new(stack_capacity: usize) -> Self {
     Self {
-      stack: std::iter::repeat(Value::Nil).take(STACK_CAPACITY).collect(),
+      stack: vec![Value::Nil; stack_capacity],
       paused_frames: vec![],
     }
   }
-  pub fn new_with_root_frame(root_frame: StackFrame) -> Self {
-    Self {
-      stack: std::iter::repeat(Value::Nil).take(STACK_CAPACITY).collect(),
-      paused_frames: vec![root_frame],
+  pub fn new_with_root_frame(
+    root_frame: StackFrame,
+    stack_capacity: usize,
+  ) -> Self {
+    let mut state = Self::new(stack_capacity);
+    state.reserve_stack(root_frame.end());
+    state.paused_frames.push(root_frame);
+    state
+  }
+  // Makes room for `size` values, at least doubling the stack when it has to
+  // grow so that deep call chains only reallocate a logarithmic number of times
+  pub fn reserve_stack(&mut self, size: usize) {
+    let len = self.stack.len();
+    if size > len {
+      self
+        .stack
+        .resize(size.max(len * 2).min(MAX_STACK_SIZE), Value::Nil);
+    }
+  }
+  // Gives back the upper half of the stack once the frames that needed it have
+  // completed and at most a quarter of it is in use. Everything above `in_use`
+  // belongs to completed frames, so nothing live is lost
+  pub fn shrink_stack(&mut self, in_use: usize, stack_capacity: usize) {
+    let len = self.stack.len();
+    if len > stack_capacity && in_use * 4 <= len {
+      self.stack.truncate((len / 2).max(stack_capacity));
+      self.stack.shrink_to_fit();
     }
   }
   pub fn pause(
@@ -86,6 +203,21 @@ pub struct PausedCoroutine {
   pub state: CoroutineState,
 }
 impl PausedCoroutine {
+  pub fn new(
+    f: CompositeFunction,
+    stack_capacity: usize,
+  ) -> RuntimeResult<Self> {
+    let block = f.block()?.clone();
+    Ok(Self {
+      started: false,
+      args: f.args,
+      arg_offset: 0,
+      state: CoroutineState::new_with_root_frame(
+        StackFrame::root(block),
+        stack_capacity,
+      ),
+    })
+  }
   pub fn begin_as_child(
     mut self,
     return_index: StackIndex,
@@ -96,7 +228,14 @@ impl PausedCoroutine {
       .paused_frames
       .pop()
       .expect("attempting to resume a PausedCoroutine with no paused_frames");
-    active_frame.return_stack_index = return_index;
+    // The coroutine's outermost frame is the one that returns to the caller,
+    // which may not be the frame it yielded from
+    self
+      .state
+      .paused_frames
+      .first_mut()
+      .unwrap_or(&mut active_frame)
+      .return_stack_index = return_index;
     (active_frame, self.state)
   }
   pub fn resume_from_child(mut self) -> (StackFrame, CoroutineState) {
@@ -108,47 +247,44 @@ impl PausedCoroutine {
     (active_frame, self.state)
   }
 }
-impl From<CompositeFunction> for PausedCoroutine {
-  fn from(f: CompositeFunction) -> Self {
-    Self {
-      started: false,
-      args: f.args,
-      arg_offset: 0,
-      state: CoroutineState::new_with_root_frame(StackFrame::root(f.block)),
-    }
+impl TryFrom<CompositeFunction> for PausedCoroutine {
+  type Error = RuntimeError;
+  fn try_from(f: CompositeFunction) -> RuntimeResult<Self> {
+    Self::new(f, DEFAULT_STACK_CAPACITY)
   }
 }
 
 #[derive(Debug)]
 pub struct StackFrame {
   pub beginning: StackIndex,
-  pub calling_function: Option<Rc<CompositeFunction>>,
   pub block: Block,
   pub instruction_index: usize,
   pub return_stack_index: StackIndex,
+  // Bindings established by `binding` forms in this frame, innermost last
+  pub dynamic_bindings: Vec<(SymbolIndex, Value)>,
 }
 impl StackFrame {
   pub fn root(block: Block) -> Self {
     Self {
       beginning: 0,
-      calling_function: None,
       block,
       instruction_index: 0,
       return_stack_index: 0,
+      dynamic_bindings: vec![],
     }
   }
   pub fn for_fn(
-    f: Rc<CompositeFunction>,
+    f: &CompositeFunction,
     beginning: StackIndex,
     return_stack_index: StackIndex,
-  ) -> Self {
-    Self {
+  ) -> RuntimeResult<Self> {
+    Ok(Self {
       beginning,
-      block: f.block.clone(),
+      block: f.block()?.clone(),
       instruction_index: 0,
-      calling_function: Some(f),
       return_stack_index,
-    }
+      dynamic_bindings: vec![],
+    })
   }
   pub fn next_instruction(&mut self) -> Instruction {
     let instruction = self.block[self.instruction_index].clone();
@@ -158,4 +294,18 @@ impl StackFrame {
   pub fn stack_consumption(&self) -> StackIndex {
     self.block.metadata as StackIndex
   }
+  // The stack index just past this frame's last register
+  pub fn end(&self) -> usize {
+    self.beginning as usize + self.stack_consumption() as usize + 1
+  }
+}
+
+// A copy of one stack frame's state, for inspecting a machine after a run
+// has stopped. The instruction is the one the frame was running, which is a
+// call for every frame but the innermost
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameView {
+  pub instruction_index: usize,
+  pub instruction: Option<Instruction>,
+  pub registers: Vec<Value>,
 }
@@ -1,15 +1,22 @@
-use std::{fmt::Display, rc::Rc};
+use std::{cell::RefCell, fmt::Display, rc::Rc, sync::OnceLock, time::Instant};
 
 use crate::runtime::{
   data::{
     GenericValue::*,
     Num::{self, *},
-    Value,
+    Tiebreak, Value,
   },
   error::{RuntimeError, RuntimeResult},
 };
 use enum_map::{Enum, EnumMap};
 
+use super::{
+  file_io::{list_dir, read_lines, slurp, spit},
+  json::{json_parse, json_write},
+  numbers::{format_float, number_to_string, parse_float, parse_int},
+  regex::{re_find, re_matches, re_pattern, re_seq, replace},
+};
+
 #[derive(Debug, Enum, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum CoreFnId {
   Print,
@@ -53,6 +60,12 @@ pub enum CoreFnId {
   GreaterThanOrEqual,
   LessThan,
   LessThanOrEqual,
+  BitAnd,
+  BitOr,
+  BitXor,
+  BitNot,
+  ShiftLeft,
+  ShiftRight,
   Rand,
   RandInt,
   Equal,
@@ -135,15 +148,85 @@ pub enum CoreFnId {
   ToString,
   ToList,
   ToMap,
+  RePattern,
+  ReFind,
+  ReMatches,
+  ReSeq,
+  Replace,
+  Slurp,
+  Spit,
+  ReadLines,
+  ListDir,
+  WithMeta,
+  Meta,
   CreateCell,
   GetCellValue,
   SetCellValue,
   UpdateCell,
+  JsonParse,
+  JsonWrite,
+  EdnRead,
+  EdnWrite,
+  NanoTime,
+  ReportElapsed,
+  Gensym,
+  Pmap,
+  CreateError,
+  Throw,
+  IsError,
+  ExMessage,
+  ExData,
+  ExCause,
+  TryCall,
+  Assert,
+  Pprint,
+  Format,
+  ParseInt,
+  ParseFloat,
+  FormatFloat,
+  NumberToString,
+  IsDigit,
+  IsAlpha,
+  IsWhitespace,
+  IsUpperCase,
+  IsBlank,
+  Compare,
+  IsIdentical,
+  Hash,
+  RandNth,
+  Shuffle,
+  Sample,
+  WithSeed,
+  Round,
+  Trunc,
+  Clamp,
+  Lerp,
+  Signum,
+  CallMethod,
 }
 use CoreFnId as F;
 
+// Nanoseconds since the first time this was called, for measuring durations
+pub(crate) fn nano_time() -> i64 {
+  static START: OnceLock<Instant> = OnceLock::new();
+  START.get_or_init(Instant::now).elapsed().as_nanos() as i64
+}
+
+// Whether a char, or every char of a non-empty string, passes the test
+fn char_predicate(
+  args: Vec<Value>,
+  test: impl Fn(char) -> bool,
+) -> RuntimeResult<Value> {
+  match &args[..] {
+    [Char(c)] => Ok(test(*c).into()),
+    [Str(s)] => Ok((!s.is_empty() && s.chars().all(test)).into()),
+    [_] => Err(RuntimeError::ArgumentNotString),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
 impl CoreFnId {
-  pub fn name(&self) -> &str {
+  pub fn name(&self) -> &'static str {
     match self {
       F::Print => "print",
       F::Apply => "apply",
@@ -186,6 +269,12 @@ impl CoreFnId {
       F::GreaterThanOrEqual => ">=",
       F::LessThan => "<",
       F::LessThanOrEqual => "<=",
+      F::BitAnd => "bit-and",
+      F::BitOr => "bit-or",
+      F::BitXor => "bit-xor",
+      F::BitNot => "bit-not",
+      F::ShiftLeft => "bit-shift-left",
+      F::ShiftRight => "bit-shift-right",
       F::Rand => "rand",
       F::RandInt => "rand-int",
       F::Equal => "=",
@@ -268,12 +357,99 @@ impl CoreFnId {
       F::ToString => "str",
       F::ToList => "to-list",
       F::ToMap => "to-hashmap",
-      F::CreateCell => todo!(),
-      F::GetCellValue => todo!(),
-      F::SetCellValue => todo!(),
-      F::UpdateCell => todo!(),
+      F::RePattern => "re-pattern",
+      F::ReFind => "re-find",
+      F::ReMatches => "re-matches",
+      F::ReSeq => "re-seq",
+      F::Replace => "replace",
+      F::Slurp => "slurp",
+      F::Spit => "spit",
+      F::ReadLines => "read-lines",
+      F::ListDir => "list-dir",
+      F::WithMeta => "with-meta",
+      F::Meta => "meta",
+      F::CreateCell => "atom",
+      F::GetCellValue => "deref",
+      F::SetCellValue => "reset!",
+      F::UpdateCell => "swap!",
+      F::JsonParse => "json/parse",
+      F::JsonWrite => "json/write",
+      F::EdnRead => "edn/read",
+      F::EdnWrite => "edn/write",
+      F::NanoTime => "nano-time",
+      F::ReportElapsed => "report-elapsed",
+      F::Gensym => "gensym",
+      F::Pmap => "pmap",
+      F::CreateError => "error",
+      F::Throw => "throw",
+      F::IsError => "error?",
+      F::ExMessage => "ex-message",
+      F::ExData => "ex-data",
+      F::ExCause => "ex-cause",
+      F::TryCall => "try-call",
+      F::Assert => "assert",
+      F::Pprint => "pprint",
+      F::Format => "format",
+      F::ParseInt => "parse-int",
+      F::ParseFloat => "parse-float",
+      F::FormatFloat => "format-float",
+      F::NumberToString => "to-string",
+      F::IsDigit => "digit?",
+      F::IsAlpha => "alpha?",
+      F::IsWhitespace => "whitespace?",
+      F::IsUpperCase => "upper-case?",
+      F::IsBlank => "blank?",
+      F::Compare => "compare",
+      F::IsIdentical => "identical?",
+      F::Hash => "hash",
+      F::RandNth => "rand-nth",
+      F::Shuffle => "shuffle",
+      F::Sample => "sample",
+      F::WithSeed => "with-seed-call",
+      F::Round => "round",
+      F::Trunc => "trunc",
+      F::Clamp => "clamp",
+      F::Lerp => "lerp",
+      F::Signum => "signum",
+      F::CallMethod => "call-method",
     }
   }
+  // Functions that touch the host's filesystem, which can only be called when
+  // the embedder has enabled the `file_io` capability
+  pub fn requires_file_io(&self) -> bool {
+    matches!(self, F::Slurp | F::Spit | F::ReadLines | F::ListDir)
+  }
+  // Functions whose calls can be seen from outside of them, by printing,
+  // touching files or cells, reading the clock, drawing random numbers, or
+  // calling into the host
+  pub fn has_side_effects(&self) -> bool {
+    self.requires_file_io()
+      || matches!(
+        self,
+        F::Print
+          | F::Pprint
+          | F::Rand
+          | F::RandInt
+          | F::RandNth
+          | F::Shuffle
+          | F::Sample
+          | F::Gensym
+          | F::SetCellValue
+          | F::UpdateCell
+          | F::NanoTime
+          | F::ReportElapsed
+          | F::CallMethod
+      )
+  }
+  // Functions that only the expansions of special forms call, like that of
+  // `time`. They have names so that expansions can be printed, but code can't
+  // refer to them by those names
+  pub fn is_internal(&self) -> bool {
+    matches!(self, F::NanoTime | F::ReportElapsed)
+  }
+  pub fn all() -> impl Iterator<Item = Self> {
+    (0..Self::LENGTH).map(Self::from_usize)
+  }
   pub fn from_name(name: &str) -> Option<Self> {
     match name {
       "print" => Some(F::Print),
@@ -317,6 +493,12 @@ impl CoreFnId {
       ">=" => Some(F::GreaterThanOrEqual),
       "<" => Some(F::LessThan),
       "<=" => Some(F::LessThanOrEqual),
+      "bit-and" => Some(F::BitAnd),
+      "bit-or" => Some(F::BitOr),
+      "bit-xor" => Some(F::BitXor),
+      "bit-not" => Some(F::BitNot),
+      "bit-shift-left" => Some(F::ShiftLeft),
+      "bit-shift-right" => Some(F::ShiftRight),
       "rand" => Some(F::Rand),
       "rand-int" => Some(F::RandInt),
       "=" => Some(F::Equal),
@@ -399,6 +581,59 @@ impl CoreFnId {
       "str" => Some(F::ToString),
       "to-list" => Some(F::ToList),
       "to-hashmap" => Some(F::ToMap),
+      "re-pattern" => Some(F::RePattern),
+      "re-find" => Some(F::ReFind),
+      "re-matches" => Some(F::ReMatches),
+      "re-seq" => Some(F::ReSeq),
+      "replace" => Some(F::Replace),
+      "slurp" => Some(F::Slurp),
+      "spit" => Some(F::Spit),
+      "read-lines" => Some(F::ReadLines),
+      "list-dir" => Some(F::ListDir),
+      "with-meta" => Some(F::WithMeta),
+      "meta" => Some(F::Meta),
+      "atom" => Some(F::CreateCell),
+      "deref" => Some(F::GetCellValue),
+      "reset!" => Some(F::SetCellValue),
+      "swap!" => Some(F::UpdateCell),
+      "json/parse" => Some(F::JsonParse),
+      "json/write" => Some(F::JsonWrite),
+      "edn/read" => Some(F::EdnRead),
+      "edn/write" => Some(F::EdnWrite),
+      "gensym" => Some(F::Gensym),
+      "pmap" => Some(F::Pmap),
+      "error" => Some(F::CreateError),
+      "throw" => Some(F::Throw),
+      "error?" => Some(F::IsError),
+      "ex-message" => Some(F::ExMessage),
+      "ex-data" => Some(F::ExData),
+      "ex-cause" => Some(F::ExCause),
+      "try-call" => Some(F::TryCall),
+      "assert" => Some(F::Assert),
+      "pprint" => Some(F::Pprint),
+      "format" => Some(F::Format),
+      "parse-int" => Some(F::ParseInt),
+      "parse-float" => Some(F::ParseFloat),
+      "format-float" => Some(F::FormatFloat),
+      "to-string" => Some(F::NumberToString),
+      "digit?" => Some(F::IsDigit),
+      "alpha?" => Some(F::IsAlpha),
+      "whitespace?" => Some(F::IsWhitespace),
+      "upper-case?" => Some(F::IsUpperCase),
+      "blank?" => Some(F::IsBlank),
+      "compare" => Some(F::Compare),
+      "identical?" => Some(F::IsIdentical),
+      "hash" => Some(F::Hash),
+      "rand-nth" => Some(F::RandNth),
+      "shuffle" => Some(F::Shuffle),
+      "sample" => Some(F::Sample),
+      "with-seed-call" => Some(F::WithSeed),
+      "round" => Some(F::Round),
+      "trunc" => Some(F::Trunc),
+      "clamp" => Some(F::Clamp),
+      "lerp" => Some(F::Lerp),
+      "signum" => Some(F::Signum),
+      "call-method" => Some(F::CallMethod),
       _ => None,
     }
   }
@@ -491,7 +726,7 @@ pub(crate) const CORE_FUNCTIONS: EnumMap<
   |args: Vec<Value>| {
     let nums = args
       .iter()
-      .map(|v| v.as_num().copied())
+      .map(|v| v.as_num().cloned())
       .collect::<RuntimeResult<Vec<Num>>>()?;
     Ok(Number(nums.into_iter().fold(Int(0), |sum, n| sum + n)))
   },
@@ -501,7 +736,7 @@ pub(crate) const CORE_FUNCTIONS: EnumMap<
   |args: Vec<Value>| {
     let nums = args
       .iter()
-      .map(|v| v.as_num().copied())
+      .map(|v| v.as_num().cloned())
       .collect::<RuntimeResult<Vec<Num>>>()?;
     Ok(Number(
       nums.into_iter().fold(Int(0), |product, n| product * n),
@@ -527,14 +762,74 @@ pub(crate) const CORE_FUNCTIONS: EnumMap<
   |_args: Vec<Value>| todo!(),
   // LessThanOrEqual
   |_args: Vec<Value>| todo!(),
-  // Rand
-  |_args: Vec<Value>| todo!(),
-  // RandInt
-  |_args: Vec<Value>| todo!(),
+  // BitAnd
+  |args: Vec<Value>| {
+    if args.len() >= 2 {
+      let mut nums = args.iter().map(|v| v.as_num());
+      let first = nums.next().unwrap()?.clone();
+      Ok(Number(nums.try_fold(first, |acc, n| acc.bit_and(n?))?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // BitOr
+  |args: Vec<Value>| {
+    if args.len() >= 2 {
+      let mut nums = args.iter().map(|v| v.as_num());
+      let first = nums.next().unwrap()?.clone();
+      Ok(Number(nums.try_fold(first, |acc, n| acc.bit_or(n?))?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // BitXor
+  |args: Vec<Value>| {
+    if args.len() >= 2 {
+      let mut nums = args.iter().map(|v| v.as_num());
+      let first = nums.next().unwrap()?.clone();
+      Ok(Number(nums.try_fold(first, |acc, n| acc.bit_xor(n?))?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // BitNot
+  |args: Vec<Value>| {
+    if args.len() == 1 {
+      Ok(Number(args[0].as_num()?.bit_not()?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // ShiftLeft
+  |args: Vec<Value>| {
+    if args.len() == 2 {
+      Ok(Number(args[0].as_num()?.shift_left(args[1].as_num()?)?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // ShiftRight
+  |args: Vec<Value>| {
+    if args.len() == 2 {
+      Ok(Number(args[0].as_num()?.shift_right(args[1].as_num()?)?))
+    } else {
+      Err(RuntimeError::InvalidArity)
+    }
+  },
+  // Rand, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // RandInt, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
   // Equal
-  |_args: Vec<Value>| todo!(),
+  |args: Vec<Value>| match &args[..] {
+    [first, rest @ ..] => Ok(rest.iter().all(|value| value == first).into()),
+    [] => Err(RuntimeError::InvalidArity),
+  },
   // NotEqual
-  |_args: Vec<Value>| todo!(),
+  |args: Vec<Value>| match &args[..] {
+    [first, rest @ ..] => Ok((!rest.iter().all(|value| value == first)).into()),
+    [] => Err(RuntimeError::InvalidArity),
+  },
   // Not
   |_args: Vec<Value>| todo!(),
   // And
@@ -569,16 +864,16 @@ pub(crate) const CORE_FUNCTIONS: EnumMap<
   |_args: Vec<Value>| todo!(),
   // UpdateIn
   |_args: Vec<Value>| todo!(),
-  // MinKey
-  |_args: Vec<Value>| todo!(),
-  // MaxKey
-  |_args: Vec<Value>| todo!(),
+  // MinKey, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // MaxKey, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
   // Push
   |_args: Vec<Value>| todo!(),
-  // Sort
-  |_args: Vec<Value>| todo!(),
-  // SortBy
-  |_args: Vec<Value>| todo!(),
+  // Sort, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // SortBy, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
   // CreateList
   |_args: Vec<Value>| todo!(),
   // Last
@@ -691,12 +986,252 @@ pub(crate) const CORE_FUNCTIONS: EnumMap<
   |_args: Vec<Value>| todo!(),
   // ToMap
   |_args: Vec<Value>| todo!(),
+  // RePattern
+  re_pattern,
+  // ReFind
+  re_find,
+  // ReMatches
+  re_matches,
+  // ReSeq
+  re_seq,
+  // Replace
+  replace,
+  // Slurp
+  slurp,
+  // Spit
+  spit,
+  // ReadLines
+  read_lines,
+  // ListDir
+  list_dir,
+  // WithMeta, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Meta, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
   // CreateCell
-  |_args: Vec<Value>| todo!(),
+  |args: Vec<Value>| match &args[..] {
+    [value] => Ok(Cell(Rc::new(RefCell::new(value.clone())))),
+    _ => Err(RuntimeError::InvalidArity),
+  },
   // GetCellValue
-  |_args: Vec<Value>| todo!(),
+  |args: Vec<Value>| match &args[..] {
+    [Cell(cell)] => Ok(cell.borrow().clone()),
+    [_] => Err(RuntimeError::ArgumentNotCell),
+    _ => Err(RuntimeError::InvalidArity),
+  },
   // SetCellValue
-  |_args: Vec<Value>| todo!(),
+  |args: Vec<Value>| match &args[..] {
+    [Cell(cell), value] => {
+      *cell.borrow_mut() = value.clone();
+      Ok(value.clone())
+    }
+    [_, _] => Err(RuntimeError::ArgumentNotCell),
+    _ => Err(RuntimeError::InvalidArity),
+  },
   // UpdateCell
-  |_args: Vec<Value>| todo!(),
+  // Calls to `swap!` are normally inlined into a call of the update function
+  // between reading and writing the cell, but when it's called indirectly
+  // the update function can't be run from here
+  |_args: Vec<Value>| Err(RuntimeError::NotYetImplemented),
+  // JsonParse
+  json_parse,
+  // JsonWrite
+  json_write,
+  // EdnRead, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // EdnWrite, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // NanoTime, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // ReportElapsed, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Gensym, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Pmap, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // CreateError
+  |args: Vec<Value>| {
+    let (message, data, cause) = match &args[..] {
+      [message] => (message, &Nil, &Nil),
+      [message, data] => (message, data, &Nil),
+      [message, data, cause] => (message, data, cause),
+      _ => return Err(RuntimeError::InvalidArity),
+    };
+    let Str(message) = message else {
+      return Err(RuntimeError::ArgumentNotString);
+    };
+    let cause = match cause {
+      Nil => None,
+      Error(cause) => Some(cause.clone()),
+      _ => return Err(RuntimeError::ArgumentNotError),
+    };
+    Ok(RuntimeError::Custom(message.to_string(), data.clone(), cause).into())
+  },
+  // Throw
+  |args: Vec<Value>| match &args[..] {
+    [Error(error)] => Err((**error).clone()),
+    [_] => Err(RuntimeError::ArgumentNotError),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // IsError
+  |args: Vec<Value>| match &args[..] {
+    [value] => Ok(matches!(value, Error(_)).into()),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // ExMessage
+  |args: Vec<Value>| match &args[..] {
+    [Error(error)] => Ok(match &**error {
+      RuntimeError::Custom(message, _, _) => message.as_str().into(),
+      error => error.to_string().into(),
+    }),
+    [_] => Err(RuntimeError::ArgumentNotError),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // ExData
+  |args: Vec<Value>| match &args[..] {
+    [Error(error)] => Ok(match &**error {
+      RuntimeError::Custom(_, data, _) => data.clone(),
+      _ => Nil,
+    }),
+    [_] => Err(RuntimeError::ArgumentNotError),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // ExCause
+  |args: Vec<Value>| match &args[..] {
+    [Error(error)] => Ok(match &**error {
+      RuntimeError::Custom(_, _, Some(cause)) => Error(cause.clone()),
+      _ => Nil,
+    }),
+    [_] => Err(RuntimeError::ArgumentNotError),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // TryCall, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Assert
+  // The second argument, if there is one, is the asserted expression, which
+  // `is` passes along to be reported if the assertion fails
+  |args: Vec<Value>| match &args[..] {
+    [value] | [value, _] if value.as_bool() => Ok(value.clone()),
+    [_] => Err(RuntimeError::Custom(
+      "assertion failed".to_string(),
+      Nil,
+      None,
+    )),
+    [_, form] => Err(RuntimeError::Custom(
+      "assertion failed".to_string(),
+      form.clone(),
+      None,
+    )),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // Pprint, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Format, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // ParseInt
+  parse_int,
+  // ParseFloat
+  parse_float,
+  // FormatFloat
+  format_float,
+  // NumberToString
+  number_to_string,
+  // IsDigit
+  |args: Vec<Value>| char_predicate(args, |c| c.is_ascii_digit()),
+  // IsAlpha
+  |args: Vec<Value>| char_predicate(args, char::is_alphabetic),
+  // IsWhitespace
+  |args: Vec<Value>| char_predicate(args, char::is_whitespace),
+  // IsUpperCase
+  // Strings only need to have no lower-case letters, so that "ABC-1" counts
+  |args: Vec<Value>| match &args[..] {
+    [Str(s)] => Ok(
+      (s.chars().any(char::is_uppercase) && !s.chars().any(char::is_lowercase))
+        .into(),
+    ),
+    _ => char_predicate(args, char::is_uppercase),
+  },
+  // IsBlank
+  |args: Vec<Value>| match &args[..] {
+    [Nil] => Ok(true.into()),
+    [Str(s)] => Ok(s.chars().all(char::is_whitespace).into()),
+    _ => char_predicate(args, char::is_whitespace),
+  },
+  // Compare, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // IsIdentical
+  |args: Vec<Value>| match &args[..] {
+    [a, b] => Ok(a.is_identical(b).into()),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // Hash
+  |args: Vec<Value>| match &args[..] {
+    [value] => Ok(value.hash_code().into()),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // RandNth, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Shuffle, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Sample, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // WithSeed, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
+  // Round
+  // Without a mode, ties go away from zero. "half-up" sends them toward
+  // positive infinity, and "half-even" to whichever neighbor is even
+  |args: Vec<Value>| {
+    let (n, tiebreak) = match &args[..] {
+      [n] => (n, Tiebreak::AwayFromZero),
+      [n, Str(mode)] => match mode.as_str() {
+        "half-up" => (n, Tiebreak::Up),
+        "half-even" => (n, Tiebreak::ToEven),
+        _ => {
+          return Err(RuntimeError::ArgumentOutOfRange(format!(
+            "rounding mode must be \"half-up\" or \"half-even\", but got \
+             \"{mode}\""
+          )))
+        }
+      },
+      [_, _] => return Err(RuntimeError::ArgumentNotString),
+      _ => return Err(RuntimeError::InvalidArity),
+    };
+    Ok(Number(n.as_num()?.round(tiebreak)))
+  },
+  // Trunc
+  |args: Vec<Value>| match &args[..] {
+    [n] => Ok(Number(n.as_num()?.trunc())),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // Clamp
+  |args: Vec<Value>| match &args[..] {
+    [n, low, high] => {
+      let (n, low, high) = (n.as_num()?, low.as_num()?, high.as_num()?);
+      if low
+        .numerical_cmp(high)
+        .is_some_and(|ordering| ordering.is_gt())
+      {
+        return Err(RuntimeError::ArgumentOutOfRange(
+          "clamp's lower bound is above its upper bound".to_string(),
+        ));
+      }
+      Ok(Number(Num::min(Num::max(n.clone(), low), high)))
+    }
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // Lerp
+  |args: Vec<Value>| match &args[..] {
+    [a, b, t] => {
+      let (a, b, t) = (a.as_num()?, b.as_num()?, t.as_num()?);
+      Ok(Number(a + &(&(b - a) * t)))
+    }
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // Signum
+  |args: Vec<Value>| match &args[..] {
+    [n] => Ok(Number(n.as_num()?.signum())),
+    _ => Err(RuntimeError::InvalidArity),
+  },
+  // CallMethod, handled by `EvaluationState::call_core_fn`
+  |_args: Vec<Value>| unreachable!(),
 ]);
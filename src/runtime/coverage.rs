@@ -0,0 +1,52 @@
+// Which instructions have run while coverage is enabled. Blocks are told
+// apart by their instructions, which every copy of a block shares, so a block
+// has to be kept alive for as long as its coverage is being looked up,
+// otherwise another block could take its place in memory
+
+use std::{
+  collections::{HashMap, HashSet},
+  rc::Rc,
+};
+
+use super::{control::Block, evaluation::Instruction};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+  executed: HashMap<*const Instruction, HashSet<usize>>,
+}
+
+impl Coverage {
+  pub(crate) fn record(
+    &mut self,
+    instructions: &Rc<[Instruction]>,
+    instruction_index: usize,
+  ) {
+    self
+      .executed
+      .entry(instructions.as_ptr())
+      .or_default()
+      .insert(instruction_index);
+  }
+  pub(crate) fn merge(&mut self, other: Coverage) {
+    for (instructions, indexes) in other.executed {
+      self
+        .executed
+        .entry(instructions)
+        .or_default()
+        .extend(indexes);
+    }
+  }
+  pub fn is_executed(&self, block: &Block, instruction_index: usize) -> bool {
+    self
+      .executed
+      .get(&block.instructions.as_ptr())
+      .is_some_and(|indexes| indexes.contains(&instruction_index))
+  }
+  // The number of the block's instructions that have run
+  pub fn executed(&self, block: &Block) -> usize {
+    self
+      .executed
+      .get(&block.instructions.as_ptr())
+      .map_or(0, |indexes| indexes.len())
+  }
+}
@@ -1,20 +1,27 @@
 use std::{
   any::Any,
   cell::RefCell,
-  collections::{HashMap, HashSet},
+  cmp::Ordering,
+  collections::HashMap,
   fmt::{Debug, Display},
-  hash::Hash,
+  hash::{DefaultHasher, Hash, Hasher},
   ops::{Add, Div, Mul, Neg, Sub},
   rc::Rc,
 };
 
+use num_bigint::BigInt as BigInteger;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
 use ordered_float::OrderedFloat;
 
 use crate::{
   blocks::GenericBlock,
   compiler::ast::token::SymbolLedger,
   instructions::GenericInstruction,
-  runtime::{control::GenericCompositeFunction, evaluation::Register},
+  runtime::{
+    control::{FunctionBody, GenericCompositeFunction},
+    evaluation::Register,
+  },
 };
 
 use super::{
@@ -22,76 +29,152 @@ use super::{
   core_functions::CoreFnId,
   error::{RuntimeError, RuntimeResult},
   evaluation::SymbolIndex,
+  external::{ExternalHandle, ExternalValue},
+  protocols::ProtocolFn,
+  records::{GenericRecord, RecordFn},
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+// Where `round` sends values that are exactly halfway between two integers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tiebreak {
+  AwayFromZero,
+  // Toward positive infinity, so -2.5 rounds to -2
+  Up,
+  ToEven,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Num {
   Int(i64),
   Float(OrderedFloat<f64>),
+  // Only used for integers that don't fit in an `i64`. Integer results that do
+  // fit are always demoted back to `Int`, so every integer has exactly one
+  // representation
+  BigInt(Rc<BigInteger>),
 }
 use Num::*;
 
 impl Num {
-  pub fn floor(&self) -> i64 {
+  pub fn from_big_int(i: BigInteger) -> Num {
+    match i.to_i64() {
+      Some(i) => Int(i),
+      None => BigInt(Rc::new(i)),
+    }
+  }
+  fn from_integral_float(f: f64) -> Num {
+    if f >= i64::MIN as f64 && f < i64::MAX as f64 {
+      Int(f as i64)
+    } else {
+      BigInteger::from_f64(f)
+        .map(Num::from_big_int)
+        .unwrap_or(Float(f.into()))
+    }
+  }
+  // Should only be called on `Int`s and `BigInt`s
+  fn to_big_int(&self) -> BigInteger {
+    match self {
+      Int(i) => BigInteger::from(*i),
+      BigInt(i) => (**i).clone(),
+      Float(f) => BigInteger::from_f64(f.trunc()).unwrap_or_default(),
+    }
+  }
+  pub fn floor(&self) -> Num {
+    match self {
+      Float(f) => Num::from_integral_float(f.floor()),
+      integer => integer.clone(),
+    }
+  }
+  pub fn ceil(&self) -> Num {
+    match self {
+      Float(f) => Num::from_integral_float(f.ceil()),
+      integer => integer.clone(),
+    }
+  }
+  // Unlike `floor` and `ceil`, these keep floats as floats
+  pub fn round(&self, tiebreak: Tiebreak) -> Num {
     match self {
-      Int(i) => *i,
-      Float(f) => f.floor() as i64,
+      Float(f) => Float(
+        match tiebreak {
+          Tiebreak::AwayFromZero => f.round(),
+          // comparing against the floor rather than taking `floor(f + 0.5)`,
+          // since the addition can itself round up
+          Tiebreak::Up if **f - f.floor() >= 0.5 => f.floor() + 1.,
+          Tiebreak::Up => f.floor(),
+          Tiebreak::ToEven => f.round_ties_even(),
+        }
+        .into(),
+      ),
+      integer => integer.clone(),
+    }
+  }
+  pub fn trunc(&self) -> Num {
+    match self {
+      Float(f) => Float(f.trunc().into()),
+      integer => integer.clone(),
     }
   }
-  pub fn ceil(&self) -> i64 {
+  // Zero and NaN are their own signs
+  pub fn signum(&self) -> Num {
     match self {
-      Int(i) => *i,
-      Float(f) => f.ceil() as i64,
+      Float(f) if **f == 0.0 || f.is_nan() => Float(*f),
+      Float(f) => Float(f.signum()),
+      Int(i) => Int(i.signum()),
+      BigInt(i) => Int(if i.is_negative() { -1 } else { 1 }),
     }
   }
   pub fn min(a: Num, b: &Num) -> Num {
-    match (a, b) {
-      (Int(a), Int(b)) => Int(a.min(*b)),
-      (Float(a), Float(b)) => Float(a.min(*b)),
-      (Int(a), Float(b)) => {
-        let b_derefed = *b;
-        if (a as f64) <= f64::from(b_derefed) {
-          Int(a)
-        } else {
-          Float(b_derefed)
+    match (&a, b) {
+      (Float(x), Float(y)) => Float((*x).min(*y)),
+      _ => match a.numerical_cmp(b) {
+        Some(Ordering::Less) => a,
+        Some(Ordering::Greater) => b.clone(),
+        // Ties between an int and a float go to the int
+        Some(Ordering::Equal) => {
+          if let Float(_) = a {
+            b.clone()
+          } else {
+            a
+          }
         }
-      }
-      (Float(a), Int(b)) => {
-        let b_derefed = *b;
-        if (b_derefed as f64) <= f64::from(a) {
-          Int(b_derefed)
-        } else {
-          Float(a)
+        // Comparison only fails when one side is NaN, which wins
+        None => {
+          if let Float(_) = a {
+            a
+          } else {
+            b.clone()
+          }
         }
-      }
+      },
     }
   }
   pub fn max(a: Num, b: &Num) -> Num {
-    match (a, b) {
-      (Int(a), Int(b)) => Int(a.max(*b)),
-      (Float(a), Float(b)) => Float(a.max(*b)),
-      (Int(a), Float(b)) => {
-        let b_derefed = *b;
-        if (a as f64) >= f64::from(b_derefed) {
-          Int(a)
-        } else {
-          Float(b_derefed)
+    match (&a, b) {
+      (Float(x), Float(y)) => Float((*x).max(*y)),
+      _ => match a.numerical_cmp(b) {
+        Some(Ordering::Greater) => a,
+        Some(Ordering::Less) => b.clone(),
+        Some(Ordering::Equal) => {
+          if let Float(_) = a {
+            b.clone()
+          } else {
+            a
+          }
         }
-      }
-      (Float(a), Int(b)) => {
-        let b_derefed = *b;
-        if (b_derefed as f64) >= f64::from(a) {
-          Int(b_derefed)
-        } else {
-          Float(a)
+        None => {
+          if let Float(_) = a {
+            a
+          } else {
+            b.clone()
+          }
         }
-      }
+      },
     }
   }
   pub fn as_float(&self) -> OrderedFloat<f64> {
     match self {
       Int(i) => OrderedFloat::from(*i as f64),
       Float(f) => *f,
+      BigInt(i) => OrderedFloat::from(i.to_f64().unwrap_or(f64::NAN)),
     }
   }
   pub fn as_int_lossless(&self) -> RuntimeResult<i64> {
@@ -105,123 +188,226 @@ impl Num {
           Err(RuntimeError::ArgumentNotInt)
         }
       }
+      BigInt(_) => Err(RuntimeError::ArgumentNotInt),
     }
   }
-  pub fn numerical_equal(&self, other: &Num) -> bool {
-    match (self, other) {
-      (Int(a), Int(b)) => a == b,
-      (Float(a), Float(b)) => a == b,
-      (Int(a), Float(b)) => (*a as f64) == **b,
-      (Float(a), Int(b)) => *a == (*b as f64),
+  pub fn is_even(&self) -> RuntimeResult<bool> {
+    match self {
+      BigInt(i) => Ok(i.is_even()),
+      n => n.as_int_lossless().map(|i| i % 2 == 0),
     }
   }
-  pub fn inc(&self) -> Num {
+  pub fn is_zero(&self) -> bool {
     match self {
-      Int(i) => Int(i + 1),
-      Float(f) => Float(f + 1.0),
+      Int(i) => *i == 0,
+      Float(f) => **f == 0.,
+      BigInt(_) => false,
     }
   }
-  pub fn dec(&self) -> Num {
+  pub fn is_positive(&self) -> bool {
     match self {
-      Int(i) => Int(i - 1),
-      Float(f) => Float(f - 1.0),
+      Int(i) => *i > 0,
+      Float(f) => **f > 0.,
+      BigInt(i) => i.is_positive(),
     }
   }
-  pub fn abs(&self) -> Num {
+  pub fn is_negative(&self) -> bool {
     match self {
-      Int(i) => Int(i.abs()),
-      Float(f) => Float(f.abs().into()),
+      Int(i) => *i < 0,
+      Float(f) => **f < 0.,
+      BigInt(i) => i.is_negative(),
     }
   }
-}
-
-impl Add for Num {
-  type Output = Num;
-  fn add(self, other: Num) -> Num {
+  // Compares exactly, without first converting integers to floats, so that
+  // large integers aren't considered equal to nearby floats
+  pub fn numerical_cmp(&self, other: &Num) -> Option<Ordering> {
     match (self, other) {
-      (Int(a), Int(b)) => (a + b).into(),
-      (Float(a), Float(b)) => (a + b).into(),
-      (Int(a), Float(b)) => ((a as f64) + *b).into(),
-      (Float(a), Int(b)) => (a + (b as f64)).into(),
+      (Int(a), Int(b)) => Some(a.cmp(b)),
+      (Float(a), Float(b)) => a.0.partial_cmp(&b.0),
+      (Float(a), b) => b.cmp_to_float(a.0).map(Ordering::reverse),
+      (a, Float(b)) => a.cmp_to_float(b.0),
+      (a, b) => Some(a.to_big_int().cmp(&b.to_big_int())),
+    }
+  }
+  fn cmp_to_float(&self, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+      None
+    } else if f.is_infinite() {
+      Some(if f > 0. {
+        Ordering::Less
+      } else {
+        Ordering::Greater
+      })
+    } else {
+      let floor = BigInteger::from_f64(f.floor())?;
+      Some(match self.to_big_int().cmp(&floor) {
+        Ordering::Equal if f.fract() != 0. => Ordering::Less,
+        ordering => ordering,
+      })
+    }
+  }
+  pub fn numerical_equal(&self, other: &Num) -> bool {
+    self.numerical_cmp(other) == Some(Ordering::Equal)
+  }
+  // Bitwise operations are only defined for integers, and treat negative
+  // integers as being in two's complement
+  fn as_integer(&self) -> RuntimeResult<Num> {
+    match self {
+      Float(_) => self.as_int_lossless().map(Int),
+      integer => Ok(integer.clone()),
+    }
+  }
+  pub fn bit_and(&self, other: &Num) -> RuntimeResult<Num> {
+    Ok(match (self.as_integer()?, other.as_integer()?) {
+      (Int(a), Int(b)) => Int(a & b),
+      (a, b) => Num::from_big_int(a.to_big_int() & b.to_big_int()),
+    })
+  }
+  pub fn bit_or(&self, other: &Num) -> RuntimeResult<Num> {
+    Ok(match (self.as_integer()?, other.as_integer()?) {
+      (Int(a), Int(b)) => Int(a | b),
+      (a, b) => Num::from_big_int(a.to_big_int() | b.to_big_int()),
+    })
+  }
+  pub fn bit_xor(&self, other: &Num) -> RuntimeResult<Num> {
+    Ok(match (self.as_integer()?, other.as_integer()?) {
+      (Int(a), Int(b)) => Int(a ^ b),
+      (a, b) => Num::from_big_int(a.to_big_int() ^ b.to_big_int()),
+    })
+  }
+  pub fn bit_not(&self) -> RuntimeResult<Num> {
+    Ok(match self.as_integer()? {
+      Int(i) => Int(!i),
+      i => Num::from_big_int(!i.to_big_int()),
+    })
+  }
+  // Shift amounts have to be from 0 to 63
+  fn shift_amount(shift: &Num) -> RuntimeResult<u32> {
+    let shift = shift.as_int_lossless()?;
+    u32::try_from(shift)
+      .ok()
+      .filter(|shift| *shift < 64)
+      .ok_or_else(|| {
+        RuntimeError::ArgumentOutOfRange(format!(
+          "shift amount must be from 0 to 63, but got {shift}"
+        ))
+      })
+  }
+  // Left shifts promote to `BigInt` rather than discarding bits
+  pub fn shift_left(&self, shift: &Num) -> RuntimeResult<Num> {
+    let shift = Num::shift_amount(shift)?;
+    Ok(match self.as_integer()? {
+      Int(i) if i.checked_shl(shift).is_some_and(|s| s >> shift == i) => {
+        Int(i << shift)
+      }
+      integer => Num::from_big_int(integer.to_big_int() << shift),
+    })
+  }
+  // Right shifts are arithmetic
+  pub fn shift_right(&self, shift: &Num) -> RuntimeResult<Num> {
+    let shift = Num::shift_amount(shift)?;
+    Ok(match self.as_integer()? {
+      Int(i) => Int(i >> shift),
+      integer => Num::from_big_int(integer.to_big_int() >> shift),
+    })
+  }
+  pub fn inc(&self) -> Num {
+    self + &Int(1)
+  }
+  pub fn dec(&self) -> Num {
+    self - &Int(1)
+  }
+  pub fn abs(&self) -> Num {
+    match self {
+      Int(i) => i
+        .checked_abs()
+        .map(Int)
+        .unwrap_or_else(|| Num::from_big_int(BigInteger::from(*i).abs())),
+      Float(f) => Float(f.abs()),
+      BigInt(i) => Num::from_big_int(i.abs()),
     }
   }
 }
+
 impl Add for &Num {
   type Output = Num;
   fn add(self, other: &Num) -> Num {
     match (self, other) {
-      (Int(a), Int(b)) => (a + b).into(),
-      (Float(a), Float(b)) => (*a + *b).into(),
-      (Int(a), Float(b)) => ((*a as f64) + **b).into(),
-      (Float(a), Int(b)) => (a + (*b as f64)).into(),
+      (Int(a), Int(b)) => a.checked_add(*b).map(Int).unwrap_or_else(|| {
+        Num::from_big_int(BigInteger::from(*a) + BigInteger::from(*b))
+      }),
+      (Float(a), b) => Float(a + b.as_float()),
+      (a, Float(b)) => Float(a.as_float() + b),
+      (a, b) => Num::from_big_int(a.to_big_int() + b.to_big_int()),
     }
   }
 }
-
-impl Sub for Num {
+impl Add for Num {
   type Output = Num;
-  fn sub(self, other: Num) -> Num {
-    match (self, other) {
-      (Int(a), Int(b)) => (a - b).into(),
-      (Float(a), Float(b)) => (a - b).into(),
-      (Int(a), Float(b)) => ((a as f64) - *b).into(),
-      (Float(a), Int(b)) => (*a - (b as f64)).into(),
-    }
+  fn add(self, other: Num) -> Num {
+    &self + &other
   }
 }
+
 impl Sub for &Num {
   type Output = Num;
   fn sub(self, other: &Num) -> Num {
     match (self, other) {
-      (Int(a), Int(b)) => (a - b).into(),
-      (Float(a), Float(b)) => (*a - b).into(),
-      (Int(a), Float(b)) => ((*a as f64) - **b).into(),
-      (Float(a), Int(b)) => (*a - (*b as f64)).into(),
+      (Int(a), Int(b)) => a.checked_sub(*b).map(Int).unwrap_or_else(|| {
+        Num::from_big_int(BigInteger::from(*a) - BigInteger::from(*b))
+      }),
+      (Float(a), b) => Float(a - b.as_float()),
+      (a, Float(b)) => Float(a.as_float() - b),
+      (a, b) => Num::from_big_int(a.to_big_int() - b.to_big_int()),
     }
   }
 }
-
-impl Neg for Num {
+impl Sub for Num {
   type Output = Num;
-  fn neg(self) -> Num {
-    match self {
-      Int(i) => Int(-i),
-      Float(f) => Float(-f),
-    }
+  fn sub(self, other: Num) -> Num {
+    &self - &other
   }
 }
+
 impl Neg for &Num {
   type Output = Num;
   fn neg(self) -> Num {
     match self {
-      Int(i) => Int(-i),
+      Int(i) => i
+        .checked_neg()
+        .map(Int)
+        .unwrap_or_else(|| Num::from_big_int(-BigInteger::from(*i))),
       Float(f) => Float(-f),
+      BigInt(i) => Num::from_big_int(-(**i).clone()),
     }
   }
 }
-
-impl Mul for Num {
+impl Neg for Num {
   type Output = Num;
-  fn mul(self, other: Num) -> Num {
-    match (self, other) {
-      (Int(a), Int(b)) => (a * b).into(),
-      (Float(a), Float(b)) => (a * b).into(),
-      (Int(a), Float(b)) => ((a as f64) * *b).into(),
-      (Float(a), Int(b)) => (a * (b as f64)).into(),
-    }
+  fn neg(self) -> Num {
+    -&self
   }
 }
+
 impl Mul for &Num {
   type Output = Num;
   fn mul(self, other: &Num) -> Num {
     match (self, other) {
-      (Int(a), Int(b)) => (a * b).into(),
-      (Float(a), Float(b)) => (*a * b).into(),
-      (Int(a), Float(b)) => ((*a as f64) * **b).into(),
-      (Float(a), Int(b)) => (a * (*b as f64)).into(),
+      (Int(a), Int(b)) => a.checked_mul(*b).map(Int).unwrap_or_else(|| {
+        Num::from_big_int(BigInteger::from(*a) * BigInteger::from(*b))
+      }),
+      (Float(a), b) => Float(a * b.as_float()),
+      (a, Float(b)) => Float(a.as_float() * b),
+      (a, b) => Num::from_big_int(a.to_big_int() * b.to_big_int()),
     }
   }
 }
+impl Mul for Num {
+  type Output = Num;
+  fn mul(self, other: Num) -> Num {
+    &self * &other
+  }
+}
 
 impl Div for Num {
   type Output = Num;
@@ -254,16 +440,63 @@ impl From<i64> for Num {
   }
 }
 
-#[derive(Clone, Debug)]
+impl From<BigInteger> for Num {
+  fn from(i: BigInteger) -> Self {
+    Num::from_big_int(i)
+  }
+}
+
+pub type ExternalFnResult = Result<Value, Rc<dyn std::error::Error>>;
+
+#[derive(Clone)]
 pub struct ExternalFunction {
   pub name: Option<String>,
-  pub f: fn(Vec<Value>) -> Result<Value, Rc<dyn std::error::Error>>,
+  // When present, calls with any other number of arguments fail with
+  // `InvalidArity` rather than reaching `f`
+  pub args: Option<AritySpecifier>,
+  pub f: Rc<dyn Fn(Vec<Value>) -> ExternalFnResult>,
+  // Whether the function has no side effects and always gives the same
+  // result for the same arguments, so that the compiler can call it early on
+  // arguments that are constants
+  pub pure: bool,
 }
 impl ExternalFunction {
-  pub fn unnamed(
-    f: fn(Vec<Value>) -> Result<Value, Rc<dyn std::error::Error>>,
+  pub fn unnamed(f: impl Fn(Vec<Value>) -> ExternalFnResult + 'static) -> Self {
+    Self {
+      name: None,
+      args: None,
+      f: Rc::new(f),
+      pure: false,
+    }
+  }
+  pub fn named(
+    name: impl Into<String>,
+    args: impl Into<AritySpecifier>,
+    f: impl Fn(Vec<Value>) -> ExternalFnResult + 'static,
   ) -> Self {
-    Self { name: None, f }
+    Self {
+      name: Some(name.into()),
+      args: Some(args.into()),
+      f: Rc::new(f),
+      pure: false,
+    }
+  }
+  pub fn can_accept(&self, count: usize) -> bool {
+    self.args.as_ref().is_none_or(|args| args.can_accept(count))
+  }
+  pub fn call(&self, args: Vec<Value>) -> RuntimeResult<Value> {
+    if !self.can_accept(args.len()) {
+      return Err(RuntimeError::InvalidArity);
+    }
+    Ok((self.f)(args)?)
+  }
+}
+impl Debug for ExternalFunction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("ExternalFunction")
+      .field("name", &self.name)
+      .field("args", &self.args)
+      .finish_non_exhaustive()
   }
 }
 
@@ -290,7 +523,16 @@ impl From<u8> for AritySpecifier {
   }
 }
 
-#[derive(Clone, Debug)]
+// Collections are persistent (structurally shared) so that modifying a shared
+// collection only copies the path to the modified node rather than the whole
+// collection. `im`'s `Vector` also keeps short lists inline in the Rc
+// allocation rather than in a separate heap buffer, since small argument lists
+// and quoted forms make up the bulk of all lists
+pub type ListStorage<T> = im_rc::Vector<T>;
+pub type MapStorage<K, V> = im_rc::HashMap<K, V>;
+pub type SetStorage<T> = im_rc::HashSet<T>;
+
+#[derive(Clone)]
 pub enum GenericValue<I, O, R, M> {
   Nil,
   Bool(bool),
@@ -298,27 +540,78 @@ pub enum GenericValue<I, O, R, M> {
   Number(Num),
   Symbol(SymbolIndex),
   Str(Rc<String>),
-  List(Rc<Vec<GenericValue<I, O, R, M>>>),
-  Hashmap(Rc<HashMap<GenericValue<I, O, R, M>, GenericValue<I, O, R, M>>>),
-  Hashset(Rc<HashSet<GenericValue<I, O, R, M>>>),
+  List(Rc<ListStorage<GenericValue<I, O, R, M>>>),
+  Hashmap(Rc<MapStorage<GenericValue<I, O, R, M>, GenericValue<I, O, R, M>>>),
+  Hashset(Rc<SetStorage<GenericValue<I, O, R, M>>>),
   CoreFn(CoreFnId),
   CompositeFn(Rc<GenericCompositeFunction<I, O, R, M>>),
   ExternalFn(Rc<ExternalFunction>),
   PartialApplication(
-    Rc<(GenericValue<I, O, R, M>, Vec<GenericValue<I, O, R, M>>)>,
+    Rc<(
+      GenericValue<I, O, R, M>,
+      ListStorage<GenericValue<I, O, R, M>>,
+    )>,
   ),
   Composition(Rc<Vec<GenericValue<I, O, R, M>>>),
-  ExternalObject(Rc<Rc<dyn Any>>),
+  ExternalObject(Rc<ExternalHandle>),
   Coroutine(Rc<Option<RefCell<Option<PausedCoroutine>>>>),
+  Cell(Rc<RefCell<GenericValue<I, O, R, M>>>),
   Error(Rc<RuntimeError>),
+  Record(Rc<GenericRecord<I, O, R, M>>),
+  RecordFn(Rc<RecordFn>),
+  ProtocolFn(Rc<ProtocolFn>),
+  #[cfg(feature = "regex")]
+  Regex(Rc<regex::Regex>),
 }
 
 pub type Value = GenericValue<Register, Register, Register, Register>;
 use GenericValue::*;
 
-impl<I, O, R, M> PartialEq for GenericValue<I, O, R, M>
-where
-  GenericValue<I, O, R, M>: Hash,
+impl<I: Clone + Debug, O: Clone + Debug, R: Clone + Debug, M: Clone + Debug>
+  Debug for GenericValue<I, O, R, M>
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Nil => write!(f, "Nil"),
+      Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+      Char(c) => f.debug_tuple("Char").field(c).finish(),
+      Number(n) => f.debug_tuple("Number").field(n).finish(),
+      Symbol(s) => f.debug_tuple("Symbol").field(s).finish(),
+      Str(s) => f.debug_tuple("Str").field(s).finish(),
+      List(list) => f.debug_tuple("List").field(list).finish(),
+      Hashmap(map) => f.debug_tuple("Hashmap").field(map).finish(),
+      Hashset(set) => f.debug_tuple("Hashset").field(set).finish(),
+      CoreFn(id) => f.debug_tuple("CoreFn").field(id).finish(),
+      CompositeFn(c) => f.debug_tuple("CompositeFn").field(c).finish(),
+      ExternalFn(e) => f.debug_tuple("ExternalFn").field(e).finish(),
+      PartialApplication(p) => {
+        f.debug_tuple("PartialApplication").field(p).finish()
+      }
+      Composition(fs) => f.debug_tuple("Composition").field(fs).finish(),
+      ExternalObject(o) => f.debug_tuple("ExternalObject").field(o).finish(),
+      Coroutine(c) => f.debug_tuple("Coroutine").field(c).finish(),
+      Cell(cell) => f.debug_tuple("Cell").field(cell).finish(),
+      Error(e) => f.debug_tuple("Error").field(e).finish(),
+      Record(record) => f.debug_tuple("Record").field(record).finish(),
+      RecordFn(record_fn) => {
+        f.debug_tuple("RecordFn").field(record_fn).finish()
+      }
+      ProtocolFn(protocol_fn) => {
+        f.debug_tuple("ProtocolFn").field(protocol_fn).finish()
+      }
+      #[cfg(feature = "regex")]
+      Regex(regex) => f.debug_tuple("Regex").field(regex).finish(),
+    }
+  }
+}
+
+// Equality as `=` sees it. Data, including strings, records, and the contents
+// of collections, is compared structurally. Functions of every kind, cells,
+// coroutines, and external objects are compared by identity, since there's no
+// way to tell whether two of them behave the same. Errors are equal when
+// they're of the same kind, and regexes when they have the same pattern
+impl<I: Clone, O: Clone, R: Clone, M: Clone> PartialEq
+  for GenericValue<I, O, R, M>
 {
   fn eq(&self, other: &Self) -> bool {
     match (self, other) {
@@ -327,23 +620,58 @@ where
       (Self::Char(a), Self::Char(b)) => a == b,
       (Self::Number(a), Self::Number(b)) => a == b,
       (Self::Symbol(a), Self::Symbol(b)) => a == b,
-      (Self::Str(a), Self::Str(b)) => a == b,
+      (Self::Str(a), Self::Str(b)) => Rc::ptr_eq(a, b) || a == b,
       (Self::List(a), Self::List(b)) => a == b,
       (Self::Hashmap(a), Self::Hashmap(b)) => a == b,
       (Self::Hashset(a), Self::Hashset(b)) => a == b,
       (Self::CoreFn(a), Self::CoreFn(b)) => a == b,
       (Self::CompositeFn(a), Self::CompositeFn(b)) => Rc::ptr_eq(a, b),
       (Self::ExternalFn(a), Self::ExternalFn(b)) => Rc::ptr_eq(a, b),
-      (Self::ExternalObject(a), Self::ExternalObject(b)) => Rc::ptr_eq(a, b),
+      (Self::PartialApplication(a), Self::PartialApplication(b)) => {
+        Rc::ptr_eq(a, b)
+      }
+      (Self::Composition(a), Self::Composition(b)) => Rc::ptr_eq(a, b),
+      (Self::ExternalObject(a), Self::ExternalObject(b)) => a == b,
       (Self::Coroutine(a), Self::Coroutine(b)) => Rc::ptr_eq(a, b),
+      (Self::Cell(a), Self::Cell(b)) => Rc::ptr_eq(a, b),
       (Self::Error(a), Self::Error(b)) => a == b,
+      (Self::Record(a), Self::Record(b)) => {
+        Rc::ptr_eq(&a.record_type, &b.record_type) && a.fields == b.fields
+      }
+      (Self::RecordFn(a), Self::RecordFn(b)) => Rc::ptr_eq(a, b),
+      (Self::ProtocolFn(a), Self::ProtocolFn(b)) => Rc::ptr_eq(a, b),
+      #[cfg(feature = "regex")]
+      (Self::Regex(a), Self::Regex(b)) => a.as_str() == b.as_str(),
       _ => false,
     }
   }
 }
-impl<I, O, R, M> Eq for GenericValue<I, O, R, M> where
-  GenericValue<I, O, R, M>: Hash
-{
+impl<I: Clone, O: Clone, R: Clone, M: Clone> Eq for GenericValue<I, O, R, M> {}
+
+impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
+  // Whether two values are the same allocation, as `identical?` sees it.
+  // Values that aren't allocated, like numbers and symbols, are identical
+  // whenever they're equal
+  pub fn is_identical(&self, other: &Self) -> bool {
+    match (self, other) {
+      (Nil, Nil)
+      | (Bool(_), Bool(_))
+      | (Char(_), Char(_))
+      | (Number(_), Number(_))
+      | (Symbol(_), Symbol(_))
+      | (CoreFn(_), CoreFn(_)) => self == other,
+      (Str(a), Str(b)) => Rc::ptr_eq(a, b),
+      (List(a), List(b)) => Rc::ptr_eq(a, b),
+      (Hashmap(a), Hashmap(b)) => Rc::ptr_eq(a, b),
+      (Hashset(a), Hashset(b)) => Rc::ptr_eq(a, b),
+      (Error(a), Error(b)) => Rc::ptr_eq(a, b),
+      (Record(a), Record(b)) => Rc::ptr_eq(a, b),
+      #[cfg(feature = "regex")]
+      (Regex(a), Regex(b)) => Rc::ptr_eq(a, b),
+      // everything else is already compared by identity
+      _ => self == other,
+    }
+  }
 }
 
 impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
@@ -364,26 +692,30 @@ impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
     translator: &F,
   ) -> Result<GenericValue<NewI, NewO, NewR, NewM>, E> {
     Ok(match self {
-      CompositeFn(f_ref) => {
-        CompositeFn(Rc::new(GenericCompositeFunction::new(
-          f_ref.args.clone(),
-          f_ref
-            .block
-            .clone()
-            .translate_inner(f_ref.args.register_count(), translator)?,
-        )))
-      }
+      CompositeFn(f_ref) => CompositeFn(Rc::new(GenericCompositeFunction {
+        args: f_ref.args.clone(),
+        body: match &f_ref.body {
+          FunctionBody::Compiled(block) => FunctionBody::Compiled(
+            block
+              .clone()
+              .translate_inner(f_ref.args.register_count(), translator)?,
+          ),
+          FunctionBody::Deferred(deferred) => {
+            FunctionBody::Deferred(deferred.clone())
+          }
+        },
+      })),
       Nil => Nil,
       Bool(b) => Bool(b),
       Char(c) => Char(c),
       Number(n) => Number(n),
       Symbol(s) => Symbol(s),
       Str(s) => Str(s),
-      List(vec) => List(Rc::new(
-        Rc::unwrap_or_clone(vec)
+      List(list) => List(Rc::new(
+        Rc::unwrap_or_clone(list)
           .into_iter()
           .map(|value| value.translate(translator))
-          .collect::<Result<Vec<_>, E>>()?,
+          .collect::<Result<ListStorage<_>, E>>()?,
       )),
       Hashmap(hashmap) => Hashmap(Rc::new(
         Rc::unwrap_or_clone(hashmap)
@@ -399,13 +731,13 @@ impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
               Ok((key.translate(translator)?, value.translate(translator)?))
             },
           )
-          .collect::<Result<HashMap<_, _>, E>>()?,
+          .collect::<Result<MapStorage<_, _>, E>>()?,
       )),
       Hashset(set) => Hashset(Rc::new(
         Rc::unwrap_or_clone(set)
           .into_iter()
           .map(|value| value.translate(translator))
-          .collect::<Result<HashSet<_>, E>>()?,
+          .collect::<Result<SetStorage<_>, E>>()?,
       )),
       CoreFn(f) => CoreFn(f),
       ExternalFn(f) => ExternalFn(f),
@@ -427,13 +759,36 @@ impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
       )),
       ExternalObject(o) => ExternalObject(o),
       Coroutine(c) => Coroutine(c),
+      Cell(cell) => Cell(Rc::new(RefCell::new(
+        Rc::unwrap_or_clone(cell)
+          .into_inner()
+          .translate(translator)?,
+      ))),
       Error(e) => Error(e),
+      Record(record) => {
+        let GenericRecord {
+          record_type,
+          fields,
+        } = Rc::unwrap_or_clone(record);
+        Record(Rc::new(GenericRecord {
+          record_type,
+          fields: fields
+            .into_vec()
+            .into_iter()
+            .map(|field| field.translate(translator))
+            .collect::<Result<_, _>>()?,
+        }))
+      }
+      RecordFn(record_fn) => RecordFn(record_fn),
+      ProtocolFn(protocol_fn) => ProtocolFn(protocol_fn),
+      #[cfg(feature = "regex")]
+      Regex(regex) => Regex(regex),
     })
   }
 }
 
 impl<I, O, R, M> GenericValue<I, O, R, M> {
-  pub(crate) fn composite_fn<
+  pub fn composite_fn<
     A: Into<AritySpecifier>,
     B: Into<GenericBlock<I, O, R, M>>,
   >(
@@ -442,6 +797,9 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
   ) -> Self {
     CompositeFn(Rc::new(GenericCompositeFunction::new(args, instructions)))
   }
+}
+
+impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
   pub(crate) fn description(
     &self,
     symbol_ledger: Option<&SymbolLedger>,
@@ -459,6 +817,7 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
           }
           s
         }
+        BigInt(i) => i.to_string(),
       },
       List(values) => {
         format!(
@@ -470,10 +829,12 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
             .join(", ")
         )
       }
+      // maps and sets are written in order, so that equal ones are always
+      // written the same way
       Hashmap(hashmap) => format!(
         "{{{}}}",
-        hashmap
-          .iter()
+        Self::sorted(hashmap.iter(), |(key, _)| *key, symbol_ledger)
+          .into_iter()
           .map(|(key, value)| key.description(symbol_ledger)
             + " "
             + &value.description(symbol_ledger))
@@ -482,8 +843,8 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
       ),
       Hashset(hashset) => format!(
         "#{{{}}}",
-        hashset
-          .iter()
+        Self::sorted(hashset.iter(), |value| *value, symbol_ledger)
+          .into_iter()
           .map(|value| value.description(symbol_ledger))
           .collect::<Vec<String>>()
           .join(", ")
@@ -499,13 +860,16 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
         }
       }
       Str(s) => format!("\"{}\"", s),
-      CompositeFn(composite_fn) => {
-        format!(
+      CompositeFn(composite_fn) => match &composite_fn.body {
+        FunctionBody::Compiled(block) => format!(
           "fn( {} args, {} instructions )\n",
           composite_fn.args.count,
-          composite_fn.block.len()
-        )
-      }
+          block.len()
+        ),
+        FunctionBody::Deferred(_) => {
+          format!("fn( {} args, uncompiled )\n", composite_fn.args.count)
+        }
+      },
       CoreFn(core_fn_id) => {
         format!("core_fn( {} )", core_fn_id)
       }
@@ -560,15 +924,115 @@ impl<I, O, R, M> GenericValue<I, O, R, M> {
           "dead".to_string()
         }
       ),
-      ExternalObject(_) => "external_object".to_string(),
+      ExternalObject(object) => object.description(),
+      Cell(cell) => match cell.try_borrow() {
+        Ok(value) => format!("atom( {} )", value.description(symbol_ledger)),
+        Err(_) => "atom( <being modified> )".to_string(),
+      },
       Error(e) => format!("error: {}", e),
+      Record(record) => format!(
+        "#{}{{{}}}",
+        record.record_type.name,
+        record
+          .record_type
+          .field_names
+          .iter()
+          .zip(record.fields.iter())
+          .map(|(name, value)| name.clone()
+            + " "
+            + &value.description(symbol_ledger))
+          .collect::<Vec<String>>()
+          .join(", ")
+      ),
+      RecordFn(record_fn) => format!("record_fn( {} )", record_fn.name()),
+      ProtocolFn(protocol_fn) => format!(
+        "protocol_fn( {}/{} )",
+        protocol_fn.protocol_name, protocol_fn.name
+      ),
+      #[cfg(feature = "regex")]
+      Regex(regex) => format!("#\"{}\"", regex.as_str()),
     }
   }
 }
 
-impl<I, O, R, M> Hash for GenericValue<I, O, R, M> {
-  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-    todo!()
+impl Hash for Num {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    // Integral floats hash the same as the corresponding int, so that hashing
+    // stays consistent even where ints and floats are compared numerically.
+    // `OrderedFloat` already hashes 0.0 and -0.0 (and all NaNs) identically
+    match self {
+      Int(i) => i.hash(state),
+      Float(f) => {
+        if f.fract() == 0. && f.0 >= i64::MIN as f64 && f.0 < i64::MAX as f64 {
+          (f.0 as i64).hash(state)
+        } else {
+          f.hash(state)
+        }
+      }
+      BigInt(i) => i.hash(state),
+    }
+  }
+}
+
+// Hashes a collection such that the result doesn't depend on iteration order,
+// since two equal maps or sets can iterate over their elements differently
+fn hash_unordered<T: Hash, H: Hasher>(
+  elements: impl Iterator<Item = T>,
+  state: &mut H,
+) {
+  let mut count = 0usize;
+  let combined = elements.fold(0u64, |combined, element| {
+    count += 1;
+    let mut element_hasher = DefaultHasher::new();
+    element.hash(&mut element_hasher);
+    combined.wrapping_add(element_hasher.finish())
+  });
+  count.hash(state);
+  combined.hash(state);
+}
+
+impl<I: Clone, O: Clone, R: Clone, M: Clone> Hash for GenericValue<I, O, R, M> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    std::mem::discriminant(self).hash(state);
+    match self {
+      Nil => {}
+      Bool(b) => b.hash(state),
+      Char(c) => c.hash(state),
+      Number(n) => n.hash(state),
+      Symbol(index) => index.hash(state),
+      Str(s) => s.hash(state),
+      List(list) => {
+        list.len().hash(state);
+        for value in list.iter() {
+          value.hash(state);
+        }
+      }
+      Hashmap(hashmap) => hash_unordered(hashmap.iter(), state),
+      Hashset(hashset) => hash_unordered(hashset.iter(), state),
+      CoreFn(id) => id.hash(state),
+      // Functions and coroutines are compared by identity, so they're hashed
+      // by address
+      CompositeFn(f) => Rc::as_ptr(f).hash(state),
+      ExternalFn(f) => Rc::as_ptr(f).hash(state),
+      PartialApplication(application) => Rc::as_ptr(application).hash(state),
+      Composition(fs) => Rc::as_ptr(fs).hash(state),
+      ExternalObject(object) => object.hash(state),
+      Coroutine(coroutine) => Rc::as_ptr(coroutine).hash(state),
+      Cell(cell) => Rc::as_ptr(cell).hash(state),
+      // Errors are compared by kind only
+      Error(error) => std::mem::discriminant(&**error).hash(state),
+      Record(record) => {
+        Rc::as_ptr(&record.record_type).hash(state);
+        for field in record.fields.iter() {
+          field.hash(state);
+        }
+      }
+      RecordFn(record_fn) => Rc::as_ptr(record_fn).hash(state),
+      ProtocolFn(protocol_fn) => Rc::as_ptr(protocol_fn).hash(state),
+      // Regexes are compared by their pattern
+      #[cfg(feature = "regex")]
+      Regex(regex) => regex.as_str().hash(state),
+    }
   }
 }
 
@@ -587,18 +1051,68 @@ impl Value {
       _ => true,
     }
   }
+  // The hash that `hash` gives. Maps and sets seed their hashers randomly,
+  // so this hashes the value the same way but with an unseeded hasher, which
+  // gives the same result every run. Equal values always hash the same, but
+  // symbols hash by index, so they can hash differently in another session
+  pub fn hash_code(&self) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    self.hash(&mut hasher);
+    hasher.finish() as i64
+  }
+  // Looks up a key in a collection, giving nil when it isn't present
+  pub fn get(&self, key: &Value) -> RuntimeResult<Value> {
+    Ok(match self {
+      Hashmap(map) => map.get(key).cloned().unwrap_or(Nil),
+      Hashset(set) => {
+        if set.contains(key) {
+          key.clone()
+        } else {
+          Nil
+        }
+      }
+      List(list) => match key {
+        Number(Int(index)) => usize::try_from(*index)
+          .ok()
+          .and_then(|index| list.get(index))
+          .cloned()
+          .unwrap_or(Nil),
+        _ => Nil,
+      },
+      Nil => Nil,
+      _ => return Err(RuntimeError::ArgumentNotList),
+    })
+  }
   pub fn external<T: Any>(external_object: T) -> Self {
-    ExternalObject(Rc::new(Rc::new(external_object)))
+    ExternalObject(Rc::new(ExternalHandle::opaque(Rc::new(external_object))))
+  }
+  // Wraps a host resource that `finalizer` cleans up once it's no longer
+  // needed
+  pub fn external_with_finalizer<T: Any>(
+    external_object: T,
+    finalizer: impl FnOnce(&T) + 'static,
+  ) -> Self {
+    ExternalObject(Rc::new(ExternalHandle::with_finalizer(
+      external_object,
+      finalizer,
+    )))
+  }
+  // Wraps a host object that can be compared, hashed, and printed
+  pub fn external_value<T: ExternalValue>(external_value: T) -> Self {
+    ExternalObject(Rc::new(ExternalHandle::of_value(external_value)))
   }
   pub fn casted_external<T: Any>(self) -> Option<Rc<T>> {
     if let ExternalObject(external_object) = self {
-      Rc::downcast::<T>((*external_object).clone()).ok()
+      Rc::downcast::<T>(external_object.object().clone()).ok()
     } else {
       None
     }
   }
-  pub fn fn_coroutine(f: CompositeFunction) -> Value {
-    Coroutine(Rc::new(Some(RefCell::new(Some(f.into())))))
+  pub fn coroutine(coroutine: PausedCoroutine) -> Value {
+    Coroutine(Rc::new(Some(RefCell::new(Some(coroutine)))))
+  }
+  pub fn fn_coroutine(f: CompositeFunction) -> RuntimeResult<Value> {
+    Ok(Value::coroutine(f.try_into()?))
   }
 }
 
@@ -618,6 +1132,11 @@ impl<I, O, R, M> From<i64> for GenericValue<I, O, R, M> {
     Number(i.into())
   }
 }
+impl<I, O, R, M> From<BigInteger> for GenericValue<I, O, R, M> {
+  fn from(i: BigInteger) -> Self {
+    Number(i.into())
+  }
+}
 impl<I, O, R, M> From<f64> for GenericValue<I, O, R, M> {
   fn from(f: f64) -> Self {
     Number(f.into())
@@ -648,13 +1167,22 @@ impl<I, O, R, M> From<&str> for GenericValue<I, O, R, M> {
     Str(Rc::new(s.to_string()))
   }
 }
-impl From<Vec<Value>> for Value {
-  fn from(values: Vec<Value>) -> Self {
+impl<I: Clone, O: Clone, R: Clone, M: Clone> From<Vec<GenericValue<I, O, R, M>>>
+  for GenericValue<I, O, R, M>
+{
+  fn from(values: Vec<GenericValue<I, O, R, M>>) -> Self {
+    List(Rc::new(values.into()))
+  }
+}
+impl<I, O, R, M> From<ListStorage<GenericValue<I, O, R, M>>>
+  for GenericValue<I, O, R, M>
+{
+  fn from(values: ListStorage<GenericValue<I, O, R, M>>) -> Self {
     List(Rc::new(values))
   }
 }
-impl From<Rc<Vec<Value>>> for Value {
-  fn from(values: Rc<Vec<Value>>) -> Self {
+impl From<Rc<ListStorage<Value>>> for Value {
+  fn from(values: Rc<ListStorage<Value>>) -> Self {
     List(values)
   }
 }
@@ -668,3 +1196,143 @@ impl From<RuntimeError> for Value {
     Error(Rc::new(e))
   }
 }
+
+impl<I, O, R, M, T: Into<GenericValue<I, O, R, M>>> From<Option<T>>
+  for GenericValue<I, O, R, M>
+{
+  fn from(maybe_value: Option<T>) -> Self {
+    maybe_value.map_or(Nil, T::into)
+  }
+}
+impl<K: Into<Value>, V: Into<Value>, S> From<HashMap<K, V, S>> for Value {
+  fn from(map: HashMap<K, V, S>) -> Self {
+    Hashmap(Rc::new(
+      map
+        .into_iter()
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect(),
+    ))
+  }
+}
+impl<T: Into<Value>> FromIterator<T> for Value {
+  fn from_iter<Iter: IntoIterator<Item = T>>(iter: Iter) -> Self {
+    List(Rc::new(iter.into_iter().map(T::into).collect()))
+  }
+}
+
+// Conversions out of values, so that external functions can take plain Rust
+// arguments. Elements of collections are converted recursively, and a failed
+// conversion reports the innermost value that couldn't be converted
+impl TryFrom<Value> for i64 {
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match &value {
+      Number(n) => n
+        .as_int_lossless()
+        .map_err(|_| RuntimeError::CantConvert(value, "int")),
+      _ => Err(RuntimeError::CantConvert(value, "int")),
+    }
+  }
+}
+impl TryFrom<Value> for f64 {
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Number(n) => Ok(*n.as_float()),
+      other => Err(RuntimeError::CantConvert(other, "float")),
+    }
+  }
+}
+impl TryFrom<Value> for bool {
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Bool(b) => Ok(b),
+      other => Err(RuntimeError::CantConvert(other, "bool")),
+    }
+  }
+}
+impl TryFrom<Value> for String {
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Str(s) => Ok(Rc::unwrap_or_clone(s)),
+      other => Err(RuntimeError::CantConvert(other, "string")),
+    }
+  }
+}
+impl<T> TryFrom<Value> for Vec<T>
+where
+  T: TryFrom<Value>,
+  T::Error: Into<RuntimeError>,
+{
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      List(list) => list
+        .iter()
+        .map(|element| T::try_from(element.clone()).map_err(Into::into))
+        .collect(),
+      other => Err(RuntimeError::CantConvert(other, "list")),
+    }
+  }
+}
+impl<K, V> TryFrom<Value> for HashMap<K, V>
+where
+  K: TryFrom<Value> + Eq + Hash,
+  K::Error: Into<RuntimeError>,
+  V: TryFrom<Value>,
+  V::Error: Into<RuntimeError>,
+{
+  type Error = RuntimeError;
+
+  fn try_from(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Hashmap(map) => map
+        .iter()
+        .map(|(key, value)| {
+          Ok((
+            K::try_from(key.clone()).map_err(Into::into)?,
+            V::try_from(value.clone()).map_err(Into::into)?,
+          ))
+        })
+        .collect(),
+      other => Err(RuntimeError::CantConvert(other, "map")),
+    }
+  }
+}
+// `Option<Value>` already converts through std's `From<T> for Option<T>`, so
+// optional conversions are implemented for each target type individually,
+// treating nil as `None`
+macro_rules! impl_try_from_value_for_option {
+  ($([$($generics:tt)*] $target:ty),* $(,)?) => {
+    $(
+      impl<$($generics)*> TryFrom<Value> for Option<$target>
+      where
+        $target: TryFrom<Value, Error = RuntimeError>,
+      {
+        type Error = RuntimeError;
+
+        fn try_from(value: Value) -> Result<Self, RuntimeError> {
+          match value {
+            Nil => Ok(None),
+            other => <$target>::try_from(other).map(Some),
+          }
+        }
+      }
+    )*
+  };
+}
+impl_try_from_value_for_option!(
+  [] i64,
+  [] f64,
+  [] bool,
+  [] String,
+  [T] Vec<T>,
+  [K, V] HashMap<K, V>,
+);
@@ -0,0 +1,308 @@
+// Reading and printing values as EDN, so that data can be written to files and
+// read back in. Unlike the pretty-printer, the output is always a single line
+// that reads back to an equal value. Vectors are read as lists, keywords are
+// read as symbols whose names start with `:`, and tagged elements are read as
+// their untagged value
+
+use std::{iter::Peekable, rc::Rc, str::Chars};
+
+use num_bigint::BigInt as BigInteger;
+
+use crate::compiler::ast::token::SymbolLedger;
+
+use super::{
+  data::{GenericValue::*, Num, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+pub(crate) fn write_edn(
+  value: &Value,
+  symbol_ledger: Option<&SymbolLedger>,
+) -> RuntimeResult<String> {
+  let mut output = String::new();
+  write_value(value, symbol_ledger, &mut output)?;
+  Ok(output)
+}
+
+fn write_sequence<'a>(
+  open: &str,
+  values: impl Iterator<Item = &'a Value>,
+  close: &str,
+  symbol_ledger: Option<&SymbolLedger>,
+  output: &mut String,
+) -> RuntimeResult<()> {
+  output.push_str(open);
+  for (i, value) in values.enumerate() {
+    if i > 0 {
+      output.push(' ');
+    }
+    write_value(value, symbol_ledger, output)?;
+  }
+  output.push_str(close);
+  Ok(())
+}
+
+fn write_value(
+  value: &Value,
+  symbol_ledger: Option<&SymbolLedger>,
+  output: &mut String,
+) -> RuntimeResult<()> {
+  match value {
+    Nil => output.push_str("nil"),
+    Bool(b) => output.push_str(if *b { "true" } else { "false" }),
+    Char(c) => {
+      output.push('\\');
+      match c {
+        '\n' => output.push_str("newline"),
+        ' ' => output.push_str("space"),
+        '\t' => output.push_str("tab"),
+        '\r' => output.push_str("return"),
+        c => output.push(*c),
+      }
+    }
+    Number(Num::Int(i)) => output.push_str(&i.to_string()),
+    Number(Num::BigInt(i)) => output.push_str(&format!("{i}N")),
+    Number(Num::Float(f)) => output.push_str(&if f.is_nan() {
+      "##NaN".to_string()
+    } else if f.is_infinite() {
+      if **f > 0. { "##Inf" } else { "##-Inf" }.to_string()
+    } else {
+      format!("{:?}", **f)
+    }),
+    Str(s) => {
+      output.push('"');
+      for c in s.chars() {
+        match c {
+          '"' => output.push_str("\\\""),
+          '\\' => output.push_str("\\\\"),
+          '\n' => output.push_str("\\n"),
+          '\t' => output.push_str("\\t"),
+          '\r' => output.push_str("\\r"),
+          c => output.push(c),
+        }
+      }
+      output.push('"');
+    }
+    Symbol(index) => output.push_str(
+      symbol_ledger
+        .and_then(|symbol_ledger| symbol_ledger.symbol_name(index))
+        .ok_or_else(|| RuntimeError::CantConvert(value.clone(), "edn"))?,
+    ),
+    List(list) => write_sequence("(", list.iter(), ")", symbol_ledger, output)?,
+    Hashset(set) => {
+      write_sequence("#{", set.iter(), "}", symbol_ledger, output)?
+    }
+    Hashmap(map) => {
+      output.push('{');
+      for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 {
+          output.push_str(", ");
+        }
+        write_value(key, symbol_ledger, output)?;
+        output.push(' ');
+        write_value(value, symbol_ledger, output)?;
+      }
+      output.push('}');
+    }
+    other => return Err(RuntimeError::CantConvert(other.clone(), "edn")),
+  }
+  Ok(())
+}
+
+// Whether `write_edn` can write a value, checked without writing it
+#[cfg(feature = "serde")]
+pub(crate) fn can_write_edn(
+  value: &Value,
+  symbol_ledger: Option<&SymbolLedger>,
+) -> bool {
+  let can_write = |value: &Value| can_write_edn(value, symbol_ledger);
+  match value {
+    Nil | Bool(_) | Char(_) | Number(_) | Str(_) => true,
+    Symbol(index) => symbol_ledger
+      .and_then(|symbol_ledger| symbol_ledger.symbol_name(index))
+      .is_some(),
+    List(list) => list.iter().all(can_write),
+    Hashset(set) => set.iter().all(can_write),
+    Hashmap(map) => map
+      .iter()
+      .all(|(key, value)| can_write(key) && can_write(value)),
+    _ => false,
+  }
+}
+
+pub(crate) fn read_edn(
+  source: &str,
+  symbol_ledger: &mut SymbolLedger,
+) -> RuntimeResult<Value> {
+  let mut reader = EdnReader {
+    chars: source.chars().peekable(),
+    symbol_ledger,
+  };
+  let value = reader.read_form()?;
+  reader.skip_whitespace()?;
+  match reader.chars.next() {
+    None => Ok(value),
+    Some(c) => Err(invalid(format!("unexpected `{c}` after first form"))),
+  }
+}
+
+fn invalid(message: impl Into<String>) -> RuntimeError {
+  RuntimeError::InvalidEdn(message.into())
+}
+
+fn is_delimiter(c: char) -> bool {
+  c.is_whitespace() || "()[]{}\",;".contains(c)
+}
+
+struct EdnReader<'a> {
+  chars: Peekable<Chars<'a>>,
+  symbol_ledger: &'a mut SymbolLedger,
+}
+
+impl EdnReader<'_> {
+  // Skips whitespace, commas, comments, and forms discarded with `#_`
+  fn skip_whitespace(&mut self) -> RuntimeResult<()> {
+    while let Some(&c) = self.chars.peek() {
+      if c == ';' {
+        while self.chars.next_if(|c| *c != '\n').is_some() {}
+      } else if c.is_whitespace() || c == ',' {
+        self.chars.next();
+      } else if c == '#' && self.chars.clone().nth(1) == Some('_') {
+        self.chars.nth(1);
+        self.read_form()?;
+      } else {
+        break;
+      }
+    }
+    Ok(())
+  }
+  fn next_char(&mut self) -> RuntimeResult<char> {
+    self
+      .chars
+      .next()
+      .ok_or_else(|| invalid("unexpected end of input"))
+  }
+  fn read_token(&mut self) -> String {
+    let mut token = String::new();
+    while let Some(c) = self.chars.next_if(|c| !is_delimiter(*c)) {
+      token.push(c);
+    }
+    token
+  }
+  // Reads forms until the closing delimiter, which is consumed
+  fn read_until(&mut self, close: char) -> RuntimeResult<Vec<Value>> {
+    let mut values = vec![];
+    loop {
+      self.skip_whitespace()?;
+      match self.chars.peek() {
+        Some(&c) if c == close => {
+          self.chars.next();
+          return Ok(values);
+        }
+        Some(_) => values.push(self.read_form()?),
+        None => return Err(invalid(format!("missing closing `{close}`"))),
+      }
+    }
+  }
+  fn read_form(&mut self) -> RuntimeResult<Value> {
+    self.skip_whitespace()?;
+    match self.next_char()? {
+      '(' => Ok(self.read_until(')')?.into()),
+      '[' => Ok(self.read_until(']')?.into()),
+      '{' => {
+        let values = self.read_until('}')?;
+        if values.len() % 2 != 0 {
+          return Err(invalid("map literal has an odd number of forms"));
+        }
+        let mut values = values.into_iter();
+        let mut map = im_rc::HashMap::new();
+        while let (Some(key), Some(value)) = (values.next(), values.next()) {
+          map.insert(key, value);
+        }
+        Ok(Hashmap(Rc::new(map)))
+      }
+      '#' => match self.chars.peek() {
+        Some('{') => {
+          self.chars.next();
+          Ok(Hashset(Rc::new(
+            self.read_until('}')?.into_iter().collect(),
+          )))
+        }
+        Some('#') => {
+          self.chars.next();
+          match self.read_token().as_str() {
+            "NaN" => Ok(f64::NAN.into()),
+            "Inf" => Ok(f64::INFINITY.into()),
+            "-Inf" => Ok(f64::NEG_INFINITY.into()),
+            other => Err(invalid(format!("unknown symbolic value ##{other}"))),
+          }
+        }
+        _ => {
+          if self.read_token().is_empty() {
+            return Err(invalid("expected a tag after `#`"));
+          }
+          self.read_form()
+        }
+      },
+      '"' => self.read_string(),
+      '\\' => self.read_char(),
+      c @ (')' | ']' | '}') => Err(invalid(format!("unexpected `{c}`"))),
+      c => {
+        let token = c.to_string() + &self.read_token();
+        self.read_atom(token)
+      }
+    }
+  }
+  fn read_string(&mut self) -> RuntimeResult<Value> {
+    let mut s = String::new();
+    loop {
+      match self.next_char()? {
+        '"' => return Ok(s.into()),
+        '\\' => s.push(match self.next_char()? {
+          'n' => '\n',
+          't' => '\t',
+          'r' => '\r',
+          c @ ('"' | '\\') => c,
+          c => return Err(invalid(format!("unknown string escape `\\{c}`"))),
+        }),
+        c => s.push(c),
+      }
+    }
+  }
+  fn read_char(&mut self) -> RuntimeResult<Value> {
+    let first = self.next_char()?;
+    let name = first.to_string() + &self.read_token();
+    Ok(Char(match name.as_str() {
+      "newline" => '\n',
+      "space" => ' ',
+      "tab" => '\t',
+      "return" => '\r',
+      _ if name.chars().count() == 1 => first,
+      _ => return Err(invalid(format!("unknown character `\\{name}`"))),
+    }))
+  }
+  fn read_atom(&mut self, token: String) -> RuntimeResult<Value> {
+    match token.as_str() {
+      "nil" => return Ok(Nil),
+      "true" => return Ok(Bool(true)),
+      "false" => return Ok(Bool(false)),
+      _ => {}
+    }
+    let starts_numeric = token
+      .trim_start_matches(['-', '+'])
+      .starts_with(|c: char| c.is_ascii_digit());
+    if !starts_numeric {
+      return Ok(Symbol(self.symbol_ledger.symbol_index(token)));
+    }
+    let integer_digits = token.strip_suffix('N').unwrap_or(&token);
+    if let Ok(i) = integer_digits.parse::<BigInteger>() {
+      return Ok(Number(Num::from_big_int(i)));
+    }
+    token
+      .strip_suffix('M')
+      .unwrap_or(&token)
+      .parse::<f64>()
+      .map(Value::from)
+      .map_err(|_| invalid(format!("invalid number `{token}`")))
+  }
+}
@@ -1,3 +1,4 @@
+use std::convert::Infallible;
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::rc::Rc;
@@ -7,15 +8,46 @@ pub enum RuntimeError {
   ArgumentNotNum,
   ArgumentNotInt,
   ArgumentNotList,
+  ArgumentNotString,
+  ArgumentNotRegex,
+  ArgumentNotRecord(String),
+  ArgumentNotCell,
+  ArgumentNotExternal(String),
+  ArgumentNotError,
+  ArgumentOutOfRange(String),
+  NoProtocolImplementation(String, String),
+  // A method call on an external object whose type has no method of that name
+  NoMethod(String),
+  InvalidRegex(String),
+  RegexUnsupported,
+  InvalidJson(String),
+  InvalidEdn(String),
+  InvalidFormat(String),
+  JsonUnsupported,
+  FileIODisabled(String),
+  FileIO(String),
   NotYetImplemented,
   CantCastToNum(Value),
+  CantConvert(Value, &'static str),
+  Serialization(String),
   CantApply(Value),
+  UndefinedGlobal(Value),
+  CantAttachMetadata(Value),
   InvalidArity,
   CantCreateCoroutine(String),
   DeadCoroutine,
   CoroutineAlreadyRunning,
   IsntCoroutine,
+  YieldOutsideCoroutine,
+  StackOverflow,
+  SharedUniqueList,
+  DeferredCompilation(String),
+  // A run that's replaying a trace asked for an input that wasn't recorded
+  ReplayDiverged(String),
   ExternalError(Rc<dyn Error>),
+  // Made by `error`, from a message, a value describing what went wrong
+  // (usually a map), and optionally the error that caused it
+  Custom(String, Value, Option<Rc<RuntimeError>>),
 }
 impl PartialEq for RuntimeError {
   fn eq(&self, other: &Self) -> bool {
@@ -28,33 +60,115 @@ impl PartialEq for RuntimeError {
 use RuntimeError::*;
 
 use super::data::Value;
+use crate::compiler::ast::token::SymbolLedger;
 
-impl Display for RuntimeError {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl RuntimeError {
+  pub(crate) fn description(
+    &self,
+    symbol_ledger: Option<&SymbolLedger>,
+  ) -> String {
     match self {
-      ArgumentNotNum => write!(f, "argument is not a number"),
-      ArgumentNotInt => write!(f, "argument is not an integer"),
-      ArgumentNotList => write!(f, "argument is not a list"),
-      NotYetImplemented => write!(f, "not yet implemented"),
-      CantCastToNum(value) => write!(f, "can't cast value {value} to number"),
-      CantApply(value) => write!(f, "can't apply value {value}"),
-      InvalidArity => write!(f, "invalid arity"),
-      CantCreateCoroutine(s) => write!(f, "{}", s),
+      ArgumentNotNum => "argument is not a number".to_string(),
+      ArgumentNotInt => "argument is not an integer".to_string(),
+      ArgumentNotList => "argument is not a list".to_string(),
+      ArgumentNotString => "argument is not a string".to_string(),
+      ArgumentNotRegex => "argument is not a regex".to_string(),
+      ArgumentNotCell => "argument is not an atom".to_string(),
+      ArgumentNotRecord(name) => format!("argument is not a {name} record"),
+      ArgumentNotExternal(type_name) => {
+        format!("argument is not an external {type_name}")
+      }
+      ArgumentNotError => "argument is not an error".to_string(),
+      ArgumentOutOfRange(message) => {
+        format!("argument out of range: {message}")
+      }
+      NoProtocolImplementation(method_name, type_name) => {
+        format!("no implementation of `{method_name}` for type {type_name}")
+      }
+      NoMethod(method_name) => {
+        format!("external object has no method `{method_name}`")
+      }
+      InvalidRegex(message) => format!("invalid regex: {message}"),
+      RegexUnsupported => {
+        "regex support is disabled (enable the `regex` feature)".to_string()
+      }
+      InvalidJson(message) => format!("invalid json: {message}"),
+      InvalidEdn(message) => format!("invalid edn: {message}"),
+      InvalidFormat(message) => format!("invalid format: {message}"),
+      JsonUnsupported => {
+        "json support is disabled (enable the `json` feature)".to_string()
+      }
+      FileIODisabled(name) => {
+        format!("can't call `{name}`, file IO is disabled for this evaluator")
+      }
+      FileIO(message) => format!("file IO error: {message}"),
+      NotYetImplemented => "not yet implemented".to_string(),
+      CantCastToNum(value) => format!(
+        "can't cast value {} to number",
+        value.description(symbol_ledger)
+      ),
+      CantConvert(value, target) => format!(
+        "can't convert value {} to {target}",
+        value.description(symbol_ledger)
+      ),
+      Serialization(message) => format!("serialization error: {message}"),
+      CantApply(value) => {
+        format!("can't apply value {}", value.description(symbol_ledger))
+      }
+      UndefinedGlobal(symbol) => format!(
+        "{} was declared but never defined",
+        symbol.description(symbol_ledger)
+      ),
+      CantAttachMetadata(value) => format!(
+        "can't attach metadata to value {}",
+        value.description(symbol_ledger)
+      ),
+      InvalidArity => "invalid arity".to_string(),
+      CantCreateCoroutine(s) => s.clone(),
       CoroutineAlreadyRunning => {
-        write!(f, "attempt to run coroutine that is already running")
+        "attempt to run coroutine that is already running".to_string()
+      }
+      IsntCoroutine => "argument is not a coroutine".to_string(),
+      YieldOutsideCoroutine => {
+        "attempt to yield outside of a coroutine".to_string()
+      }
+      DeadCoroutine => "attempt to run dead coroutine".to_string(),
+      StackOverflow => "stack overflow".to_string(),
+      SharedUniqueList => {
+        "list marked as unique is referenced elsewhere".to_string()
       }
-      IsntCoroutine => write!(f, "argument is not a coroutine"),
-      DeadCoroutine => write!(f, "attempt to run dead coroutine"),
+      DeferredCompilation(error) => {
+        format!("failed to compile function: {error}")
+      }
+      ReplayDiverged(message) => format!("replay diverged: {message}"),
       ExternalError(external_error) => {
-        write!(f, "external error: \"{}\"", external_error)
+        format!("external error: \"{}\"", external_error)
+      }
+      Custom(message, data, cause) => {
+        let mut description = message.clone();
+        if *data != Value::Nil {
+          description += &format!(" {}", data.description(symbol_ledger));
+        }
+        if let Some(cause) = cause {
+          description +=
+            &format!(", caused by: {}", cause.description(symbol_ledger));
+        }
+        description
       }
     }
   }
 }
+
+impl Display for RuntimeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.description(None))
+  }
+}
 impl Error for RuntimeError {
   fn source(&self) -> Option<&(dyn Error + 'static)> {
     match self {
       ExternalError(external_error) => Some(&**external_error),
+      Custom(_, _, Some(cause)) => Some(&**cause),
       _ => None,
     }
   }
@@ -64,4 +178,19 @@ impl From<RuntimeError> for Rc<dyn Error> {
     Rc::new(pidgin_error)
   }
 }
+impl From<Infallible> for RuntimeError {
+  fn from(infallible: Infallible) -> Self {
+    match infallible {}
+  }
+}
+// Runtime errors that an external function propagated with `?` come back as
+// themselves rather than wrapped in `ExternalError`
+impl From<Rc<dyn Error>> for RuntimeError {
+  fn from(external_error: Rc<dyn Error>) -> Self {
+    external_error
+      .downcast_ref::<RuntimeError>()
+      .cloned()
+      .unwrap_or(ExternalError(external_error))
+  }
+}
 pub type RuntimeResult<T> = std::result::Result<T, RuntimeError>;
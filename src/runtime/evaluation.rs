@@ -1,16 +1,28 @@
+use std::any::TypeId;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 
-use crate::runtime::core_functions::CORE_FUNCTIONS;
+use crate::compiler::ast::token::SymbolLedger;
+use crate::runtime::core_functions::{nano_time, CoreFnId, CORE_FUNCTIONS};
 use crate::string_utils::pad;
 use crate::{
-  instructions::GenericInstruction::{self, *},
+  instructions::{
+    Argument::{self, *},
+    GenericInstruction::{self, *},
+  },
   runtime::{
-    control::{CoroutineState, StackFrame},
+    control::{
+      CoroutineState, FrameView, StackFrame, DEFAULT_STACK_CAPACITY,
+      MAX_STACK_SIZE,
+    },
     data::{
-      AritySpecifier,
+      AritySpecifier, ExternalFunction,
       GenericValue::*,
+      ListStorage,
       Num::{self, *},
       Value,
     },
@@ -20,29 +32,642 @@ use crate::{
 
 use take_mut::take;
 
+use super::capabilities::Capabilities;
 use super::control::{Block, CompositeFunction, PausedCoroutine};
+use super::coverage::Coverage;
+use super::edn::{read_edn, write_edn};
 use super::error::{RuntimeError, RuntimeResult};
+use super::external::Finalizer;
+use super::format::format;
+use super::interner::StringInterner;
+use super::memory::{HeapStats, HeapWalker};
+use super::metadata::MetadataTable;
+use super::methods::MethodTable;
+use super::pretty_print::PrettyPrintOptions;
+use super::profile::{Profile, Profiler};
+use super::random::{call_random_fn, Rng};
+use super::replay::{Replay, Trace};
+use super::validation::{validate_block, ValidationError};
 
 pub type Register = u8;
 pub type StackIndex = u16;
 pub type SymbolIndex = u16;
 pub type ConstIndex = u16;
 pub type Instruction = GenericInstruction<Register, Register, Register>;
+type RegisterWatch = Rc<dyn Fn(Register, &Value)>;
+type OutputSink = Rc<dyn Fn(&str)>;
 
 pub struct EvaluationState {
   current_frame: StackFrame,
   current_coroutine: CoroutineState,
   parent_coroutine_stack: Vec<(StackIndex, PausedCoroutine)>,
+  string_interner: StringInterner,
+  pub(super) symbol_ledger: Option<Rc<SymbolLedger>>,
+  pub(super) capabilities: Capabilities,
+  metadata: MetadataTable,
+  // When set, `Lookup` instructions cache the globals they resolve, and reuse
+  // them as long as they're evaluated with the same generation
+  environment_generation: Option<u64>,
+  // The size that new coroutines' stacks start out with, and that stacks are
+  // never shrunk below
+  pub(super) stack_capacity: usize,
+  // Values passed between the host and the VM through the stack-style API in
+  // `host_stack`
+  pub(super) host_stack: Vec<Value>,
+  // Callbacks for registers being written, in whichever frame is running
+  register_watches: Vec<(Register, RegisterWatch)>,
+  // The most stack slots that frames have taken up at once, across all runs
+  peak_stack_depth: usize,
+  coverage: Option<Coverage>,
+  profiler: Option<Profiler>,
+  // Shared with the machines that core functions start, which hand it back
+  // once they finish, so that seeding it makes a whole run deterministic
+  rng: Rng,
+  // Shared with the machines that core functions start, so that a trace
+  // covers everything a run does
+  replay: Rc<RefCell<Replay>>,
+  methods: Rc<MethodTable>,
+  // Where `print`, `pprint`, and `time` write their lines, or standard output
+  // if unset. Shared with the machines that core functions start
+  output: Option<OutputSink>,
+  // The finalizers of external objects that have to be run by the time the
+  // machine is dropped, even if the objects are still reachable
+  finalizers: Vec<Weak<Finalizer>>,
+}
+
+// A generation number that no other global environment has used. Generation
+// 0 is reserved for empty environments, which have nothing to cache
+pub(crate) fn next_environment_generation() -> u64 {
+  static GENERATIONS: AtomicU64 = AtomicU64::new(1);
+  GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl std::ops::Drop for EvaluationState {
+  fn drop(&mut self) {
+    for finalizer in self.finalizers.drain(..) {
+      if let Some(finalizer) = finalizer.upgrade() {
+        finalizer.run();
+      }
+    }
+  }
+}
+
+impl Default for EvaluationState {
+  fn default() -> Self {
+    Self::new(Block::new(vec![], vec![]))
+  }
 }
 
 impl EvaluationState {
   pub fn new(block: Block) -> Self {
+    Self::new_with_interner(block, StringInterner::default())
+  }
+  // Like `new`, but first checks that the block is well-formed, so that
+  // malformed bytecode is reported up front rather than panicking mid-run
+  pub fn new_validated(block: Block) -> Result<Self, ValidationError> {
+    validate_block(&block, false)?;
+    Ok(Self::new(block))
+  }
+  pub fn new_with_interner(
+    block: Block,
+    mut string_interner: StringInterner,
+  ) -> Self {
+    let current_frame = StackFrame::root(string_interner.intern_block(block));
+    let current_frame_end = current_frame.end();
+    let mut current_coroutine = CoroutineState::new(DEFAULT_STACK_CAPACITY);
+    current_coroutine.reserve_stack(current_frame.end());
     Self {
-      current_frame: StackFrame::root(block),
-      current_coroutine: CoroutineState::new(),
+      current_frame,
+      current_coroutine,
       parent_coroutine_stack: vec![],
+      string_interner,
+      symbol_ledger: None,
+      capabilities: Capabilities::default(),
+      metadata: MetadataTable::default(),
+      environment_generation: None,
+      stack_capacity: DEFAULT_STACK_CAPACITY,
+      host_stack: vec![],
+      register_watches: vec![],
+      peak_stack_depth: current_frame_end,
+      coverage: None,
+      profiler: None,
+      rng: Rng::from_entropy(),
+      replay: Rc::default(),
+      methods: Rc::default(),
+      output: None,
+      finalizers: vec![],
+    }
+  }
+  pub fn with_stack_capacity(mut self, stack_capacity: usize) -> Self {
+    self.stack_capacity = stack_capacity;
+    self
+      .current_coroutine
+      .stack
+      .resize(stack_capacity.max(self.current_frame.end()), Value::Nil);
+    self
+  }
+  pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+    self.capabilities = capabilities;
+    self
+  }
+  // Dynamic bindings that are in effect for the whole run, innermost last
+  pub(crate) fn with_dynamic_bindings(
+    mut self,
+    dynamic_bindings: Vec<(SymbolIndex, Value)>,
+  ) -> Self {
+    self.current_frame.dynamic_bindings = dynamic_bindings;
+    self
+  }
+  // Enables caching of global lookups. The generation must change whenever
+  // the global bindings passed to `evaluate` do
+  pub fn with_environment_generation(mut self, generation: u64) -> Self {
+    self.environment_generation = Some(generation);
+    self
+  }
+  // Readies the machine to run another top-level block, keeping its stack
+  // buffer, interner, and metadata from earlier runs. Frames left behind by a
+  // run that failed partway through are dropped, along with any values still
+  // on the stack
+  pub(crate) fn reset(&mut self, block: Block) {
+    self.current_frame =
+      StackFrame::root(self.string_interner.intern_block(block));
+    self.parent_coroutine_stack.clear();
+    let coroutine = &mut self.current_coroutine;
+    coroutine.paused_frames.clear();
+    coroutine.stack.truncate(self.stack_capacity);
+    coroutine.stack.fill(Value::Nil);
+    coroutine.reserve_stack(self.current_frame.end());
+    self.peak_stack_depth = self.peak_stack_depth.max(self.current_frame.end());
+  }
+  pub(crate) fn set_capabilities(&mut self, capabilities: Capabilities) {
+    self.capabilities = capabilities;
+  }
+  pub(crate) fn set_environment_generation(&mut self, generation: u64) {
+    self.environment_generation = Some(generation);
+  }
+  pub(crate) fn set_symbol_ledger(&mut self, symbol_ledger: Rc<SymbolLedger>) {
+    self.symbol_ledger = Some(symbol_ledger);
+  }
+  // Makes sure that the finalizer of an external object made with
+  // `Value::external_with_finalizer` runs when this machine is dropped, if it
+  // hasn't already. Has no effect on other values
+  pub fn track_finalizer(&mut self, value: &Value) {
+    if let ExternalObject(handle) = value {
+      if let Some(finalizer) = handle.finalizer() {
+        self
+          .finalizers
+          .retain(|finalizer| finalizer.strong_count() > 0);
+        self.finalizers.push(finalizer);
+      }
+    }
+  }
+  #[cfg(feature = "tokio")]
+  pub(crate) fn share_methods(&mut self, other: &EvaluationState) {
+    self.methods = other.methods.clone();
+  }
+  fn call_core_fn(
+    &mut self,
+    core_fn_id: CoreFnId,
+    args: Vec<Value>,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Value> {
+    if core_fn_id.requires_file_io() && !self.capabilities.file_io {
+      return Err(RuntimeError::FileIODisabled(core_fn_id.name().to_string()));
+    }
+    // Metadata functions need access to the evaluator's metadata table, EDN
+    // functions, `gensym`, `pprint`, `format`, and the functions that order
+    // values need its symbol ledger, the random functions need its random
+    // number generator, `call-method` needs its method table, `nano-time` and
+    // `report-elapsed` need its trace, and `pmap`, `try-call`,
+    // `with-seed-call`, and the `-by` and `-key` functions need to run
+    // functions, so they can't be called through `CORE_FUNCTIONS`
+    match (core_fn_id, &args[..]) {
+      (CoreFnId::Meta, [value]) => Ok(self.metadata.meta(value)),
+      (CoreFnId::WithMeta, [value, metadata]) => {
+        self.metadata.with_meta(value, metadata.clone())
+      }
+      (CoreFnId::EdnRead, [Str(s)]) => read_edn(
+        s,
+        Rc::make_mut(self.symbol_ledger.get_or_insert_with(Default::default)),
+      ),
+      (CoreFnId::EdnRead, [_]) => Err(RuntimeError::ArgumentNotString),
+      (CoreFnId::EdnWrite, [value]) => {
+        write_edn(value, self.symbol_ledger.as_deref()).map(Value::from)
+      }
+      (CoreFnId::Gensym, [] | [Str(_)]) => {
+        let prefix = match &args[..] {
+          [Str(prefix)] => prefix.as_str(),
+          _ => "G__",
+        };
+        Ok(Symbol(
+          Rc::make_mut(self.symbol_ledger.get_or_insert_with(Default::default))
+            .generate_prefixed_symbol(prefix),
+        ))
+      }
+      (CoreFnId::Gensym, [_]) => Err(RuntimeError::ArgumentNotString),
+      (CoreFnId::Pprint, [value] | [value, Number(Int(_))]) => {
+        let width = match &args[..] {
+          [_, Number(Int(width))] => (*width).max(0) as usize,
+          _ => PrettyPrintOptions::default().width,
+        };
+        self.write_line(&value.pretty_description(
+          self.symbol_ledger.as_deref(),
+          &PrettyPrintOptions {
+            width,
+            ..PrettyPrintOptions::default()
+          },
+        ));
+        Ok(Nil)
+      }
+      (CoreFnId::Pprint, [_, _]) => Err(RuntimeError::ArgumentNotInt),
+      (CoreFnId::Format, [Str(template), format_args @ ..]) => {
+        format(template, format_args, self.symbol_ledger.as_deref())
+          .map(Value::from)
+      }
+      (CoreFnId::Format, [_, ..]) => Err(RuntimeError::ArgumentNotString),
+      (
+        CoreFnId::Rand
+        | CoreFnId::RandInt
+        | CoreFnId::RandNth
+        | CoreFnId::Shuffle
+        | CoreFnId::Sample,
+        _,
+      ) => call_random_fn(core_fn_id, &args, &mut self.rng),
+      // the seeded generator only lasts for the body, after which the outer
+      // one carries on from where it was
+      (CoreFnId::WithSeed, [Number(Int(seed)), body]) => {
+        let outer_rng =
+          std::mem::replace(&mut self.rng, Rng::seeded(*seed as u64));
+        let result = self.call_function(body, vec![Nil], global_bindings);
+        self.rng = outer_rng;
+        result
+      }
+      (CoreFnId::WithSeed, [_, _]) => Err(RuntimeError::ArgumentNotInt),
+      (CoreFnId::Compare, [a, b]) => {
+        Ok((a.total_cmp(b, self.symbol_ledger.as_deref()) as i64).into())
+      }
+      (CoreFnId::Sort, [collection]) => self.sorted_list(collection),
+      (CoreFnId::SortBy, [f, collection]) => {
+        let keyed = self.keyed(f, collection, global_bindings)?;
+        let sorted = Value::sorted(
+          keyed.into_iter(),
+          |(key, _)| key,
+          self.symbol_ledger.as_deref(),
+        );
+        Ok(sorted.into_iter().map(|(_, item)| item).collect())
+      }
+      (CoreFnId::MinKey, [f, collection]) => {
+        self.extreme_by_key(f, collection, false, global_bindings)
+      }
+      (CoreFnId::MaxKey, [f, collection]) => {
+        self.extreme_by_key(f, collection, true, global_bindings)
+      }
+      (CoreFnId::Pmap, [f, List(items)]) => {
+        self.pmap(f, items, global_bindings)
+      }
+      (CoreFnId::Pmap, [_, _]) => Err(RuntimeError::ArgumentNotList),
+      (CoreFnId::TryCall, [body, handler, cleanup]) => {
+        self.try_call(body, handler, cleanup, global_bindings)
+      }
+      // the method can be named by a string or a symbol
+      (CoreFnId::CallMethod, [object, name, args @ ..]) => {
+        let name = match name {
+          Str(name) => name.as_str(),
+          Symbol(index) => self
+            .symbol_ledger
+            .as_deref()
+            .and_then(|symbol_ledger| symbol_ledger.symbol_name(index))
+            .ok_or(RuntimeError::ArgumentNotString)?,
+          _ => return Err(RuntimeError::ArgumentNotString),
+        };
+        let method = self.methods.method(object, name)?;
+        self.call_external(
+          &method,
+          std::iter::once(object.clone())
+            .chain(args.iter().cloned())
+            .collect(),
+        )
+      }
+      (CoreFnId::NanoTime, []) => {
+        Ok(self.replay.borrow_mut().time(nano_time)?.into())
+      }
+      (CoreFnId::ReportElapsed, [Number(Int(start)), value]) => {
+        let elapsed = self.replay.borrow_mut().time(nano_time)? - start;
+        self.write_line(&format!(
+          "Elapsed time: {} msecs",
+          elapsed as f64 / 1_000_000.
+        ));
+        Ok(value.clone())
+      }
+      (CoreFnId::ReportElapsed, [start, _]) => {
+        Err(RuntimeError::CantCastToNum(start.clone()))
+      }
+      (
+        CoreFnId::Meta
+        | CoreFnId::WithMeta
+        | CoreFnId::EdnRead
+        | CoreFnId::EdnWrite
+        | CoreFnId::Gensym
+        | CoreFnId::Pprint
+        | CoreFnId::Format
+        | CoreFnId::WithSeed
+        | CoreFnId::Compare
+        | CoreFnId::Sort
+        | CoreFnId::SortBy
+        | CoreFnId::MinKey
+        | CoreFnId::MaxKey
+        | CoreFnId::Pmap
+        | CoreFnId::TryCall
+        | CoreFnId::CallMethod
+        | CoreFnId::NanoTime
+        | CoreFnId::ReportElapsed,
+        _,
+      ) => Err(RuntimeError::InvalidArity),
+      _ => CORE_FUNCTIONS[core_fn_id](args),
+    }
+  }
+  // A fresh machine for running a block on the side, which sees the same
+  // symbols, capabilities, and dynamic bindings as this one
+  pub(super) fn sub_vm(&self, block: Block) -> EvaluationState {
+    let mut state = EvaluationState::new(block)
+      .with_stack_capacity(self.stack_capacity)
+      .with_capabilities(self.capabilities);
+    state.symbol_ledger = self.symbol_ledger.clone();
+    state.environment_generation = self.environment_generation;
+    state.current_frame.dynamic_bindings = self.active_dynamic_bindings();
+    if self.coverage.is_some() {
+      state.enable_coverage();
+    }
+    state.profiler = self.profiler.as_ref().map(Profiler::nested);
+    state.rng = self.rng;
+    state.replay = self.replay.clone();
+    state.methods = self.methods.clone();
+    state.output = self.output.clone();
+    state
+  }
+  // Runs the body of a `try` form, passing the error to the handler if it
+  // fails, and then runs the cleanup, if there is one. Each of them runs in a
+  // machine of its own, so they can't yield from a coroutine that the `try` is
+  // inside of
+  fn try_call(
+    &mut self,
+    body: &Value,
+    handler: &Value,
+    cleanup: &Value,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Value> {
+    let mut result = self.call_function(body, vec![Nil], global_bindings);
+    if let (Err(error), false) = (&result, matches!(handler, Nil)) {
+      result = self.call_function(
+        handler,
+        vec![error.clone().into()],
+        global_bindings,
+      );
+    }
+    if !matches!(cleanup, Nil) {
+      self.call_function(cleanup, vec![Nil], global_bindings)?;
+    }
+    result
+  }
+  // Calls a function from a core function, in a machine of its own
+  fn call_function(
+    &mut self,
+    f: &Value,
+    args: Vec<Value>,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Value> {
+    let mut state = self.sub_vm(Block::for_call(f.clone(), args));
+    let result = state.evaluate(global_bindings);
+    self.symbol_ledger = state.take_symbol_ledger();
+    self.merge_sub_vm(&mut state);
+    Ok(result?.unwrap_or(Nil))
+  }
+  fn sorted_list(&self, collection: &Value) -> RuntimeResult<Value> {
+    let symbol_ledger = self.symbol_ledger.as_deref();
+    let items = match collection {
+      List(list) => list.iter().collect::<Vec<_>>(),
+      Hashset(set) => set.iter().collect(),
+      Nil => vec![],
+      _ => return Err(RuntimeError::ArgumentNotList),
+    };
+    Ok(
+      Value::sorted(items.into_iter(), |item| *item, symbol_ledger)
+        .into_iter()
+        .cloned()
+        .collect(),
+    )
+  }
+  // The items of a list paired with the result of calling `f` on each
+  fn keyed(
+    &mut self,
+    f: &Value,
+    collection: &Value,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Vec<(Value, Value)>> {
+    let items = match collection {
+      List(list) => list.iter().cloned().collect::<Vec<_>>(),
+      Hashset(set) => set.iter().cloned().collect(),
+      Nil => vec![],
+      _ => return Err(RuntimeError::ArgumentNotList),
+    };
+    items
+      .into_iter()
+      .map(|item| {
+        Ok((
+          self.call_function(f, vec![item.clone()], global_bindings)?,
+          item,
+        ))
+      })
+      .collect()
+  }
+  // The item whose key comes first in the total ordering of values, or last
+  // if `max` is set, or nil for an empty collection
+  fn extreme_by_key(
+    &mut self,
+    f: &Value,
+    collection: &Value,
+    max: bool,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Value> {
+    let keyed = self.keyed(f, collection, global_bindings)?;
+    let symbol_ledger = self.symbol_ledger.as_deref();
+    let cmp = |(a, _): &&(Value, Value), (b, _): &&(Value, Value)| {
+      a.total_cmp(b, symbol_ledger)
+    };
+    let extreme = if max {
+      keyed.iter().max_by(cmp)
+    } else {
+      keyed.iter().min_by(cmp)
+    };
+    Ok(extreme.map_or(Nil, |(_, item)| item.clone()))
+  }
+  pub(super) fn describe(&self, value: &Value) -> String {
+    value.description(self.symbol_ledger.as_deref())
+  }
+  // Symbols read by `edn/read` are added to the ledger, so the evaluator takes
+  // it back once evaluation finishes
+  pub(crate) fn take_symbol_ledger(&mut self) -> Option<Rc<SymbolLedger>> {
+    self.symbol_ledger.take()
+  }
+  // Calls `callback` with the register and its new value whenever an
+  // instruction or a returning function writes to that register, in any frame
+  pub fn watch_register(
+    &mut self,
+    register: Register,
+    callback: impl Fn(Register, &Value) + 'static,
+  ) {
+    self.register_watches.push((register, Rc::new(callback)));
+  }
+  pub fn unwatch_registers(&mut self) {
+    self.register_watches.clear();
+  }
+  // Sends the lines that `print`, `pprint`, and `time` write to `output`
+  // rather than to standard output
+  pub fn set_output(&mut self, output: impl Fn(&str) + 'static) {
+    self.output = Some(Rc::new(output));
+  }
+  fn write_line(&self, line: &str) {
+    match &self.output {
+      Some(output) => output(line),
+      None => println!("{line}"),
+    }
+  }
+  fn notify_register_watches(&self, register: Register) {
+    let value = self.get_register(register);
+    for (watched_register, callback) in &self.register_watches {
+      if *watched_register == register {
+        callback(register, value);
+      }
+    }
+  }
+  // Starts recording which instructions run, including those run by the
+  // machines that core functions like `pmap` start
+  pub fn enable_coverage(&mut self) {
+    self.coverage.get_or_insert_with(Default::default);
+  }
+  pub fn coverage(&self) -> Option<&Coverage> {
+    self.coverage.as_ref()
+  }
+  // Starts recording how often each instruction runs and how long it takes
+  pub fn enable_profiling(&mut self) {
+    self.profiler.get_or_insert_with(Default::default);
+  }
+  // Registers `f` as the method `name` for external objects of the type
+  // `type_id`, with the object as its first argument
+  pub(crate) fn define_method(
+    &mut self,
+    type_id: TypeId,
+    name: String,
+    f: ExternalFunction,
+  ) {
+    Rc::make_mut(&mut self.methods).define(type_id, name, Rc::new(f));
+  }
+  pub fn seed_rng(&mut self, seed: u64) {
+    self.rng = Rng::seeded(seed);
+  }
+  pub fn rng(&self) -> Rng {
+    self.rng
+  }
+  // Starts recording the run's inputs into a trace, replacing any trace
+  // that was being recorded or replayed
+  pub fn start_recording(&mut self) {
+    *self.replay.borrow_mut() = Replay::Recording(Trace {
+      seed: self.rng.state(),
+      events: vec![],
+    });
+  }
+  // Starts feeding the inputs of a recorded trace to the run, from the start
+  pub fn start_replay(&mut self, trace: Trace) {
+    self.rng = Rng::seeded(trace.seed);
+    *self.replay.borrow_mut() = Replay::Replaying(trace, 0);
+  }
+  // Stops recording or replaying, returning the trace being recorded, or
+  // what's left of the trace being replayed
+  pub fn stop_tracing(&mut self) -> Option<Trace> {
+    match std::mem::take(&mut *self.replay.borrow_mut()) {
+      Replay::Off => None,
+      Replay::Recording(trace) => Some(trace),
+      Replay::Replaying(mut trace, next) => {
+        trace.events.drain(..next);
+        Some(trace)
+      }
     }
   }
+  // Host functions are called through this, so that their results can be
+  // recorded and replayed
+  fn call_external(
+    &self,
+    f: &ExternalFunction,
+    args: Vec<Value>,
+  ) -> RuntimeResult<Value> {
+    if let Some(result) = self.replay.borrow_mut().replayed_call(f) {
+      return result;
+    }
+    // the function isn't called while the trace is borrowed, in case it
+    // starts a run of its own
+    let result = f.call(args);
+    self.replay.borrow_mut().record_call(f, &result);
+    result
+  }
+  pub fn profile(&self) -> Option<&Profile> {
+    self.profiler.as_ref().map(|profiler| &profiler.profile)
+  }
+  // Adds what a machine started by `sub_vm` recorded to this one's coverage
+  // and profile, and takes back the random number generator
+  pub(super) fn merge_sub_vm(&mut self, sub_vm: &mut EvaluationState) {
+    self.rng = sub_vm.rng;
+    if let (Some(coverage), Some(sub_vm_coverage)) =
+      (&mut self.coverage, sub_vm.coverage.take())
+    {
+      coverage.merge(sub_vm_coverage);
+    }
+    if let (Some(profiler), Some(sub_vm_profiler)) =
+      (&mut self.profiler, sub_vm.profiler.take())
+    {
+      profiler.merge_nested(sub_vm_profiler);
+    }
+  }
+  // Counts the values that the machine's stacks and running code can reach.
+  // Globals are passed to `evaluate` rather than held by the machine, so
+  // they're left out
+  pub fn heap_stats(&self) -> HeapStats {
+    let mut walker = HeapWalker::default();
+    self.walk_heap(&mut walker);
+    walker.stats
+  }
+  pub(crate) fn walk_heap(&self, walker: &mut HeapWalker) {
+    walker.block_constants(&self.current_frame.block);
+    walker.coroutine_state(&self.current_coroutine);
+    for (_, paused_coroutine) in &self.parent_coroutine_stack {
+      walker.coroutine_state(&paused_coroutine.state);
+    }
+    walker.values(self.host_stack.iter());
+    walker.stats.peak_stack_depth = self.peak_stack_depth;
+  }
+  // The frames of the running coroutine, innermost first. Frames are left in
+  // place when a run fails, so until the machine is reset this shows where
+  // the error happened
+  pub fn frames(&self) -> Vec<FrameView> {
+    std::iter::once(&self.current_frame)
+      .chain(self.current_coroutine.paused_frames.iter().rev())
+      .map(|frame| {
+        let instruction_index = frame.instruction_index.saturating_sub(1);
+        FrameView {
+          instruction_index,
+          instruction: frame.block.instructions.get(instruction_index).cloned(),
+          registers: self
+            .current_coroutine
+            .stack
+            .get(frame.beginning as usize..frame.end())
+            .unwrap_or_default()
+            .to_vec(),
+        }
+      })
+      .collect()
+  }
+  pub fn into_interner(mut self) -> StringInterner {
+    std::mem::take(&mut self.string_interner)
+  }
   fn describe_stack(&self) -> String {
     self
       .current_coroutine
@@ -75,11 +700,7 @@ impl EvaluationState {
           indent_lines(
             7,
             (start..=end)
-              .map(|i| format!(
-                "{}: {}",
-                i,
-                self.get_stack(i).description(None)
-              ))
+              .map(|i| format!("{}: {}", i, self.describe(self.get_stack(i))))
               .collect::<Vec<String>>()
               .join("\n")
           ),
@@ -124,6 +745,18 @@ impl EvaluationState {
   fn return_value(&mut self, value: Value) -> Option<Value> {
     if let Some(completed_frame) = self.complete_frame() {
       self.set_stack(completed_frame.return_stack_index, value);
+      if !self.register_watches.is_empty() {
+        if let Some(register) = completed_frame
+          .return_stack_index
+          .checked_sub(self.current_frame.beginning)
+          .and_then(|register| Register::try_from(register).ok())
+        {
+          self.notify_register_watches(register);
+        }
+      }
+      self
+        .current_coroutine
+        .shrink_stack(self.current_frame.end(), self.stack_capacity);
       None
     } else {
       Some(value)
@@ -135,7 +768,14 @@ impl EvaluationState {
     new_arg_count_and_offset: Option<(AritySpecifier, u8)>,
     kill: bool,
   ) {
-    let return_stack_index = self.current_frame.return_stack_index;
+    // Values are yielded to wherever the coroutine's outermost frame would
+    // return to, even when they're yielded from a function it called
+    let return_stack_index = self
+      .current_coroutine
+      .paused_frames
+      .first()
+      .unwrap_or(&self.current_frame)
+      .return_stack_index;
     let (child_coroutine_stack_index, parent_coroutine) = self
       .parent_coroutine_stack
       .pop()
@@ -212,6 +852,9 @@ impl EvaluationState {
   }
   fn set_register<T: Into<Value>>(&mut self, register: Register, value: T) {
     self.set_stack(self.register_stack_index(register), value.into());
+    if !self.register_watches.is_empty() {
+      self.notify_register_watches(register);
+    }
   }
   fn swap_register<T: Into<Value>>(
     &mut self,
@@ -229,24 +872,50 @@ impl EvaluationState {
   pub(crate) fn get_register_mut(&mut self, register: Register) -> &mut Value {
     self.get_stack_mut(self.register_stack_index(register))
   }
+  // The list in a register that an instruction has been marked as holding the
+  // only reference to. The compiler guarantees this, so a shared list means
+  // the block was built by hand incorrectly
+  fn get_unique_list(
+    &mut self,
+    register: Register,
+  ) -> RuntimeResult<&mut ListStorage<Value>> {
+    match self.get_register_mut(register) {
+      List(list) => Rc::get_mut(list).ok_or(RuntimeError::SharedUniqueList),
+      _ => Err(RuntimeError::ArgumentNotList),
+    }
+  }
+  // Grows the stack to fit a new frame, failing if the frame would reach past
+  // the last index the stack can address
+  fn reserve_frame(&mut self, frame: &StackFrame) -> RuntimeResult<()> {
+    if frame.end() > MAX_STACK_SIZE {
+      return Err(RuntimeError::StackOverflow);
+    }
+    self.current_coroutine.reserve_stack(frame.end());
+    self.peak_stack_depth = self.peak_stack_depth.max(frame.end());
+    Ok(())
+  }
   fn create_fn_stack_frame(
     &mut self,
-    f: Rc<CompositeFunction>,
+    f: &CompositeFunction,
     return_stack_index: StackIndex,
-  ) -> StackFrame {
-    StackFrame::for_fn(
-      f,
-      self.current_frame.beginning + self.current_frame.stack_consumption() + 1,
-      return_stack_index,
-    )
+  ) -> RuntimeResult<StackFrame> {
+    let beginning = self.current_frame.end();
+    if beginning >= MAX_STACK_SIZE {
+      return Err(RuntimeError::StackOverflow);
+    }
+    let frame =
+      StackFrame::for_fn(f, beginning as StackIndex, return_stack_index)?;
+    self.reserve_frame(&frame)?;
+    Ok(frame)
   }
   fn start_fn_stack_frame(
     &mut self,
-    f: Rc<CompositeFunction>,
+    f: &CompositeFunction,
     return_stack_index: StackIndex,
-  ) {
-    let frame = self.create_fn_stack_frame(f, return_stack_index);
+  ) -> RuntimeResult<()> {
+    let frame = self.create_fn_stack_frame(f, return_stack_index)?;
     self.push_frame(frame);
+    Ok(())
   }
   fn next_instruction(&mut self) -> Instruction {
     self.current_frame.next_instruction()
@@ -277,72 +946,123 @@ impl EvaluationState {
   }
   fn move_args_from(
     &mut self,
-    arg_count: u8,
+    args: &[Argument<Register>],
     beginning_stack_index: StackIndex,
-    frame: &mut StackFrame,
+    frame: &StackFrame,
   ) {
-    for i in 0..arg_count {
-      match frame.next_instruction() {
-        CopyArgument(arg_register) => {
-          let value = self
-            .get_stack(frame.beginning + arg_register as StackIndex)
-            .clone();
-          self.set_stack(beginning_stack_index + i as StackIndex, value)
-        }
+    for (i, arg) in args.iter().enumerate() {
+      let value = match *arg {
+        CopyArgument(arg_register) => self
+          .get_stack(frame.beginning + arg_register as StackIndex)
+          .clone(),
         StealArgument(arg_register) => {
-          let stolen_value = self
-            .steal_stack(frame.beginning + arg_register as StackIndex)
-            .clone();
-          self.set_stack(beginning_stack_index + i as StackIndex, stolen_value)
+          self.steal_stack(frame.beginning + arg_register as StackIndex)
         }
-        other => panic!(
-          "Expected CopyArgument or StealArgument instruction {}/{},
-           found {:?}",
-          i + 1,
-          arg_count,
-          other
-        ),
-      }
+      };
+      self.set_stack(beginning_stack_index + i as StackIndex, value)
     }
   }
-  fn move_args(&mut self, arg_count: u8, beginning_stack_index: StackIndex) {
-    for i in 0..arg_count {
-      match self.next_instruction() {
-        CopyArgument(arg_register) => {
-          let value = self.get_register(arg_register).clone();
-          self.set_stack(beginning_stack_index + i as StackIndex, value)
-        }
-        StealArgument(arg_register) => {
-          let stolen_value = self.steal_register(arg_register).clone();
-          self.set_stack(beginning_stack_index + i as StackIndex, stolen_value)
-        }
-        other => panic!(
-          "Expected CopyArgument or StealArgument instruction {}/{},
-           found {:?}",
-          i + 1,
-          arg_count,
-          other
-        ),
-      }
+  fn move_args(
+    &mut self,
+    args: &[Argument<Register>],
+    beginning_stack_index: StackIndex,
+  ) {
+    for (i, arg) in args.iter().enumerate() {
+      let value = match *arg {
+        CopyArgument(arg_register) => self.get_register(arg_register).clone(),
+        StealArgument(arg_register) => self.steal_register(arg_register),
+      };
+      self.set_stack(beginning_stack_index + i as StackIndex, value)
     }
   }
-  fn take_args(&mut self, arg_count: u8) -> Vec<Value> {
-    (0..arg_count)
-      .map(|i| match self.next_instruction() {
+  fn take_args(&mut self, args: &[Argument<Register>]) -> Vec<Value> {
+    args
+      .iter()
+      .map(|arg| match *arg {
         CopyArgument(arg_register) => self.get_register(arg_register).clone(),
+        StealArgument(arg_register) => self.steal_register(arg_register),
+      })
+      .collect()
+  }
+  // Finds the innermost `binding` of a dynamic var, searching outward through
+  // the frames of the current coroutine and then those of its parents
+  fn dynamic_binding(&self, symbol_index: SymbolIndex) -> Option<&Value> {
+    self
+      .dynamic_bindings()
+      .find(|(bound_symbol, _)| *bound_symbol == symbol_index)
+      .map(|(_, value)| value)
+  }
+  // Every dynamic binding in effect, innermost first
+  fn dynamic_bindings(&self) -> impl Iterator<Item = &(SymbolIndex, Value)> {
+    std::iter::once(&self.current_frame)
+      .chain(self.current_coroutine.paused_frames.iter().rev())
+      .chain(
+        self
+          .parent_coroutine_stack
+          .iter()
+          .rev()
+          .flat_map(|(_, parent)| parent.state.paused_frames.iter().rev()),
+      )
+      .flat_map(|frame| frame.dynamic_bindings.iter().rev())
+  }
+  fn active_dynamic_bindings(&self) -> Vec<(SymbolIndex, Value)> {
+    let mut bindings: Vec<_> = self.dynamic_bindings().cloned().collect();
+    bindings.reverse();
+    bindings
+  }
+  // Protocol fns are replaced by the implementation for their first argument
+  fn resolve_protocol_fn(
+    &self,
+    f: Value,
+    args: &[Argument<Register>],
+  ) -> RuntimeResult<Value> {
+    match f {
+      ProtocolFn(protocol_fn) => protocol_fn
+        .resolve(args.first().map(|arg| self.get_register(*arg.register()))),
+      f => Ok(f),
+    }
+  }
+  fn take_args_from(
+    &mut self,
+    args: &[Argument<Register>],
+    frame: &StackFrame,
+  ) -> Vec<Value> {
+    args
+      .iter()
+      .map(|arg| match *arg {
+        CopyArgument(arg_register) => self
+          .get_stack(frame.beginning + arg_register as StackIndex)
+          .clone(),
         StealArgument(arg_register) => {
-          self.steal_register(arg_register).clone()
+          self.steal_stack(frame.beginning + arg_register as StackIndex)
         }
-        other => panic!(
-          "Expected CopyArgument or StealArgument instruction {}/{},
-           found {:?}",
-          i + 1,
-          arg_count,
-          other
-        ),
       })
       .collect()
   }
+  // Arguments to a coroutine are written straight into the stack of the frame
+  // it'll resume in, so they don't need to be gathered up first
+  fn move_args_into_coroutine(
+    &mut self,
+    args: &[Argument<Register>],
+    coroutine: &mut PausedCoroutine,
+  ) {
+    let beginning = coroutine
+      .state
+      .paused_frames
+      .last()
+      .expect(
+        "attempting to pass arguments to a PausedCoroutine with no frames",
+      )
+      .beginning
+      + coroutine.arg_offset as StackIndex;
+    for (i, arg) in args.iter().enumerate() {
+      coroutine.state.stack[(beginning + i as StackIndex) as usize] = match *arg
+      {
+        CopyArgument(arg_register) => self.get_register(arg_register).clone(),
+        StealArgument(arg_register) => self.steal_register(arg_register),
+      };
+    }
+  }
   fn set_args(&mut self, args: Vec<Value>, arg_offset: u8) {
     for (i, arg_value) in args.into_iter().enumerate() {
       self.set_register(i as Register + arg_offset, arg_value);
@@ -353,13 +1073,14 @@ impl EvaluationState {
     result_register: Register,
     f: &Value,
     args: Vec<Value>,
+    global_bindings: &HashMap<SymbolIndex, Value>,
   ) -> RuntimeResult<()> {
     match f {
       CompositeFn(composite_fn) => {
         self.start_fn_stack_frame(
-          composite_fn.clone(),
+          composite_fn,
           self.register_stack_index(result_register),
-        );
+        )?;
         let provided_arg_count = args.len();
         #[cfg(debug_assertions)]
         if !composite_fn.args.can_accept(provided_arg_count) {
@@ -371,18 +1092,26 @@ impl EvaluationState {
         }
         self.set_args(args, 0);
       }
-      CoreFn(core_fn_id) => match CORE_FUNCTIONS[*core_fn_id](args) {
-        Ok(value) => self.set_register(result_register, value),
-        Err(error) => return Err(error),
-      },
+      CoreFn(core_fn_id) => {
+        match self.call_core_fn(*core_fn_id, args, global_bindings) {
+          Ok(value) => self.set_register(result_register, value),
+          Err(error) => return Err(error),
+        }
+      }
       ExternalFn(external_fn) => {
-        let f = (*external_fn).f;
-        self.set_register(
+        let value = self.call_external(external_fn, args)?;
+        self.set_register(result_register, value)
+      }
+      RecordFn(record_fn) => {
+        self.set_register(result_register, record_fn.call(args)?)
+      }
+      ProtocolFn(protocol_fn) => {
+        let implementation = protocol_fn.resolve(args.first())?;
+        return self.apply(
           result_register,
-          f(args).expect(
-            "external_fn returned an error, and we don't have error \
-            handling yet :(",
-          ),
+          &implementation,
+          args,
+          global_bindings,
         );
       }
       PartialApplication(f_and_args) => todo!(),
@@ -401,13 +1130,42 @@ impl EvaluationState {
     &mut self,
     global_bindings: &HashMap<SymbolIndex, Value>,
   ) -> RuntimeResult<Option<Value>> {
+    let result = self.run_instructions(global_bindings);
+    if let Some(profiler) = &mut self.profiler {
+      profiler.stop(Instant::now());
+    }
+    result
+  }
+  fn run_instructions(
+    &mut self,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Option<Value>> {
+    // Instructions are borrowed from a handle on the running block rather
+    // than cloned out of the frame, and the handle is only replaced when a
+    // call or return switches to a different block
+    let mut instructions = self.current_frame.block.instructions.clone();
     loop {
-      if self.current_frame.instruction_index >= self.current_frame.block.len()
-      {
+      if !Rc::ptr_eq(&instructions, &self.current_frame.block.instructions) {
+        instructions = self.current_frame.block.instructions.clone();
+      }
+      let Some(instruction) =
+        instructions.get(self.current_frame.instruction_index)
+      else {
         break;
+      };
+      if let Some(coverage) = &mut self.coverage {
+        coverage.record(&instructions, self.current_frame.instruction_index);
+      }
+      if let Some(profiler) = &mut self.profiler {
+        profiler.record(
+          &instructions,
+          self.current_frame.instruction_index,
+          &self.current_coroutine.paused_frames,
+        );
       }
+      self.current_frame.instruction_index += 1;
       let instruction_result: RuntimeResult<Option<Value>> = 'instruction: {
-        match self.next_instruction() {
+        match *instruction {
           DebugPrint(id) => {
             println!(
               "{}\n\
@@ -430,7 +1188,10 @@ impl EvaluationState {
             );
           }
           Print(value) => {
-            println!("{}", self.get_register(value).description(None))
+            self.write_line(&self.get_register(value).pretty_description(
+              self.symbol_ledger.as_deref(),
+              &PrettyPrintOptions::default(),
+            ))
           }
           Return(value) => {
             let return_value = self.steal_register(value);
@@ -438,44 +1199,49 @@ impl EvaluationState {
               break 'instruction Ok(Some(final_value));
             }
           }
-          CopyArgument(_) => {
-            panic!("CopyArgument instruction called, this should never happen")
-          }
-          StealArgument(_) => {
-            panic!("CopyArgument instruction called, this should never happen")
-          }
-          Call(target, f, arg_count) => {
-            let f_value = self.get_register(f).clone();
+          Call(target, f, ref args) => {
+            let f_value = match self
+              .resolve_protocol_fn(self.get_register(f).clone(), args)
+            {
+              Ok(f_value) => f_value,
+              Err(e) => break 'instruction Err(e),
+            };
             match f_value {
               CompositeFn(composite_fn) => {
-                let new_frame = self.create_fn_stack_frame(
-                  composite_fn,
+                let new_frame = match self.create_fn_stack_frame(
+                  &composite_fn,
                   self.register_stack_index(target),
-                );
-                self.move_args(arg_count, new_frame.beginning);
+                ) {
+                  Ok(new_frame) => new_frame,
+                  Err(e) => break 'instruction Err(e),
+                };
+                self.move_args(args, new_frame.beginning);
                 self.push_frame(new_frame);
               }
               CoreFn(f) => {
-                let args = self.take_args(arg_count);
-                match CORE_FUNCTIONS[f](args) {
+                let args = self.take_args(args);
+                match self.call_core_fn(f, args, global_bindings) {
                   Ok(output) => self.set_register(target, output),
                   Err(e) => break 'instruction Err(e),
                 }
               }
               ExternalFn(external_fn) => {
-                let args = self.take_args(arg_count);
-                let f = (*external_fn).f;
-                self.set_register(
-                  target,
-                  f(args).expect(
-                    "external_fn returned an error, and we don't have error \
-                    handling yet :(",
-                  ),
-                );
+                let args = self.take_args(args);
+                match self.call_external(&external_fn, args) {
+                  Ok(output) => self.set_register(target, output),
+                  Err(e) => break 'instruction Err(e),
+                }
+              }
+              RecordFn(record_fn) => {
+                let args = self.take_args(args);
+                match record_fn.call(args) {
+                  Ok(output) => self.set_register(target, output),
+                  Err(e) => break 'instruction Err(e),
+                }
               }
               PartialApplication(f_and_args) => {
                 let (partial_f, partial_args) = &*f_and_args;
-                let args = self.take_args(arg_count);
+                let args = self.take_args(args);
                 if let Err(err) = self.apply(
                   target,
                   partial_f,
@@ -484,18 +1250,19 @@ impl EvaluationState {
                     .cloned()
                     .chain(args.into_iter())
                     .collect(),
+                  global_bindings,
                 ) {
                   break 'instruction Err(err);
                 }
               }
               Composition(fs) => {
-                let args = self.take_args(arg_count);
+                let args = self.take_args(args);
                 let mut f_iter = fs.iter();
                 if let Some(first_f) = f_iter.next() {
-                  self.apply(target, first_f, args)?;
+                  self.apply(target, first_f, args, global_bindings)?;
                   for next_f in f_iter {
                     let value = self.steal_register(target);
-                    self.apply(target, next_f, vec![value])?;
+                    self.apply(target, next_f, vec![value], global_bindings)?;
                   }
                 } else {
                   self.set_register(
@@ -506,18 +1273,16 @@ impl EvaluationState {
               }
               Coroutine(maybe_coroutine) => {
                 if let Some(coroutine_ref) = &*maybe_coroutine {
-                  if let Some(coroutine) = coroutine_ref.replace(None) {
-                    if !coroutine.args.can_accept(arg_count as usize) {
+                  if let Some(mut coroutine) = coroutine_ref.replace(None) {
+                    if !coroutine.args.can_accept(args.len()) {
                       break 'instruction Err(RuntimeError::InvalidArity);
                     }
-                    let args = self.take_args(arg_count);
-                    let arg_offset = coroutine.arg_offset;
+                    self.move_args_into_coroutine(args, &mut coroutine);
                     self.push_child_coroutine(
                       coroutine,
                       self.register_stack_index(f),
                       self.register_stack_index(target),
                     );
-                    self.set_args(args, arg_offset);
                   } else {
                     break 'instruction Err(
                       RuntimeError::CoroutineAlreadyRunning,
@@ -541,32 +1306,61 @@ impl EvaluationState {
               if let Err(err) = self.apply(
                 args_and_result,
                 &f_value,
-                Rc::unwrap_or_clone(arg_list),
+                Rc::unwrap_or_clone(arg_list).into_iter().collect(),
+                global_bindings,
               ) {
                 break 'instruction Err(err);
               }
             } else {
-              panic!("Apply called with non-List value");
+              break 'instruction Err(RuntimeError::ArgumentNotList);
             }
           }
-          CallAndReturn(f, arg_count) => {
-            let f_value = self.get_register(f).clone();
-            if let Some(mut completed_frame) = self.complete_frame() {
+          CallAndReturn(f, ref args) => {
+            let f_value = match self
+              .resolve_protocol_fn(self.get_register(f).clone(), args)
+            {
+              Ok(f_value) => f_value,
+              Err(e) => break 'instruction Err(e),
+            };
+            if let Some(completed_frame) = self.complete_frame() {
               match f_value {
                 CompositeFn(composite_fn) => {
-                  let new_frame = StackFrame::for_fn(
-                    composite_fn,
+                  let new_frame = match StackFrame::for_fn(
+                    &composite_fn,
                     completed_frame.beginning,
                     completed_frame.return_stack_index,
-                  );
+                  ) {
+                    Ok(new_frame) => new_frame,
+                    Err(e) => break 'instruction Err(e),
+                  };
+                  if let Err(e) = self.reserve_frame(&new_frame) {
+                    break 'instruction Err(e);
+                  }
                   self.move_args_from(
-                    arg_count,
+                    args,
                     new_frame.beginning,
-                    &mut completed_frame,
+                    &completed_frame,
                   );
                   self.push_frame(new_frame);
                 }
-                ExternalFn(_) => todo!(),
+                ExternalFn(external_fn) => {
+                  let args = self.take_args_from(args, &completed_frame);
+                  match self.call_external(&external_fn, args) {
+                    Ok(output) => {
+                      self.set_stack(completed_frame.return_stack_index, output)
+                    }
+                    Err(e) => break 'instruction Err(e),
+                  }
+                }
+                RecordFn(record_fn) => {
+                  let args = self.take_args_from(args, &completed_frame);
+                  match record_fn.call(args) {
+                    Ok(output) => {
+                      self.set_stack(completed_frame.return_stack_index, output)
+                    }
+                    Err(e) => break 'instruction Err(e),
+                  }
+                }
                 CoreFn(_) => {
                   panic!(
                     "CallAndReturn instruction called with CoreFn value, this \
@@ -590,11 +1384,68 @@ impl EvaluationState {
             todo!()
           }
           Lookup(register, symbol_index) => {
-            self.set_register(register, global_bindings[&symbol_index].clone());
+            let index = self.current_frame.instruction_index - 1;
+            let cache = &self.current_frame.block.lookup_cache;
+            let cached = self
+              .environment_generation
+              .and_then(|generation| cache.get(index, generation));
+            let value = match cached {
+              Some(value) => value,
+              None => {
+                let Some(value) = global_bindings.get(&symbol_index) else {
+                  break 'instruction Err(RuntimeError::UndefinedGlobal(
+                    Symbol(symbol_index),
+                  ));
+                };
+                if let Some(generation) = self.environment_generation {
+                  cache.insert(index, generation, value.clone());
+                }
+                value.clone()
+              }
+            };
+            self.set_register(register, value);
+          }
+          LookupDynamic(register, symbol_index) => {
+            let Some(value) = self
+              .dynamic_binding(symbol_index)
+              .or_else(|| global_bindings.get(&symbol_index))
+            else {
+              break 'instruction Err(RuntimeError::UndefinedGlobal(Symbol(
+                symbol_index,
+              )));
+            };
+            self.set_register(register, value.clone());
+          }
+          PushDynamicBinding(symbol_index, value) => {
+            let value = self.get_register(value).clone();
+            self
+              .current_frame
+              .dynamic_bindings
+              .push((symbol_index, value));
+          }
+          PopDynamicBindings(count) => {
+            let dynamic_bindings = &mut self.current_frame.dynamic_bindings;
+            dynamic_bindings.truncate(dynamic_bindings.len() - count as usize);
           }
           Jump(instruction_index) => {
             self.current_frame.instruction_index = instruction_index as usize;
           }
+          RelativeJump(offset) => {
+            // the instruction index has already moved past the jump
+            self.current_frame.instruction_index =
+              (self.current_frame.instruction_index as isize - 1
+                + offset as isize) as usize;
+          }
+          JumpIf(condition, instruction_index) => {
+            if self.get_register(condition).as_bool() {
+              self.current_frame.instruction_index = instruction_index as usize;
+            }
+          }
+          JumpIfNot(condition, instruction_index) => {
+            if !self.get_register(condition).as_bool() {
+              self.current_frame.instruction_index = instruction_index as usize;
+            }
+          }
           If(condition) => {
             if !self.get_register(condition).as_bool() {
               // skip to next Else, ElseIf, or EndIf instruction
@@ -640,8 +1491,7 @@ impl EvaluationState {
           IsZero(result, num) => self.set_register(
             result,
             match self.get_register(num) {
-              Number(Float(f)) => *f == 0.,
-              Number(Int(i)) => *i == 0,
+              Number(n) => n.is_zero(),
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
             },
           ),
@@ -649,7 +1499,7 @@ impl EvaluationState {
             result,
             match self.get_register(num) {
               Number(Float(f)) => f.is_nan(),
-              Number(Int(_)) => false,
+              Number(_) => false,
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
             },
           ),
@@ -657,15 +1507,15 @@ impl EvaluationState {
             result,
             match self.get_register(num) {
               Number(Float(f)) => f.is_infinite(),
-              Number(Int(_)) => false,
+              Number(_) => false,
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
             },
           ),
           IsEven(result, num) => self.set_register(
             result,
             match self.get_register(num) {
-              Number(n) => match n.as_int_lossless() {
-                Ok(i) => i % 2 == 0,
+              Number(n) => match n.is_even() {
+                Ok(even) => even,
                 Err(error) => break 'instruction Err(error),
               },
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
@@ -674,8 +1524,8 @@ impl EvaluationState {
           IsOdd(result, num) => self.set_register(
             result,
             match self.get_register(num) {
-              Number(n) => match n.as_int_lossless() {
-                Ok(i) => i % 2 == 1,
+              Number(n) => match n.is_even() {
+                Ok(even) => !even,
                 Err(error) => break 'instruction Err(error),
               },
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
@@ -684,16 +1534,14 @@ impl EvaluationState {
           IsPos(result, num) => self.set_register(
             result,
             match self.get_register(num) {
-              Number(Float(f)) => **f > 0.,
-              Number(Int(i)) => *i > 0,
+              Number(n) => n.is_positive(),
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
             },
           ),
           IsNeg(result, num) => self.set_register(
             result,
             match self.get_register(num) {
-              Number(Float(f)) => **f < 0.,
-              Number(Int(i)) => *i < 0,
+              Number(n) => n.is_negative(),
               _ => break 'instruction Err(RuntimeError::ArgumentNotNum),
             },
           ),
@@ -714,7 +1562,7 @@ impl EvaluationState {
           Negate(result, num) => self.set_register(
             result,
             match self.get_register(num).as_num() {
-              Ok(n) => -*n,
+              Ok(n) => -n,
               Err(error) => break 'instruction Err(error),
             },
           ),
@@ -777,20 +1625,20 @@ impl EvaluationState {
           Add(result, num_1, num_2) => self.set_register(
             result,
             match self.get_register(num_1).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             } + match self.get_register(num_2).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             },
           ),
           Subtract(result, num_1, num_2) => self.set_register(
             result,
             match self.get_register(num_1).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             } - match self.get_register(num_2).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             },
           ),
@@ -798,10 +1646,10 @@ impl EvaluationState {
             self.set_register(
               result,
               match self.get_register(num_1).as_num() {
-                Ok(n) => *n,
+                Ok(n) => n,
                 Err(error) => break 'instruction Err(error),
               } * match self.get_register(num_2).as_num() {
-                Ok(n) => *n,
+                Ok(n) => n,
                 Err(error) => break 'instruction Err(error),
               },
             );
@@ -809,10 +1657,10 @@ impl EvaluationState {
           Divide(result, num_1, num_2) => self.set_register(
             result,
             match self.get_register(num_1).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             } / match self.get_register(num_2).as_num() {
-              Ok(n) => *n,
+              Ok(n) => n,
               Err(error) => break 'instruction Err(error),
             },
           ),
@@ -825,13 +1673,90 @@ impl EvaluationState {
           GreaterThanOrEqual(result, num_1, num_2) => todo!(),
           LessThan(result, num_1, num_2) => todo!(),
           LessThanOrEqual(result, num_1, num_2) => todo!(),
-          Rand(result) => todo!(),
-          UpperBoundedRand(result, upper_bound) => todo!(),
+          BitAnd(result, num_1, num_2) => self.set_register(
+            result,
+            match self
+              .get_register(num_1)
+              .as_num()
+              .and_then(|a| a.bit_and(self.get_register(num_2).as_num()?))
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          BitOr(result, num_1, num_2) => self.set_register(
+            result,
+            match self
+              .get_register(num_1)
+              .as_num()
+              .and_then(|a| a.bit_or(self.get_register(num_2).as_num()?))
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          BitXor(result, num_1, num_2) => self.set_register(
+            result,
+            match self
+              .get_register(num_1)
+              .as_num()
+              .and_then(|a| a.bit_xor(self.get_register(num_2).as_num()?))
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          BitNot(result, num) => self.set_register(
+            result,
+            match self.get_register(num).as_num().and_then(Num::bit_not) {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          ShiftLeft(result, num_1, num_2) => self.set_register(
+            result,
+            match self
+              .get_register(num_1)
+              .as_num()
+              .and_then(|a| a.shift_left(self.get_register(num_2).as_num()?))
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          ShiftRight(result, num_1, num_2) => self.set_register(
+            result,
+            match self
+              .get_register(num_1)
+              .as_num()
+              .and_then(|a| a.shift_right(self.get_register(num_2).as_num()?))
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          Rand(result) => {
+            let value = self.rng.next_f64();
+            self.set_register(result, value);
+          }
+          UpperBoundedRand(result, upper_bound) => {
+            let args = [self.get_register(upper_bound).clone()];
+            match call_random_fn(CoreFnId::Rand, &args, &mut self.rng) {
+              Ok(value) => self.set_register(result, value),
+              Err(error) => break 'instruction Err(error),
+            }
+          }
           LowerUpperBoundedRand(result, lower_bound, upper_bound) => todo!(),
           RandInt(result, upper_bound) => todo!(),
           LowerBoundedRandInt(result, lower_bound, upper_bound) => todo!(),
-          Equal(result, value_1, value_2) => todo!(),
-          NotEqual(result, value_1, value_2) => todo!(),
+          Equal(result, value_1, value_2) => self.set_register(
+            result,
+            self.get_register(value_1) == self.get_register(value_2),
+          ),
+          NotEqual(result, value_1, value_2) => self.set_register(
+            result,
+            self.get_register(value_1) != self.get_register(value_2),
+          ),
           Not(result, value) => todo!(),
           And(result, bool_1, bool_2) => todo!(),
           Or(result, bool_1, bool_2) => todo!(),
@@ -849,7 +1774,7 @@ impl EvaluationState {
           First(result, collection) => self.set_register(
             result,
             match self.get_register(collection) {
-              List(list) => list.first().cloned().unwrap_or(Nil),
+              List(list) => list.front().cloned().unwrap_or(Nil),
               Hashset(set) => set.iter().next().cloned().unwrap_or(Nil),
               Hashmap(hashmap) => hashmap
                 .iter()
@@ -875,7 +1800,13 @@ impl EvaluationState {
           MultiCollectionMap(list_of_collections_and_result, f) => todo!(),
           Set(collection_and_result, value, key) => todo!(),
           SetIn(collection_and_result, value, path) => todo!(),
-          Get(result, collection, key) => todo!(),
+          Get(result, collection, key) => self.set_register(
+            result,
+            match self.get_register(collection).get(self.get_register(key)) {
+              Ok(value) => value,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
           GetIn(result, collection, path) => todo!(),
           Update(collection_and_result, f, key) => todo!(),
           UpdateIn(collection_and_result, f, path) => todo!(),
@@ -886,17 +1817,8 @@ impl EvaluationState {
             let collection_value = self.steal_register(collection_and_result);
             match collection_value {
               List(mut list_value) => {
-                self.set_register(
-                  collection_and_result,
-                  if let Some(owned_list_value) = Rc::get_mut(&mut list_value) {
-                    owned_list_value.push(value);
-                    list_value
-                  } else {
-                    let mut list_value_clone = (*list_value).clone();
-                    list_value_clone.push(value);
-                    Rc::new(list_value_clone)
-                  },
-                );
+                Rc::make_mut(&mut list_value).push_back(value);
+                self.set_register(collection_and_result, list_value);
               }
               Hashmap(hashmap) => todo!(),
               Hashset(set) => todo!(),
@@ -904,7 +1826,13 @@ impl EvaluationState {
               _ => break 'instruction Err(RuntimeError::ArgumentNotList),
             };
           }
-          Sort(collection_and_result) => todo!(),
+          Sort(collection_and_result) => {
+            let collection = self.steal_register(collection_and_result);
+            match self.sorted_list(&collection) {
+              Ok(sorted) => self.set_register(collection_and_result, sorted),
+              Err(error) => break 'instruction Err(error),
+            }
+          }
           SortBy(collection_and_result, f) => todo!(),
           EmptyList(result) => {
             self.set_register(result, Vec::new());
@@ -912,7 +1840,7 @@ impl EvaluationState {
           Last(result, list) => self.set_register(
             result,
             match self.get_register(list) {
-              List(list) => list.last().cloned().unwrap_or(Nil),
+              List(list) => list.back().cloned().unwrap_or(Nil),
               Nil => Nil,
               _ => break 'instruction Err(RuntimeError::ArgumentNotList),
             },
@@ -921,19 +1849,8 @@ impl EvaluationState {
             let list_value = self.steal_register(list_and_result);
             match list_value {
               List(mut list_value) => {
-                self.set_register(
-                  list_and_result,
-                  if let Some(owned_list_value) = Rc::get_mut(&mut list_value) {
-                    if !owned_list_value.is_empty() {
-                      owned_list_value.remove(0);
-                    }
-                    list_value
-                  } else {
-                    let mut list_value_clone = (*list_value).clone();
-                    list_value_clone.remove(0);
-                    Rc::new(list_value_clone)
-                  },
-                );
+                Rc::make_mut(&mut list_value).pop_front();
+                self.set_register(list_and_result, list_value);
               }
               Nil => self.set_register(list_and_result, Nil),
               _ => break 'instruction Err(RuntimeError::ArgumentNotList),
@@ -943,17 +1860,8 @@ impl EvaluationState {
             let list_value = self.steal_register(list_and_result);
             match list_value {
               List(mut list_value) => {
-                self.set_register(
-                  list_and_result,
-                  if let Some(owned_list_value) = Rc::get_mut(&mut list_value) {
-                    owned_list_value.pop();
-                    list_value
-                  } else {
-                    let mut list_value_clone = (*list_value).clone();
-                    list_value_clone.pop();
-                    Rc::new(list_value_clone)
-                  },
-                );
+                Rc::make_mut(&mut list_value).pop_back();
+                self.set_register(list_and_result, list_value);
               }
               Nil => self.set_register(list_and_result, Nil),
               _ => break 'instruction Err(RuntimeError::ArgumentNotList),
@@ -970,32 +1878,8 @@ impl EvaluationState {
             let collection_value = self.steal_register(list_and_result);
             match collection_value {
               List(mut list_value) => {
-                self.set_register(
-                  list_and_result,
-                  if list_value.is_empty() {
-                    if let Some(owned_list_value) = Rc::get_mut(&mut list_value)
-                    {
-                      owned_list_value.push(value);
-                      list_value
-                    } else {
-                      let mut list_value_clone = (*list_value).clone();
-                      list_value_clone.push(value);
-                      Rc::new(list_value_clone)
-                    }
-                  } else {
-                    if let Some(owned_list_value) = Rc::get_mut(&mut list_value)
-                    {
-                      owned_list_value.insert(0, value);
-                      list_value
-                    } else {
-                      Rc::new(
-                        std::iter::once(value)
-                          .chain((*list_value).iter().cloned())
-                          .collect(),
-                      )
-                    }
-                  },
-                );
+                Rc::make_mut(&mut list_value).push_front(value);
+                self.set_register(list_and_result, list_value);
               }
               Hashmap(hashmap) => todo!(),
               Hashset(set) => todo!(),
@@ -1036,17 +1920,37 @@ impl EvaluationState {
           BoundedRepeatedly(result, f, count) => todo!(),
           InfiniteIterate(result, f, initial_value) => todo!(),
           BoundedIterate(bound_and_result, f, initial_value) => todo!(),
-          CreateCell(result) => todo!(),
-          GetCellValue(result, cell) => todo!(),
-          SetCellValue(result, value) => todo!(),
-          UpdateCell(result, f) => todo!(),
+          CreateCell(result, value) => {
+            let value = self.get_register(value).clone();
+            self.set_register(result, Cell(Rc::new(RefCell::new(value))))
+          }
+          GetCellValue(result, cell) => match self.get_register(cell) {
+            Cell(cell) => {
+              let value = cell.borrow().clone();
+              self.set_register(result, value)
+            }
+            _ => break 'instruction Err(RuntimeError::ArgumentNotCell),
+          },
+          SetCellValue(cell, value) => match self.get_register(cell) {
+            Cell(cell) => {
+              *cell.borrow_mut() = self.get_register(value).clone();
+            }
+            _ => break 'instruction Err(RuntimeError::ArgumentNotCell),
+          },
           CreateCoroutine(f_and_result) => {
             let f_value = self.steal_register(f_and_result);
             match f_value {
-              CompositeFn(f) => self.set_register(
-                f_and_result,
-                Value::fn_coroutine(Rc::unwrap_or_clone(f)),
-              ),
+              CompositeFn(f) => {
+                match PausedCoroutine::new(
+                  Rc::unwrap_or_clone(f),
+                  self.stack_capacity,
+                ) {
+                  Ok(coroutine) => {
+                    self.set_register(f_and_result, Value::coroutine(coroutine))
+                  }
+                  Err(e) => break 'instruction Err(e),
+                }
+              }
               ExternalFn(_) => {
                 break 'instruction Err(RuntimeError::CantCreateCoroutine(
                   "can't create a coroutine from an external function"
@@ -1072,6 +1976,11 @@ impl EvaluationState {
               break 'instruction Err(RuntimeError::IsntCoroutine);
             }
           }
+          Yield(_) | YieldAndAccept(..)
+            if self.parent_coroutine_stack.is_empty() =>
+          {
+            break 'instruction Err(RuntimeError::YieldOutsideCoroutine);
+          }
           Yield(value) => {
             let yielded_value = self.get_register(value).clone();
             self.yield_value(yielded_value, None, false);
@@ -1127,11 +2036,15 @@ impl EvaluationState {
           IsInt(result, value) => {
             self.set_register(
               result,
-              Bool(if let Number(Num::Int(_)) = self.get_register(value) {
-                true
-              } else {
-                false
-              }),
+              Bool(
+                if let Number(Num::Int(_) | Num::BigInt(_)) =
+                  self.get_register(value)
+                {
+                  true
+                } else {
+                  false
+                },
+              ),
             );
           }
           IsFloat(result, value) => {
@@ -1212,6 +2125,8 @@ impl EvaluationState {
                 CoreFn(_) => true,
                 CompositeFn(_) => true,
                 ExternalFn(_) => true,
+                RecordFn(_) => true,
+                ProtocolFn(_) => true,
                 _ => false,
               }),
             );
@@ -1226,7 +2141,8 @@ impl EvaluationState {
               }),
             );
           }
-          IsCell(result, value) => todo!(),
+          IsCell(result, value) => self
+            .set_register(result, matches!(self.get_register(value), Cell(_))),
           IsCoroutine(result, value) => {
             self.set_register(
               result,
@@ -1248,11 +2164,85 @@ impl EvaluationState {
           ToMap(result, value) => todo!(),
           ToSet(result, value) => todo!(),
           ToError(result, value) => todo!(),
+          ConstAdd(result, const_index, num) => self.set_register(
+            result,
+            match self.current_frame.block.constants[const_index as usize]
+              .as_num()
+            {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            } + match self.get_register(num).as_num() {
+              Ok(n) => n,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          IncAndJumpIfPos(num, instruction_index) => {
+            let incremented = match self.get_register(num).as_num() {
+              Ok(n) => n.inc(),
+              Err(error) => break 'instruction Err(error),
+            };
+            let positive = incremented.is_positive();
+            self.set_register(num, incremented);
+            if positive {
+              self.current_frame.instruction_index = instruction_index as usize;
+            }
+          }
+          GetConstKey(result, collection, const_index) => self.set_register(
+            result,
+            match self
+              .get_register(collection)
+              .get(&self.current_frame.block.constants[const_index as usize])
+            {
+              Ok(value) => value,
+              Err(error) => break 'instruction Err(error),
+            },
+          ),
+          PushUnique(list_and_result, value) => {
+            let value = self.get_register(value).clone();
+            match self.get_unique_list(list_and_result) {
+              Ok(list) => list.push_back(value),
+              Err(error) => break 'instruction Err(error),
+            }
+          }
+          ConsUnique(list_and_result, value) => {
+            let value = self.get_register(value).clone();
+            match self.get_unique_list(list_and_result) {
+              Ok(list) => list.push_front(value),
+              Err(error) => break 'instruction Err(error),
+            }
+          }
+          RestUnique(list_and_result) => {
+            match self.get_unique_list(list_and_result) {
+              Ok(list) => {
+                list.pop_front();
+              }
+              Err(error) => break 'instruction Err(error),
+            }
+          }
+          ButLastUnique(list_and_result) => {
+            match self.get_unique_list(list_and_result) {
+              Ok(list) => {
+                list.pop_back();
+              }
+              Err(error) => break 'instruction Err(error),
+            }
+          }
         }
         Ok(None)
       };
       match instruction_result {
-        Ok(None) => {}
+        Ok(None) => {
+          // lists marked unique are updated in place rather than written
+          if !self.register_watches.is_empty() {
+            if let PushUnique(register, _)
+            | ConsUnique(register, _)
+            | RestUnique(register)
+            | ButLastUnique(register) = *instruction
+            {
+              self.notify_register_watches(register);
+            }
+          }
+        }
         Ok(Some(value)) => return Ok(Some(value)),
         Err(error) => {
           if self.parent_coroutine_stack.is_empty() {
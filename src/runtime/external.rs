@@ -0,0 +1,153 @@
+// Host objects that scripts can hold onto. Any Rust value can be wrapped with
+// `Value::external`, but it's then only equal to itself and prints as
+// `external_object`. A type that implements `ExternalValue` and is wrapped
+// with `Value::external_value` can decide how it's compared, hashed, and
+// printed instead, so that it can be used as a map key or set element.
+//
+// A host resource like a file or socket can be wrapped with
+// `Value::external_with_finalizer`, whose finalizer cleans it up once the last
+// value holding it is dropped, or once the machine it's registered with (with
+// `EvaluationState::track_finalizer`) is, whichever comes first
+
+use std::{
+  any::Any,
+  cell::RefCell,
+  fmt::Debug,
+  hash::{Hash, Hasher},
+  rc::{Rc, Weak},
+};
+
+pub trait ExternalValue: Any + Debug + Sized {
+  // Only called with another object of the same type. By default, objects
+  // are only equal to themselves
+  fn equals(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+  // Equal objects have to hash the same
+  fn hash_value(&self, state: &mut dyn Hasher) {
+    state.write_usize(self as *const Self as usize);
+  }
+  fn describe(&self) -> String {
+    format!("{self:?}")
+  }
+}
+
+trait DynExternalValue {
+  fn equals_any(&self, other: &dyn Any) -> bool;
+  fn hash_value(&self, state: &mut dyn Hasher);
+  fn describe(&self) -> String;
+}
+
+impl<T: ExternalValue> DynExternalValue for T {
+  fn equals_any(&self, other: &dyn Any) -> bool {
+    other
+      .downcast_ref::<T>()
+      .is_some_and(|other| self.equals(other))
+  }
+  fn hash_value(&self, state: &mut dyn Hasher) {
+    ExternalValue::hash_value(self, state)
+  }
+  fn describe(&self) -> String {
+    ExternalValue::describe(self)
+  }
+}
+
+// Runs at most once, however many times it's asked to
+pub(crate) struct Finalizer(RefCell<Option<Box<dyn FnOnce()>>>);
+
+impl Finalizer {
+  pub(crate) fn run(&self) {
+    let f = self.0.borrow_mut().take();
+    if let Some(f) = f {
+      f()
+    }
+  }
+}
+
+// What an external object value holds
+pub struct ExternalHandle {
+  object: Rc<dyn Any>,
+  // The same object, for those made from an `ExternalValue`
+  value: Option<Rc<dyn DynExternalValue>>,
+  finalizer: Option<Rc<Finalizer>>,
+}
+
+impl ExternalHandle {
+  pub(crate) fn opaque(object: Rc<dyn Any>) -> Self {
+    Self {
+      object,
+      value: None,
+      finalizer: None,
+    }
+  }
+  pub(crate) fn of_value<T: ExternalValue>(value: T) -> Self {
+    let value = Rc::new(value);
+    Self {
+      object: value.clone(),
+      value: Some(value),
+      finalizer: None,
+    }
+  }
+  pub(crate) fn with_finalizer<T: Any>(
+    object: T,
+    finalizer: impl FnOnce(&T) + 'static,
+  ) -> Self {
+    let object = Rc::new(object);
+    let finalized = object.clone();
+    Self {
+      object,
+      value: None,
+      finalizer: Some(Rc::new(Finalizer(RefCell::new(Some(Box::new(
+        move || finalizer(&finalized),
+      )))))),
+    }
+  }
+  pub(crate) fn finalizer(&self) -> Option<Weak<Finalizer>> {
+    self.finalizer.as_ref().map(Rc::downgrade)
+  }
+  pub(crate) fn object(&self) -> &Rc<dyn Any> {
+    &self.object
+  }
+  pub(crate) fn description(&self) -> String {
+    match &self.value {
+      Some(value) => value.describe(),
+      None => "external_object".to_string(),
+    }
+  }
+}
+
+impl Drop for ExternalHandle {
+  fn drop(&mut self) {
+    if let Some(finalizer) = &self.finalizer {
+      finalizer.run();
+    }
+  }
+}
+
+impl PartialEq for ExternalHandle {
+  fn eq(&self, other: &Self) -> bool {
+    match (&self.value, &other.value) {
+      (Some(value), Some(_)) => value.equals_any(other.object.as_ref()),
+      (None, None) => Rc::ptr_eq(&self.object, &other.object),
+      _ => false,
+    }
+  }
+}
+
+impl Hash for ExternalHandle {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    match &self.value {
+      Some(value) => value.hash_value(state),
+      None => (Rc::as_ptr(&self.object) as *const () as usize).hash(state),
+    }
+  }
+}
+
+impl Debug for ExternalHandle {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.value {
+      Some(value) => write!(f, "{}", value.describe()),
+      None => f.write_str("external_object"),
+    }
+  }
+}
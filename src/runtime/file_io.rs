@@ -0,0 +1,76 @@
+use std::{fs, rc::Rc};
+
+use super::{
+  data::{GenericValue::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+fn path_arg(value: &Value) -> RuntimeResult<&str> {
+  match value {
+    Str(path) => Ok(path.as_str()),
+    _ => Err(RuntimeError::ArgumentNotString),
+  }
+}
+
+fn io_error(error: std::io::Error) -> RuntimeError {
+  RuntimeError::FileIO(error.to_string())
+}
+
+pub(crate) fn slurp(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [path] => Ok(
+      fs::read_to_string(path_arg(path)?)
+        .map_err(io_error)?
+        .into(),
+    ),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
+// Strings are written as-is, anything else is written as its description
+pub(crate) fn spit(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [path, content] => {
+      let content = match content {
+        Str(s) => s.to_string(),
+        other => other.description(None),
+      };
+      fs::write(path_arg(path)?, content).map_err(io_error)?;
+      Ok(Nil)
+    }
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
+pub(crate) fn read_lines(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [path] => Ok(List(Rc::new(
+      fs::read_to_string(path_arg(path)?)
+        .map_err(io_error)?
+        .lines()
+        .map(Value::from)
+        .collect(),
+    ))),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
+// Returns the paths of the entries in a directory, sorted so that the result
+// doesn't depend on the platform's iteration order
+pub(crate) fn list_dir(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [path] => {
+      let mut paths = fs::read_dir(path_arg(path)?)
+        .map_err(io_error)?
+        .map(|entry| {
+          entry
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .map_err(io_error)
+        })
+        .collect::<RuntimeResult<Vec<String>>>()?;
+      paths.sort();
+      Ok(List(Rc::new(paths.into_iter().map(Value::from).collect())))
+    }
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
@@ -0,0 +1,203 @@
+// `format`, which fills in a printf-style template like `"x=%d, name=%s"`.
+// Each directive is a `%`, optionally followed by the flags `-` (align left)
+// and `0` (pad numbers with zeros), a minimum width, and a `.precision`, and
+// then one of:
+//
+//   %s  the value as `str` would show it, so strings and chars aren't quoted
+//   %v  the value as the REPL would show it
+//   %d  an integer, in decimal
+//   %x  an integer, in hexadecimal (also %o for octal and %b for binary)
+//   %f  a number, with six digits after the point unless a precision is given
+//   %e  a number, in scientific notation
+//   %%  a literal percent sign, which takes no argument
+
+use std::{iter::Peekable, str::Chars};
+
+use crate::compiler::ast::token::SymbolLedger;
+
+use super::{
+  data::{GenericValue::*, Num, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+#[derive(Default)]
+struct Directive {
+  left_align: bool,
+  zero_pad: bool,
+  width: usize,
+  precision: Option<usize>,
+  kind: char,
+}
+
+enum Piece {
+  Literal(String),
+  Directive(Directive),
+}
+
+fn invalid(message: impl Into<String>) -> RuntimeError {
+  RuntimeError::InvalidFormat(message.into())
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> Option<usize> {
+  let mut digits = String::new();
+  while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+    digits.push(digit);
+  }
+  digits.parse().ok()
+}
+
+fn parse(template: &str) -> RuntimeResult<Vec<Piece>> {
+  let mut pieces = vec![];
+  let mut literal = String::new();
+  let mut chars = template.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '%' {
+      literal.push(c);
+      continue;
+    }
+    if chars.next_if_eq(&'%').is_some() {
+      literal.push('%');
+      continue;
+    }
+    let mut directive = Directive::default();
+    while let Some(flag) = chars.next_if(|c| matches!(c, '-' | '0')) {
+      match flag {
+        '-' => directive.left_align = true,
+        _ => directive.zero_pad = true,
+      }
+    }
+    directive.width = digits(&mut chars).unwrap_or(0);
+    if chars.next_if_eq(&'.').is_some() {
+      directive.precision = Some(digits(&mut chars).unwrap_or(0));
+    }
+    directive.kind = match chars.next() {
+      Some(kind @ ('s' | 'v' | 'd' | 'x' | 'o' | 'b' | 'f' | 'e')) => kind,
+      Some(other) => {
+        return Err(invalid(format!("unknown directive %{other}")))
+      }
+      None => return Err(invalid("format string ends in a directive")),
+    };
+    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+    pieces.push(Piece::Directive(directive));
+  }
+  pieces.push(Piece::Literal(literal));
+  Ok(pieces)
+}
+
+fn integer(directive: &Directive, value: &Value) -> RuntimeResult<String> {
+  let radix = match directive.kind {
+    'x' => 16,
+    'o' => 8,
+    'b' => 2,
+    _ => 10,
+  };
+  match value {
+    Number(Num::Int(i)) => {
+      let magnitude = match radix {
+        16 => format!("{:x}", i.unsigned_abs()),
+        8 => format!("{:o}", i.unsigned_abs()),
+        2 => format!("{:b}", i.unsigned_abs()),
+        _ => i.unsigned_abs().to_string(),
+      };
+      Ok(if *i < 0 {
+        format!("-{magnitude}")
+      } else {
+        magnitude
+      })
+    }
+    Number(Num::BigInt(i)) => Ok(i.to_str_radix(radix)),
+    _ => Err(mismatch(directive, "an integer", value)),
+  }
+}
+
+fn float(directive: &Directive, value: &Value) -> RuntimeResult<String> {
+  let Number(n) = value else {
+    return Err(mismatch(directive, "a number", value));
+  };
+  let f = n.as_float().0;
+  let precision = directive.precision.unwrap_or(6);
+  Ok(match directive.kind {
+    'e' => format!("{f:.precision$e}"),
+    _ => format!("{f:.precision$}"),
+  })
+}
+
+fn mismatch(
+  directive: &Directive,
+  expected: &str,
+  value: &Value,
+) -> RuntimeError {
+  invalid(format!(
+    "%{} expects {expected}, but got {}",
+    directive.kind,
+    value.description(None).trim_end()
+  ))
+}
+
+fn pad(directive: &Directive, text: String) -> String {
+  let length = text.chars().count();
+  if length >= directive.width {
+    return text;
+  }
+  let padding = directive.width - length;
+  if directive.left_align {
+    text + &" ".repeat(padding)
+  } else if directive.zero_pad && "dxobfe".contains(directive.kind) {
+    // zeros go between the sign and the digits
+    let (sign, digits) = match text.strip_prefix('-') {
+      Some(digits) => ("-", digits),
+      None => ("", text.as_str()),
+    };
+    format!("{sign}{}{digits}", "0".repeat(padding))
+  } else {
+    " ".repeat(padding) + &text
+  }
+}
+
+pub(crate) fn format(
+  template: &str,
+  args: &[Value],
+  symbol_ledger: Option<&SymbolLedger>,
+) -> RuntimeResult<String> {
+  let pieces = parse(template)?;
+  let directive_count = pieces
+    .iter()
+    .filter(|piece| matches!(piece, Piece::Directive(_)))
+    .count();
+  if directive_count != args.len() {
+    return Err(invalid(format!(
+      "format string has {directive_count} directive(s), but {} argument(s) \
+       were given",
+      args.len()
+    )));
+  }
+  let mut output = String::new();
+  let mut args = args.iter();
+  for piece in pieces {
+    match piece {
+      Piece::Literal(literal) => output.push_str(&literal),
+      Piece::Directive(directive) => {
+        let arg = args.next().unwrap();
+        let text = match directive.kind {
+          's' => match arg {
+            Str(s) => s.to_string(),
+            Char(c) => c.to_string(),
+            other => other.description(symbol_ledger).trim_end().to_string(),
+          },
+          'v' => arg.description(symbol_ledger).trim_end().to_string(),
+          'f' | 'e' => float(&directive, arg)?,
+          _ => integer(&directive, arg)?,
+        };
+        // precision truncates strings, like in printf
+        let text = match (directive.kind, directive.precision) {
+          ('s' | 'v', Some(precision)) => {
+            text.chars().take(precision).collect()
+          }
+          _ => text,
+        };
+        output.push_str(&pad(&directive, text));
+      }
+    }
+  }
+  Ok(output)
+}
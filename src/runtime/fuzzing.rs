@@ -0,0 +1,173 @@
+// Generators for random blocks and values, and a harness that runs random
+// programs through the VM, as a foundation for fuzzing the interpreter. Only
+// the data subset of values is generated, since functions, cells, and
+// coroutines can't be meaningfully constructed from raw bytes
+
+use std::{
+  collections::HashMap,
+  panic::{catch_unwind, AssertUnwindSafe},
+  rc::Rc,
+};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use ordered_float::OrderedFloat;
+
+use crate::instructions::GenericInstruction::*;
+
+use super::{
+  control::Block,
+  data::{GenericValue::*, Num, Value},
+  error::RuntimeResult,
+  evaluation::{ConstIndex, EvaluationState, Instruction},
+};
+
+const MAX_DEPTH: usize = 3;
+
+fn arbitrary_value(u: &mut Unstructured, depth: usize) -> Result<Value> {
+  let variant_count = if depth < MAX_DEPTH { 10 } else { 7 };
+  Ok(match u.choose_index(variant_count)? {
+    0 => Nil,
+    1 => Bool(u.arbitrary()?),
+    2 => Char(u.arbitrary()?),
+    3 => Number(Num::Int(u.arbitrary()?)),
+    4 => Number(Num::Float(OrderedFloat(u.arbitrary()?))),
+    5 => Symbol(u.arbitrary()?),
+    6 => Str(Rc::new(u.arbitrary()?)),
+    7 => arbitrary_values(u, depth + 1)?.into(),
+    8 => Hashset(Rc::new(
+      arbitrary_values(u, depth + 1)?.into_iter().collect(),
+    )),
+    _ => {
+      let mut map = im_rc::HashMap::new();
+      let mut entries = arbitrary_values(u, depth + 1)?.into_iter();
+      while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+        map.insert(key, value);
+      }
+      Hashmap(Rc::new(map))
+    }
+  })
+}
+
+fn arbitrary_values(u: &mut Unstructured, depth: usize) -> Result<Vec<Value>> {
+  (0..u.int_in_range(0..=8)?)
+    .map(|_| arbitrary_value(u, depth))
+    .collect()
+}
+
+impl<'a> Arbitrary<'a> for Value {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+    arbitrary_value(u, 0)
+  }
+}
+
+impl<'a> Arbitrary<'a> for Block {
+  fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+    let instructions = (0..u.int_in_range(0..=64)?)
+      .map(|_| u.arbitrary())
+      .collect::<Result<Vec<Instruction>>>()?;
+    let constants = arbitrary_values(u, 0)?;
+    // Constant indexes are wrapped into range so that most blocks get past
+    // validation
+    let instructions: Vec<Instruction> = instructions
+      .into_iter()
+      .map(|instruction| match instruction {
+        Const(register, index) if !constants.is_empty() => {
+          Const(register, index % constants.len() as ConstIndex)
+        }
+        ConstAdd(output, index, input) if !constants.is_empty() => {
+          ConstAdd(output, index % constants.len() as ConstIndex, input)
+        }
+        GetConstKey(output, input, index) if !constants.is_empty() => {
+          GetConstKey(output, input, index % constants.len() as ConstIndex)
+        }
+        instruction => instruction,
+      })
+      .collect();
+    // `usages` doesn't support `YieldAndAccept` yet, so its registers are
+    // counted by hand
+    let max_register = instructions
+      .iter()
+      .filter_map(|instruction| match instruction {
+        YieldAndAccept(output, _, first_input) => {
+          Some(*output.max(first_input))
+        }
+        instruction => {
+          let usages = instruction.usages();
+          usages
+            .inputs
+            .chain(usages.outputs)
+            .chain(usages.replacements)
+            .max()
+        }
+      })
+      .max()
+      .unwrap_or(0);
+    Ok(Block::new_with_metadata(
+      instructions,
+      constants,
+      max_register,
+    ))
+  }
+}
+
+// The ways a random program can fail to produce a result
+#[derive(Debug)]
+pub enum ProgramOutcome {
+  // The bytes didn't describe a block that passes validation, or the block
+  // could loop forever or print, so it wasn't run
+  Rejected,
+  Finished(RuntimeResult<Option<Value>>),
+  // The VM reached an instruction it doesn't implement yet
+  Unimplemented,
+  Panicked(String),
+}
+
+// Whether running the block is guaranteed to terminate without side effects.
+// Backward jumps are the only way a block without function constants can loop
+fn is_runnable(block: &Block) -> bool {
+  block
+    .instructions
+    .iter()
+    .enumerate()
+    .all(|(i, instruction)| {
+      let backward = match instruction {
+        Jump(target)
+        | JumpIf(_, target)
+        | JumpIfNot(_, target)
+        | IncAndJumpIfPos(_, target) => (*target as usize) <= i,
+        RelativeJump(offset) => *offset <= 0,
+        _ => false,
+      };
+      !backward && !matches!(instruction, Print(_) | DebugPrint(_))
+    })
+}
+
+// Interprets the bytes as a block and runs it if it validates. A
+// `Panicked` outcome means the VM has a bug, since validated programs are
+// supposed to either finish or fail with a `RuntimeError`
+pub fn run_arbitrary_program(data: &[u8]) -> ProgramOutcome {
+  let Ok(block) = Block::arbitrary_take_rest(Unstructured::new(data)) else {
+    return ProgramOutcome::Rejected;
+  };
+  if !is_runnable(&block) {
+    return ProgramOutcome::Rejected;
+  }
+  let Ok(mut state) = EvaluationState::new_validated(block) else {
+    return ProgramOutcome::Rejected;
+  };
+  match catch_unwind(AssertUnwindSafe(|| state.evaluate(&HashMap::new()))) {
+    Ok(result) => ProgramOutcome::Finished(result),
+    Err(payload) => {
+      let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+      if message.starts_with("not yet implemented") {
+        ProgramOutcome::Unimplemented
+      } else {
+        ProgramOutcome::Panicked(message)
+      }
+    }
+  }
+}
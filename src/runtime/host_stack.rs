@@ -0,0 +1,114 @@
+// A Lua-style interface for hosts, which passes values through a stack rather
+// than handing `Value`s back and forth, so that bindings for other languages
+// don't need to know anything about how values are represented. Positive
+// indexes count up from the bottom of the stack starting at 1, and negative
+// ones count down from the top starting at -1
+
+use std::collections::HashMap;
+
+use super::{
+  control::Block,
+  data::{GenericValue::*, Num, Value},
+  error::{RuntimeError, RuntimeResult},
+  evaluation::{EvaluationState, Register, SymbolIndex},
+};
+
+impl EvaluationState {
+  fn host_stack_position(&self, index: i32) -> Option<usize> {
+    let len = self.host_stack.len() as i32;
+    let position = if index > 0 { index - 1 } else { len + index };
+    (index != 0 && (0..len).contains(&position)).then_some(position as usize)
+  }
+  pub fn get_top(&self) -> usize {
+    self.host_stack.len()
+  }
+  pub fn push_value(&mut self, value: Value) {
+    self.host_stack.push(value)
+  }
+  pub fn push_nil(&mut self) {
+    self.push_value(Nil)
+  }
+  pub fn push_bool(&mut self, b: bool) {
+    self.push_value(b.into())
+  }
+  pub fn push_i64(&mut self, i: i64) {
+    self.push_value(i.into())
+  }
+  pub fn push_number(&mut self, n: f64) {
+    self.push_value(n.into())
+  }
+  pub fn push_string(&mut self, s: &str) {
+    self.push_value(s.into())
+  }
+  // Pops up to `count` values off the top of the stack
+  pub fn pop(&mut self, count: usize) {
+    let len = self.host_stack.len();
+    self.host_stack.truncate(len.saturating_sub(count));
+  }
+  pub fn value_at(&self, index: i32) -> Option<&Value> {
+    self
+      .host_stack_position(index)
+      .map(|position| &self.host_stack[position])
+  }
+  pub fn is_nil(&self, index: i32) -> bool {
+    matches!(self.value_at(index), Some(Nil))
+  }
+  pub fn to_bool(&self, index: i32) -> Option<bool> {
+    match self.value_at(index)? {
+      Bool(b) => Some(*b),
+      _ => None,
+    }
+  }
+  pub fn to_i64(&self, index: i32) -> Option<i64> {
+    match self.value_at(index)? {
+      Number(Num::Int(i)) => Some(*i),
+      _ => None,
+    }
+  }
+  // Integers are converted to floats, like Lua's `tonumber`
+  pub fn to_number(&self, index: i32) -> Option<f64> {
+    match self.value_at(index)? {
+      Number(Num::Int(i)) => Some(*i as f64),
+      Number(Num::Float(f)) => Some(**f),
+      _ => None,
+    }
+  }
+  pub fn to_str(&self, index: i32) -> Option<&str> {
+    match self.value_at(index)? {
+      Str(s) => Some(s.as_str()),
+      _ => None,
+    }
+  }
+  // How the value at `index` would be printed
+  pub fn describe_at(&self, index: i32) -> Option<String> {
+    self.value_at(index).map(|value| self.describe(value))
+  }
+  // Pops a function and the `arg_count` arguments pushed after it, calls the
+  // function, and pushes `result_count` results. Functions only return one
+  // value, so any results past the first are nil. The function and arguments
+  // are popped even if the call fails
+  pub fn call(
+    &mut self,
+    arg_count: usize,
+    result_count: usize,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<()> {
+    if arg_count >= self.host_stack.len() {
+      return Err(RuntimeError::InvalidArity);
+    }
+    let args = self.host_stack.split_off(self.host_stack.len() - arg_count);
+    let f = self.host_stack.pop().unwrap();
+    if arg_count >= Register::MAX as usize {
+      return Err(RuntimeError::InvalidArity);
+    }
+    self.reset(Block::for_call(f, args));
+    let result = self.evaluate(global_bindings)?.unwrap_or(Nil);
+    if result_count > 0 {
+      self.host_stack.push(result);
+      for _ in 1..result_count {
+        self.host_stack.push(Nil);
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,104 @@
+use std::{collections::HashSet, rc::Rc};
+
+use super::{
+  control::{Block, CompositeFunction, FunctionBody},
+  data::{GenericValue::*, Value},
+};
+
+// The fewest strings the interner holds before it starts pruning
+const MIN_PRUNE_SIZE: usize = 1024;
+
+// Deduplicates strings so that identical strings share a single allocation.
+// Every string constant is interned when a block is loaded, so comparing two
+// strings that came from constants usually only requires a pointer comparison.
+// Strings that nothing but the interner refers to anymore are pruned whenever
+// the number of strings has doubled since the last pruning, so a long session
+// doesn't keep every string it has ever loaded
+#[derive(Debug, Default)]
+pub struct StringInterner {
+  strings: HashSet<Rc<String>>,
+  prune_size: usize,
+}
+
+impl StringInterner {
+  pub fn intern(&mut self, s: Rc<String>) -> Rc<String> {
+    if let Some(existing) = self.strings.get(&s) {
+      existing.clone()
+    } else {
+      if self.strings.len() >= self.prune_size.max(MIN_PRUNE_SIZE) {
+        self.prune();
+      }
+      self.strings.insert(s.clone());
+      s
+    }
+  }
+  fn prune(&mut self) {
+    self.strings.retain(|s| Rc::strong_count(s) > 1);
+    self.prune_size = self.strings.len() * 2;
+  }
+  #[cfg(test)]
+  pub(crate) fn len(&self) -> usize {
+    self.strings.len()
+  }
+  pub fn intern_value(&mut self, value: Value) -> Value {
+    match value {
+      Str(s) => Str(self.intern(s)),
+      List(list) => List(Rc::new(
+        Rc::unwrap_or_clone(list)
+          .into_iter()
+          .map(|value| self.intern_value(value))
+          .collect(),
+      )),
+      Hashmap(map) => Hashmap(Rc::new(
+        Rc::unwrap_or_clone(map)
+          .into_iter()
+          .map(|(key, value)| {
+            (self.intern_value(key), self.intern_value(value))
+          })
+          .collect(),
+      )),
+      Hashset(set) => Hashset(Rc::new(
+        Rc::unwrap_or_clone(set)
+          .into_iter()
+          .map(|value| self.intern_value(value))
+          .collect(),
+      )),
+      // deferred functions keep their IR, so their strings aren't interned
+      CompositeFn(f) => match Rc::unwrap_or_clone(f) {
+        CompositeFunction {
+          args,
+          body: FunctionBody::Compiled(block),
+        } => CompositeFn(Rc::new(CompositeFunction::new(
+          args,
+          self.intern_block(block),
+        ))),
+        f => CompositeFn(Rc::new(f)),
+      },
+      PartialApplication(application) => {
+        let (f, args) = Rc::unwrap_or_clone(application);
+        PartialApplication(Rc::new((
+          self.intern_value(f),
+          args.into_iter().map(|arg| self.intern_value(arg)).collect(),
+        )))
+      }
+      Composition(fs) => Composition(Rc::new(
+        Rc::unwrap_or_clone(fs)
+          .into_iter()
+          .map(|f| self.intern_value(f))
+          .collect(),
+      )),
+      other => other,
+    }
+  }
+  pub fn intern_block(&mut self, block: Block) -> Block {
+    Block {
+      constants: block
+        .constants
+        .iter()
+        .cloned()
+        .map(|value| self.intern_value(value))
+        .collect(),
+      ..block
+    }
+  }
+}
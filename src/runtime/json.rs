@@ -0,0 +1,76 @@
+#[cfg(feature = "json")]
+mod enabled {
+  use std::{collections::HashMap, str::FromStr};
+
+  use num_bigint::BigInt as BigInteger;
+
+  use crate::runtime::{
+    data::{GenericValue::*, Num, Value},
+    error::{RuntimeError, RuntimeResult},
+    serde_bridge::from_value,
+  };
+
+  // serde_json keeps the text of each number, so integers too big for an
+  // `i64` can become big ints rather than losing precision as floats
+  fn from_json(json: serde_json::Value) -> Value {
+    match json {
+      serde_json::Value::Null => Nil,
+      serde_json::Value::Bool(b) => Bool(b),
+      serde_json::Value::Number(n) => Number(match n.as_i64() {
+        Some(i) => Num::Int(i),
+        None => match BigInteger::from_str(n.as_str()) {
+          Ok(i) => Num::from_big_int(i),
+          Err(_) => Num::Float(n.as_f64().unwrap_or(f64::NAN).into()),
+        },
+      }),
+      serde_json::Value::String(s) => s.into(),
+      serde_json::Value::Array(items) => {
+        items.into_iter().map(from_json).collect()
+      }
+      serde_json::Value::Object(entries) => entries
+        .into_iter()
+        .map(|(key, value)| (key, from_json(value)))
+        .collect::<HashMap<_, _>>()
+        .into(),
+    }
+  }
+
+  // JSON arrays become lists and objects become maps with string keys. Only
+  // values made of nil, bools, numbers, strings, lists, sets, and maps with
+  // string keys can be written
+  pub(crate) fn json_parse(args: Vec<Value>) -> RuntimeResult<Value> {
+    match &args[..] {
+      [Str(s)] => Ok(from_json(
+        serde_json::from_str::<serde_json::Value>(s)
+          .map_err(|error| RuntimeError::InvalidJson(error.to_string()))?,
+      )),
+      [_] => Err(RuntimeError::ArgumentNotString),
+      _ => Err(RuntimeError::InvalidArity),
+    }
+  }
+
+  pub(crate) fn json_write(args: Vec<Value>) -> RuntimeResult<Value> {
+    match &args[..] {
+      [value] => Ok(
+        from_value::<serde_json::Value>(value.clone())?
+          .to_string()
+          .into(),
+      ),
+      _ => Err(RuntimeError::InvalidArity),
+    }
+  }
+}
+#[cfg(feature = "json")]
+pub(crate) use enabled::{json_parse, json_write};
+
+#[cfg(not(feature = "json"))]
+use super::{
+  data::Value,
+  error::{RuntimeError, RuntimeResult},
+};
+#[cfg(not(feature = "json"))]
+pub(crate) fn unsupported(_args: Vec<Value>) -> RuntimeResult<Value> {
+  Err(RuntimeError::JsonUnsupported)
+}
+#[cfg(not(feature = "json"))]
+pub(crate) use {unsupported as json_parse, unsupported as json_write};
@@ -0,0 +1,293 @@
+// Approximate memory usage of the values a machine can reach, for embedders
+// that want to keep an eye on what scripts allocate. Values that share an
+// allocation are only counted once, and the sizes are estimates based on the
+// number of elements held rather than on what the allocator actually handed
+// out. `inspect_sharing` looks at a single value's allocations instead, to
+// show which operations copy and which share
+
+use std::{
+  collections::{HashMap, HashSet},
+  mem::{size_of, size_of_val},
+  rc::Rc,
+};
+
+use super::{
+  control::{
+    Block, CompositeFunction, CoroutineState, FunctionBody, PausedCoroutine,
+  },
+  data::{GenericValue::*, ListStorage, Value},
+  evaluation::Instruction,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KindStats {
+  pub count: usize,
+  pub bytes: usize,
+}
+
+impl KindStats {
+  fn add(&mut self, bytes: usize) {
+    self.count += 1;
+    self.bytes += bytes;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct HeapStats {
+  pub lists: KindStats,
+  // Hashmaps and hashsets
+  pub maps: KindStats,
+  pub strings: KindStats,
+  // Composite functions, along with partial applications and compositions
+  pub closures: KindStats,
+  pub coroutines: KindStats,
+  // The most stack slots that the machine's frames have taken up at once
+  pub peak_stack_depth: usize,
+}
+
+impl HeapStats {
+  pub fn total_bytes(&self) -> usize {
+    [
+      self.lists,
+      self.maps,
+      self.strings,
+      self.closures,
+      self.coroutines,
+    ]
+    .iter()
+    .map(|kind| kind.bytes)
+    .sum()
+  }
+}
+
+#[derive(Default)]
+pub(crate) struct HeapWalker {
+  pub stats: HeapStats,
+  visited: HashSet<*const ()>,
+}
+
+impl HeapWalker {
+  // Whether an allocation hasn't been counted yet, marking it as counted
+  fn first_visit<T: ?Sized>(&mut self, pointer: *const T) -> bool {
+    self.visited.insert(pointer as *const ())
+  }
+  pub fn values<'a>(&mut self, values: impl IntoIterator<Item = &'a Value>) {
+    for value in values {
+      self.value(value);
+    }
+  }
+  pub fn value(&mut self, value: &Value) {
+    match value {
+      Str(string) if self.first_visit(&**string) => self
+        .stats
+        .strings
+        .add(size_of::<String>() + string.capacity()),
+      List(list) if self.first_visit(&**list) => {
+        self.stats.lists.add(
+          size_of::<ListStorage<Value>>() + list.len() * size_of::<Value>(),
+        );
+        self.values(list.iter());
+      }
+      Hashmap(map) if self.first_visit(&**map) => {
+        self
+          .stats
+          .maps
+          .add(size_of_val(&**map) + map.len() * 2 * size_of::<Value>());
+        for (key, value) in map.iter() {
+          self.value(key);
+          self.value(value);
+        }
+      }
+      Hashset(set) if self.first_visit(&**set) => {
+        self
+          .stats
+          .maps
+          .add(size_of_val(&**set) + set.len() * size_of::<Value>());
+        self.values(set.iter());
+      }
+      CompositeFn(f) if self.first_visit(&**f) => self.function(f),
+      PartialApplication(f_and_args) if self.first_visit(&**f_and_args) => {
+        let (f, args) = &**f_and_args;
+        self
+          .stats
+          .closures
+          .add(size_of_val(&**f_and_args) + args.len() * size_of::<Value>());
+        self.value(f);
+        self.values(args.iter());
+      }
+      Composition(fs) if self.first_visit(&**fs) => {
+        self
+          .stats
+          .closures
+          .add(size_of::<Vec<Value>>() + fs.capacity() * size_of::<Value>());
+        self.values(fs.iter());
+      }
+      Coroutine(coroutine) if self.first_visit(&**coroutine) => {
+        let paused = (**coroutine).as_ref().map(|paused| paused.borrow());
+        let stack_size = match paused.as_deref() {
+          Some(Some(PausedCoroutine { state, .. })) => {
+            state.stack.capacity() * size_of::<Value>()
+          }
+          _ => 0,
+        };
+        self
+          .stats
+          .coroutines
+          .add(size_of::<Option<PausedCoroutine>>() + stack_size);
+        if let Some(Some(paused)) = paused.as_deref() {
+          self.coroutine_state(&paused.state);
+        }
+      }
+      Cell(cell) if self.first_visit(&**cell) => self.value(&cell.borrow()),
+      Record(record) if self.first_visit(&**record) => {
+        self.values(record.fields.iter())
+      }
+      _ => {}
+    }
+  }
+  fn function(&mut self, f: &CompositeFunction) {
+    let mut bytes = size_of::<CompositeFunction>();
+    if let FunctionBody::Compiled(block) = &f.body {
+      bytes += block.instructions.len() * size_of::<Instruction>()
+        + block.constants.len() * size_of::<Value>();
+      self.block_constants(block);
+    }
+    self.stats.closures.add(bytes);
+  }
+  pub fn block_constants(&mut self, block: &Block) {
+    self.values(block.constants.iter());
+  }
+  pub fn coroutine_state(&mut self, state: &CoroutineState) {
+    self.values(state.stack.iter());
+    for frame in state.paused_frames.iter() {
+      self.block_constants(&frame.block);
+    }
+  }
+}
+
+// How the reference-counted allocations in a value's graph are shared. A
+// node reached along two paths counts twice toward `total_nodes` but once
+// toward `unique_nodes`. Lists, maps, and sets also share chunks of their
+// contents internally, which isn't visible here
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SharingReport {
+  pub total_nodes: usize,
+  pub unique_nodes: usize,
+  // The kind and strong count of each unique node, in the order they were
+  // reached
+  pub strong_counts: Vec<(&'static str, usize)>,
+}
+
+impl SharingReport {
+  // The unique nodes that something outside of their parent in the graph
+  // also refers to, which copy-on-write updates can't modify in place
+  pub fn shared_nodes(&self) -> usize {
+    self
+      .strong_counts
+      .iter()
+      .filter(|(_, strong_count)| *strong_count > 1)
+      .count()
+  }
+}
+
+pub fn inspect_sharing(value: &Value) -> SharingReport {
+  let mut inspector = SharingInspector::default();
+  inspector.report.total_nodes = inspector.node(value);
+  inspector.report
+}
+
+type NodeInfo = (*const (), &'static str, usize);
+
+fn node_info(value: &Value) -> Option<NodeInfo> {
+  fn info<T: ?Sized>(rc: &Rc<T>, kind: &'static str) -> Option<NodeInfo> {
+    Some((Rc::as_ptr(rc) as *const (), kind, Rc::strong_count(rc)))
+  }
+  match value {
+    Str(string) => info(string, "string"),
+    List(list) => info(list, "list"),
+    Hashmap(map) => info(map, "map"),
+    Hashset(set) => info(set, "set"),
+    CompositeFn(f) => info(f, "fn"),
+    ExternalFn(f) => info(f, "external fn"),
+    PartialApplication(f_and_args) => info(f_and_args, "partial application"),
+    Composition(fs) => info(fs, "composition"),
+    ExternalObject(object) => info(object, "external object"),
+    Coroutine(coroutine) => info(coroutine, "coroutine"),
+    Cell(cell) => info(cell, "cell"),
+    Error(error) => info(error, "error"),
+    Record(record) => info(record, "record"),
+    RecordFn(f) => info(f, "record fn"),
+    ProtocolFn(f) => info(f, "protocol fn"),
+    #[cfg(feature = "regex")]
+    Regex(regex) => info(regex, "regex"),
+    Nil | Bool(_) | Char(_) | Number(_) | Symbol(_) | CoreFn(_) => None,
+  }
+}
+
+#[derive(Default)]
+struct SharingInspector {
+  report: SharingReport,
+  // The number of nodes reachable from each node that's been reached,
+  // counting every path, or `None` while its children are being walked
+  subtree_sizes: HashMap<*const (), Option<usize>>,
+}
+
+impl SharingInspector {
+  // Values are only ever borrowed while walking, since cloning one would
+  // change the strong counts being reported
+  fn node(&mut self, value: &Value) -> usize {
+    let Some((pointer, kind, strong_count)) = node_info(value) else {
+      return 0;
+    };
+    match self.subtree_sizes.get(&pointer) {
+      Some(Some(size)) => return *size,
+      // cells can form cycles, which are only followed once
+      Some(None) => return 1,
+      None => {}
+    }
+    self.subtree_sizes.insert(pointer, None);
+    self.report.unique_nodes += 1;
+    self.report.strong_counts.push((kind, strong_count));
+    let size = 1 + self.children(value);
+    self.subtree_sizes.insert(pointer, Some(size));
+    size
+  }
+  fn nodes<'a>(
+    &mut self,
+    values: impl IntoIterator<Item = &'a Value>,
+  ) -> usize {
+    values.into_iter().map(|value| self.node(value)).sum()
+  }
+  fn children(&mut self, value: &Value) -> usize {
+    match value {
+      List(list) => self.nodes(list.iter()),
+      Hashmap(map) => map
+        .iter()
+        .map(|(key, value)| self.node(key) + self.node(value))
+        .sum(),
+      Hashset(set) => self.nodes(set.iter()),
+      CompositeFn(f) => match &f.body {
+        FunctionBody::Compiled(block) => self.nodes(block.constants.iter()),
+        FunctionBody::Deferred(_) => 0,
+      },
+      PartialApplication(f_and_args) => {
+        let (f, args) = &**f_and_args;
+        self.node(f) + self.nodes(args.iter())
+      }
+      Composition(fs) => self.nodes(fs.iter()),
+      Coroutine(coroutine) => {
+        match (**coroutine)
+          .as_ref()
+          .map(|paused| paused.borrow())
+          .as_deref()
+        {
+          Some(Some(paused)) => self.nodes(paused.state.stack.iter()),
+          _ => 0,
+        }
+      }
+      Cell(cell) => self.node(&cell.borrow()),
+      Record(record) => self.nodes(record.fields.iter()),
+      _ => 0,
+    }
+  }
+}
@@ -0,0 +1,81 @@
+use std::{
+  any::Any,
+  collections::HashMap,
+  rc::{Rc, Weak},
+};
+
+use super::{
+  data::{GenericValue::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+// Metadata is kept in a table keyed by the address of the value's allocation
+// rather than inside the values themselves, so that it can't affect equality
+// or hashing. Each entry holds a weak reference to its allocation, which keeps
+// the address from being reused while the entry exists
+#[derive(Default)]
+pub struct MetadataTable {
+  entries: HashMap<*const (), (Weak<dyn Any>, Value)>,
+  // The entry count after the last time dead entries were removed
+  retained_count: usize,
+}
+
+fn allocation(value: &Value) -> Option<(*const (), Weak<dyn Any>)> {
+  fn entry<T: Any>(rc: &Rc<T>) -> (*const (), Weak<dyn Any>) {
+    let weak: Weak<T> = Rc::downgrade(rc);
+    (Rc::as_ptr(rc) as *const (), weak)
+  }
+  match value {
+    List(list) => Some(entry(list)),
+    Hashmap(hashmap) => Some(entry(hashmap)),
+    Hashset(hashset) => Some(entry(hashset)),
+    CompositeFn(f) => Some(entry(f)),
+    PartialApplication(application) => Some(entry(application)),
+    Composition(fs) => Some(entry(fs)),
+    Record(record) => Some(entry(record)),
+    _ => None,
+  }
+}
+
+// Copies the outer allocation of a value, so that metadata can be attached to
+// the copy without affecting the original
+fn reallocate(value: &Value) -> Option<Value> {
+  Some(match value {
+    List(list) => List(Rc::new((**list).clone())),
+    Hashmap(hashmap) => Hashmap(Rc::new((**hashmap).clone())),
+    Hashset(hashset) => Hashset(Rc::new((**hashset).clone())),
+    CompositeFn(f) => CompositeFn(Rc::new((**f).clone())),
+    PartialApplication(application) => {
+      PartialApplication(Rc::new((**application).clone()))
+    }
+    Composition(fs) => Composition(Rc::new((**fs).clone())),
+    Record(record) => Record(Rc::new((**record).clone())),
+    _ => return None,
+  })
+}
+
+impl MetadataTable {
+  pub fn meta(&self, value: &Value) -> Value {
+    allocation(value)
+      .and_then(|(address, _)| self.entries.get(&address))
+      .map(|(_, metadata)| metadata.clone())
+      .unwrap_or(Nil)
+  }
+  pub fn with_meta(
+    &mut self,
+    value: &Value,
+    metadata: Value,
+  ) -> RuntimeResult<Value> {
+    let value = reallocate(value)
+      .ok_or_else(|| RuntimeError::CantAttachMetadata(value.clone()))?;
+    if metadata != Nil {
+      let (address, weak) = allocation(&value).unwrap();
+      self.entries.insert(address, (weak, metadata));
+      if self.entries.len() > 2 * self.retained_count.max(16) {
+        self.entries.retain(|_, (weak, _)| weak.strong_count() > 0);
+        self.retained_count = self.entries.len();
+      }
+    }
+    Ok(value)
+  }
+}
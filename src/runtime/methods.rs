@@ -0,0 +1,49 @@
+// The methods registered for host types with `Evaluator::define_method`, which
+// `(. object method args...)` and `call-method` look up by the type of the
+// object they're called on, so that different types can have methods of the
+// same name
+
+use std::{
+  any::{Any, TypeId},
+  collections::HashMap,
+  rc::Rc,
+};
+
+use super::{
+  data::{ExternalFunction, GenericValue::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+#[derive(Clone, Default)]
+pub(crate) struct MethodTable {
+  methods: HashMap<(TypeId, String), Rc<ExternalFunction>>,
+}
+
+impl MethodTable {
+  // `f` takes the object as its first argument
+  pub(crate) fn define(
+    &mut self,
+    type_id: TypeId,
+    name: String,
+    f: Rc<ExternalFunction>,
+  ) {
+    self.methods.insert((type_id, name), f);
+  }
+  // The method that `(. object name args...)` calls, with the object as its
+  // first argument
+  pub(crate) fn method(
+    &self,
+    object: &Value,
+    name: &str,
+  ) -> RuntimeResult<Rc<ExternalFunction>> {
+    let ExternalObject(handle) = object else {
+      return Err(RuntimeError::ArgumentNotExternal("object".to_string()));
+    };
+    let type_id = Any::type_id(handle.object().as_ref());
+    self
+      .methods
+      .get(&(type_id, name.to_string()))
+      .cloned()
+      .ok_or_else(|| RuntimeError::NoMethod(name.to_string()))
+  }
+}
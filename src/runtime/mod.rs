@@ -1,18 +1,55 @@
+#[cfg(feature = "tokio")]
+pub mod async_host;
+pub mod capabilities;
 pub mod control;
 pub mod core_functions;
+pub mod coverage;
 pub mod data;
+pub mod edn;
 pub mod error;
 pub mod evaluation;
+pub mod external;
+pub mod file_io;
+pub mod format;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+pub mod host_stack;
+pub mod interner;
+pub mod json;
+pub mod memory;
+pub mod metadata;
+pub(crate) mod methods;
+pub mod numbers;
+pub mod ordering;
+mod parallel;
+#[cfg(feature = "serde")]
+pub(crate) mod portable;
+pub mod pretty_print;
+pub mod profile;
+pub mod protocols;
+pub mod random;
+pub mod records;
+pub mod regex;
+pub mod replay;
+#[cfg(feature = "serde")]
+pub mod serde_bridge;
+pub mod validation;
 
 #[cfg(test)]
 mod tests {
-  use std::{collections::HashMap, rc::Rc};
+  use std::{
+    cell::Cell,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::Rc,
+  };
 
   use crate::{
-    instructions::GenericInstruction::*,
+    blocks::BlockBuilder,
+    instructions::{Argument::*, GenericInstruction::*},
     runtime::{
-      control::Block,
-      core_functions::CoreFnId,
+      control::{Block, CoroutineState},
+      core_functions::{CoreFnId, CORE_FUNCTIONS},
       data::{
         ExternalFunction,
         GenericValue::{self, *},
@@ -20,9 +57,13 @@ mod tests {
       },
       error::RuntimeError,
       evaluation::EvaluationState,
+      interner::StringInterner,
+      pretty_print::{pretty, PrettyPrintOptions},
+      validation::{validate_block, ValidationError},
     },
   };
   use block_macros::block;
+  use im_rc::vector;
 
   macro_rules! assert_register {
     ($state:expr, $register:expr, $value:expr) => {
@@ -62,6 +103,160 @@ mod tests {
     );
   }
 
+  #[test]
+  fn string_constants_are_interned() {
+    let mut state =
+      EvaluationState::new(block![Const(0, "Hello!"), Const(1, "Hello!"),]);
+    state.evaluate(&HashMap::new()).unwrap();
+    match (state.get_register(0), state.get_register(1)) {
+      (Str(a), Str(b)) => assert!(Rc::ptr_eq(a, b)),
+      _ => panic!("expected strings"),
+    }
+  }
+
+  #[test]
+  fn strings_in_maps_and_sets_are_interned() {
+    let map = Value::from(HashMap::from([("a".to_string(), "b")]));
+    let set = Hashset(Rc::new([Value::from("a")].into_iter().collect()));
+    let mut state = EvaluationState::new(block![
+      Const(0, "a"),
+      Const(1, "b"),
+      Const(2, map),
+      Const(3, set),
+    ]);
+    state.evaluate(&HashMap::new()).unwrap();
+    let (Str(a), Str(b), Hashmap(map), Hashset(set)) = (
+      state.get_register(0),
+      state.get_register(1),
+      state.get_register(2),
+      state.get_register(3),
+    ) else {
+      panic!("expected strings, a map, and a set")
+    };
+    let (Str(key), Str(value)) = map.iter().next().unwrap() else {
+      panic!("expected a map of strings")
+    };
+    let Str(member) = set.iter().next().unwrap() else {
+      panic!("expected a set of strings")
+    };
+    assert!(Rc::ptr_eq(a, key) && Rc::ptr_eq(b, value));
+    assert!(Rc::ptr_eq(a, member));
+  }
+
+  #[test]
+  fn interner_prunes_unused_strings() {
+    let mut interner = StringInterner::default();
+    let kept = interner.intern(Rc::new("kept".to_string()));
+    for i in 0..10000 {
+      interner.intern(Rc::new(i.to_string()));
+    }
+    assert!(interner.len() < 2048);
+    let again = interner.intern(Rc::new("kept".to_string()));
+    assert!(Rc::ptr_eq(&kept, &again));
+  }
+
+  #[test]
+  fn equality_instructions() {
+    run_and_check_registers!(
+      block![
+        EmptyList(0),
+        EmptyList(1),
+        Equal(2, 0, 1),
+        NotEqual(3, 0, 1),
+        Const(4, 1),
+        Const(5, 1.0),
+        Equal(6, 4, 5)
+      ],
+      (2, true),
+      (3, false),
+      (6, false)
+    );
+  }
+
+  #[test]
+  fn identity() {
+    let list = Value::from(vec![1.into()]);
+    assert!(list.is_identical(&list.clone()));
+    assert!(!list.is_identical(&Value::from(vec![1.into()])));
+    assert_eq!(list, Value::from(vec![1.into()]));
+    let f = Value::from(ExternalFunction {
+      name: None,
+      args: None,
+      f: Rc::new(|_| Ok(Nil)),
+      pure: false,
+    });
+    assert!(f.is_identical(&f.clone()));
+    assert_eq!(f, f.clone());
+    assert!(Value::from(3).is_identical(&3.into()));
+    assert!(!Value::from(3).is_identical(&3.0.into()));
+  }
+
+  #[test]
+  fn finalizers() {
+    let closed = Rc::new(Cell::new(0));
+    let resource = |closed: &Rc<Cell<i64>>| {
+      let closed = closed.clone();
+      Value::external_with_finalizer(7i64, move |handle: &i64| {
+        closed.set(closed.get() + handle)
+      })
+    };
+    let file = resource(&closed);
+    let copy = file.clone();
+    assert_eq!(copy.clone().casted_external::<i64>().as_deref(), Some(&7));
+    drop(file);
+    assert_eq!(closed.get(), 0);
+    drop(copy);
+    assert_eq!(closed.get(), 7);
+    // a machine runs the finalizers it tracks when it's dropped, even if the
+    // objects outlive it
+    let mut state = EvaluationState::default();
+    let socket = resource(&closed);
+    state.track_finalizer(&socket);
+    drop(state);
+    assert_eq!(closed.get(), 14);
+    drop(socket);
+    assert_eq!(closed.get(), 14);
+  }
+
+  #[test]
+  fn heap_stats() {
+    let mut state = EvaluationState::new(block![
+      Const(0, "Hello!"),
+      Const(1, "Hello!"),
+      EmptyList(2),
+      Push(2, 0),
+      Copy(3, 2)
+    ]);
+    state.evaluate(&HashMap::new()).unwrap();
+    let stats = state.heap_stats();
+    // shared strings and lists are only counted once
+    assert_eq!(stats.strings.count, 1);
+    assert_eq!(stats.lists.count, 1);
+    assert_eq!(
+      stats.lists.bytes,
+      std::mem::size_of::<super::data::ListStorage<Value>>()
+        + std::mem::size_of::<Value>()
+    );
+    assert_eq!(stats.closures.count, 0);
+    assert_eq!(stats.peak_stack_depth, 4);
+    assert_eq!(stats.total_bytes(), stats.strings.bytes + stats.lists.bytes);
+  }
+
+  #[test]
+  fn inspect_sharing() {
+    let inner: Value = vec![Value::from("x"), 1.into()].into_iter().collect();
+    let outer: Value = vec![inner.clone(), inner.clone()].into_iter().collect();
+    let report = super::memory::inspect_sharing(&outer);
+    assert_eq!(report.total_nodes, 5);
+    assert_eq!(report.unique_nodes, 3);
+    assert_eq!(
+      report.strong_counts,
+      vec![("list", 1), ("list", 3), ("string", 1)]
+    );
+    assert_eq!(report.shared_nodes(), 1);
+    assert_eq!(super::memory::inspect_sharing(&1.into()).total_nodes, 0);
+  }
+
   simple_register_test!(
     arithmetic,
     block![
@@ -81,6 +276,66 @@ mod tests {
     (8, -2.)
   );
 
+  simple_register_test!(
+    int_overflow_promotes_to_big_int,
+    block![
+      Const(0, i64::MAX),
+      Inc(1, 0),
+      Dec(2, 1),
+      Multiply(3, 0, 0),
+      Const(4, 2),
+      Subtract(5, 4, 1),
+    ],
+    (1, num_bigint::BigInt::from(i64::MAX) + 1i64),
+    (2, i64::MAX),
+    (
+      3,
+      num_bigint::BigInt::from(i64::MAX) * num_bigint::BigInt::from(i64::MAX)
+    ),
+    (5, i64::MIN + 2)
+  );
+
+  simple_register_test!(
+    bitwise_operations,
+    block![
+      Const(0, 0b1100),
+      Const(1, 0b1010),
+      BitAnd(2, 0, 1),
+      BitOr(3, 0, 1),
+      BitXor(4, 0, 1),
+      BitNot(5, 0),
+      Const(6, 2),
+      ShiftLeft(7, 0, 6),
+      ShiftRight(8, 0, 6),
+      Const(9, 63),
+      ShiftLeft(10, 6, 9),
+    ],
+    (2, 0b1000),
+    (3, 0b1110),
+    (4, 0b0110),
+    (5, -0b1101),
+    (7, 0b110000),
+    (8, 0b11),
+    (10, num_bigint::BigInt::from(2) << 63usize)
+  );
+
+  #[test]
+  fn shifts_out_of_range() {
+    for shift in [-1, 64] {
+      for instruction in [ShiftLeft(2, 0, 1), ShiftRight(2, 0, 1)] {
+        let mut state = EvaluationState::new(block![
+          Const(0, 1),
+          Const(1, shift),
+          instruction,
+        ]);
+        assert_eq!(
+          state.evaluate(&HashMap::new()),
+          Err(RuntimeError::ArgumentOutOfRange(String::new()))
+        );
+      }
+    }
+  }
+
   #[test]
   fn environment_lookup() {
     let mut state = EvaluationState::new(block![Lookup(0, 0)]);
@@ -98,7 +353,7 @@ mod tests {
     call_constant_function,
     block![
       Const(0, Value::composite_fn(0, block![Const(0, 5), Return(0)])),
-      Call(1, 0, 0)
+      Call(1, 0, [])
     ],
     (1, 5)
   );
@@ -111,8 +366,7 @@ mod tests {
         1,
         Value::composite_fn(1, block![Multiply(0, 0, 0), Return(0)])
       ),
-      Call(2, 1, 1),
-      CopyArgument(0)
+      Call(2, 1, [CopyArgument(0)])
     ],
     (0, 10),
     (2, 100),
@@ -126,10 +380,8 @@ mod tests {
         1,
         Value::composite_fn(1, block![Multiply(0, 0, 0), Return(0)])
       ),
-      Call(0, 1, 1),
-      StealArgument(0),
-      Call(0, 1, 1),
-      StealArgument(0),
+      Call(0, 1, [StealArgument(0)]),
+      Call(0, 1, [StealArgument(0)]),
     ],
     (0, 10000),
   );
@@ -147,16 +399,13 @@ mod tests {
               1,
               Value::composite_fn(1, block![Multiply(0, 0, 0), Return(0)])
             ),
-            Call(0, 1, 1),
-            StealArgument(0),
-            Call(0, 1, 1),
-            StealArgument(0),
+            Call(0, 1, [StealArgument(0)]),
+            Call(0, 1, [StealArgument(0)]),
             Return(0)
           ]
         )
       ),
-      Call(0, 1, 1),
-      StealArgument(0)
+      Call(0, 1, [StealArgument(0)])
     ],
     (0, 10000)
   );
@@ -173,9 +422,7 @@ mod tests {
           block![Multiply(0, 1, 0), Multiply(0, 0, 0), Return(0)]
         )
       ),
-      Call(0, 2, 2),
-      StealArgument(0),
-      StealArgument(1),
+      Call(0, 2, [StealArgument(0), StealArgument(1)]),
     ],
     (0, 36)
   );
@@ -193,10 +440,7 @@ mod tests {
           block![Multiply(0, 1, 0), Multiply(0, 2, 0), Return(0)]
         )
       ),
-      Call(3, 4, 3),
-      StealArgument(0),
-      StealArgument(1),
-      StealArgument(2),
+      Call(3, 4, [StealArgument(0), StealArgument(1), StealArgument(2)]),
     ],
     (3, 24)
   );
@@ -220,7 +464,7 @@ mod tests {
   simple_register_test!(
     apply_core_fn_add,
     block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       Const(1, CoreFn(CoreFnId::Add)),
       Apply(0, 1),
     ],
@@ -237,31 +481,46 @@ mod tests {
   simple_register_test!(
     list_push,
     block![EmptyList(0), Const(1, "test"), Push(0, 1)],
-    (0, List(Rc::new(vec!["test".into()])))
+    (0, List(Rc::new(vector!["test".into()])))
+  );
+
+  simple_register_test!(
+    list_push_leaves_shared_list_unchanged,
+    block![
+      Const(0, vec![1.into(), 2.into(), 3.into(), 4.into()]),
+      Copy(1, 0),
+      Const(2, 5),
+      Push(0, 2)
+    ],
+    (
+      0,
+      Value::from(vec![1.into(), 2.into(), 3.into(), 4.into(), 5.into()])
+    ),
+    (1, Value::from(vec![1.into(), 2.into(), 3.into(), 4.into()]))
   );
 
   simple_register_test!(
     list_rest,
     block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into()]))),
       Rest(0),
-      Const(1, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(1, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       Rest(1)
     ],
-    (0, List(Rc::new(vec![2.into()]))),
-    (1, List(Rc::new(vec![2.into(), 3.into()])))
+    (0, List(Rc::new(vector![2.into()]))),
+    (1, List(Rc::new(vector![2.into(), 3.into()])))
   );
 
   simple_register_test!(
     list_butlast,
     block![
-      Const(0, List(Rc::new(vec![1.into(), 2.into()]))),
+      Const(0, List(Rc::new(vector![1.into(), 2.into()]))),
       ButLast(0),
-      Const(1, List(Rc::new(vec![1.into(), 2.into(), 3.into()]))),
+      Const(1, List(Rc::new(vector![1.into(), 2.into(), 3.into()]))),
       ButLast(1)
     ],
-    (0, List(Rc::new(vec![1.into()]))),
-    (1, List(Rc::new(vec![1.into(), 2.into()])))
+    (0, List(Rc::new(vector![1.into()]))),
+    (1, List(Rc::new(vector![1.into(), 2.into()])))
   );
 
   simple_register_test!(
@@ -377,12 +636,311 @@ mod tests {
           block![IsPos(1, 0), If(1), Dec(0, 0), Jump(0), EndIf, Return(0)]
         )
       ),
-      Call(0, 1, 1),
-      StealArgument(0),
+      Call(0, 1, [StealArgument(0)]),
     ],
     (0, 0),
   );
 
+  simple_register_test!(
+    jump_to_labels,
+    block![
+      Const(0, 3),
+      Const(1, 0),
+      label loop_start,
+      IsPos(2, 0),
+      If(2),
+      Dec(0, 0),
+      Inc(1, 1),
+      Jump(loop_start),
+      EndIf,
+      Jump(end),
+      Const(1, 100),
+      label end,
+    ],
+    (0, 0),
+    (1, 3),
+  );
+
+  simple_register_test!(
+    conditional_and_relative_jumps,
+    block![
+      Const(0, 3),
+      Const(1, 0),
+      label loop_start,
+      IsPos(2, 0),
+      JumpIfNot(2, end),
+      Dec(0, 0),
+      Inc(1, 1),
+      RelativeJump(loop_start),
+      label end,
+      IsZero(3, 0),
+      JumpIf(3, skip),
+      Const(1, 100),
+      label skip,
+    ],
+    (0, 0),
+    (1, 3),
+  );
+
+  #[test]
+  fn build_block_with_conditional_jumps() {
+    let mut builder = BlockBuilder::new();
+    let loop_start = builder.new_label();
+    let end = builder.new_label();
+    builder
+      .push_const(0, 3)
+      .push_const(1, 0)
+      .place_label(loop_start)
+      .push_instruction(IsPos(2, 0))
+      .push_jump_if_not(2, end)
+      .push_instruction(Dec(0, 0))
+      .push_instruction(Inc(1, 1))
+      .push_jump(loop_start)
+      .place_label(end);
+    let block = builder.finish();
+    assert_eq!(block[3], JumpIfNot(2, 7));
+    run_and_check_registers!(block, (0, 0), (1, 3));
+  }
+
+  #[test]
+  fn build_block_with_labels() {
+    let mut builder = BlockBuilder::new();
+    let loop_start = builder.new_label();
+    let end = builder.new_label();
+    builder
+      .push_const(0, 3)
+      .push_const(1, 0)
+      .place_label(loop_start)
+      .push_instruction(IsPos(2, 0))
+      .push_instruction(If(2))
+      .push_instruction(Dec(0, 0))
+      .push_instruction(Inc(1, 1))
+      .push_jump(loop_start)
+      .push_instruction(EndIf)
+      .push_jump(end)
+      .push_const(1, 100)
+      .place_label(end);
+    run_and_check_registers!(builder.finish(), (0, 0), (1, 3));
+  }
+
+  #[test]
+  fn block_constants_use_outer_bindings() {
+    let start = 3;
+    let doubled = [2i64; 2];
+    run_and_check_registers!(
+      block![
+        Const(0, start),
+        Const(1, Value::composite_fn(1, block![Inc(0, 0), Return(0)])),
+        Call(1, 1, [CopyArgument(0)]),
+        Const(2, doubled.iter().sum::<i64>()),
+      ],
+      (0, 3),
+      (1, 4),
+      (2, 4)
+    );
+  }
+
+  #[test]
+  fn cache_global_lookups() {
+    let block = block![Lookup(0, 7), Return(0)];
+    let run = |generation: u64, value: i64| {
+      EvaluationState::new(block.clone())
+        .with_environment_generation(generation)
+        .evaluate(&HashMap::from([(7, value.into())]))
+        .unwrap()
+    };
+    assert_eq!(run(1, 10), Some(10.into()));
+    // the environment hasn't changed generations, so the cached value is used
+    assert_eq!(run(1, 20), Some(10.into()));
+    assert_eq!(run(2, 20), Some(20.into()));
+    // without a generation nothing is cached
+    assert_eq!(
+      EvaluationState::new(block.clone())
+        .evaluate(&HashMap::from([(7, 30.into())])),
+      Ok(Some(30.into()))
+    );
+  }
+
+  #[test]
+  fn grow_and_shrink_stacks() {
+    // counts down to zero by recursing, passing itself along as an argument
+    let countdown = Value::composite_fn(
+      2,
+      block![
+        IsPos(2, 1),
+        If(2),
+        Dec(1, 1),
+        Call(0, 0, [CopyArgument(0), CopyArgument(1)]),
+        Else,
+        Copy(0, 1),
+        EndIf,
+        Return(0)
+      ],
+    );
+    let run = |depth: i64| {
+      EvaluationState::new(block![
+        Const(0, countdown.clone()),
+        Const(1, depth),
+        Call(2, 0, [CopyArgument(0), CopyArgument(1)]),
+        Return(2)
+      ])
+      .with_stack_capacity(16)
+      .evaluate(&HashMap::new())
+    };
+    assert_eq!(run(5000), Ok(Some(0.into())));
+    assert_eq!(run(30000), Err(RuntimeError::StackOverflow));
+    let mut state = CoroutineState::new(16);
+    state.reserve_stack(100);
+    assert_eq!(state.stack.len(), 100);
+    state.reserve_stack(120);
+    assert_eq!(state.stack.len(), 200);
+    state.shrink_stack(60, 16);
+    assert_eq!(state.stack.len(), 200);
+    state.shrink_stack(50, 16);
+    assert_eq!(state.stack.len(), 100);
+    state.shrink_stack(1, 64);
+    assert_eq!(state.stack.len(), 64);
+  }
+
+  #[test]
+  fn unique_list_updates() {
+    let mut state = EvaluationState::new(block![
+      EmptyList(0),
+      Const(1, 1),
+      PushUnique(0, 1),
+      ConsUnique(0, 1),
+      ButLastUnique(0),
+      Return(0)
+    ]);
+    assert_eq!(
+      state.evaluate(&HashMap::new()),
+      Ok(Some(vector![1.into()].into()))
+    );
+    // a hand-built block can mark a list that isn't actually unique
+    let mut state = EvaluationState::new(block![
+      EmptyList(0),
+      Copy(1, 0),
+      RestUnique(0),
+      Return(0)
+    ]);
+    assert_eq!(
+      state.evaluate(&HashMap::new()),
+      Err(RuntimeError::SharedUniqueList)
+    );
+  }
+
+  #[test]
+  fn validate_blocks() {
+    let valid = block![
+      Const(0, 1),
+      Const(1, Value::composite_fn(1, block![Inc(0, 0), Return(0)])),
+      IsPos(2, 0),
+      If(2),
+      Call(0, 1, [StealArgument(0)]),
+      EndIf,
+    ];
+    assert_eq!(validate_block(&valid, false), Ok(()));
+    assert!(EvaluationState::new_validated(valid).is_ok());
+    assert_eq!(
+      validate_block(&Block::new(vec![Const(0, 1)], vec![]), false),
+      Err(ValidationError::ConstIndexOutOfRange {
+        instruction: 0,
+        index: 1,
+        constant_count: 0
+      })
+    );
+    assert_eq!(
+      validate_block(
+        &Block::new_with_metadata(vec![Copy(4, 0)], vec![], 1),
+        false
+      ),
+      Err(ValidationError::RegisterOutOfRange {
+        instruction: 0,
+        register: 4,
+        max_register: 1
+      })
+    );
+    assert_eq!(
+      validate_block(&block![Jump(3)], false),
+      Err(ValidationError::JumpOutOfRange {
+        instruction: 0,
+        target: 3
+      })
+    );
+    assert_eq!(
+      validate_block(&block![Inc(0, 0), RelativeJump(-2)], false),
+      Err(ValidationError::JumpOutOfRange {
+        instruction: 1,
+        target: -1
+      })
+    );
+    assert_eq!(
+      validate_block(&block![Const(0, 1), If(0), Inc(0, 0)], false),
+      Err(ValidationError::UnclosedIf(1))
+    );
+    assert_eq!(
+      validate_block(&block![Else, EndIf], false),
+      Err(ValidationError::UnexpectedBranch(0))
+    );
+    assert_eq!(
+      validate_block(
+        &Block::new_with_metadata(
+          vec![Call(0, 0, [CopyArgument(1), StealArgument(3)].into())],
+          vec![],
+          1
+        ),
+        false
+      ),
+      Err(ValidationError::RegisterOutOfRange {
+        instruction: 0,
+        register: 3,
+        max_register: 1
+      })
+    );
+    assert_eq!(
+      validate_block(&block![PopDynamicBindings(1)], false),
+      Err(ValidationError::UnbalancedDynamicBindings(0))
+    );
+    assert_eq!(
+      validate_block(&block![Const(0, 1), CallAndReturn(0, [])], false),
+      Err(ValidationError::TailCallOutsideFunction(1))
+    );
+    assert_eq!(
+      validate_block(&block![Const(0, 1), Inc(0, 0)], true),
+      Err(ValidationError::MissingReturn)
+    );
+    assert_eq!(
+      validate_block(
+        &block![Const(0, Value::composite_fn(1, block![Inc(0, 0)]))],
+        false
+      ),
+      Err(ValidationError::InConstant {
+        index: 0,
+        error: Box::new(ValidationError::MissingReturn)
+      })
+    );
+  }
+
+  #[cfg(feature = "arbitrary")]
+  #[test]
+  fn random_programs_dont_panic() {
+    use crate::runtime::fuzzing::{run_arbitrary_program, ProgramOutcome};
+    // xorshift, so that the programs are the same on every run
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut next = || {
+      state ^= state << 13;
+      state ^= state >> 7;
+      state ^= state << 17;
+      state
+    };
+    for _ in 0..5000 {
+      let data: Vec<u8> = (0..next() % 400).map(|_| next() as u8).collect();
+      if let ProgramOutcome::Panicked(message) = run_arbitrary_program(&data) {
+        panic!("VM panicked with \"{message}\" on input {data:?}");
+      }
+    }
+  }
+
   simple_register_test!(
     call_external_function,
     block![
@@ -394,9 +952,7 @@ mod tests {
           Ok((args[0].as_num()? + args[1].as_num()?).into())
         })
       ),
-      Call(0, 2, 2),
-      StealArgument(0),
-      StealArgument(1)
+      Call(0, 2, [StealArgument(0), StealArgument(1)])
     ],
     (0, 3)
   );
@@ -414,9 +970,7 @@ mod tests {
           Ok(Number(Num::from(a.0 + b.0 + a.1 + b.1)))
         })
       ),
-      Call(0, 2, 2),
-      StealArgument(0),
-      StealArgument(1)
+      Call(0, 2, [StealArgument(0), StealArgument(1)])
     ],
     (0, 10)
   );
@@ -434,9 +988,9 @@ mod tests {
     block![
       Const(0, Value::composite_fn(0, block![EmptyList(0), Return(0)])),
       CreateCoroutine(0),
-      Call(1, 0, 0)
+      Call(1, 0, [])
     ],
-    (1, List(Rc::new(vec![])))
+    (1, List(Rc::new(vector![])))
   );
 
   simple_register_test!(
@@ -449,15 +1003,15 @@ mod tests {
           block![
             Const(0, Value::composite_fn(0, block![EmptyList(0), Return(0)])),
             CreateCoroutine(0),
-            Call(1, 0, 0),
+            Call(1, 0, []),
             Return(1)
           ]
         )
       ),
       CreateCoroutine(0),
-      Call(1, 0, 0)
+      Call(1, 0, [])
     ],
-    (1, List(Rc::new(vec![])))
+    (1, List(Rc::new(vector![])))
   );
 
   simple_register_test!(
@@ -476,8 +1030,8 @@ mod tests {
         )
       ),
       CreateCoroutine(0),
-      Call(1, 0, 0),
-      Call(2, 0, 0)
+      Call(1, 0, []),
+      Call(2, 0, [])
     ],
     (1, "yielded value!"),
     (2, "returned value!")
@@ -504,9 +1058,9 @@ mod tests {
               )
             ),
             CreateCoroutine(0),
-            Call(1, 0, 0),
+            Call(1, 0, []),
             Yield(1),
-            Call(2, 0, 0),
+            Call(2, 0, []),
             Yield(2),
             Const(
               0,
@@ -521,18 +1075,18 @@ mod tests {
               )
             ),
             CreateCoroutine(0),
-            Call(1, 0, 0),
+            Call(1, 0, []),
             Yield(1),
-            Call(2, 0, 0),
+            Call(2, 0, []),
             Yield(2)
           ]
         )
       ),
       CreateCoroutine(0),
-      Call(1, 0, 0),
-      Call(2, 0, 0),
-      Call(3, 0, 0),
-      Call(4, 0, 0)
+      Call(1, 0, []),
+      Call(2, 0, []),
+      Call(3, 0, []),
+      Call(4, 0, [])
     ],
     (1, "first yield!"),
     (2, "first return!"),
@@ -547,9 +1101,7 @@ mod tests {
       CreateCoroutine(0),
       Const(1, 1),
       Const(2, 2),
-      Call(1, 0, 2),
-      StealArgument(1),
-      StealArgument(2)
+      Call(1, 0, [StealArgument(1), StealArgument(2)])
     ],
     (1, 3)
   );
@@ -573,14 +1125,10 @@ mod tests {
       CreateCoroutine(0),
       Const(1, 1),
       Const(2, 2),
-      Call(1, 0, 2),
-      CopyArgument(1),
-      CopyArgument(2),
+      Call(1, 0, [CopyArgument(1), CopyArgument(2)]),
       Const(2, 3),
       Const(3, 4),
-      Call(2, 0, 2),
-      CopyArgument(2),
-      CopyArgument(3),
+      Call(2, 0, [CopyArgument(2), CopyArgument(3)]),
     ],
     (1, 3),
     (2, 10)
@@ -593,9 +1141,7 @@ mod tests {
       CreateCoroutine(0),
       Const(1, "this isn't a number!!!"),
       Const(2, "this isn't either! so adding these will throw an error"),
-      Call(3, 0, 2),
-      StealArgument(1),
-      StealArgument(2),
+      Call(3, 0, [StealArgument(1), StealArgument(2)]),
       IsError(4, 3),
       DebugPrint(0),
       IsCoroutineAlive(5, 0)
@@ -614,13 +1160,376 @@ mod tests {
       Const(0, Value::composite_fn(1, block![Yield(0), Return(0)])),
       CreateCoroutine(0),
       Const(1, 1),
-      Call(1, 0, 1),
-      CopyArgument(1),
+      Call(1, 0, [CopyArgument(1)]),
       IsCoroutineAlive(2, 0),
-      Call(1, 0, 0),
+      Call(1, 0, []),
       IsCoroutineAlive(3, 0),
     ],
     (2, true),
     (3, false),
   );
+
+  #[test]
+  fn pretty_print_wraps_wide_lists() {
+    let value = Value::from(vec![
+      Value::from(vec![1.into(), 2.into(), 3.into()]),
+      "a fairly long string".into(),
+    ]);
+    let options = PrettyPrintOptions::default();
+    assert_eq!(
+      value.pretty_description(None, &options),
+      "[[1, 2, 3], \"a fairly long string\"]"
+    );
+    let options = PrettyPrintOptions {
+      width: 20,
+      ..PrettyPrintOptions::default()
+    };
+    assert_eq!(
+      value.pretty_description(None, &options),
+      "[\n  [1, 2, 3],\n  \"a fairly long string\"\n]"
+    );
+  }
+
+  #[test]
+  fn pretty_fits_what_it_can_on_each_line() {
+    let row = |start: i64| {
+      Value::from((start..start + 3).map(Value::from).collect::<Vec<_>>())
+    };
+    let value = Value::from(vec![row(1), row(4), row(7)]);
+    assert_eq!(pretty(&value, 80), "[[1, 2, 3], [4, 5, 6], [7, 8, 9]]");
+    assert_eq!(
+      pretty(&value, 20),
+      "[\n  [1, 2, 3],\n  [4, 5, 6],\n  [7, 8, 9]\n]"
+    );
+    assert_eq!(
+      pretty(&value, 10),
+      "[\n  [\n    1,\n    2,\n    3\n  ],\n  [\n    4,\n    5,\n    6\n  \
+       ],\n  [\n    7,\n    8,\n    9\n  ]\n]"
+    );
+    // the comma after an item has to fit on its line too
+    let value = Value::from(vec![row(1), "abc".into()]);
+    assert_eq!(pretty(&value, 12), "[\n  [1, 2, 3],\n  \"abc\"\n]");
+    assert_eq!(
+      pretty(&value, 11),
+      "[\n  [\n    1,\n    2,\n    3\n  ],\n  \"abc\"\n]"
+    );
+  }
+
+  #[test]
+  fn pretty_print_truncates() {
+    let value = Value::from(vec![
+      Value::from(vec![1.into(), 2.into()]),
+      3.into(),
+      4.into(),
+    ]);
+    let options = PrettyPrintOptions {
+      max_depth: Some(1),
+      max_items: Some(2),
+      ..PrettyPrintOptions::default()
+    };
+    assert_eq!(value.pretty_description(None, &options), "[[...], 3, ...]");
+  }
+
+  #[test]
+  fn pretty_print_colors() {
+    let value = Value::from(vec![1.into(), "a".into(), Value::Nil]);
+    let options = PrettyPrintOptions {
+      color: true,
+      ..PrettyPrintOptions::default()
+    };
+    assert_eq!(
+      value.pretty_description(None, &options),
+      "[\x1b[36m1\x1b[0m, \x1b[32m\"a\"\x1b[0m, \x1b[33mnil\x1b[0m]"
+    );
+  }
+
+  #[test]
+  fn char_predicates() {
+    let check = |f: CoreFnId, arg: Value| CORE_FUNCTIONS[f](vec![arg]);
+    assert_eq!(check(CoreFnId::IsDigit, '7'.into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsDigit, "12a".into()), Ok(false.into()));
+    assert_eq!(check(CoreFnId::IsDigit, "".into()), Ok(false.into()));
+    assert_eq!(check(CoreFnId::IsAlpha, "héllo".into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsWhitespace, '\t'.into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsWhitespace, " \n".into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsUpperCase, 'a'.into()), Ok(false.into()));
+    assert_eq!(
+      check(CoreFnId::IsUpperCase, "ABC-1".into()),
+      Ok(true.into())
+    );
+    assert_eq!(check(CoreFnId::IsUpperCase, "-1".into()), Ok(false.into()));
+    assert_eq!(check(CoreFnId::IsBlank, "".into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsBlank, " \t".into()), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsBlank, Nil), Ok(true.into()));
+    assert_eq!(check(CoreFnId::IsBlank, "a ".into()), Ok(false.into()));
+    assert_eq!(
+      check(CoreFnId::IsAlpha, 1.into()),
+      Err(RuntimeError::ArgumentNotString)
+    );
+  }
+
+  #[test]
+  fn total_ordering() {
+    use std::cmp::Ordering::*;
+    let ascending: Vec<Value> = vec![
+      Nil,
+      false.into(),
+      true.into(),
+      (-1.5).into(),
+      1.into(),
+      1.0.into(),
+      f64::NAN.into(),
+      'a'.into(),
+      "a".into(),
+      "b".into(),
+      Value::from(vec![1.into()]),
+      Value::from(vec![1.into(), 2.into()]),
+      Value::from(vec![2.into()]),
+      Value::from(HashMap::from([(1, 2)])),
+      CoreFn(CoreFnId::Add),
+    ];
+    for (i, a) in ascending.iter().enumerate() {
+      for (j, b) in ascending.iter().enumerate() {
+        assert_eq!(a.total_cmp(b, None), i.cmp(&j), "{a:?} vs {b:?}");
+      }
+    }
+    let map = |entries: [(i64, i64); 2]| Value::from(HashMap::from(entries));
+    assert_eq!(
+      map([(1, 2), (3, 4)]).total_cmp(&map([(3, 4), (1, 2)]), None),
+      Equal
+    );
+    assert_eq!(
+      map([(1, 2), (3, 4)]).total_cmp(&map([(1, 2), (3, 5)]), None),
+      Less
+    );
+  }
+
+  fn hash_of(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+  }
+
+  #[test]
+  fn equal_values_hash_equally() {
+    assert_eq!(Value::from(0.), Value::from(-0.));
+    assert_eq!(hash_of(&0.0.into()), hash_of(&(-0.0).into()));
+    assert_eq!(Value::from(f64::NAN), Value::from(f64::NAN));
+    assert_eq!(hash_of(&f64::NAN.into()), hash_of(&f64::NAN.into()));
+    let a: Value = Hashmap(Rc::new(
+      (0..20)
+        .map(|i| (Value::from(i), Value::from(i * 2)))
+        .collect(),
+    ));
+    let b: Value = Hashmap(Rc::new(
+      (0..20)
+        .rev()
+        .map(|i| (Value::from(i), Value::from(i * 2)))
+        .collect(),
+    ));
+    assert_eq!(a, b);
+    assert_eq!(hash_of(&a), hash_of(&b));
+    let f = Value::composite_fn(0, block![Const(0, 5), Return(0)]);
+    assert_eq!(hash_of(&f), hash_of(&f.clone()));
+  }
+
+  #[test]
+  fn hash_codes_match_map_hashing() {
+    let values = [
+      Value::from(1),
+      Value::from(1.0),
+      "a".into(),
+      Value::from(vec![1.into(), "a".into()]),
+    ];
+    for value in values.iter() {
+      assert_eq!(value.hash_code(), hash_of(value) as i64);
+      assert_eq!(
+        value.hash_code(),
+        CORE_FUNCTIONS[CoreFnId::Hash](vec![value.clone()])
+          .unwrap()
+          .as_num()
+          .unwrap()
+          .as_int_lossless()
+          .unwrap()
+      );
+    }
+    // integral floats hash the same as the corresponding ints
+    assert_eq!(values[0].hash_code(), values[1].hash_code());
+  }
+
+  #[test]
+  fn value_conversions() {
+    let list: Value = vec![1i64, 2, 3].into_iter().collect();
+    assert_eq!(Vec::<i64>::try_from(list.clone()), Ok(vec![1, 2, 3]));
+    assert_eq!(
+      Vec::<String>::try_from(list),
+      Err(RuntimeError::CantConvert(1.into(), "string"))
+    );
+    let map: Value =
+      HashMap::from([("a".to_string(), 1.5), ("b".to_string(), 2.)]).into();
+    assert_eq!(
+      HashMap::<String, f64>::try_from(map),
+      Ok(HashMap::from([
+        ("a".to_string(), 1.5),
+        ("b".to_string(), 2.)
+      ]))
+    );
+    assert_eq!(Value::from(None::<i64>), Nil);
+    assert_eq!(Value::from(Some(true)), Bool(true));
+    assert_eq!(Option::<bool>::try_from(Nil), Ok(None));
+    assert_eq!(Option::<bool>::try_from(Bool(false)), Ok(Some(false)));
+    assert_eq!(i64::try_from(Value::from(2.)), Ok(2));
+    assert_eq!(
+      i64::try_from(Value::from(2.5)),
+      Err(RuntimeError::CantConvert(2.5.into(), "int"))
+    );
+    assert_eq!(f64::try_from(Value::from(2)), Ok(2.));
+    assert_eq!(
+      RuntimeError::CantConvert("x".into(), "bool").description(None),
+      "can't convert value \"x\" to bool"
+    );
+  }
+
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_round_trip() {
+    use super::serde_bridge::{from_value, to_value};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+      Point,
+      Circle(f64),
+      Rect { width: i64, height: i64 },
+    }
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+      name: String,
+      retries: u8,
+      tags: Vec<String>,
+      limit: Option<u64>,
+      shapes: Vec<Shape>,
+    }
+    let config = Config {
+      name: "demo".to_string(),
+      retries: 3,
+      tags: vec!["a".to_string(), "b".to_string()],
+      limit: None,
+      shapes: vec![
+        Shape::Point,
+        Shape::Circle(1.5),
+        Shape::Rect {
+          width: 2,
+          height: 3,
+        },
+      ],
+    };
+    let value = to_value(&config).unwrap();
+    let Hashmap(map) = &value else {
+      panic!("struct didn't serialize to a map")
+    };
+    assert_eq!(map.get(&"retries".into()), Some(&3.into()));
+    assert_eq!(map.get(&"limit".into()), Some(&Nil));
+    assert_eq!(
+      map.get(&"tags".into()),
+      Some(&vec![Value::from("a"), "b".into()].into())
+    );
+    assert_eq!(from_value::<Config>(value), Ok(config));
+    assert_eq!(
+      from_value::<u8>(1000.into()).map_err(|e| e.description(None)),
+      Err(
+        "serialization error: invalid value: integer `1000`, expected u8"
+          .to_string()
+      )
+    );
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn json_objects() {
+    use super::json::{json_parse, json_write};
+
+    let parsed =
+      json_parse(vec![r#"{"a": [1, {"b": null}], "c": true}"#.into()]).unwrap();
+    let Hashmap(map) = &parsed else {
+      panic!("json object didn't parse to a map")
+    };
+    assert_eq!(map.get(&"c".into()), Some(&Bool(true)));
+    assert_eq!(
+      json_write(vec![json_parse(vec![r#"{"a":[1]}"#.into()]).unwrap()]),
+      Ok(r#"{"a":[1]}"#.into())
+    );
+    let non_string_key: Value =
+      Hashmap(Rc::new([(1.into(), 2.into())].into_iter().collect()));
+    assert_eq!(
+      json_write(vec![non_string_key]),
+      Err(RuntimeError::Serialization(String::new()))
+    );
+  }
+
+  #[test]
+  fn edn_round_trip() {
+    use super::edn::{read_edn, write_edn};
+    use crate::compiler::ast::token::SymbolLedger;
+
+    let mut ledger = SymbolLedger::default();
+    let value = read_edn(
+      r#"{:name "a \"quoted\"\nname", :tags #{x y}, ; a comment
+          :nums [1 2.5 -3 12345678901234567890N ##-Inf #_ignored],
+          :chars (\a \space), :tagged #inst "2024", :none nil}"#,
+      &mut ledger,
+    )
+    .unwrap();
+    let written = write_edn(&value, Some(&ledger)).unwrap();
+    assert_eq!(read_edn(&written, &mut ledger), Ok(value.clone()));
+    let Hashmap(map) = &value else {
+      panic!("edn map didn't read as a map")
+    };
+    let key = Symbol(ledger.symbol_index(":nums".to_string()));
+    assert_eq!(
+      write_edn(&map[&key], Some(&ledger)),
+      Ok("(1 2.5 -3 12345678901234567890N ##-Inf)".to_string())
+    );
+    assert_eq!(
+      read_edn("(1 2", &mut ledger),
+      Err(RuntimeError::InvalidEdn(String::new()))
+    );
+    assert_eq!(
+      read_edn("{1}", &mut ledger),
+      Err(RuntimeError::InvalidEdn(String::new()))
+    );
+    assert_eq!(
+      write_edn(&Value::composite_fn(0, block![Return(0)]), Some(&ledger)),
+      Err(RuntimeError::CantConvert(Nil, "edn"))
+    );
+  }
+
+  #[test]
+  fn host_stack_calls() {
+    let mut state = EvaluationState::default();
+    let globals = HashMap::new();
+    state
+      .push_value(Value::composite_fn(2, block![Multiply(0, 0, 1), Return(0)]));
+    state.push_i64(6);
+    state.push_i64(7);
+    state.call(2, 1, &globals).unwrap();
+    assert_eq!(state.get_top(), 1);
+    assert_eq!(state.to_i64(-1), Some(42));
+    assert_eq!(state.to_number(1), Some(42.));
+    assert_eq!(state.to_str(1), None);
+    state.pop(1);
+    state.push_string("label");
+    state.push_value(CoreFn(CoreFnId::Add));
+    state.push_number(1.5);
+    state.push_i64(2);
+    state.call(2, 2, &globals).unwrap();
+    assert_eq!(state.to_str(1), Some("label"));
+    assert_eq!(state.to_number(2), Some(3.5));
+    assert!(state.is_nil(-1));
+    assert_eq!(state.value_at(4), None);
+    state.pop(3);
+    state.push_i64(1);
+    state.call(0, 1, &globals).unwrap_err();
+    assert_eq!(state.get_top(), 0);
+  }
 }
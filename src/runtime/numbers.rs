@@ -0,0 +1,80 @@
+// Converting between numbers and strings. The parsing functions return nil
+// for strings that aren't numbers, so that user input can be checked without
+// having to catch an error
+
+use num_bigint::BigInt as BigInteger;
+use num_traits::Num as _;
+
+use super::{
+  data::{GenericValue::*, Num::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+fn radix(radix: &Value) -> RuntimeResult<u32> {
+  match radix {
+    Number(Int(radix @ 2..=36)) => Ok(*radix as u32),
+    Number(Int(radix)) => Err(RuntimeError::ArgumentOutOfRange(format!(
+      "radix must be between 2 and 36, but got {radix}"
+    ))),
+    _ => Err(RuntimeError::ArgumentNotInt),
+  }
+}
+
+pub(crate) fn parse_int(args: Vec<Value>) -> RuntimeResult<Value> {
+  let (s, radix) = match &args[..] {
+    [Str(s)] => (s, 10),
+    [Str(s), r] => (s, radix(r)?),
+    [_] | [_, _] => return Err(RuntimeError::ArgumentNotString),
+    _ => return Err(RuntimeError::InvalidArity),
+  };
+  // `BigInteger` would also accept underscores between digits
+  if s.contains('_') {
+    return Ok(Nil);
+  }
+  Ok(match i64::from_str_radix(s, radix) {
+    Ok(i) => Number(Int(i)),
+    Err(_) => match BigInteger::from_str_radix(s, radix) {
+      Ok(i) => i.into(),
+      Err(_) => Nil,
+    },
+  })
+}
+
+pub(crate) fn parse_float(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [Str(s)] => Ok(s.parse::<f64>().map_or(Nil, Value::from)),
+    [_] => Err(RuntimeError::ArgumentNotString),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
+// Always writes exactly `precision` digits after the decimal point
+pub(crate) fn format_float(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [Number(n), Number(Int(precision @ 0..))] => {
+      let precision = *precision as usize;
+      Ok(format!("{:.precision$}", n.as_float().0).into())
+    }
+    [Number(_), Number(Int(precision))] => {
+      Err(RuntimeError::ArgumentOutOfRange(format!(
+        "precision can't be negative, but got {precision}"
+      )))
+    }
+    [Number(_), _] => Err(RuntimeError::ArgumentNotInt),
+    [_, _] => Err(RuntimeError::ArgumentNotNum),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
+
+pub(crate) fn number_to_string(args: Vec<Value>) -> RuntimeResult<Value> {
+  match &args[..] {
+    [value @ Number(_)] => Ok(value.description(None).into()),
+    [Number(Int(i)), r] => {
+      Ok(BigInteger::from(*i).to_str_radix(radix(r)?).into())
+    }
+    [Number(BigInt(i)), r] => Ok(i.to_str_radix(radix(r)?).into()),
+    [Number(_), _] => Err(RuntimeError::ArgumentNotInt),
+    [_] | [_, _] => Err(RuntimeError::ArgumentNotNum),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
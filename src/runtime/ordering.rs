@@ -0,0 +1,179 @@
+// A total ordering over all values, so that any collection can be sorted and
+// maps and sets can be printed in a stable order. Values of different kinds
+// are ordered by kind:
+//
+//   nil < bools < numbers < chars < strings < symbols < keywords < lists
+//     < sets < maps < records < errors < regexes < functions < cells
+//     < coroutines < external objects
+//
+// Numbers are compared numerically, with NaN after every other number and an
+// int before an equal float. Without a symbol ledger, keywords can't be told
+// apart from other symbols, and symbols are ordered by when they were first
+// seen rather than by name. Values that are compared by identity, like
+// functions and cells, are ordered by address, which is only stable for as
+// long as they're alive
+
+use std::{cmp::Ordering, rc::Rc};
+
+use enum_map::Enum;
+
+use crate::compiler::ast::token::SymbolLedger;
+
+use super::data::{
+  GenericValue::{self, *},
+  Num::{self, *},
+};
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+  Rc::as_ptr(rc) as *const () as usize
+}
+
+fn num_cmp(a: &Num, b: &Num) -> Ordering {
+  let is_nan = |n: &Num| matches!(n, Float(f) if f.is_nan());
+  let representation = |n: &Num| match n {
+    Int(_) => 0,
+    Float(_) => 1,
+    BigInt(_) => 2,
+  };
+  is_nan(a)
+    .cmp(&is_nan(b))
+    .then_with(|| a.numerical_cmp(b).unwrap_or(Ordering::Equal))
+    .then_with(|| representation(a).cmp(&representation(b)))
+}
+
+fn lexicographic_cmp<T>(
+  mut a: impl Iterator<Item = T>,
+  mut b: impl Iterator<Item = T>,
+  cmp: impl Fn(T, T) -> Ordering,
+) -> Ordering {
+  loop {
+    match (a.next(), b.next()) {
+      (None, None) => return Ordering::Equal,
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(a), Some(b)) => match cmp(a, b) {
+        Ordering::Equal => {}
+        ordering => return ordering,
+      },
+    }
+  }
+}
+
+impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
+  fn kind_rank(&self, symbol_ledger: Option<&SymbolLedger>) -> u8 {
+    match self {
+      Nil => 0,
+      Bool(_) => 1,
+      Number(_) => 2,
+      Char(_) => 3,
+      Str(_) => 4,
+      Symbol(index) => {
+        let is_keyword = symbol_ledger
+          .and_then(|symbol_ledger| symbol_ledger.symbol_name(index))
+          .is_some_and(|name| name.starts_with(':'));
+        if is_keyword {
+          6
+        } else {
+          5
+        }
+      }
+      List(_) => 7,
+      Hashset(_) => 8,
+      Hashmap(_) => 9,
+      Record(_) => 10,
+      Error(_) => 11,
+      #[cfg(feature = "regex")]
+      Regex(_) => 12,
+      CoreFn(_) => 13,
+      CompositeFn(_) => 14,
+      ExternalFn(_) => 15,
+      PartialApplication(_) => 16,
+      Composition(_) => 17,
+      RecordFn(_) => 18,
+      ProtocolFn(_) => 19,
+      Cell(_) => 20,
+      Coroutine(_) => 21,
+      ExternalObject(_) => 22,
+    }
+  }
+  // The elements of a set or the entries of a map, in order
+  pub(crate) fn sorted<T>(
+    elements: impl Iterator<Item = T>,
+    key: impl Fn(&T) -> &Self,
+    symbol_ledger: Option<&SymbolLedger>,
+  ) -> Vec<T> {
+    let mut elements = elements.collect::<Vec<_>>();
+    elements.sort_by(|a, b| key(a).total_cmp(key(b), symbol_ledger));
+    elements
+  }
+  // Consistent with `==`, so two values are only ordered as equal when
+  // they're equal
+  pub(crate) fn total_cmp(
+    &self,
+    other: &Self,
+    symbol_ledger: Option<&SymbolLedger>,
+  ) -> Ordering {
+    let rank_ordering = self
+      .kind_rank(symbol_ledger)
+      .cmp(&other.kind_rank(symbol_ledger));
+    if rank_ordering != Ordering::Equal {
+      return rank_ordering;
+    }
+    let cmp = |a: &Self, b: &Self| a.total_cmp(b, symbol_ledger);
+    match (self, other) {
+      (Bool(a), Bool(b)) => a.cmp(b),
+      (Number(a), Number(b)) => num_cmp(a, b),
+      (Char(a), Char(b)) => a.cmp(b),
+      (Str(a), Str(b)) => a.cmp(b),
+      (Symbol(a), Symbol(b)) => {
+        let name = |index| {
+          symbol_ledger
+            .and_then(|symbol_ledger| symbol_ledger.symbol_name(index))
+        };
+        name(a).cmp(&name(b)).then_with(|| a.cmp(b))
+      }
+      (List(a), List(b)) => lexicographic_cmp(a.iter(), b.iter(), cmp),
+      (Hashset(a), Hashset(b)) => a.len().cmp(&b.len()).then_with(|| {
+        lexicographic_cmp(
+          Self::sorted(a.iter(), |value| *value, symbol_ledger).into_iter(),
+          Self::sorted(b.iter(), |value| *value, symbol_ledger).into_iter(),
+          cmp,
+        )
+      }),
+      (Hashmap(a), Hashmap(b)) => a.len().cmp(&b.len()).then_with(|| {
+        lexicographic_cmp(
+          Self::sorted(a.iter(), |(key, _)| *key, symbol_ledger).into_iter(),
+          Self::sorted(b.iter(), |(key, _)| *key, symbol_ledger).into_iter(),
+          |(a_key, a_value), (b_key, b_value)| {
+            cmp(a_key, b_key).then_with(|| cmp(a_value, b_value))
+          },
+        )
+      }),
+      (Record(a), Record(b)) => a
+        .record_type
+        .name
+        .cmp(&b.record_type.name)
+        .then_with(|| address(&a.record_type).cmp(&address(&b.record_type)))
+        .then_with(|| lexicographic_cmp(a.fields.iter(), b.fields.iter(), cmp)),
+      // errors of the same kind are equal, whatever their messages
+      (Error(a), Error(b)) if a == b => Ordering::Equal,
+      (Error(a), Error(b)) => a.description(None).cmp(&b.description(None)),
+      #[cfg(feature = "regex")]
+      (Regex(a), Regex(b)) => a.as_str().cmp(b.as_str()),
+      (CoreFn(a), CoreFn(b)) => a.into_usize().cmp(&b.into_usize()),
+      (CompositeFn(a), CompositeFn(b)) => address(a).cmp(&address(b)),
+      (ExternalFn(a), ExternalFn(b)) => address(a).cmp(&address(b)),
+      (PartialApplication(a), PartialApplication(b)) => {
+        address(a).cmp(&address(b))
+      }
+      (Composition(a), Composition(b)) => address(a).cmp(&address(b)),
+      (RecordFn(a), RecordFn(b)) => address(a).cmp(&address(b)),
+      (ProtocolFn(a), ProtocolFn(b)) => address(a).cmp(&address(b)),
+      (Cell(a), Cell(b)) => address(a).cmp(&address(b)),
+      (Coroutine(a), Coroutine(b)) => address(a).cmp(&address(b)),
+      (ExternalObject(a), ExternalObject(b)) if a == b => Ordering::Equal,
+      (ExternalObject(a), ExternalObject(b)) => address(a).cmp(&address(b)),
+      _ => Ordering::Equal,
+    }
+  }
+}
@@ -0,0 +1,189 @@
+// `pmap` maps a function over a list. With the `rayon` feature, pure functions
+// are run over chunks of the list on rayon's thread pool, each thread running
+// its chunk in a machine of its own. Values can't be shared between threads,
+// so the function and the chunks are sent to the threads as EDN, and their
+// results come back the same way. Functions that might read globals or have
+// side effects, and lists that can't be written as EDN, are mapped one item at
+// a time instead, as is everything when the `rayon` feature is disabled
+
+use std::collections::HashMap;
+#[cfg(feature = "rayon")]
+use std::rc::Rc;
+
+#[cfg(feature = "rayon")]
+use itertools::Itertools;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "rayon")]
+use crate::{
+  compiler::ast::token::SymbolLedger, instructions::GenericInstruction::*,
+};
+
+#[cfg(feature = "rayon")]
+use super::{
+  capabilities::Capabilities,
+  control::CompositeFunction,
+  edn::{read_edn, write_edn},
+  portable::{decode_block, encode_block, list},
+};
+use super::{
+  control::Block,
+  data::{GenericValue::*, ListStorage, Value},
+  error::RuntimeResult,
+  evaluation::{EvaluationState, SymbolIndex},
+};
+
+// Lists shorter than this aren't worth the trip through EDN
+#[cfg(feature = "rayon")]
+const MIN_PARALLEL_ITEMS: usize = 16;
+
+// Whether a block and the functions it contains can run without the globals,
+// and without side effects, so that running them again is harmless
+#[cfg(feature = "rayon")]
+fn is_pure(block: &Block) -> bool {
+  block.instructions.iter().all(|instruction| {
+    !matches!(
+      instruction,
+      Lookup(..)
+        | LookupDynamic(..)
+        | PushDynamicBinding(..)
+        | DebugPrint(..)
+        | Print(..)
+        | Rand(..)
+        | UpperBoundedRand(..)
+        | LowerUpperBoundedRand(..)
+        | RandInt(..)
+        | LowerBoundedRandInt(..)
+        | SetCellValue(..)
+    )
+  }) && block.constants.iter().all(is_pure_value)
+}
+
+#[cfg(feature = "rayon")]
+fn is_pure_value(value: &Value) -> bool {
+  match value {
+    CompositeFn(f) => f.block().is_ok_and(is_pure),
+    CoreFn(id) => !id.has_side_effects(),
+    PartialApplication(partial) => {
+      is_pure_value(&partial.0) && partial.1.iter().all(is_pure_value)
+    }
+    Composition(fs) => fs.iter().all(is_pure_value),
+    ExternalFn(_) | ExternalObject(_) | Cell(_) => false,
+    _ => true,
+  }
+}
+
+// Runs on one of rayon's threads. Any failure gives up on the whole `pmap`,
+// so that the sequential fallback can report the error, which is safe since
+// the function has no side effects to repeat
+#[cfg(feature = "rayon")]
+fn map_chunk(
+  f: &str,
+  chunk: &str,
+  symbol_names: &[String],
+  capabilities: Capabilities,
+  stack_capacity: usize,
+) -> Option<String> {
+  // The ledger itself can't be shared, since it holds onto host builtins, but
+  // symbols only need to keep their indexes
+  let mut symbol_ledger = SymbolLedger::default();
+  for name in symbol_names {
+    symbol_ledger.symbol_index(name.clone());
+  }
+  let [args, block]: [Value; 2] = list(read_edn(f, &mut symbol_ledger).ok()?)
+    .ok()?
+    .try_into()
+    .ok()?;
+  let f = CompositeFn(Rc::new(CompositeFunction::new(
+    i64::try_from(args).ok()? as u8,
+    decode_block(&block, &Ok).ok()?,
+  )));
+  let items = list(read_edn(chunk, &mut symbol_ledger).ok()?).ok()?;
+  let mut symbol_ledger = Rc::new(symbol_ledger);
+  let mut results = vec![];
+  for item in items {
+    let mut state =
+      EvaluationState::new(Block::for_call(f.clone(), vec![item]))
+        .with_capabilities(capabilities)
+        .with_stack_capacity(stack_capacity);
+    state.set_symbol_ledger(symbol_ledger);
+    let result = state.evaluate(&HashMap::new());
+    symbol_ledger = state.take_symbol_ledger()?;
+    results.push(result.ok()?.unwrap_or(Nil));
+  }
+  write_edn(&results.into_iter().collect(), Some(&symbol_ledger)).ok()
+}
+
+impl EvaluationState {
+  pub(super) fn pmap(
+    &mut self,
+    f: &Value,
+    items: &ListStorage<Value>,
+    global_bindings: &HashMap<SymbolIndex, Value>,
+  ) -> RuntimeResult<Value> {
+    // code run on other threads can't record coverage or be profiled
+    #[cfg(feature = "rayon")]
+    if self.coverage().is_none() && self.profile().is_none() {
+      if let Some(results) = self.map_in_parallel(f, items) {
+        return Ok(results);
+      }
+    }
+    items
+      .iter()
+      .map(|item| {
+        let mut state =
+          self.sub_vm(Block::for_call(f.clone(), vec![item.clone()]));
+        let result = state.evaluate(global_bindings);
+        self.merge_sub_vm(&mut state);
+        Ok(result?.unwrap_or(Nil))
+      })
+      .collect()
+  }
+  #[cfg(feature = "rayon")]
+  fn map_in_parallel(
+    &mut self,
+    f: &Value,
+    items: &ListStorage<Value>,
+  ) -> Option<Value> {
+    let CompositeFn(composite_fn) = f else {
+      return None;
+    };
+    let block = composite_fn.block().ok()?;
+    if items.len() < MIN_PARALLEL_ITEMS || !is_pure(block) {
+      return None;
+    }
+    let symbol_ledger =
+      Rc::make_mut(self.symbol_ledger.get_or_insert_with(Default::default));
+    let f = write_edn(
+      &Value::from(vec![
+        (composite_fn.args.count as i64).into(),
+        encode_block(block).ok()?,
+      ]),
+      Some(symbol_ledger),
+    )
+    .ok()?;
+    let chunk_size = items.len().div_ceil(rayon::current_num_threads());
+    let chunks = items
+      .iter()
+      .cloned()
+      .chunks(chunk_size)
+      .into_iter()
+      .map(|chunk| write_edn(&chunk.collect(), Some(symbol_ledger)).ok())
+      .collect::<Option<Vec<String>>>()?;
+    let (capabilities, stack_capacity) =
+      (self.capabilities, self.stack_capacity);
+    let symbol_names = symbol_ledger.symbol_names().cloned().collect_vec();
+    let results = chunks
+      .par_iter()
+      .map(|chunk| {
+        map_chunk(&f, chunk, &symbol_names, capabilities, stack_capacity)
+      })
+      .collect::<Option<Vec<String>>>()?;
+    let mut values = ListStorage::new();
+    for result in results {
+      values.append(list(read_edn(&result, symbol_ledger).ok()?).ok()?.into());
+    }
+    Some(List(Rc::new(values)))
+  }
+}
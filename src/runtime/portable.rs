@@ -0,0 +1,138 @@
+// Encodes blocks as plain values, so that bytecode can be written out as EDN
+// and read back somewhere else, like from an artifact that was compiled ahead
+// of time, or on another thread. Functions among a block's constants are
+// stored as `("fn" args block)`, core functions as `("core-fn" name)`, and any
+// other constant as `("value" value)`
+
+use std::collections::HashMap;
+
+use super::{
+  control::{Block, CompositeFunction},
+  core_functions::CoreFnId,
+  data::{GenericValue::*, Value},
+  edn::can_write_edn,
+  error::{RuntimeError, RuntimeResult},
+  evaluation::{Instruction, Register, SymbolIndex},
+  serde_bridge::{from_value, to_value},
+};
+use crate::{
+  compiler::ast::token::SymbolLedger, instructions::GenericInstruction::*,
+};
+
+fn malformed(message: impl Into<String>) -> RuntimeError {
+  RuntimeError::Serialization(message.into())
+}
+
+pub(crate) fn string_map(entries: Vec<(&str, Value)>) -> Value {
+  entries
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect::<HashMap<_, _>>()
+    .into()
+}
+
+pub(crate) fn field(value: &Value, key: &str) -> RuntimeResult<Value> {
+  match value {
+    Hashmap(map) => map
+      .get(&key.into())
+      .cloned()
+      .ok_or_else(|| malformed(format!("missing `{key}`"))),
+    _ => Err(malformed(format!("expected a map with `{key}`"))),
+  }
+}
+
+pub(crate) fn list(value: Value) -> RuntimeResult<Vec<Value>> {
+  match value {
+    List(list) => Ok(list.iter().cloned().collect()),
+    _ => Err(malformed("expected a list")),
+  }
+}
+
+// Whether a constant can be encoded and then written as EDN, checked without
+// doing either
+pub(crate) fn is_portable(
+  constant: &Value,
+  symbol_ledger: Option<&SymbolLedger>,
+) -> bool {
+  match constant {
+    CompositeFn(f) => f.block().is_ok_and(|block| {
+      block
+        .constants
+        .iter()
+        .all(|constant| is_portable(constant, symbol_ledger))
+    }),
+    CoreFn(_) => true,
+    value => can_write_edn(value, symbol_ledger),
+  }
+}
+
+// Deferred functions are compiled first, since only bytecode can be encoded
+pub(crate) fn encode_block(block: &Block) -> RuntimeResult<Value> {
+  let constants = block
+    .constants
+    .iter()
+    .map(|constant| match constant {
+      CompositeFn(f) => Ok(Value::from(vec![
+        "fn".into(),
+        (f.args.count as i64).into(),
+        encode_block(f.block()?)?,
+      ])),
+      CoreFn(id) => Ok(Value::from(vec!["core-fn".into(), id.name().into()])),
+      constant => Ok(Value::from(vec!["value".into(), constant.clone()])),
+    })
+    .collect::<RuntimeResult<Vec<Value>>>()?;
+  Ok(string_map(vec![
+    ("instructions", to_value(&block.instructions)?),
+    ("constants", constants.into()),
+    ("max-register", (block.metadata as i64).into()),
+  ]))
+}
+
+// The symbol indexes in the encoded instructions are passed through `symbol`,
+// for when they were encoded against a different symbol ledger
+pub(crate) fn decode_block(
+  value: &Value,
+  symbol: &impl Fn(SymbolIndex) -> RuntimeResult<SymbolIndex>,
+) -> RuntimeResult<Block> {
+  let instructions =
+    from_value::<Vec<Instruction>>(field(value, "instructions")?)?
+      .into_iter()
+      .map(|instruction| {
+        Ok(match instruction {
+          Lookup(output, index) => Lookup(output, symbol(index)?),
+          LookupDynamic(output, index) => LookupDynamic(output, symbol(index)?),
+          PushDynamicBinding(index, input) => {
+            PushDynamicBinding(symbol(index)?, input)
+          }
+          instruction => instruction,
+        })
+      })
+      .collect::<RuntimeResult<Vec<_>>>()?;
+  let constants = list(field(value, "constants")?)?
+    .into_iter()
+    .map(|constant| {
+      let parts = list(constant)?;
+      match parts.as_slice() {
+        [Str(kind), value] if kind.as_str() == "value" => Ok(value.clone()),
+        [Str(kind), args, block] if kind.as_str() == "fn" => Ok(CompositeFn(
+          CompositeFunction::new(
+            i64::try_from(args.clone())? as u8,
+            decode_block(block, symbol)?,
+          )
+          .into(),
+        )),
+        [Str(kind), Str(name)] if kind.as_str() == "core-fn" => CoreFnId::all()
+          .find(|core_fn_id| core_fn_id.name() == name.as_str())
+          .map(CoreFn)
+          .ok_or_else(|| malformed(format!("unknown core function {name}"))),
+        _ => Err(malformed("malformed constant")),
+      }
+    })
+    .collect::<RuntimeResult<Vec<_>>>()?;
+  let max_register = i64::try_from(field(value, "max-register")?)?;
+  Ok(Block::new_with_metadata(
+    instructions,
+    constants,
+    max_register as Register,
+  ))
+}
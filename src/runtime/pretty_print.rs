@@ -0,0 +1,214 @@
+use crate::{
+  compiler::ast::token::SymbolLedger,
+  string_utils::{highlight, render, Layout},
+};
+
+use super::data::{
+  GenericValue::{self, *},
+  Value,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrettyPrintOptions {
+  pub width: usize,
+  pub indent: usize,
+  pub max_depth: Option<usize>,
+  pub max_items: Option<usize>,
+  // Whether to color literals with ANSI escape codes, which should only be
+  // enabled when printing to a terminal
+  pub color: bool,
+}
+
+impl Default for PrettyPrintOptions {
+  fn default() -> Self {
+    Self {
+      width: 80,
+      indent: 2,
+      max_depth: None,
+      max_items: None,
+      color: false,
+    }
+  }
+}
+
+struct PrettyPrinter<'a> {
+  options: &'a PrettyPrintOptions,
+  symbol_ledger: Option<&'a SymbolLedger>,
+  // Addresses of the collections currently being printed, used to detect
+  // values that contain themselves
+  ancestors: Vec<*const ()>,
+}
+
+impl<'a> PrettyPrinter<'a> {
+  fn print<I: Clone, O: Clone, R: Clone, M: Clone>(
+    &mut self,
+    value: &GenericValue<I, O, R, M>,
+    depth: usize,
+  ) -> Layout {
+    let address = match value {
+      List(list) => &**list as *const _ as *const (),
+      Hashmap(hashmap) => &**hashmap as *const _ as *const (),
+      Hashset(hashset) => &**hashset as *const _ as *const (),
+      PartialApplication(application) => {
+        &**application as *const _ as *const ()
+      }
+      Composition(fs) => &**fs as *const _ as *const (),
+      other => {
+        return Layout::text(other.description(self.symbol_ledger).trim_end())
+      }
+    };
+    if self.ancestors.contains(&address) {
+      return Layout::text("<cycle>");
+    }
+    self.ancestors.push(address);
+    let layout = match value {
+      List(list) => self.print_collection("[", "]", list.iter(), depth),
+      Hashmap(hashmap) if self.elided(depth, hashmap.is_empty()) => {
+        Layout::text("{...}")
+      }
+      Hashmap(hashmap) => {
+        let entries = GenericValue::sorted(
+          hashmap.iter(),
+          |(key, _)| *key,
+          self.symbol_ledger,
+        );
+        let truncated = self.truncate(entries.len());
+        let mut items = entries
+          .into_iter()
+          .take(self.options.max_items.unwrap_or(usize::MAX))
+          .map(|(key, value)| {
+            Layout::Concat(vec![
+              self.print(key, depth + 1),
+              Layout::text(" "),
+              self.print(value, depth + 1),
+            ])
+          })
+          .collect::<Vec<_>>();
+        if truncated {
+          items.push(Layout::text("..."))
+        }
+        self.join("{", "}", items)
+      }
+      Hashset(hashset) => self.print_collection(
+        "#{",
+        "}",
+        GenericValue::sorted(
+          hashset.iter(),
+          |value| *value,
+          self.symbol_ledger,
+        )
+        .into_iter(),
+        depth,
+      ),
+      PartialApplication(application) => {
+        let (f, args) = &**application;
+        Layout::Concat(vec![
+          Layout::text("partial application: f = "),
+          self.print(f, depth + 1),
+          Layout::text(", args = "),
+          self.print_collection("[", "]", args.iter(), depth),
+        ])
+      }
+      Composition(fs) => Layout::Concat(vec![
+        Layout::text("composition: "),
+        self.print_collection("[", "]", fs.iter(), depth),
+      ]),
+      _ => unreachable!(),
+    };
+    self.ancestors.pop();
+    layout
+  }
+  fn truncate(&self, count: usize) -> bool {
+    self
+      .options
+      .max_items
+      .map(|max_items| count > max_items)
+      .unwrap_or(false)
+  }
+  fn print_collection<
+    'v,
+    I: Clone + 'v,
+    O: Clone + 'v,
+    R: Clone + 'v,
+    M: Clone + 'v,
+  >(
+    &mut self,
+    open: &str,
+    close: &str,
+    values: impl ExactSizeIterator<Item = &'v GenericValue<I, O, R, M>>,
+    depth: usize,
+  ) -> Layout {
+    if self.elided(depth, values.len() == 0) {
+      return Layout::text(format!("{open}...{close}"));
+    }
+    let truncated = self.truncate(values.len());
+    let mut items = values
+      .take(self.options.max_items.unwrap_or(usize::MAX))
+      .map(|value| self.print(value, depth + 1))
+      .collect::<Vec<_>>();
+    if truncated {
+      items.push(Layout::text("..."))
+    }
+    self.join(open, close, items)
+  }
+  fn elided(&self, depth: usize, empty: bool) -> bool {
+    !empty
+      && self
+        .options
+        .max_depth
+        .map(|max_depth| depth >= max_depth)
+        .unwrap_or(false)
+  }
+  // Either all on one line, or with each item on its own indented line
+  fn join(&self, open: &str, close: &str, items: Vec<Layout>) -> Layout {
+    if items.is_empty() {
+      return Layout::text(format!("{open}{close}"));
+    }
+    let mut inner = vec![Layout::Break("")];
+    for (i, item) in items.into_iter().enumerate() {
+      if i > 0 {
+        inner.extend([Layout::text(","), Layout::Break(" ")]);
+      }
+      inner.push(item);
+    }
+    Layout::Group(vec![
+      Layout::text(open),
+      Layout::Concat(inner).nest(self.options.indent),
+      Layout::Break(""),
+      Layout::text(close),
+    ])
+  }
+}
+
+impl<I: Clone, O: Clone, R: Clone, M: Clone> GenericValue<I, O, R, M> {
+  pub(crate) fn pretty_description(
+    &self,
+    symbol_ledger: Option<&SymbolLedger>,
+    options: &PrettyPrintOptions,
+  ) -> String {
+    let layout = PrettyPrinter {
+      options,
+      symbol_ledger,
+      ancestors: vec![],
+    }
+    .print(self, 0);
+    let description = render(&layout, options.width);
+    if options.color {
+      highlight(&description)
+    } else {
+      description
+    }
+  }
+}
+
+// Lays a value out to fit within `width` columns where it can, breaking
+// collections that don't fit across multiple lines
+pub fn pretty(value: &Value, width: usize) -> String {
+  value.pretty_description(
+    None,
+    &PrettyPrintOptions {
+      width,
+      ..PrettyPrintOptions::default()
+    },
+  )
+}
@@ -0,0 +1,137 @@
+// Hit counts and time spent per instruction, and time spent per call stack,
+// recorded while profiling is enabled. Each instruction is timed from when
+// it's fetched until the next one is, so a call of a core function or an
+// external function counts the time spent in it toward the calling
+// instruction. Like coverage, blocks are told apart by their instructions
+
+use std::{collections::HashMap, rc::Rc, time::Instant};
+
+use super::{
+  control::{Block, StackFrame},
+  evaluation::Instruction,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstructionProfile {
+  pub hits: u64,
+  pub nanos: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+  instructions: HashMap<(*const Instruction, usize), InstructionProfile>,
+  // Keyed by the blocks of each frame, outermost first
+  stacks: HashMap<Rc<Vec<*const Instruction>>, u64>,
+}
+
+impl Profile {
+  pub fn instruction(
+    &self,
+    block: &Block,
+    instruction_index: usize,
+  ) -> InstructionProfile {
+    self
+      .instructions
+      .get(&(block.instructions.as_ptr(), instruction_index))
+      .copied()
+      .unwrap_or_default()
+  }
+  // The nanoseconds spent in each call stack, not counting time spent in the
+  // functions called from it
+  pub fn stacks(&self) -> impl Iterator<Item = (&[*const Instruction], u64)> {
+    self
+      .stacks
+      .iter()
+      .map(|(stack, nanos)| (stack.as_slice(), *nanos))
+  }
+  fn merge(&mut self, other: Profile) {
+    for (key, other) in other.instructions {
+      let profile = self.instructions.entry(key).or_default();
+      profile.hits += other.hits;
+      profile.nanos += other.nanos;
+    }
+    for (stack, nanos) in other.stacks {
+      *self.stacks.entry(stack).or_default() += nanos;
+    }
+  }
+}
+
+struct Running {
+  fetched: Instant,
+  instruction: (*const Instruction, usize),
+}
+
+#[derive(Default)]
+pub(crate) struct Profiler {
+  pub profile: Profile,
+  // The frames of the machine that started this one, if any, which its own
+  // stacks are nested under
+  outer_stack: Vec<*const Instruction>,
+  stack: Rc<Vec<*const Instruction>>,
+  running: Option<Running>,
+}
+
+impl Profiler {
+  // A profiler for a machine started by a core function, whose stacks sit on
+  // top of the ones running in this profiler's machine
+  pub fn nested(&self) -> Self {
+    Self {
+      outer_stack: self.stack.to_vec(),
+      ..Self::default()
+    }
+  }
+  pub fn record(
+    &mut self,
+    instructions: &Rc<[Instruction]>,
+    instruction_index: usize,
+    paused_frames: &[StackFrame],
+  ) {
+    let now = Instant::now();
+    self.stop(now);
+    let block = instructions.as_ptr();
+    let stack = || {
+      self
+        .outer_stack
+        .iter()
+        .copied()
+        .chain(
+          paused_frames
+            .iter()
+            .map(|frame| frame.block.instructions.as_ptr()),
+        )
+        .chain(std::iter::once(block))
+    };
+    // the stack only has to be rebuilt once a call or return has changed it
+    if !self.stack.iter().copied().eq(stack()) {
+      self.stack = Rc::new(stack().collect());
+    }
+    self.running = Some(Running {
+      fetched: now,
+      instruction: (block, instruction_index),
+    });
+  }
+  // Stops timing the instruction that's running, if any
+  pub fn stop(&mut self, now: Instant) {
+    if let Some(running) = self.running.take() {
+      let nanos = now.duration_since(running.fetched).as_nanos() as u64;
+      let profile = self
+        .profile
+        .instructions
+        .entry(running.instruction)
+        .or_default();
+      profile.hits += 1;
+      profile.nanos += nanos;
+      *self.profile.stacks.entry(self.stack.clone()).or_default() += nanos;
+    }
+  }
+  // Adds the profile of a machine started by the instruction that's running,
+  // whose time isn't counted again toward that instruction
+  pub fn merge_nested(&mut self, mut nested: Profiler) {
+    let now = Instant::now();
+    nested.stop(now);
+    self.profile.merge(nested.profile);
+    if let Some(running) = &mut self.running {
+      running.fetched = now;
+    }
+  }
+}
@@ -0,0 +1,174 @@
+use std::{
+  cell::RefCell,
+  collections::HashMap,
+  hash::{Hash, Hasher},
+  rc::Rc,
+};
+
+use super::{
+  data::{
+    GenericValue::{self, *},
+    Value,
+  },
+  error::{RuntimeError, RuntimeResult},
+  records::RecordType,
+};
+
+// The type that protocol methods are dispatched on. Records are keyed by the
+// identity of their record type, so that redefining a record requires its
+// protocol implementations to be redefined too
+#[derive(Clone, Debug)]
+pub enum TypeKey {
+  Builtin(&'static str),
+  Record(Rc<RecordType>),
+  Default,
+}
+
+impl PartialEq for TypeKey {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (TypeKey::Builtin(a), TypeKey::Builtin(b)) => a == b,
+      (TypeKey::Record(a), TypeKey::Record(b)) => Rc::ptr_eq(a, b),
+      (TypeKey::Default, TypeKey::Default) => true,
+      _ => false,
+    }
+  }
+}
+impl Eq for TypeKey {}
+
+impl Hash for TypeKey {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    std::mem::discriminant(self).hash(state);
+    match self {
+      TypeKey::Builtin(name) => name.hash(state),
+      TypeKey::Record(record_type) => Rc::as_ptr(record_type).hash(state),
+      TypeKey::Default => {}
+    }
+  }
+}
+
+pub const BUILTIN_TYPE_NAMES: [&str; 15] = [
+  "Nil",
+  "Bool",
+  "Char",
+  "Number",
+  "Symbol",
+  "String",
+  "List",
+  "Map",
+  "Set",
+  "Fn",
+  "Coroutine",
+  "Atom",
+  "Error",
+  "External",
+  "Regex",
+];
+
+impl TypeKey {
+  // Finds the type with the given name, as used in `extend-type`. Record
+  // types are looked up separately, since they aren't known statically
+  pub fn builtin(name: &str) -> Option<Self> {
+    if name == "default" {
+      Some(TypeKey::Default)
+    } else {
+      BUILTIN_TYPE_NAMES
+        .iter()
+        .find(|builtin_name| **builtin_name == name)
+        .map(|builtin_name| TypeKey::Builtin(builtin_name))
+    }
+  }
+  pub fn of(value: &Value) -> Self {
+    TypeKey::Builtin(match value {
+      Nil => "Nil",
+      Bool(_) => "Bool",
+      Char(_) => "Char",
+      Number(_) => "Number",
+      Symbol(_) => "Symbol",
+      Str(_) => "String",
+      List(_) => "List",
+      Hashmap(_) => "Map",
+      Hashset(_) => "Set",
+      CoreFn(_)
+      | CompositeFn(_)
+      | ExternalFn(_)
+      | PartialApplication(_)
+      | Composition(_)
+      | RecordFn(_)
+      | GenericValue::ProtocolFn(_) => "Fn",
+      Coroutine(_) => "Coroutine",
+      Cell(_) => "Atom",
+      Error(_) => "Error",
+      ExternalObject(_) => "External",
+      #[cfg(feature = "regex")]
+      Regex(_) => "Regex",
+      Record(record) => return TypeKey::Record(record.record_type.clone()),
+    })
+  }
+  pub fn name(&self) -> String {
+    match self {
+      TypeKey::Builtin(name) => name.to_string(),
+      TypeKey::Record(record_type) => record_type.name.clone(),
+      TypeKey::Default => "default".to_string(),
+    }
+  }
+}
+
+// A method of a protocol defined with `(defprotocol Name method ...)`.
+// Implementations are added with `extend-type`, and are chosen based on the
+// type of the first argument
+#[derive(Debug)]
+pub struct ProtocolFn {
+  pub protocol_name: String,
+  pub name: String,
+  implementations: RefCell<HashMap<TypeKey, Value>>,
+}
+
+impl ProtocolFn {
+  pub fn new(protocol_name: String, name: String) -> Self {
+    Self {
+      protocol_name,
+      name,
+      implementations: RefCell::new(HashMap::new()),
+    }
+  }
+  pub fn implement(&self, type_key: TypeKey, implementation: Value) {
+    self
+      .implementations
+      .borrow_mut()
+      .insert(type_key, implementation);
+  }
+  pub fn resolve(&self, first_arg: Option<&Value>) -> RuntimeResult<Value> {
+    let type_key = TypeKey::of(first_arg.ok_or(RuntimeError::InvalidArity)?);
+    let implementations = self.implementations.borrow();
+    implementations
+      .get(&type_key)
+      .or_else(|| implementations.get(&TypeKey::Default))
+      .cloned()
+      .ok_or_else(|| {
+        RuntimeError::NoProtocolImplementation(
+          self.name.clone(),
+          type_key.name(),
+        )
+      })
+  }
+}
+
+// Returns the name and value of each method of a protocol
+pub fn protocol_fns(
+  protocol_name: String,
+  method_names: Vec<String>,
+) -> Vec<(String, Value)> {
+  method_names
+    .into_iter()
+    .map(|method_name| {
+      (
+        method_name.clone(),
+        GenericValue::ProtocolFn(Rc::new(ProtocolFn::new(
+          protocol_name.clone(),
+          method_name,
+        ))),
+      )
+    })
+    .collect()
+}
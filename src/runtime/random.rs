@@ -0,0 +1,126 @@
+// The random number generator behind `rand` and the other random functions.
+// It's a SplitMix64 generator, whose whole state is a single number, so that
+// it's cheap to copy into the machines that core functions start and to seed
+// for `with-seed`. It's fine for simulations and games, but it isn't meant to
+// be unpredictable enough for anything security-related
+
+use std::hash::{BuildHasher, RandomState};
+
+use super::{
+  core_functions::CoreFnId,
+  data::{GenericValue::*, Num::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+  state: u64,
+}
+
+impl Rng {
+  pub fn seeded(seed: u64) -> Self {
+    Self { state: seed }
+  }
+  // Seeded from the random keys that std generates for each hash map
+  pub fn from_entropy() -> Self {
+    Self::seeded(RandomState::new().hash_one(0))
+  }
+  // A generator seeded with this picks up where this one left off
+  pub fn state(&self) -> u64 {
+    self.state
+  }
+  pub fn next_u64(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+  // Between 0 (inclusive) and 1 (exclusive)
+  pub fn next_f64(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+  // Below `bound`, which must be positive, with every value equally likely
+  pub fn below(&mut self, bound: u64) -> u64 {
+    let unbiased_limit = u64::MAX - u64::MAX % bound;
+    loop {
+      let x = self.next_u64();
+      if x < unbiased_limit {
+        return x % bound;
+      }
+    }
+  }
+  // Shuffles the first `count` items into a random selection of all of them,
+  // leaving the rest in no particular order
+  fn partial_shuffle<T>(&mut self, items: &mut [T], count: usize) {
+    for i in 0..count.min(items.len()) {
+      let j = i + self.below((items.len() - i) as u64) as usize;
+      items.swap(i, j);
+    }
+  }
+}
+
+fn items(collection: &Value) -> RuntimeResult<Vec<Value>> {
+  match collection {
+    List(list) => Ok(list.iter().cloned().collect()),
+    Hashset(set) => Ok(set.iter().cloned().collect()),
+    Nil => Ok(vec![]),
+    _ => Err(RuntimeError::ArgumentNotList),
+  }
+}
+
+// Calls `rand`, `rand-int`, `rand-nth`, `shuffle`, or `sample`
+pub(crate) fn call_random_fn(
+  core_fn_id: CoreFnId,
+  args: &[Value],
+  rng: &mut Rng,
+) -> RuntimeResult<Value> {
+  match (core_fn_id, args) {
+    (CoreFnId::Rand, []) => Ok(rng.next_f64().into()),
+    (CoreFnId::Rand, [Number(n)]) => {
+      Ok((rng.next_f64() * n.as_float().0).into())
+    }
+    (CoreFnId::Rand, [_]) => Err(RuntimeError::ArgumentNotNum),
+    (CoreFnId::RandInt, [Number(Int(bound @ 1..))]) => {
+      Ok((rng.below(*bound as u64) as i64).into())
+    }
+    (CoreFnId::RandInt, [Number(Int(bound))]) => {
+      Err(RuntimeError::ArgumentOutOfRange(format!(
+        "rand-int needs a positive bound, but got {bound}"
+      )))
+    }
+    (CoreFnId::RandInt, [_]) => Err(RuntimeError::ArgumentNotInt),
+    (CoreFnId::RandNth, [collection]) => {
+      let mut items = items(collection)?;
+      if items.is_empty() {
+        return Err(RuntimeError::ArgumentOutOfRange(
+          "rand-nth needs a non-empty collection".to_string(),
+        ));
+      }
+      let index = rng.below(items.len() as u64) as usize;
+      Ok(items.swap_remove(index))
+    }
+    (CoreFnId::Shuffle, [collection]) => {
+      let mut items = items(collection)?;
+      let count = items.len();
+      rng.partial_shuffle(&mut items, count);
+      Ok(items.into())
+    }
+    // `count` of the collection's items, each picked at most once, or all of
+    // them if there aren't that many
+    (CoreFnId::Sample, [Number(Int(count @ 0..)), collection]) => {
+      let mut items = items(collection)?;
+      let count = (*count as usize).min(items.len());
+      rng.partial_shuffle(&mut items, count);
+      items.truncate(count);
+      Ok(items.into())
+    }
+    (CoreFnId::Sample, [Number(Int(count)), _]) => {
+      Err(RuntimeError::ArgumentOutOfRange(format!(
+        "sample needs a count of at least 0, but got {count}"
+      )))
+    }
+    (CoreFnId::Sample, [_, _]) => Err(RuntimeError::ArgumentNotInt),
+    _ => Err(RuntimeError::InvalidArity),
+  }
+}
@@ -0,0 +1,112 @@
+use std::{fmt::Debug, rc::Rc};
+
+use super::{
+  data::{GenericValue, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+// Created by `(defrecord Name (field ...))`. Record types are compared by
+// identity, so redefining a record creates a new, distinct type
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecordType {
+  pub name: String,
+  pub field_names: Vec<String>,
+}
+
+impl RecordType {
+  pub fn new(name: String, field_names: Vec<String>) -> Self {
+    Self { name, field_names }
+  }
+}
+
+// Records store their fields positionally rather than in a map keyed by field
+// name, since the names are shared by every record of the same type
+#[derive(Clone)]
+pub struct GenericRecord<I, O, R, M> {
+  pub record_type: Rc<RecordType>,
+  pub fields: Box<[GenericValue<I, O, R, M>]>,
+}
+
+impl<I: Clone + Debug, O: Clone + Debug, R: Clone + Debug, M: Clone + Debug>
+  Debug for GenericRecord<I, O, R, M>
+{
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("GenericRecord")
+      .field("record_type", &self.record_type.name)
+      .field("fields", &self.fields)
+      .finish()
+  }
+}
+
+pub type Record = GenericRecord<
+  super::evaluation::Register,
+  super::evaluation::Register,
+  super::evaluation::Register,
+  super::evaluation::Register,
+>;
+
+// The functions that `defrecord` binds for each record type
+#[derive(Debug)]
+pub enum RecordFn {
+  Constructor(Rc<RecordType>),
+  Predicate(Rc<RecordType>),
+  Accessor(Rc<RecordType>, usize),
+}
+
+impl RecordFn {
+  pub fn name(&self) -> String {
+    match self {
+      RecordFn::Constructor(record_type) => format!("->{}", record_type.name),
+      RecordFn::Predicate(record_type) => format!("{}?", record_type.name),
+      RecordFn::Accessor(record_type, field_index) => format!(
+        "{}-{}",
+        record_type.name, record_type.field_names[*field_index]
+      ),
+    }
+  }
+  pub fn call(&self, args: Vec<Value>) -> RuntimeResult<Value> {
+    match self {
+      RecordFn::Constructor(record_type) => {
+        if args.len() == record_type.field_names.len() {
+          Ok(GenericValue::Record(Rc::new(Record {
+            record_type: record_type.clone(),
+            fields: args.into(),
+          })))
+        } else {
+          Err(RuntimeError::InvalidArity)
+        }
+      }
+      RecordFn::Predicate(record_type) => match &args[..] {
+        [GenericValue::Record(record)] => {
+          Ok(Rc::ptr_eq(&record.record_type, record_type).into())
+        }
+        [_] => Ok(false.into()),
+        _ => Err(RuntimeError::InvalidArity),
+      },
+      RecordFn::Accessor(record_type, field_index) => match &args[..] {
+        [GenericValue::Record(record)]
+          if Rc::ptr_eq(&record.record_type, record_type) =>
+        {
+          Ok(record.fields[*field_index].clone())
+        }
+        [_] => Err(RuntimeError::ArgumentNotRecord(record_type.name.clone())),
+        _ => Err(RuntimeError::InvalidArity),
+      },
+    }
+  }
+}
+
+// Returns the name and value of each function that `defrecord` defines
+pub fn record_fns(record_type: Rc<RecordType>) -> Vec<(String, Value)> {
+  std::iter::once(RecordFn::Constructor(record_type.clone()))
+    .chain(std::iter::once(RecordFn::Predicate(record_type.clone())))
+    .chain(
+      (0..record_type.field_names.len()).map(|field_index| {
+        RecordFn::Accessor(record_type.clone(), field_index)
+      }),
+    )
+    .map(|record_fn| {
+      (record_fn.name(), GenericValue::RecordFn(Rc::new(record_fn)))
+    })
+    .collect()
+}
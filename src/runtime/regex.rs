@@ -0,0 +1,137 @@
+use super::{
+  data::{GenericValue::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+fn string_arg(value: &Value) -> RuntimeResult<&str> {
+  match value {
+    Str(s) => Ok(s.as_str()),
+    _ => Err(RuntimeError::ArgumentNotString),
+  }
+}
+
+// `replace` is available even without the `regex` feature, in which case only
+// plain strings can be used as the pattern
+pub(crate) fn replace(args: Vec<Value>) -> RuntimeResult<Value> {
+  if args.len() != 3 {
+    return Err(RuntimeError::InvalidArity);
+  }
+  let s = string_arg(&args[0])?;
+  let replacement = string_arg(&args[2])?;
+  Ok(
+    match &args[1] {
+      Str(pattern) => s.replace(pattern.as_str(), replacement),
+      #[cfg(feature = "regex")]
+      Regex(regex) => regex.replace_all(s, replacement).into_owned(),
+      _ => return Err(RuntimeError::ArgumentNotRegex),
+    }
+    .into(),
+  )
+}
+
+#[cfg(feature = "regex")]
+mod enabled {
+  use std::rc::Rc;
+
+  use ::regex::{Captures, Regex};
+
+  use super::string_arg;
+  use crate::runtime::{
+    data::{GenericValue::*, ListStorage, Value},
+    error::{RuntimeError, RuntimeResult},
+  };
+
+  fn compile(pattern: &str) -> RuntimeResult<Regex> {
+    Regex::new(pattern)
+      .map_err(|error| RuntimeError::InvalidRegex(error.to_string()))
+  }
+
+  // Strings are accepted anywhere a regex is, and compiled on the spot
+  fn regex_arg(value: &Value) -> RuntimeResult<Rc<Regex>> {
+    match value {
+      Regex(regex) => Ok(regex.clone()),
+      Str(pattern) => compile(pattern).map(Rc::new),
+      _ => Err(RuntimeError::ArgumentNotRegex),
+    }
+  }
+
+  // A match is just the matched string when the regex has no capture groups,
+  // and otherwise a list of the whole match followed by each group, with nil
+  // for groups that didn't participate in the match
+  fn match_value(captures: Captures) -> Value {
+    if captures.len() == 1 {
+      captures[0].into()
+    } else {
+      List(Rc::new(
+        captures
+          .iter()
+          .map(|group| group.map(|group| group.as_str().into()).unwrap_or(Nil))
+          .collect::<ListStorage<Value>>(),
+      ))
+    }
+  }
+
+  pub(crate) fn re_pattern(args: Vec<Value>) -> RuntimeResult<Value> {
+    match &args[..] {
+      [Regex(regex)] => Ok(Regex(regex.clone())),
+      [Str(pattern)] => Ok(Regex(Rc::new(compile(pattern)?))),
+      [_] => Err(RuntimeError::ArgumentNotString),
+      _ => Err(RuntimeError::InvalidArity),
+    }
+  }
+
+  pub(crate) fn re_find(args: Vec<Value>) -> RuntimeResult<Value> {
+    if args.len() != 2 {
+      return Err(RuntimeError::InvalidArity);
+    }
+    let regex = regex_arg(&args[0])?;
+    Ok(
+      regex
+        .captures(string_arg(&args[1])?)
+        .map(match_value)
+        .unwrap_or(Nil),
+    )
+  }
+
+  pub(crate) fn re_matches(args: Vec<Value>) -> RuntimeResult<Value> {
+    if args.len() != 2 {
+      return Err(RuntimeError::InvalidArity);
+    }
+    // Anchoring the pattern rather than checking the bounds of the first match
+    // ensures that a full match is found even when a shorter alternative would
+    // match first
+    let anchored =
+      compile(&format!("^(?:{})$", regex_arg(&args[0])?.as_str()))?;
+    Ok(
+      anchored
+        .captures(string_arg(&args[1])?)
+        .map(match_value)
+        .unwrap_or(Nil),
+    )
+  }
+
+  pub(crate) fn re_seq(args: Vec<Value>) -> RuntimeResult<Value> {
+    if args.len() != 2 {
+      return Err(RuntimeError::InvalidArity);
+    }
+    let regex = regex_arg(&args[0])?;
+    Ok(List(Rc::new(
+      regex
+        .captures_iter(string_arg(&args[1])?)
+        .map(match_value)
+        .collect(),
+    )))
+  }
+}
+#[cfg(feature = "regex")]
+pub(crate) use enabled::{re_find, re_matches, re_pattern, re_seq};
+
+#[cfg(not(feature = "regex"))]
+pub(crate) fn unsupported(_args: Vec<Value>) -> RuntimeResult<Value> {
+  Err(RuntimeError::RegexUnsupported)
+}
+#[cfg(not(feature = "regex"))]
+pub(crate) use {
+  unsupported as re_find, unsupported as re_matches, unsupported as re_pattern,
+  unsupported as re_seq,
+};
@@ -0,0 +1,182 @@
+// Records the inputs that can make two runs of the same code differ, so that
+// they can be fed back to a later run, which then behaves exactly the same
+// way. That's what's needed to reproduce a bug that only shows up sometimes.
+// The inputs are the random number generator's seed, the times that
+// `nano-time` and `report-elapsed` read, and the results of host functions.
+// While a trace is replayed, host functions aren't called at all, and a run
+// that asks for a different input than the next recorded one, or for more
+// inputs than were recorded, fails with `ReplayDiverged`
+
+use std::rc::Rc;
+
+use super::{
+  data::{ExternalFunction, GenericValue::*, Num::*, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+  Time(i64),
+  // The name of the host function, and what it returned
+  Call(String, RuntimeResult<Value>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+  // The state of the random number generator when recording started
+  pub seed: u64,
+  pub events: Vec<TraceEvent>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) enum Replay {
+  #[default]
+  Off,
+  Recording(Trace),
+  // The trace, and the index of the next event to replay
+  Replaying(Trace, usize),
+}
+
+fn function_name(f: &ExternalFunction) -> &str {
+  f.name.as_deref().unwrap_or("anonymous host function")
+}
+
+fn diverged(expected: &str, event: Option<&TraceEvent>) -> RuntimeError {
+  let recorded = match event {
+    Some(TraceEvent::Time(_)) => "a time".to_string(),
+    Some(TraceEvent::Call(name, _)) => format!("a call to {name}"),
+    None => "nothing".to_string(),
+  };
+  RuntimeError::ReplayDiverged(format!(
+    "the run asked for {expected}, but the trace recorded {recorded}"
+  ))
+}
+
+impl Replay {
+  pub(crate) fn time(
+    &mut self,
+    now: impl FnOnce() -> i64,
+  ) -> RuntimeResult<i64> {
+    match self {
+      Replay::Off => Ok(now()),
+      Replay::Recording(trace) => {
+        let time = now();
+        trace.events.push(TraceEvent::Time(time));
+        Ok(time)
+      }
+      Replay::Replaying(trace, next) => match trace.events.get(*next) {
+        Some(TraceEvent::Time(time)) => {
+          *next += 1;
+          Ok(*time)
+        }
+        event => Err(diverged("a time", event)),
+      },
+    }
+  }
+  // The recorded result of calling `f`, if a trace is being replayed
+  pub(crate) fn replayed_call(
+    &mut self,
+    f: &ExternalFunction,
+  ) -> Option<RuntimeResult<Value>> {
+    let Replay::Replaying(trace, next) = self else {
+      return None;
+    };
+    let name = function_name(f);
+    Some(match trace.events.get(*next) {
+      Some(TraceEvent::Call(recorded_name, result))
+        if recorded_name == name =>
+      {
+        *next += 1;
+        result.clone()
+      }
+      event => Err(diverged(&format!("a call to {name}"), event)),
+    })
+  }
+  pub(crate) fn record_call(
+    &mut self,
+    f: &ExternalFunction,
+    result: &RuntimeResult<Value>,
+  ) {
+    if let Replay::Recording(trace) = self {
+      trace.events.push(TraceEvent::Call(
+        function_name(f).to_string(),
+        result.clone(),
+      ));
+    }
+  }
+}
+
+// Traces are written as a list of the seed followed by the events, where a
+// time is `("time" nanoseconds)` and a call is `("call" name "ok" value)` or
+// `("call" name "error" message)`. Only the messages of errors are kept, so
+// they're replayed as external errors
+impl Trace {
+  pub(crate) fn encode(
+    &self,
+    describe: impl Fn(&RuntimeError) -> String,
+  ) -> Value {
+    std::iter::once((self.seed as i64).into())
+      .chain(self.events.iter().map(|event| match event {
+        TraceEvent::Time(time) => {
+          Value::from(vec!["time".into(), (*time).into()])
+        }
+        TraceEvent::Call(name, Ok(value)) => Value::from(vec![
+          "call".into(),
+          name.as_str().into(),
+          "ok".into(),
+          value.clone(),
+        ]),
+        TraceEvent::Call(name, Err(error)) => Value::from(vec![
+          "call".into(),
+          name.as_str().into(),
+          "error".into(),
+          describe(error).into(),
+        ]),
+      }))
+      .collect()
+  }
+  pub(crate) fn decode(value: &Value) -> RuntimeResult<Self> {
+    let malformed =
+      || RuntimeError::Serialization("malformed trace".to_string());
+    let List(items) = value else {
+      return Err(malformed());
+    };
+    let Some(Number(Int(seed))) = items.front() else {
+      return Err(malformed());
+    };
+    let events = items
+      .iter()
+      .skip(1)
+      .map(|event| {
+        let List(parts) = event else {
+          return Err(malformed());
+        };
+        let parts: Vec<&Value> = parts.iter().collect();
+        match parts.as_slice() {
+          [Str(kind), Number(Int(time))] if kind.as_str() == "time" => {
+            Ok(TraceEvent::Time(*time))
+          }
+          [Str(kind), Str(name), Str(outcome), value]
+            if kind.as_str() == "call" =>
+          {
+            let result = match (outcome.as_str(), value) {
+              ("ok", value) => Ok((*value).clone()),
+              ("error", Str(message)) => Err(RuntimeError::ExternalError(
+                Rc::<dyn std::error::Error>::from(
+                  Box::<dyn std::error::Error>::from(message.to_string()),
+                ),
+              )),
+              _ => return Err(malformed()),
+            };
+            Ok(TraceEvent::Call(name.to_string(), result))
+          }
+          _ => Err(malformed()),
+        }
+      })
+      .collect::<RuntimeResult<Vec<_>>>()?;
+    Ok(Trace {
+      seed: *seed as u64,
+      events,
+    })
+  }
+}
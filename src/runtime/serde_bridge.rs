@@ -0,0 +1,556 @@
+// Converts between `Value`s and any Rust type that implements `Serialize` or
+// `Deserialize`. Structs and maps become hashmaps with string keys, sequences
+// and tuples become lists, and enum variants follow serde's externally tagged
+// representation: unit variants are strings, and variants with content are
+// single-entry maps from the variant name to the content
+
+use std::{fmt::Display, rc::Rc};
+
+use num_bigint::BigInt as BigInteger;
+use serde::{
+  de::{
+    self, value::StringDeserializer, DeserializeOwned, EnumAccess,
+    IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+  },
+  forward_to_deserialize_any,
+  ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+    SerializeStructVariant, SerializeTuple, SerializeTupleStruct,
+    SerializeTupleVariant,
+  },
+  Deserializer, Serializer,
+};
+
+use super::{
+  data::{GenericValue::*, ListStorage, Num, Value},
+  error::{RuntimeError, RuntimeResult},
+};
+
+impl ser::Error for RuntimeError {
+  fn custom<T: Display>(message: T) -> Self {
+    RuntimeError::Serialization(message.to_string())
+  }
+}
+impl de::Error for RuntimeError {
+  fn custom<T: Display>(message: T) -> Self {
+    RuntimeError::Serialization(message.to_string())
+  }
+}
+
+pub fn to_value<T: Serialize>(t: T) -> RuntimeResult<Value> {
+  t.serialize(ValueSerializer)
+}
+
+pub fn from_value<T: DeserializeOwned>(value: Value) -> RuntimeResult<T> {
+  T::deserialize(value)
+}
+
+fn variant_map(variant: &'static str, content: Value) -> Value {
+  Hashmap(Rc::new([(variant.into(), content)].into_iter().collect()))
+}
+
+struct ValueSerializer;
+
+struct ListSerializer {
+  variant: Option<&'static str>,
+  elements: ListStorage<Value>,
+}
+impl ListSerializer {
+  fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> RuntimeResult<()> {
+    self.elements.push_back(value.serialize(ValueSerializer)?);
+    Ok(())
+  }
+  fn finish(self) -> RuntimeResult<Value> {
+    let list = self.elements.into();
+    Ok(match self.variant {
+      Some(variant) => variant_map(variant, list),
+      None => list,
+    })
+  }
+}
+
+struct MapSerializer {
+  variant: Option<&'static str>,
+  entries: Vec<(Value, Value)>,
+  pending_key: Option<Value>,
+}
+impl MapSerializer {
+  fn new(variant: Option<&'static str>) -> Self {
+    Self {
+      variant,
+      entries: vec![],
+      pending_key: None,
+    }
+  }
+  fn push_field<T: Serialize + ?Sized>(
+    &mut self,
+    key: &'static str,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self
+      .entries
+      .push((key.into(), value.serialize(ValueSerializer)?));
+    Ok(())
+  }
+  fn finish(self) -> RuntimeResult<Value> {
+    let map = Hashmap(Rc::new(self.entries.into_iter().collect()));
+    Ok(match self.variant {
+      Some(variant) => variant_map(variant, map),
+      None => map,
+    })
+  }
+}
+
+impl Serializer for ValueSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+  type SerializeSeq = ListSerializer;
+  type SerializeTuple = ListSerializer;
+  type SerializeTupleStruct = ListSerializer;
+  type SerializeTupleVariant = ListSerializer;
+  type SerializeMap = MapSerializer;
+  type SerializeStruct = MapSerializer;
+  type SerializeStructVariant = MapSerializer;
+
+  fn serialize_bool(self, b: bool) -> RuntimeResult<Value> {
+    Ok(b.into())
+  }
+  fn serialize_i8(self, i: i8) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_i16(self, i: i16) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_i32(self, i: i32) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_i64(self, i: i64) -> RuntimeResult<Value> {
+    Ok(i.into())
+  }
+  fn serialize_i128(self, i: i128) -> RuntimeResult<Value> {
+    Ok(Number(Num::from_big_int(BigInteger::from(i))))
+  }
+  fn serialize_u8(self, i: u8) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_u16(self, i: u16) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_u32(self, i: u32) -> RuntimeResult<Value> {
+    Ok((i as i64).into())
+  }
+  fn serialize_u64(self, i: u64) -> RuntimeResult<Value> {
+    Ok(Number(Num::from_big_int(BigInteger::from(i))))
+  }
+  fn serialize_u128(self, i: u128) -> RuntimeResult<Value> {
+    Ok(Number(Num::from_big_int(BigInteger::from(i))))
+  }
+  fn serialize_f32(self, f: f32) -> RuntimeResult<Value> {
+    Ok((f as f64).into())
+  }
+  fn serialize_f64(self, f: f64) -> RuntimeResult<Value> {
+    Ok(f.into())
+  }
+  fn serialize_char(self, c: char) -> RuntimeResult<Value> {
+    Ok(c.into())
+  }
+  fn serialize_str(self, s: &str) -> RuntimeResult<Value> {
+    Ok(s.into())
+  }
+  fn serialize_bytes(self, bytes: &[u8]) -> RuntimeResult<Value> {
+    Ok(bytes.iter().map(|byte| *byte as i64).collect())
+  }
+  fn serialize_none(self) -> RuntimeResult<Value> {
+    Ok(Nil)
+  }
+  fn serialize_some<T: Serialize + ?Sized>(
+    self,
+    value: &T,
+  ) -> RuntimeResult<Value> {
+    value.serialize(self)
+  }
+  fn serialize_unit(self) -> RuntimeResult<Value> {
+    Ok(Nil)
+  }
+  fn serialize_unit_struct(self, _name: &'static str) -> RuntimeResult<Value> {
+    Ok(Nil)
+  }
+  fn serialize_unit_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+  ) -> RuntimeResult<Value> {
+    Ok(variant.into())
+  }
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    value: &T,
+  ) -> RuntimeResult<Value> {
+    value.serialize(self)
+  }
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    value: &T,
+  ) -> RuntimeResult<Value> {
+    Ok(variant_map(variant, value.serialize(self)?))
+  }
+  fn serialize_seq(self, _len: Option<usize>) -> RuntimeResult<ListSerializer> {
+    Ok(ListSerializer {
+      variant: None,
+      elements: ListStorage::new(),
+    })
+  }
+  fn serialize_tuple(self, len: usize) -> RuntimeResult<ListSerializer> {
+    self.serialize_seq(Some(len))
+  }
+  fn serialize_tuple_struct(
+    self,
+    _name: &'static str,
+    len: usize,
+  ) -> RuntimeResult<ListSerializer> {
+    self.serialize_seq(Some(len))
+  }
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> RuntimeResult<ListSerializer> {
+    Ok(ListSerializer {
+      variant: Some(variant),
+      elements: ListStorage::new(),
+    })
+  }
+  fn serialize_map(self, _len: Option<usize>) -> RuntimeResult<MapSerializer> {
+    Ok(MapSerializer::new(None))
+  }
+  fn serialize_struct(
+    self,
+    _name: &'static str,
+    _len: usize,
+  ) -> RuntimeResult<MapSerializer> {
+    Ok(MapSerializer::new(None))
+  }
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> RuntimeResult<MapSerializer> {
+    Ok(MapSerializer::new(Some(variant)))
+  }
+}
+
+impl SerializeSeq for ListSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_element<T: Serialize + ?Sized>(
+    &mut self,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push(value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeTuple for ListSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_element<T: Serialize + ?Sized>(
+    &mut self,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push(value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeTupleStruct for ListSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_field<T: Serialize + ?Sized>(
+    &mut self,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push(value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeTupleVariant for ListSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_field<T: Serialize + ?Sized>(
+    &mut self,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push(value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeMap for MapSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_key<T: Serialize + ?Sized>(
+    &mut self,
+    key: &T,
+  ) -> RuntimeResult<()> {
+    self.pending_key = Some(key.serialize(ValueSerializer)?);
+    Ok(())
+  }
+  fn serialize_value<T: Serialize + ?Sized>(
+    &mut self,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    let key = self
+      .pending_key
+      .take()
+      .expect("serialize_value called before serialize_key");
+    self.entries.push((key, value.serialize(ValueSerializer)?));
+    Ok(())
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeStruct for MapSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_field<T: Serialize + ?Sized>(
+    &mut self,
+    key: &'static str,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push_field(key, value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+impl SerializeStructVariant for MapSerializer {
+  type Ok = Value;
+  type Error = RuntimeError;
+
+  fn serialize_field<T: Serialize + ?Sized>(
+    &mut self,
+    key: &'static str,
+    value: &T,
+  ) -> RuntimeResult<()> {
+    self.push_field(key, value)
+  }
+  fn end(self) -> RuntimeResult<Value> {
+    self.finish()
+  }
+}
+
+struct ListDeserializer {
+  elements: std::vec::IntoIter<Value>,
+}
+impl<'de> SeqAccess<'de> for ListDeserializer {
+  type Error = RuntimeError;
+
+  fn next_element_seed<T: de::DeserializeSeed<'de>>(
+    &mut self,
+    seed: T,
+  ) -> RuntimeResult<Option<T::Value>> {
+    self
+      .elements
+      .next()
+      .map(|element| seed.deserialize(element))
+      .transpose()
+  }
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.elements.len())
+  }
+}
+
+struct MapDeserializer {
+  entries: std::vec::IntoIter<(Value, Value)>,
+  pending_value: Option<Value>,
+}
+impl<'de> MapAccess<'de> for MapDeserializer {
+  type Error = RuntimeError;
+
+  fn next_key_seed<K: de::DeserializeSeed<'de>>(
+    &mut self,
+    seed: K,
+  ) -> RuntimeResult<Option<K::Value>> {
+    match self.entries.next() {
+      Some((key, value)) => {
+        self.pending_value = Some(value);
+        seed.deserialize(key).map(Some)
+      }
+      None => Ok(None),
+    }
+  }
+  fn next_value_seed<V: de::DeserializeSeed<'de>>(
+    &mut self,
+    seed: V,
+  ) -> RuntimeResult<V::Value> {
+    seed.deserialize(
+      self
+        .pending_value
+        .take()
+        .expect("next_value_seed called before next_key_seed"),
+    )
+  }
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.entries.len())
+  }
+}
+
+struct VariantDeserializer {
+  variant: Value,
+  content: Value,
+}
+impl<'de> EnumAccess<'de> for VariantDeserializer {
+  type Error = RuntimeError;
+  type Variant = Value;
+
+  fn variant_seed<V: de::DeserializeSeed<'de>>(
+    self,
+    seed: V,
+  ) -> RuntimeResult<(V::Value, Value)> {
+    Ok((seed.deserialize(self.variant)?, self.content))
+  }
+}
+impl<'de> VariantAccess<'de> for Value {
+  type Error = RuntimeError;
+
+  fn unit_variant(self) -> RuntimeResult<()> {
+    de::Deserialize::deserialize(self)
+  }
+  fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+    self,
+    seed: T,
+  ) -> RuntimeResult<T::Value> {
+    seed.deserialize(self)
+  }
+  fn tuple_variant<V: Visitor<'de>>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    self.deserialize_seq(visitor)
+  }
+  fn struct_variant<V: Visitor<'de>>(
+    self,
+    _fields: &'static [&'static str],
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    self.deserialize_map(visitor)
+  }
+}
+
+impl<'de> Deserializer<'de> for Value {
+  type Error = RuntimeError;
+
+  fn deserialize_any<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    match self {
+      Nil => visitor.visit_unit(),
+      Bool(b) => visitor.visit_bool(b),
+      Char(c) => visitor.visit_char(c),
+      Number(Num::Int(i)) => visitor.visit_i64(i),
+      Number(Num::Float(f)) => visitor.visit_f64(*f),
+      Number(Num::BigInt(ref i)) => {
+        match (u64::try_from(&**i), i128::try_from(&**i)) {
+          (Ok(u), _) => visitor.visit_u64(u),
+          (_, Ok(i)) => visitor.visit_i128(i),
+          _ => Err(RuntimeError::CantConvert(self, "serializable data")),
+        }
+      }
+      Str(s) => visitor.visit_string(Rc::unwrap_or_clone(s)),
+      List(list) => visitor.visit_seq(ListDeserializer {
+        elements: list.iter().cloned().collect::<Vec<_>>().into_iter(),
+      }),
+      Hashset(set) => visitor.visit_seq(ListDeserializer {
+        elements: set.iter().cloned().collect::<Vec<_>>().into_iter(),
+      }),
+      Hashmap(map) => visitor.visit_map(MapDeserializer {
+        entries: map
+          .iter()
+          .map(|(key, value)| (key.clone(), value.clone()))
+          .collect::<Vec<_>>()
+          .into_iter(),
+        pending_value: None,
+      }),
+      Record(record) => visitor.visit_map(MapDeserializer {
+        entries: record
+          .record_type
+          .field_names
+          .iter()
+          .map(|name| Value::from(name.as_str()))
+          .zip(record.fields.iter().cloned())
+          .collect::<Vec<_>>()
+          .into_iter(),
+        pending_value: None,
+      }),
+      other => Err(RuntimeError::CantConvert(other, "serializable data")),
+    }
+  }
+  fn deserialize_option<V: Visitor<'de>>(
+    self,
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    match self {
+      Nil => visitor.visit_none(),
+      other => visitor.visit_some(other),
+    }
+  }
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    visitor.visit_newtype_struct(self)
+  }
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> RuntimeResult<V::Value> {
+    match self {
+      Str(variant) => {
+        let variant: StringDeserializer<RuntimeError> =
+          Rc::unwrap_or_clone(variant).into_deserializer();
+        visitor.visit_enum(variant)
+      }
+      Hashmap(ref map) if map.len() == 1 => {
+        let (variant, content) = map.iter().next().unwrap();
+        visitor.visit_enum(VariantDeserializer {
+          variant: variant.clone(),
+          content: content.clone(),
+        })
+      }
+      other => Err(RuntimeError::CantConvert(other, "enum")),
+    }
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+    byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    ignored_any
+  }
+}
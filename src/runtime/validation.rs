@@ -0,0 +1,207 @@
+// Checks that a block is well-formed before it's handed to the VM, which
+// otherwise assumes the compiler's invariants hold and panics mid-run when
+// they don't
+
+use std::fmt::Display;
+
+use crate::instructions::GenericInstruction::*;
+
+use super::{
+  control::{Block, FunctionBody},
+  data::GenericValue::CompositeFn,
+  evaluation::{ConstIndex, Register},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+  ConstIndexOutOfRange {
+    instruction: usize,
+    index: ConstIndex,
+    constant_count: usize,
+  },
+  RegisterOutOfRange {
+    instruction: usize,
+    register: Register,
+    max_register: Register,
+  },
+  JumpOutOfRange {
+    instruction: usize,
+    target: isize,
+  },
+  UnexpectedBranch(usize),
+  UnclosedIf(usize),
+  UnbalancedDynamicBindings(usize),
+  TailCallOutsideFunction(usize),
+  MissingReturn,
+  InConstant {
+    index: usize,
+    error: Box<ValidationError>,
+  },
+}
+use ValidationError::*;
+
+impl Display for ValidationError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ConstIndexOutOfRange {
+        instruction,
+        index,
+        constant_count,
+      } => write!(
+        f,
+        "instruction {instruction} refers to constant {index}, but the block \
+         only has {constant_count}"
+      ),
+      RegisterOutOfRange {
+        instruction,
+        register,
+        max_register,
+      } => write!(
+        f,
+        "instruction {instruction} uses register {register}, but the block \
+         only reserves registers up to {max_register}"
+      ),
+      JumpOutOfRange {
+        instruction,
+        target,
+      } => write!(
+        f,
+        "instruction {instruction} jumps to {target}, outside of the block"
+      ),
+      UnexpectedBranch(instruction) => write!(
+        f,
+        "instruction {instruction} is an Else, ElseIf, or EndIf without a \
+         matching If"
+      ),
+      UnclosedIf(instruction) => {
+        write!(
+          f,
+          "the If at instruction {instruction} has no matching EndIf"
+        )
+      }
+      UnbalancedDynamicBindings(instruction) => write!(
+        f,
+        "instruction {instruction} pops more dynamic bindings than have been \
+         pushed"
+      ),
+      TailCallOutsideFunction(instruction) => write!(
+        f,
+        "instruction {instruction} returns from the root block, which has no \
+         caller to return to"
+      ),
+      MissingReturn => write!(f, "function block never returns"),
+      InConstant { index, error } => {
+        write!(f, "in the function at constant {index}: {error}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Function blocks must contain an instruction that returns from the frame,
+// while the root block is allowed to simply run off its end
+pub fn validate_block(
+  block: &Block,
+  is_function: bool,
+) -> Result<(), ValidationError> {
+  let instructions = &block.instructions;
+  let max_register = block.metadata;
+  let mut open_ifs = vec![];
+  let mut returns = false;
+  let mut dynamic_bindings = 0usize;
+  for (i, instruction) in instructions.iter().enumerate() {
+    let out_of_range = match instruction {
+      YieldAndAccept(output, _, first_input) => [*output, *first_input]
+        .into_iter()
+        .find(|r| *r > max_register),
+      instruction => {
+        let usages = instruction.usages();
+        usages
+          .inputs
+          .chain(usages.outputs)
+          .chain(usages.replacements)
+          .find(|r| *r > max_register)
+      }
+    };
+    if let Some(register) = out_of_range {
+      return Err(RegisterOutOfRange {
+        instruction: i,
+        register,
+        max_register,
+      });
+    }
+    match instruction {
+      Const(_, index) | ConstAdd(_, index, _) | GetConstKey(_, _, index)
+        if *index as usize >= block.constants.len() =>
+      {
+        return Err(ConstIndexOutOfRange {
+          instruction: i,
+          index: *index,
+          constant_count: block.constants.len(),
+        })
+      }
+      Jump(target)
+      | JumpIf(_, target)
+      | JumpIfNot(_, target)
+      | IncAndJumpIfPos(_, target)
+        if *target as usize > instructions.len() =>
+      {
+        return Err(JumpOutOfRange {
+          instruction: i,
+          target: *target as isize,
+        })
+      }
+      RelativeJump(offset)
+        if !(0..=instructions.len() as isize)
+          .contains(&(i as isize + *offset as isize)) =>
+      {
+        return Err(JumpOutOfRange {
+          instruction: i,
+          target: i as isize + *offset as isize,
+        })
+      }
+      CallAndReturn(..) | ApplyAndReturn(..) if !is_function => {
+        return Err(TailCallOutsideFunction(i))
+      }
+      PushDynamicBinding(..) => dynamic_bindings += 1,
+      PopDynamicBindings(count) => {
+        dynamic_bindings = dynamic_bindings
+          .checked_sub(*count as usize)
+          .ok_or(UnbalancedDynamicBindings(i))?
+      }
+      If(_) => open_ifs.push(i),
+      Else | ElseIf(_) if open_ifs.is_empty() => {
+        return Err(UnexpectedBranch(i))
+      }
+      EndIf if open_ifs.pop().is_none() => return Err(UnexpectedBranch(i)),
+      _ => {}
+    }
+    if matches!(
+      instruction,
+      Return(_) | CallAndReturn(..) | ApplyAndReturn(..)
+    ) {
+      returns = true;
+    }
+  }
+  if let Some(&unclosed) = open_ifs.last() {
+    return Err(UnclosedIf(unclosed));
+  }
+  if is_function && !returns {
+    return Err(MissingReturn);
+  }
+  // deferred functions come straight from the compiler, so only functions
+  // that were handed over already compiled are checked
+  for (index, constant) in block.constants.iter().enumerate() {
+    if let CompositeFn(f) = constant {
+      let FunctionBody::Compiled(f_block) = &f.body else {
+        continue;
+      };
+      validate_block(f_block, true).map_err(|error| InConstant {
+        index,
+        error: Box::new(error),
+      })?;
+    }
+  }
+  Ok(())
+}
@@ -11,3 +11,205 @@ pub(crate) fn pad(length: usize, c: char, mut s: String) -> String {
   }
   s
 }
+
+// A document to be laid out within a maximum width, in the style of Wadler's
+// "prettier printer". A group is printed flat when it fits on the rest of its
+// line, up to the next break outside of it, and otherwise all of its own
+// breaks become newlines while the groups inside it get to decide for
+// themselves
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Layout {
+  Text(String),
+  // The given separator when flat, or a newline followed by the indentation
+  Break(&'static str),
+  // Indents the lines started by breaks inside of it by this many spaces
+  Nest(usize, Box<Layout>),
+  Concat(Vec<Layout>),
+  Group(Vec<Layout>),
+}
+
+impl Layout {
+  pub fn text(s: impl Into<String>) -> Self {
+    Self::Text(s.into())
+  }
+  pub fn nest(self, spaces: usize) -> Self {
+    Self::Nest(spaces, Box::new(self))
+  }
+}
+
+// Takes the width of a layout away from `remaining`, stopping at the first
+// newline, and returns whether there was one
+fn measure(layout: &Layout, flat: bool, remaining: &mut isize) -> bool {
+  match layout {
+    Layout::Text(s) => {
+      let (line, newline) = match s.split_once('\n') {
+        Some((line, _)) => (line, true),
+        None => (s.as_str(), false),
+      };
+      *remaining -= line.chars().count() as isize;
+      newline
+    }
+    Layout::Break(separator) if flat => {
+      *remaining -= separator.chars().count() as isize;
+      false
+    }
+    Layout::Break(_) => true,
+    Layout::Nest(_, inner) => measure(inner, flat, remaining),
+    Layout::Concat(items) | Layout::Group(items) => {
+      items.iter().any(|item| measure(item, flat, remaining))
+    }
+  }
+}
+
+// Whether a group fits flat in the remaining width, along with whatever
+// follows it on the same line
+fn fits(
+  items: &[Layout],
+  pending: &[(usize, bool, &Layout)],
+  mut remaining: isize,
+) -> bool {
+  // text with a newline in it can't be part of a flat group
+  if items.iter().any(|item| measure(item, true, &mut remaining)) {
+    return false;
+  }
+  for (_, flat, layout) in pending.iter().rev() {
+    if measure(layout, *flat, &mut remaining) {
+      break;
+    }
+  }
+  remaining >= 0
+}
+
+pub(crate) fn render(layout: &Layout, width: usize) -> String {
+  let mut output = String::new();
+  let mut column = 0;
+  // the layouts left to print, last first, with their indentation and whether
+  // they're flat
+  let mut pending = vec![(0, false, layout)];
+  while let Some((indent, flat, layout)) = pending.pop() {
+    match layout {
+      Layout::Text(s) => {
+        output.push_str(s);
+        column = match s.rsplit_once('\n') {
+          Some((_, last_line)) => last_line.chars().count(),
+          None => column + s.chars().count(),
+        };
+      }
+      Layout::Break(separator) if flat => {
+        output.push_str(separator);
+        column += separator.chars().count();
+      }
+      Layout::Break(_) => {
+        output.push('\n');
+        output.push_str(&" ".repeat(indent));
+        column = indent;
+      }
+      Layout::Nest(spaces, inner) => {
+        pending.push((indent + spaces, flat, inner))
+      }
+      Layout::Concat(items) => {
+        pending.extend(items.iter().rev().map(|item| (indent, flat, item)))
+      }
+      Layout::Group(items) => {
+        let flat =
+          flat || fits(items, &pending, width as isize - column as isize);
+        pending.extend(items.iter().rev().map(|item| (indent, flat, item)))
+      }
+    }
+  }
+  output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Color {
+  Red,
+  Green,
+  Yellow,
+  Magenta,
+  Cyan,
+}
+
+impl Color {
+  fn ansi_code(&self) -> u8 {
+    match self {
+      Color::Red => 31,
+      Color::Green => 32,
+      Color::Yellow => 33,
+      Color::Magenta => 35,
+      Color::Cyan => 36,
+    }
+  }
+}
+
+pub(crate) fn colored(s: &str, color: Color) -> String {
+  format!("\x1b[{}m{s}\x1b[0m", color.ansi_code())
+}
+
+fn token_color(token: &str) -> Option<Color> {
+  let unsigned = token.trim_start_matches(['-', '+']);
+  if unsigned.starts_with(|c: char| c.is_ascii_digit())
+    || matches!(unsigned, "inf" | "NaN")
+  {
+    Some(Color::Cyan)
+  } else if token.starts_with(':') {
+    Some(Color::Magenta)
+  } else if matches!(token, "nil" | "true" | "false") {
+    Some(Color::Yellow)
+  } else {
+    None
+  }
+}
+
+// Colors the literals in printed Pidgin data. This works on the printed text
+// rather than on values so that it can't affect how the text is laid out
+pub(crate) fn highlight(s: &str) -> String {
+  let mut highlighted = String::new();
+  let mut chars = s.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == '"' {
+      let mut string = c.to_string();
+      while let Some(c) = chars.next() {
+        string.push(c);
+        if c == '\\' {
+          string.extend(chars.next());
+        } else if c == '"' {
+          break;
+        }
+      }
+      highlighted.push_str(&colored(&string, Color::Green));
+    } else if c.is_whitespace() || "()[]{}#,".contains(c) {
+      highlighted.push(c);
+    } else {
+      let mut token = c.to_string();
+      while let Some(c) =
+        chars.next_if(|c| !c.is_whitespace() && !"()[]{}\",".contains(*c))
+      {
+        token.push(c);
+      }
+      match token_color(&token) {
+        Some(color) => highlighted.push_str(&colored(&token, color)),
+        None => highlighted.push_str(&token),
+      }
+    }
+  }
+  highlighted
+}
+
+// Underlines the first occurrence of `token` in `line` that isn't part of a
+// longer token, returning the line with the underline beneath it
+pub(crate) fn underline_token(line: &str, token: &str) -> Option<String> {
+  let is_boundary = |c: Option<char>| {
+    c.is_none_or(|c| c.is_whitespace() || "()[]{}\"'@,".contains(c))
+  };
+  let start = line.match_indices(token).map(|(i, _)| i).find(|&i| {
+    is_boundary(line[..i].chars().next_back())
+      && is_boundary(line[i + token.len()..].chars().next())
+  })?;
+  let offset = line[..start].chars().count();
+  let length = token.chars().count().max(1);
+  Some(format!(
+    "{line}\n{}^{}",
+    " ".repeat(offset),
+    "~".repeat(length - 1)
+  ))
+}